@@ -46,7 +46,7 @@ USAGE:
 
 FLAGS:
     -s, --script             The script to run
-    -w, --watch              Watch the script file for changes
+    -w, --watch              Watch the script and the modules it imports for changes
     -h, --help               Prints help information
     -v, --version            Prints version information
 ",
@@ -115,18 +115,20 @@ fn main() -> Result<(), Box<dyn Error>> {
             eprintln!("{}", e);
         }
 
+        // Imported modules are expected to be next to the script, or in its subdirectories
+        let watch_dir = match script_path.parent() {
+            Some(dir) if dir != Path::new("") => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
         let mut hotwatch = Hotwatch::new_with_custom_delay(Duration::from_secs_f64(0.25))
             .expect("Failed to initialize file watcher");
         hotwatch
-            .watch(&args.script, move |event: Event| {
-                // dbg!(&event);
-                match event {
-                    Event::Create(script_path) | Event::Write(script_path) => {
-                        if let Err(e) = compile_and_run(&mut koto, &script_path) {
-                            eprintln!("{}", e);
-                        }
+            .watch(watch_dir, move |event: Event| {
+                if let Event::Create(_) | Event::Write(_) = event {
+                    if let Err(e) = koto.reload_changed() {
+                        eprintln!("Error while reloading script: {}", e);
                     }
-                    _ => {}
                 }
                 Flow::Continue
             })
@@ -1,53 +1,19 @@
 //! A Koto language module for working with JSON data
 
 use {
-    koto_runtime::{runtime_error, Value, ValueList, ValueMap, ValueVec},
-    koto_serialize::SerializableValue,
+    koto_runtime::{runtime_error, Value, ValueMap},
+    koto_serialize::{to_value, SerializableValue},
     serde_json::Value as JsonValue,
 };
 
-fn json_value_to_koto_value(value: &serde_json::Value) -> Result<Value, String> {
-    let result = match value {
-        JsonValue::Null => Value::Empty,
-        JsonValue::Bool(b) => Value::Bool(*b),
-        JsonValue::Number(n) => match n.as_i64() {
-            Some(n64) => Value::Number(n64.into()),
-            None => match n.as_f64() {
-                Some(n64) => Value::Number(n64.into()),
-                None => return Err(format!("Number is out of range: {}", n)),
-            },
-        },
-        JsonValue::String(s) => Value::Str(s.as_str().into()),
-        JsonValue::Array(a) => {
-            match a
-                .iter()
-                .map(|entry| json_value_to_koto_value(entry))
-                .collect::<Result<ValueVec, String>>()
-            {
-                Ok(result) => Value::List(ValueList::with_data(result)),
-                Err(e) => return Err(e),
-            }
-        }
-        JsonValue::Object(o) => {
-            let mut map = ValueMap::with_capacity(o.len());
-            for (key, value) in o.iter() {
-                map.add_value(key, json_value_to_koto_value(value)?);
-            }
-            Value::Map(map)
-        }
-    };
-
-    Ok(result)
-}
-
 pub fn make_module() -> ValueMap {
     use Value::*;
 
     let mut result = ValueMap::new();
 
     result.add_fn("from_string", |vm, args| match vm.get_args(args) {
-        [Str(s)] => match serde_json::from_str(&s) {
-            Ok(value) => match json_value_to_koto_value(&value) {
+        [Str(s)] => match serde_json::from_str::<JsonValue>(&s) {
+            Ok(value) => match to_value(&value) {
                 Ok(result) => Ok(result),
                 Err(e) => runtime_error!("json.from_string: Error while parsing input: {}", e),
             },
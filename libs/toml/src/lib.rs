@@ -1,38 +1,24 @@
 //! A Koto language module for working with TOML data
 
 use {
-    koto_runtime::{runtime_error, Value, ValueList, ValueMap, ValueVec},
-    koto_serialize::SerializableValue,
+    koto_runtime::{runtime_error, Value, ValueMap},
+    koto_serialize::{to_value, SerializableValue},
+    serde::{Serialize, Serializer},
     toml::Value as Toml,
 };
 
-fn toml_to_koto_value(value: &Toml) -> Result<Value, String> {
-    let result = match value {
-        Toml::Boolean(b) => Value::Bool(*b),
-        Toml::Integer(i) => Value::Number(i.into()),
-        Toml::Float(f) => Value::Number(f.into()),
-        Toml::String(s) => Value::Str(s.as_str().into()),
-        Toml::Array(a) => {
-            match a
-                .iter()
-                .map(|entry| toml_to_koto_value(entry))
-                .collect::<Result<ValueVec, String>>()
-            {
-                Ok(result) => Value::List(ValueList::with_data(result)),
-                Err(e) => return Err(e),
-            }
-        }
-        Toml::Table(o) => {
-            let mut map = ValueMap::with_capacity(o.len());
-            for (key, value) in o.iter() {
-                map.add_value(key, toml_to_koto_value(value)?);
-            }
-            Value::Map(map)
-        }
-        Toml::Datetime(dt) => Value::Str(dt.to_string().into()),
-    };
+// Wraps a TOML value so that datetimes are serialized as strings
+struct TomlValue<'a>(&'a Toml);
 
-    Ok(result)
+impl<'a> Serialize for TomlValue<'a> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Toml::Datetime(dt) => s.serialize_str(&dt.to_string()),
+            Toml::Array(a) => s.collect_seq(a.iter().map(TomlValue)),
+            Toml::Table(t) => s.collect_map(t.iter().map(|(key, value)| (key, TomlValue(value)))),
+            other => other.serialize(s),
+        }
+    }
 }
 
 pub fn make_module() -> ValueMap {
@@ -41,8 +27,8 @@ pub fn make_module() -> ValueMap {
     let mut result = ValueMap::new();
 
     result.add_fn("from_string", |vm, args| match vm.get_args(args) {
-        [Str(s)] => match toml::from_str(s) {
-            Ok(toml) => match toml_to_koto_value(&toml) {
+        [Str(s)] => match toml::from_str::<Toml>(s) {
+            Ok(toml) => match to_value(&TomlValue(&toml)) {
                 Ok(result) => Ok(result),
                 Err(e) => runtime_error!("toml.from_string: Error while parsing input: {}", e),
             },
@@ -1,13 +1,30 @@
 use {
-    crate::InstructionReader,
-    koto_parser::{ConstantPool, Span},
+    crate::{CompilerWarning, InstructionReader},
+    koto_parser::{ConstantIndex, ConstantPool, Span},
     std::{path::PathBuf, sync::Arc},
 };
 
+/// Debug information for a function's frame, or for the top-level frame of a program
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrameDebugInfo {
+    /// The ip of the frame's first instruction
+    pub start_ip: usize,
+    /// The ip following the frame's last instruction
+    pub end_ip: usize,
+    /// The source span of the frame's function, or of the program's main block
+    pub span: Span,
+    /// The names of the frame's local registers, indexed by register
+    ///
+    /// The names are indices into the chunk's constant pool,
+    /// `None` is used for registers that don't have a name, e.g. wildcard arguments.
+    pub locals: Vec<Option<ConstantIndex>>,
+}
+
 /// Debug information for a Koto program
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct DebugInfo {
-    source_map: Vec<(usize, Span)>,
+    pub(crate) source_map: Vec<(usize, Span)>,
+    pub(crate) frames: Vec<FrameDebugInfo>,
     /// The source of the program that the debug info was derived from
     pub source: String,
 }
@@ -28,17 +45,27 @@ impl DebugInfo {
     /// Returns a source span for a given instruction pointer
     pub fn get_source_span(&self, ip: usize) -> Option<Span> {
         // Find the last entry with an ip less than or equal to the input
-        // an upper_bound would nice here, but this isn't currently a performance sensitive function
-        // so a scan through the entries will do.
-        let mut result = None;
-        for entry in self.source_map.iter() {
-            if entry.0 <= ip {
-                result = Some(entry.1);
-            } else {
-                break;
-            }
+        // This is called for every instruction while a debugger is attached,
+        // so a binary search is used rather than a scan through the entries.
+        match self
+            .source_map
+            .partition_point(|(entry_ip, _)| *entry_ip <= ip)
+        {
+            0 => None,
+            end => Some(self.source_map[end - 1].1),
         }
-        result
+    }
+
+    pub fn push_frame(&mut self, frame: FrameDebugInfo) {
+        self.frames.push(frame);
+    }
+
+    /// Returns the debug info for the innermost frame that contains the given instruction pointer
+    pub fn get_frame(&self, ip: usize) -> Option<&FrameDebugInfo> {
+        self.frames
+            .iter()
+            .filter(|frame| frame.start_ip <= ip && ip < frame.end_ip)
+            .max_by_key(|frame| frame.start_ip)
     }
 }
 
@@ -55,6 +82,8 @@ pub struct Chunk {
     pub source_path: Option<PathBuf>,
     /// Debug information associated with the chunk's bytecode
     pub debug_info: DebugInfo,
+    /// The warnings that were reported while compiling the chunk
+    pub warnings: Vec<CompilerWarning>,
 }
 
 impl Default for Chunk {
@@ -65,6 +94,7 @@ impl Default for Chunk {
             string_constants_arc: String::default().into(),
             source_path: None,
             debug_info: DebugInfo::default(),
+            warnings: vec![],
         }
     }
 }
@@ -82,6 +112,7 @@ impl Chunk {
             constants,
             source_path,
             debug_info,
+            warnings: vec![],
         }
     }
 
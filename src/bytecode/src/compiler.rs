@@ -1,8 +1,9 @@
 use {
-    crate::{DebugInfo, FunctionFlags, Op, TypeId},
+    crate::{warnings, CompilerWarning, DebugInfo, FrameDebugInfo, FunctionFlags, Op, TypeId},
     koto_parser::{
         AssignOp, AssignTarget, Ast, AstFor, AstIf, AstIndex, AstNode, AstOp, AstTry,
-        ConstantIndex, Function, LookupNode, MapKey, MatchArm, Node, Scope, Span, SwitchArm,
+        ConstantIndex, ConstantPool, Function, LookupNode, MapKey, MatchArm, Node, Scope, Span,
+        SwitchArm,
     },
     smallvec::SmallVec,
     std::{convert::TryFrom, error, fmt},
@@ -368,10 +369,16 @@ pub struct Compiler {
 }
 
 impl Compiler {
+    /// Compiles the AST into bytecode
+    ///
+    /// The compiled bytecode is returned along with its debug info, and any warnings that were
+    /// found while checking the AST. The constants produced by the parser are used to look up
+    /// the names of locals when checking for warnings.
     pub fn compile(
         ast: &Ast,
+        constants: &ConstantPool,
         settings: CompilerSettings,
-    ) -> Result<(Vec<u8>, DebugInfo), CompilerError> {
+    ) -> Result<(Vec<u8>, DebugInfo, Vec<CompilerWarning>), CompilerError> {
         let mut compiler = Compiler {
             settings,
             ..Default::default()
@@ -381,7 +388,9 @@ impl Compiler {
             compiler.compile_node(ResultRegister::None, entry_point, ast)?;
         }
 
-        Ok((compiler.bytes, compiler.debug_info))
+        let warnings = warnings::check_ast(ast, constants, compiler.settings.repl_mode);
+
+        Ok((compiler.bytes, compiler.debug_info, warnings))
     }
 
     fn compile_node(
@@ -772,6 +781,8 @@ impl Compiler {
         ast: &Ast,
         allow_implicit_return: bool,
     ) -> Result<(), CompilerError> {
+        let frame_start_ip = self.bytes.len();
+
         self.frame_stack.push(Frame::new(
             local_count,
             &self.collect_args(args, ast)?,
@@ -819,6 +830,22 @@ impl Compiler {
             self.pop_register()?;
         }
 
+        let locals = self
+            .frame()
+            .local_registers
+            .iter()
+            .map(|local_register| match local_register {
+                LocalRegister::Assigned(id) | LocalRegister::Reserved(id, _) => Some(*id),
+                LocalRegister::Allocated => None,
+            })
+            .collect();
+        self.debug_info.push_frame(FrameDebugInfo {
+            start_ip: frame_start_ip,
+            end_ip: self.bytes.len(),
+            span: self.span(),
+            locals,
+        });
+
         self.frame_stack.pop();
 
         Ok(())
@@ -2323,7 +2350,11 @@ impl Compiler {
             .compile_node(ResultRegister::Any, ast.node(*condition), ast)?
             .unwrap();
 
-        self.push_op_without_span(JumpFalse, &[condition_register.register]);
+        // The condition's jump is given the condition's span, so that it's attributed to the
+        // condition's line even when the condition doesn't need any instructions of its own.
+        self.span_stack.push(*ast.span(ast.node(*condition).span));
+        self.push_op(JumpFalse, &[condition_register.register]);
+        self.span_stack.pop();
         let condition_jump_ip = self.push_offset_placeholder();
 
         if condition_register.is_temporary {
@@ -2353,7 +2384,10 @@ impl Compiler {
                         .compile_node(ResultRegister::Any, ast.node(*else_if_condition), ast)?
                         .unwrap();
 
-                    self.push_op_without_span(JumpFalse, &[condition.register]);
+                    self.span_stack
+                        .push(*ast.span(ast.node(*else_if_condition).span));
+                    self.push_op(JumpFalse, &[condition.register]);
+                    self.span_stack.pop();
                     let conditon_jump_ip = self.push_offset_placeholder();
 
                     if condition.is_temporary {
@@ -6,12 +6,20 @@ mod chunk;
 mod compiler;
 mod instruction_reader;
 mod loader;
+mod module_resolver;
 mod op;
+mod serialization;
+mod warnings;
 
 pub use {
-    chunk::{Chunk, DebugInfo},
+    chunk::{Chunk, DebugInfo, FrameDebugInfo},
     compiler::{Compiler, CompilerError, CompilerSettings},
     instruction_reader::{FunctionFlags, Instruction, InstructionReader, TypeId},
-    loader::{Loader, LoaderError},
+    loader::{Loader, LoaderError, LoaderErrorType},
+    module_resolver::{
+        find_module, FileSystemResolver, InMemoryResolver, ModuleResolver, SearchPathResolver,
+    },
     op::Op,
+    serialization::{ChunkFormatError, CHUNK_FORMAT_VERSION},
+    warnings::{CompilerWarning, WarningKind},
 };
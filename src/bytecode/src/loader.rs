@@ -1,13 +1,20 @@
 use {
-    crate::{Chunk, Compiler, CompilerError, CompilerSettings},
+    crate::{Chunk, Compiler, CompilerError, CompilerSettings, FileSystemResolver, ModuleResolver},
     koto_parser::{format_error_with_excerpt, Parser, ParserError},
-    std::{collections::HashMap, error, fmt, path::PathBuf, sync::Arc},
+    std::{
+        collections::HashMap,
+        error, fmt, fs,
+        path::{Path, PathBuf},
+        sync::Arc,
+        time::SystemTime,
+    },
 };
 
 /// Errors that can be returned from [Loader] operations
 #[derive(Clone, Debug)]
 pub enum LoaderErrorType {
-    ParserError(ParserError),
+    /// The errors that were found while parsing the script, in the order they were encountered
+    ParserErrors(Vec<ParserError>),
     CompilerError(CompilerError),
     IoError(String),
 }
@@ -24,9 +31,17 @@ impl LoaderError {
         error: ParserError,
         source: &str,
         source_path: Option<PathBuf>,
+    ) -> Self {
+        Self::from_parser_errors(vec![error], source, source_path)
+    }
+
+    pub fn from_parser_errors(
+        errors: Vec<ParserError>,
+        source: &str,
+        source_path: Option<PathBuf>,
     ) -> Self {
         Self {
-            error: LoaderErrorType::ParserError(error),
+            error: LoaderErrorType::ParserErrors(errors),
             source: source.into(),
             source_path,
         }
@@ -52,9 +67,19 @@ impl LoaderError {
         }
     }
 
+    /// The type of error that occurred
+    pub fn error_type(&self) -> &LoaderErrorType {
+        &self.error
+    }
+
+    /// True if the first error is an indentation error
+    ///
+    /// This can be used to detect incomplete input, e.g. in a REPL.
     pub fn is_indentation_error(&self) -> bool {
         match &self.error {
-            LoaderErrorType::ParserError(e) => e.is_indentation_error(),
+            LoaderErrorType::ParserErrors(errors) => {
+                matches!(errors.first(), Some(error) if error.is_indentation_error())
+            }
             _ => false,
         }
     }
@@ -66,22 +91,35 @@ impl fmt::Display for LoaderError {
 
         if f.alternate() {
             match &self.error {
-                ParserError(koto_parser::ParserError { error, .. }) => {
-                    f.write_str(&error.to_string())
+                ParserErrors(errors) => {
+                    for (i, error) in errors.iter().enumerate() {
+                        if i > 0 {
+                            f.write_str("\n")?;
+                        }
+                        f.write_str(&error.to_string())?;
+                    }
+                    Ok(())
                 }
                 CompilerError(crate::CompilerError { message, .. }) => f.write_str(message),
                 IoError(e) => f.write_str(&e),
             }
         } else {
             match &self.error {
-                ParserError(koto_parser::ParserError { error, span }) => {
-                    f.write_str(&format_error_with_excerpt(
-                        Some(&error.to_string()),
-                        &self.source_path,
-                        &self.source,
-                        span.start,
-                        span.end,
-                    ))
+                ParserErrors(errors) => {
+                    // Each error is displayed with its own excerpt, separated by blank lines
+                    for (i, koto_parser::ParserError { error, span }) in errors.iter().enumerate() {
+                        if i > 0 {
+                            f.write_str("\n\n")?;
+                        }
+                        f.write_str(&format_error_with_excerpt(
+                            Some(&error.to_string()),
+                            &self.source_path,
+                            &self.source,
+                            span.start,
+                            span.end,
+                        ))?;
+                    }
+                    Ok(())
                 }
                 CompilerError(crate::CompilerError { message, span }) => {
                     f.write_str(&format_error_with_excerpt(
@@ -101,36 +139,123 @@ impl fmt::Display for LoaderError {
 impl error::Error for LoaderError {}
 
 /// Helper for loading, compiling, and caching Koto modules
-#[derive(Clone, Default)]
+///
+/// Compiled chunks are cached in memory, and optionally on disk when a cache directory is
+/// provided, see [Loader::set_cache_dir].
+///
+/// Imported modules are found with a [ModuleResolver], which defaults to a [FileSystemResolver],
+/// see [Loader::set_module_resolver].
+#[derive(Clone)]
 pub struct Loader {
-    chunks: HashMap<PathBuf, Arc<Chunk>>,
+    modules: HashMap<PathBuf, CompiledModule>,
+    cache_dir: Option<PathBuf>,
+    module_resolver: Arc<dyn ModuleResolver>,
+}
+
+impl Default for Loader {
+    fn default() -> Self {
+        Self {
+            modules: HashMap::new(),
+            cache_dir: None,
+            module_resolver: Arc::new(FileSystemResolver),
+        }
+    }
 }
 
 impl Loader {
+    /// Makes a Loader that caches compiled chunks in the provided directory
+    pub fn with_cache_dir(cache_dir: PathBuf) -> Self {
+        Self {
+            cache_dir: Some(cache_dir),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the directory used to cache compiled chunks
+    ///
+    /// Chunks are stored in the directory using the [Chunk::to_bytes] format, keyed by a hash of
+    /// the script's source and path. When a script is compiled that matches a cached chunk, then
+    /// the cached chunk is used and the script doesn't need to be parsed and compiled.
+    ///
+    /// The directory will be created if it doesn't already exist.
+    pub fn set_cache_dir(&mut self, cache_dir: Option<PathBuf>) {
+        self.cache_dir = cache_dir;
+    }
+
+    /// The directory used to cache compiled chunks
+    pub fn cache_dir(&self) -> Option<&Path> {
+        self.cache_dir.as_deref()
+    }
+
+    /// Sets the resolver that's used to find and load modules, see [Loader::compile_module]
+    pub fn set_module_resolver(&mut self, module_resolver: Arc<dyn ModuleResolver>) {
+        self.module_resolver = module_resolver;
+    }
+
+    /// The resolver that's used to find and load modules
+    pub fn module_resolver(&self) -> &Arc<dyn ModuleResolver> {
+        &self.module_resolver
+    }
+
+    /// Clears the loader's in-memory cache of compiled modules
+    ///
+    /// The loader's settings, and any chunks cached on disk, are kept.
+    pub fn clear(&mut self) {
+        self.modules.clear();
+    }
+
     fn compile(
         &mut self,
         script: &str,
         script_path: Option<PathBuf>,
         compiler_settings: CompilerSettings,
     ) -> Result<Arc<Chunk>, LoaderError> {
-        match Parser::parse(&script) {
-            Ok((ast, constants)) => {
-                let (bytes, mut debug_info) = match Compiler::compile(&ast, compiler_settings) {
-                    Ok((bytes, debug_info)) => (bytes, debug_info),
-                    Err(e) => return Err(LoaderError::from_compiler_error(e, script, script_path)),
-                };
-
-                debug_info.source = script.to_string();
-
-                Ok(Arc::new(Chunk::new(
-                    bytes,
-                    constants,
-                    script_path,
-                    debug_info,
-                )))
+        let cache_path = match &self.cache_dir {
+            Some(cache_dir) if !compiler_settings.repl_mode => {
+                Some(cache_dir.join(cached_chunk_file_name(script, &script_path)))
+            }
+            _ => None,
+        };
+
+        if let Some(cache_path) = &cache_path {
+            if let Some(chunk) = load_cached_chunk(cache_path, script, &script_path) {
+                return Ok(chunk);
             }
-            Err(e) => Err(LoaderError::from_parser_error(e, script, script_path)),
         }
+
+        let chunk = self.parse_and_compile(script, script_path, compiler_settings)?;
+
+        if let Some(cache_path) = &cache_path {
+            // Failing to write to the cache isn't an error, the chunk will be recompiled next time
+            let _ = write_cached_chunk(cache_path, &chunk);
+        }
+
+        Ok(chunk)
+    }
+
+    fn parse_and_compile(
+        &mut self,
+        script: &str,
+        script_path: Option<PathBuf>,
+        compiler_settings: CompilerSettings,
+    ) -> Result<Arc<Chunk>, LoaderError> {
+        // Parsing continues past errors so that all of the script's errors can be reported
+        let (ast, constants, errors) = Parser::parse_with_recovery(&script);
+        if !errors.is_empty() {
+            return Err(LoaderError::from_parser_errors(errors, script, script_path));
+        }
+
+        let (bytes, mut debug_info, warnings) =
+            match Compiler::compile(&ast, &constants, compiler_settings) {
+                Ok(result) => result,
+                Err(e) => return Err(LoaderError::from_compiler_error(e, script, script_path)),
+            };
+
+        debug_info.source = script.to_string();
+
+        let mut chunk = Chunk::new(bytes, constants, script_path, debug_info);
+        chunk.warnings = warnings;
+        Ok(Arc::new(chunk))
     }
 
     pub fn compile_repl(&mut self, script: &str) -> Result<Arc<Chunk>, LoaderError> {
@@ -145,70 +270,151 @@ impl Loader {
         self.compile(script, script_path.clone(), CompilerSettings::default())
     }
 
+    /// Compiles the module with the given name, found with the loader's [ModuleResolver]
+    ///
+    /// `load_from_path` is the path of the script that's importing the module, if available.
+    ///
+    /// Compiled modules are cached using the module's resolved path, which is returned along with
+    /// the compiled chunk.
     pub fn compile_module(
         &mut self,
         name: &str,
         load_from_path: Option<PathBuf>,
     ) -> Result<(Arc<Chunk>, PathBuf), LoaderError> {
-        // Get either the directory of the provided path, or the current working directory
-        let path = match &load_from_path {
-            Some(path) => match path.canonicalize() {
-                Ok(canonicalized) if canonicalized.is_file() => match canonicalized.parent() {
-                    Some(parent_dir) => parent_dir.to_path_buf(),
-                    None => {
-                        return Err(LoaderError::io_error(
-                            "Failed to get parent of provided path".to_string(),
-                        ))
-                    }
-                },
-                Ok(canonicalized) => canonicalized,
-                Err(e) => return Err(LoaderError::io_error(e.to_string())),
-            },
-            None => match std::env::current_dir() {
-                Ok(path) => path,
-                Err(e) => return Err(LoaderError::io_error(e.to_string())),
-            },
-        };
+        let module_path = self
+            .module_resolver
+            .resolve(name, load_from_path.as_deref())?;
 
-        let mut load_module_from_path = |module_path: PathBuf| match self.chunks.get(&module_path) {
-            Some(chunk) => Ok((chunk.clone(), module_path.clone())),
-            None => match std::fs::read_to_string(&module_path) {
-                Ok(script) => {
-                    let chunk = self.compile(
-                        &script,
-                        Some(module_path.clone()),
-                        CompilerSettings::default(),
-                    )?;
-
-                    self.chunks.insert(module_path.clone(), chunk.clone());
-                    Ok((chunk, module_path))
+        match self.modules.get(&module_path) {
+            Some(module) => Ok((module.chunk.clone(), module_path)),
+            None => {
+                // The modification time is checked before loading the source, so that changes
+                // made while the module is being compiled will be picked up later.
+                let modified = self.module_resolver.modified(&module_path);
+                let script = self.module_resolver.load(&module_path)?;
+                let chunk = self.compile(
+                    &script,
+                    Some(module_path.clone()),
+                    CompilerSettings::default(),
+                )?;
+
+                self.modules.insert(
+                    module_path.clone(),
+                    CompiledModule {
+                        chunk: chunk.clone(),
+                        source_hash: source_hash(&script),
+                        modified,
+                    },
+                );
+                Ok((chunk, module_path))
+            }
+        }
+    }
+
+    /// Returns the paths of the compiled modules with sources that have changed since compilation
+    ///
+    /// Modules with a modification time that's unchanged since compilation are skipped, see
+    /// [ModuleResolver::modified]. Otherwise the sources are retrieved with the loader's
+    /// [ModuleResolver] and compared with the compiled sources, with modules that can no longer
+    /// be loaded also counting as having changed.
+    pub fn changed_modules(&self) -> Vec<PathBuf> {
+        self.modules
+            .iter()
+            .filter(|(path, module)| {
+                let modified = self.module_resolver.modified(path);
+                if modified.is_some() && modified == module.modified {
+                    return false;
                 }
-                Err(_) => Err(LoaderError::io_error(format!(
-                    "File not found: {}",
-                    module_path.to_string_lossy()
-                ))),
-            },
-        };
 
-        let extension = "koto";
-        let named_path = path.join(name);
+                match self.module_resolver.load(path) {
+                    Ok(script) => source_hash(&script) != module.source_hash,
+                    Err(_) => true,
+                }
+            })
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
 
-        // first, check for a neighbouring file with a matching name
-        let module_path = named_path.with_extension(extension);
-        if module_path.exists() {
-            load_module_from_path(module_path)
-        } else {
-            // alternatively, check for a neighbouring directory with a matching name,
-            // containing a main file
-            let module_path = named_path.join("main").with_extension(extension);
-            if module_path.exists() {
-                load_module_from_path(module_path)
-            } else {
-                Err(LoaderError::io_error(format!(
-                    "Unable to find module '{}'",
-                    name
-                )))
-            }
+    /// Removes a compiled module from the in-memory cache, so that it will be recompiled on import
+    pub fn invalidate_module(&mut self, path: &Path) {
+        self.modules.remove(path);
+    }
+
+    /// Adds the compiled modules from another loader that aren't already in this loader's cache
+    ///
+    /// This is used to keep track of modules that were compiled by a loader's clone, e.g. by a
+    /// module's own imports.
+    pub fn add_compiled_modules(&mut self, other: &Loader) {
+        for (path, module) in other.modules.iter() {
+            self.modules
+                .entry(path.clone())
+                .or_insert_with(|| module.clone());
         }
     }
 }
+
+// A module that has been compiled by the loader,
+// along with a hash of its source and the time at which it was last modified.
+#[derive(Clone)]
+struct CompiledModule {
+    chunk: Arc<Chunk>,
+    source_hash: u64,
+    modified: Option<SystemTime>,
+}
+
+// FNV-1a is used rather than std's DefaultHasher,
+// which isn't guaranteed to produce stable hashes across Rust versions.
+fn fnv1a_hash(parts: &[&[u8]]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in parts.iter().flat_map(|part| part.iter()) {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+fn source_hash(script: &str) -> u64 {
+    fnv1a_hash(&[script.as_bytes()])
+}
+
+fn cached_chunk_file_name(script: &str, script_path: &Option<PathBuf>) -> String {
+    let path = script_path
+        .as_ref()
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let hash = fnv1a_hash(&[script.as_bytes(), &[0], path.as_bytes()]);
+
+    format!("{:016x}.kotoc", hash)
+}
+
+fn load_cached_chunk(
+    cache_path: &Path,
+    script: &str,
+    script_path: &Option<PathBuf>,
+) -> Option<Arc<Chunk>> {
+    let data = fs::read(cache_path).ok()?;
+    let chunk = Chunk::from_bytes(&data).ok()?;
+
+    // Guard against hash collisions by checking that the chunk matches the script
+    if chunk.debug_info.source == script && &chunk.source_path == script_path {
+        Some(Arc::new(chunk))
+    } else {
+        None
+    }
+}
+
+fn write_cached_chunk(cache_path: &Path, chunk: &Chunk) -> std::io::Result<()> {
+    if let Some(cache_dir) = cache_path.parent() {
+        fs::create_dir_all(cache_dir)?;
+    }
+
+    // Write to a temporary file first so that other processes don't see a partially written chunk
+    let temp_path = cache_path.with_extension(format!("tmp{}", std::process::id()));
+    fs::write(&temp_path, chunk.to_bytes())?;
+    if let Err(error) = fs::rename(&temp_path, cache_path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(error);
+    }
+
+    Ok(())
+}
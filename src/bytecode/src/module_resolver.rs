@@ -0,0 +1,215 @@
+use {
+    crate::LoaderError,
+    std::{
+        collections::HashMap,
+        env, fs,
+        path::{Path, PathBuf},
+        time::SystemTime,
+    },
+};
+
+/// A trait for types that find and load the modules that get imported by scripts
+///
+/// The [Loader](crate::Loader) calls [ModuleResolver::resolve] to find the module's path, which
+/// is used to identify the module and to cache its compiled chunk. If the module hasn't been
+/// compiled yet, then its source is retrieved with [ModuleResolver::load].
+///
+/// See [FileSystemResolver], [InMemoryResolver], and [SearchPathResolver].
+pub trait ModuleResolver: Send + Sync {
+    /// Returns the path of the module with the given name
+    ///
+    /// `importer_path` is the path of the script that's importing the module, if available.
+    fn resolve(&self, name: &str, importer_path: Option<&Path>) -> Result<PathBuf, LoaderError>;
+
+    /// Returns the source of the module with the given path
+    fn load(&self, path: &Path) -> Result<String, LoaderError>;
+
+    /// Returns the time at which the module with the given path was last modified, if available
+    ///
+    /// This allows [Loader::changed_modules](crate::Loader::changed_modules) to skip loading
+    /// the sources of modules that haven't been modified since they were compiled. By default
+    /// no time is provided, so the module's source is always loaded and compared.
+    fn modified(&self, _path: &Path) -> Option<SystemTime> {
+        None
+    }
+}
+
+/// Finds modules on the file system, relative to the importing script
+///
+/// This is the default resolver, see [find_module].
+#[derive(Clone, Debug, Default)]
+pub struct FileSystemResolver;
+
+impl ModuleResolver for FileSystemResolver {
+    fn resolve(&self, name: &str, importer_path: Option<&Path>) -> Result<PathBuf, LoaderError> {
+        find_module(name, importer_path)
+    }
+
+    fn load(&self, path: &Path) -> Result<String, LoaderError> {
+        read_module(path)
+    }
+
+    fn modified(&self, path: &Path) -> Option<SystemTime> {
+        modified_time(path)
+    }
+}
+
+/// Provides modules from sources that are held in memory, e.g. scripts embedded in a binary
+///
+/// Modules are found by name, regardless of the importing script's path.
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryResolver {
+    modules: HashMap<String, String>,
+}
+
+impl InMemoryResolver {
+    /// Adds a module with the given name and source
+    pub fn add_module(&mut self, name: &str, source: impl Into<String>) {
+        self.modules.insert(name.to_string(), source.into());
+    }
+}
+
+impl<'a> From<HashMap<String, &'a str>> for InMemoryResolver {
+    fn from(modules: HashMap<String, &'a str>) -> Self {
+        Self {
+            modules: modules
+                .into_iter()
+                .map(|(name, source)| (name, source.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl ModuleResolver for InMemoryResolver {
+    fn resolve(&self, name: &str, _importer_path: Option<&Path>) -> Result<PathBuf, LoaderError> {
+        if self.modules.contains_key(name) {
+            Ok(PathBuf::from(name))
+        } else {
+            Err(module_not_found(name))
+        }
+    }
+
+    fn load(&self, path: &Path) -> Result<String, LoaderError> {
+        path.to_str()
+            .and_then(|name| self.modules.get(name))
+            .cloned()
+            .ok_or_else(|| {
+                LoaderError::io_error(format!("Module not found: {}", path.to_string_lossy()))
+            })
+    }
+}
+
+/// Finds modules relative to the importing script, and then in a list of search paths
+///
+/// The search paths are checked in order, with the first matching module being used.
+#[derive(Clone, Debug, Default)]
+pub struct SearchPathResolver {
+    search_paths: Vec<PathBuf>,
+}
+
+impl SearchPathResolver {
+    /// Makes a resolver that searches the provided paths
+    pub fn new(search_paths: Vec<PathBuf>) -> Self {
+        Self { search_paths }
+    }
+
+    /// Makes a resolver that searches the paths listed in the `KOTO_PATH` environment variable
+    ///
+    /// The paths are separated in the same way as the platform's `PATH` variable, i.e. with `:`,
+    /// or `;` on Windows.
+    pub fn from_env() -> Self {
+        match env::var_os("KOTO_PATH") {
+            Some(paths) => Self::new(env::split_paths(&paths).collect()),
+            None => Self::default(),
+        }
+    }
+
+    /// The paths that are searched for modules
+    pub fn search_paths(&self) -> &[PathBuf] {
+        &self.search_paths
+    }
+}
+
+impl ModuleResolver for SearchPathResolver {
+    fn resolve(&self, name: &str, importer_path: Option<&Path>) -> Result<PathBuf, LoaderError> {
+        find_module(name, importer_path).or_else(|error| {
+            self.search_paths
+                .iter()
+                .find_map(|search_path| find_module_in_dir(name, search_path))
+                .ok_or(error)
+        })
+    }
+
+    fn load(&self, path: &Path) -> Result<String, LoaderError> {
+        read_module(path)
+    }
+
+    fn modified(&self, path: &Path) -> Option<SystemTime> {
+        modified_time(path)
+    }
+}
+
+/// Finds the path of the module with the given name
+///
+/// Modules are searched for relative to `load_from_path`, or the current working directory when
+/// no path is provided. A module is either a neighbouring file with a matching name and a `.koto`
+/// extension, or a neighbouring directory with a matching name that contains a `main.koto` file.
+pub fn find_module(name: &str, load_from_path: Option<&Path>) -> Result<PathBuf, LoaderError> {
+    // Get either the directory of the provided path, or the current working directory
+    let path = match load_from_path {
+        Some(path) => match path.canonicalize() {
+            Ok(canonicalized) if canonicalized.is_file() => match canonicalized.parent() {
+                Some(parent_dir) => parent_dir.to_path_buf(),
+                None => {
+                    return Err(LoaderError::io_error(
+                        "Failed to get parent of provided path".to_string(),
+                    ))
+                }
+            },
+            Ok(canonicalized) => canonicalized,
+            Err(e) => return Err(LoaderError::io_error(e.to_string())),
+        },
+        None => match env::current_dir() {
+            Ok(path) => path,
+            Err(e) => return Err(LoaderError::io_error(e.to_string())),
+        },
+    };
+
+    find_module_in_dir(name, &path).ok_or_else(|| module_not_found(name))
+}
+
+// Looks for a module with the given name in a directory
+fn find_module_in_dir(name: &str, dir: &Path) -> Option<PathBuf> {
+    let extension = "koto";
+    let named_path = dir.join(name);
+
+    // first, check for a neighbouring file with a matching name
+    let module_path = named_path.with_extension(extension);
+    if module_path.exists() {
+        Some(module_path)
+    } else {
+        // alternatively, check for a neighbouring directory with a matching name,
+        // containing a main file
+        let module_path = named_path.join("main").with_extension(extension);
+        if module_path.exists() {
+            Some(module_path)
+        } else {
+            None
+        }
+    }
+}
+
+fn module_not_found(name: &str) -> LoaderError {
+    LoaderError::io_error(format!("Unable to find module '{}'", name))
+}
+
+fn read_module(path: &Path) -> Result<String, LoaderError> {
+    fs::read_to_string(path)
+        .map_err(|_| LoaderError::io_error(format!("File not found: {}", path.to_string_lossy())))
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
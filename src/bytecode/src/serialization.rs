@@ -0,0 +1,414 @@
+use {
+    crate::{Chunk, CompilerWarning, DebugInfo, FrameDebugInfo, WarningKind},
+    koto_parser::{Constant, ConstantPoolBuilder, Position, Span},
+    std::{convert::TryInto, error, fmt, path::PathBuf},
+};
+
+/// The identifier placed at the start of serialized chunks
+const CHUNK_MAGIC: &[u8; 4] = b"KOTO";
+
+/// The version of the binary chunk format
+///
+/// This needs to be incremented whenever the format or the bytecode's instructions change.
+pub const CHUNK_FORMAT_VERSION: u32 = 3;
+
+const CONSTANT_F64: u8 = 0;
+const CONSTANT_I64: u8 = 1;
+const CONSTANT_STR: u8 = 2;
+
+const WARNING_UNUSED_LOCAL: u8 = 0;
+const WARNING_UNUSED_ARGUMENT: u8 = 1;
+const WARNING_UNREAD_ASSIGNMENT: u8 = 2;
+const WARNING_UNREACHABLE_CODE: u8 = 3;
+const WARNING_CAPTURE_REASSIGNED: u8 = 4;
+
+/// Errors that can be returned while deserializing a [Chunk]
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChunkFormatError {
+    /// The data doesn't start with the expected header
+    InvalidHeader,
+    /// The data was produced by an incompatible version of the chunk format
+    UnsupportedVersion {
+        found: u32,
+        compiler_version: String,
+    },
+    /// The data ended unexpectedly
+    UnexpectedEnd,
+    /// The data contains an invalid value
+    InvalidData(String),
+}
+
+impl fmt::Display for ChunkFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ChunkFormatError::*;
+
+        match &self {
+            InvalidHeader => f.write_str("Invalid chunk header"),
+            UnsupportedVersion {
+                found,
+                compiler_version,
+            } => write!(
+                f,
+                "Unsupported chunk format (version {}, compiled by Koto {}), expected version {} \
+                 from Koto {}",
+                found,
+                compiler_version,
+                CHUNK_FORMAT_VERSION,
+                env!("CARGO_PKG_VERSION")
+            ),
+            UnexpectedEnd => f.write_str("Unexpected end of chunk data"),
+            InvalidData(message) => write!(f, "Invalid chunk data: {}", message),
+        }
+    }
+}
+
+impl error::Error for ChunkFormatError {}
+
+impl Chunk {
+    /// Serializes the chunk into Koto's versioned binary chunk format
+    ///
+    /// The chunk can be restored with [Chunk::from_bytes].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = ChunkWriter::default();
+
+        writer.bytes(CHUNK_MAGIC);
+        writer.u32(CHUNK_FORMAT_VERSION);
+        writer.str(env!("CARGO_PKG_VERSION"));
+
+        writer.u64(self.bytes.len() as u64);
+        writer.bytes(&self.bytes);
+
+        writer.u32(self.constants.len() as u32);
+        for constant in self.constants.iter() {
+            match constant {
+                Constant::F64(n) => {
+                    writer.u8(CONSTANT_F64);
+                    writer.u64(n.to_bits());
+                }
+                Constant::I64(n) => {
+                    writer.u8(CONSTANT_I64);
+                    writer.u64(n as u64);
+                }
+                Constant::Str(s) => {
+                    writer.u8(CONSTANT_STR);
+                    writer.str(s);
+                }
+            }
+        }
+
+        match &self.source_path {
+            Some(path) => {
+                writer.u8(1);
+                writer.str(&path.to_string_lossy());
+            }
+            None => writer.u8(0),
+        }
+
+        writer.str(&self.debug_info.source);
+        writer.u64(self.debug_info.source_map.len() as u64);
+        for (ip, span) in self.debug_info.source_map.iter() {
+            writer.u64(*ip as u64);
+            writer.span(span);
+        }
+
+        writer.u64(self.debug_info.frames.len() as u64);
+        for frame in self.debug_info.frames.iter() {
+            writer.u64(frame.start_ip as u64);
+            writer.u64(frame.end_ip as u64);
+            writer.span(&frame.span);
+            writer.u64(frame.locals.len() as u64);
+            for local in frame.locals.iter() {
+                match local {
+                    Some(constant) => {
+                        writer.u8(1);
+                        writer.u32(*constant);
+                    }
+                    None => writer.u8(0),
+                }
+            }
+        }
+
+        writer.u64(self.warnings.len() as u64);
+        for warning in self.warnings.iter() {
+            writer.u8(match warning.kind {
+                WarningKind::UnusedLocal => WARNING_UNUSED_LOCAL,
+                WarningKind::UnusedArgument => WARNING_UNUSED_ARGUMENT,
+                WarningKind::UnreadAssignment => WARNING_UNREAD_ASSIGNMENT,
+                WarningKind::UnreachableCode => WARNING_UNREACHABLE_CODE,
+                WarningKind::CaptureReassigned => WARNING_CAPTURE_REASSIGNED,
+            });
+            writer.span(&warning.span);
+        }
+
+        writer.0
+    }
+
+    /// Deserializes a chunk that was serialized with [Chunk::to_bytes]
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ChunkFormatError> {
+        let mut reader = ChunkReader { data, position: 0 };
+
+        if reader.bytes(CHUNK_MAGIC.len())? != CHUNK_MAGIC {
+            return Err(ChunkFormatError::InvalidHeader);
+        }
+
+        let version = reader.u32()?;
+        let compiler_version = reader.str()?;
+        if version != CHUNK_FORMAT_VERSION || compiler_version != env!("CARGO_PKG_VERSION") {
+            return Err(ChunkFormatError::UnsupportedVersion {
+                found: version,
+                compiler_version,
+            });
+        }
+
+        let bytes_len = reader.len()?;
+        let bytes = reader.bytes(bytes_len)?.to_vec();
+
+        let constant_count = reader.u32()?;
+        let mut constants = ConstantPoolBuilder::new();
+        for _ in 0..constant_count {
+            match reader.u8()? {
+                CONSTANT_F64 => constants.add_f64(f64::from_bits(reader.u64()?)),
+                CONSTANT_I64 => constants.add_i64(reader.u64()? as i64),
+                CONSTANT_STR => constants.add_string(&reader.str()?),
+                unexpected => {
+                    return Err(ChunkFormatError::InvalidData(format!(
+                        "unexpected constant type '{}'",
+                        unexpected
+                    )))
+                }
+            };
+        }
+        let constants = constants.build();
+        if constants.len() != constant_count as usize {
+            return Err(ChunkFormatError::InvalidData(
+                "duplicate entries in constant pool".to_string(),
+            ));
+        }
+
+        let source_path = match reader.u8()? {
+            0 => None,
+            1 => Some(PathBuf::from(reader.str()?)),
+            unexpected => {
+                return Err(ChunkFormatError::InvalidData(format!(
+                    "unexpected source path flag '{}'",
+                    unexpected
+                )))
+            }
+        };
+
+        let mut debug_info = DebugInfo {
+            source: reader.str()?,
+            ..Default::default()
+        };
+        let source_map_len = reader.len()?;
+        for _ in 0..source_map_len {
+            let ip = reader.len()?;
+            let span = reader.span()?;
+            debug_info.source_map.push((ip, span));
+        }
+
+        let frame_count = reader.len()?;
+        for _ in 0..frame_count {
+            let start_ip = reader.len()?;
+            let end_ip = reader.len()?;
+            let span = reader.span()?;
+            let local_count = reader.len()?;
+            let mut locals = Vec::new();
+            for _ in 0..local_count {
+                let local = match reader.u8()? {
+                    0 => None,
+                    1 => Some(reader.u32()?),
+                    unexpected => {
+                        return Err(ChunkFormatError::InvalidData(format!(
+                            "unexpected local flag '{}'",
+                            unexpected
+                        )))
+                    }
+                };
+                locals.push(local);
+            }
+            debug_info.push_frame(FrameDebugInfo {
+                start_ip,
+                end_ip,
+                span,
+                locals,
+            });
+        }
+
+        let warning_count = reader.len()?;
+        let mut warnings = Vec::new();
+        for _ in 0..warning_count {
+            let kind = match reader.u8()? {
+                WARNING_UNUSED_LOCAL => WarningKind::UnusedLocal,
+                WARNING_UNUSED_ARGUMENT => WarningKind::UnusedArgument,
+                WARNING_UNREAD_ASSIGNMENT => WarningKind::UnreadAssignment,
+                WARNING_UNREACHABLE_CODE => WarningKind::UnreachableCode,
+                WARNING_CAPTURE_REASSIGNED => WarningKind::CaptureReassigned,
+                unexpected => {
+                    return Err(ChunkFormatError::InvalidData(format!(
+                        "unexpected warning kind '{}'",
+                        unexpected
+                    )))
+                }
+            };
+            let span = reader.span()?;
+            warnings.push(CompilerWarning { kind, span });
+        }
+
+        if reader.position != data.len() {
+            return Err(ChunkFormatError::InvalidData(
+                "unexpected data at end of chunk".to_string(),
+            ));
+        }
+
+        let mut chunk = Self::new(bytes, constants, source_path, debug_info);
+        chunk.warnings = warnings;
+        Ok(chunk)
+    }
+}
+
+#[derive(Default)]
+struct ChunkWriter(Vec<u8>);
+
+impl ChunkWriter {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn u8(&mut self, n: u8) {
+        self.0.push(n);
+    }
+
+    fn u32(&mut self, n: u32) {
+        self.bytes(&n.to_le_bytes());
+    }
+
+    fn u64(&mut self, n: u64) {
+        self.bytes(&n.to_le_bytes());
+    }
+
+    fn str(&mut self, s: &str) {
+        self.u64(s.len() as u64);
+        self.bytes(s.as_bytes());
+    }
+
+    fn span(&mut self, span: &Span) {
+        self.u32(span.start.line);
+        self.u32(span.start.column);
+        self.u32(span.end.line);
+        self.u32(span.end.column);
+    }
+}
+
+struct ChunkReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ChunkReader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], ChunkFormatError> {
+        let end = self
+            .position
+            .checked_add(count)
+            .ok_or(ChunkFormatError::UnexpectedEnd)?;
+        let result = self
+            .data
+            .get(self.position..end)
+            .ok_or(ChunkFormatError::UnexpectedEnd)?;
+        self.position = end;
+        Ok(result)
+    }
+
+    fn u8(&mut self) -> Result<u8, ChunkFormatError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, ChunkFormatError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, ChunkFormatError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> Result<usize, ChunkFormatError> {
+        let len = self.u64()?;
+        len.try_into()
+            .map_err(|_| ChunkFormatError::InvalidData(format!("length out of range: {}", len)))
+    }
+
+    fn str(&mut self) -> Result<String, ChunkFormatError> {
+        let len = self.len()?;
+        match std::str::from_utf8(self.bytes(len)?) {
+            Ok(s) => Ok(s.to_string()),
+            Err(e) => Err(ChunkFormatError::InvalidData(e.to_string())),
+        }
+    }
+
+    fn span(&mut self) -> Result<Span, ChunkFormatError> {
+        let start = Position {
+            line: self.u32()?,
+            column: self.u32()?,
+        };
+        let end = Position {
+            line: self.u32()?,
+            column: self.u32()?,
+        };
+        Ok(Span { start, end })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::Loader, std::sync::Arc};
+
+    fn compile(script: &str, path: Option<PathBuf>) -> Arc<Chunk> {
+        Loader::default().compile_script(script, &path).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let script = r#"
+x = 1, 2.5, "three"
+f = |n| n * 42
+f x[0] + 99999999999
+g = |unused| 42
+"#;
+        let chunk = compile(script, Some(PathBuf::from("foo/bar.koto")));
+        assert!(!chunk.warnings.is_empty());
+        let deserialized = Chunk::from_bytes(&chunk.to_bytes()).unwrap();
+        assert_eq!(*chunk, deserialized);
+        assert_eq!(
+            chunk.string_constants_arc,
+            deserialized.string_constants_arc
+        );
+    }
+
+    #[test]
+    fn invalid_header() {
+        let mut data = compile("1 + 1", None).to_bytes();
+        data[0] = b'X';
+        assert_eq!(
+            Chunk::from_bytes(&data),
+            Err(ChunkFormatError::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let mut data = compile("1 + 1", None).to_bytes();
+        data[4..8].copy_from_slice(&(CHUNK_FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            Chunk::from_bytes(&data),
+            Err(ChunkFormatError::UnsupportedVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn truncated_data() {
+        let data = compile("x = \"hello\"", None).to_bytes();
+        for len in 0..data.len() {
+            assert!(Chunk::from_bytes(&data[..len]).is_err());
+        }
+    }
+}
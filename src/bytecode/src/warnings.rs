@@ -0,0 +1,663 @@
+use {
+    koto_parser::{
+        AssignOp, AssignTarget, Ast, AstIndex, ConstantIndex, ConstantPool, LookupNode, MapKey,
+        Node, Scope, Span,
+    },
+    std::fmt,
+};
+
+/// The different kinds of warning that can be reported by the [Compiler](crate::Compiler)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WarningKind {
+    /// A local value is assigned but never used
+    UnusedLocal,
+    /// A function argument is never used
+    UnusedArgument,
+    /// A value is assigned to a local, and then overwritten before being read
+    UnreadAssignment,
+    /// Code that follows a `return`, `throw`, `break`, or `continue` expression
+    UnreachableCode,
+    /// A captured value is reassigned, which only changes the function's copy of the value
+    CaptureReassigned,
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use WarningKind::*;
+
+        match self {
+            UnusedLocal => f.write_str("Unused local value"),
+            UnusedArgument => f.write_str("Unused function argument"),
+            UnreadAssignment => f.write_str("The assigned value is overwritten before being read"),
+            UnreachableCode => f.write_str("Unreachable code"),
+            CaptureReassigned => f.write_str(
+                "Assigning to a captured value only changes the function's copy of the value",
+            ),
+        }
+    }
+}
+
+/// A warning reported by the [Compiler](crate::Compiler)
+///
+/// Warnings don't prevent a script from being compiled.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompilerWarning {
+    pub kind: WarningKind,
+    pub span: Span,
+}
+
+impl fmt::Display for CompilerWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+/// Checks the AST for code that's valid but probably not what was intended
+pub(crate) fn check_ast(
+    ast: &Ast,
+    constants: &ConstantPool,
+    repl_mode: bool,
+) -> Vec<CompilerWarning> {
+    let mut checker = WarningChecker {
+        ast,
+        constants,
+        repl_mode,
+        frames: vec![],
+        try_depth: 0,
+        warnings: vec![],
+    };
+
+    if let Some(entry_point) = ast.entry_point() {
+        if let Node::MainBlock { body, .. } = &entry_point.node {
+            checker.frames.push(Frame::default());
+            checker.visit_block(body);
+            checker.finish_frame();
+        }
+    }
+
+    let mut warnings = checker.warnings;
+    warnings.sort_by_key(|warning| (warning.span.start.line, warning.span.start.column));
+    warnings
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LocalKind {
+    Local,
+    Argument,
+    // Loop variables, catch arguments, imports, and reassigned captures aren't reported when unused
+    Unchecked,
+}
+
+struct Local {
+    id: ConstantIndex,
+    span: Span,
+    kind: LocalKind,
+    read: bool,
+}
+
+// The locals of a function, or of the script's main block
+#[derive(Default)]
+struct Frame {
+    locals: Vec<Local>,
+    // The IDs that are captured from enclosing frames
+    captures: Vec<ConstantIndex>,
+    // Assignments that were overwritten without being read,
+    // they're only reported if the local is used elsewhere.
+    unread_assignments: Vec<(ConstantIndex, Span)>,
+}
+
+struct WarningChecker<'a> {
+    ast: &'a Ast,
+    constants: &'a ConstantPool,
+    repl_mode: bool,
+    frames: Vec<Frame>,
+    try_depth: usize,
+    warnings: Vec<CompilerWarning>,
+}
+
+impl<'a> WarningChecker<'a> {
+    fn visit(&mut self, index: AstIndex) {
+        match &self.ast.node(index).node {
+            Node::Id(id) => {
+                self.read(*id);
+            }
+            Node::Lookup((lookup, next)) => {
+                match lookup {
+                    LookupNode::Root(node) | LookupNode::Index(node) => self.visit(*node),
+                    LookupNode::Call(args) => self.visit_nodes(args),
+                    LookupNode::Id(_) => {}
+                }
+                if let Some(next) = next {
+                    self.visit(*next);
+                }
+            }
+            Node::Num2(elements)
+            | Node::Num4(elements)
+            | Node::List(elements)
+            | Node::Tuple(elements)
+            | Node::TempTuple(elements) => self.visit_nodes(elements),
+            Node::Block(body) | Node::MainBlock { body, .. } => self.visit_block(body),
+            Node::Range { start, end, .. } => {
+                self.visit(*start);
+                self.visit(*end);
+            }
+            Node::RangeFrom { start } => self.visit(*start),
+            Node::RangeTo { end, .. } => self.visit(*end),
+            Node::Map(entries) => {
+                for (key, value) in entries.iter() {
+                    match (key, value) {
+                        (_, Some(value)) => self.visit(*value),
+                        // A map entry without a value refers to a value with the same name
+                        (MapKey::Id(id), None) => {
+                            self.read(*id);
+                        }
+                        (MapKey::Meta(_), None) => {}
+                    }
+                }
+            }
+            Node::Function(function) => {
+                // Captured values are copied into the function when it's created
+                let captures = function
+                    .accessed_non_locals
+                    .iter()
+                    .filter(|id| self.read(**id))
+                    .cloned()
+                    .collect();
+
+                self.frames.push(Frame {
+                    captures,
+                    ..Default::default()
+                });
+
+                for (i, arg) in function.args.iter().enumerate() {
+                    let kind = if i == 0 && function.is_instance_function {
+                        LocalKind::Unchecked
+                    } else {
+                        LocalKind::Argument
+                    };
+                    self.define_pattern(*arg, kind);
+                }
+
+                let try_depth = self.try_depth;
+                self.try_depth = 0;
+                self.visit(function.body);
+                self.try_depth = try_depth;
+
+                self.finish_frame();
+            }
+            Node::Call { function, args } => {
+                self.visit(*function);
+                self.visit_nodes(args);
+            }
+            Node::Import { items, .. } => {
+                // Import items don't have their own spans, so they aren't reported when unused
+                let span = self.span(index);
+                for item in items.iter() {
+                    if let Some(id) = item.last() {
+                        self.assign(*id, Scope::Local, span, LocalKind::Unchecked);
+                    }
+                }
+            }
+            Node::Assign {
+                target,
+                op,
+                expression,
+            } => {
+                if *op != AssignOp::Equal {
+                    if let Node::Id(id) = &self.ast.node(target.target_index).node {
+                        self.read(*id);
+                    }
+                }
+
+                // Functions are able to capture themselves, so the target is assigned first
+                if matches!(self.ast.node(*expression).node, Node::Function(_)) {
+                    self.visit_assign_target(target);
+                    self.visit(*expression);
+                } else {
+                    self.visit(*expression);
+                    self.visit_assign_target(target);
+                }
+            }
+            Node::MultiAssign {
+                targets,
+                expression,
+            } => {
+                self.visit(*expression);
+                for target in targets.iter() {
+                    self.visit_assign_target(target);
+                }
+            }
+            Node::BinaryOp { lhs, rhs, .. } => {
+                self.visit(*lhs);
+                self.visit(*rhs);
+            }
+            Node::If(ast_if) => {
+                self.visit(ast_if.condition);
+                self.visit(ast_if.then_node);
+                for (condition, block) in ast_if.else_if_blocks.iter() {
+                    self.visit(*condition);
+                    self.visit(*block);
+                }
+                if let Some(else_node) = ast_if.else_node {
+                    self.visit(else_node);
+                }
+            }
+            Node::Match { expression, arms } => {
+                self.visit(*expression);
+                for arm in arms.iter() {
+                    for pattern in arm.patterns.iter() {
+                        self.define_pattern(*pattern, LocalKind::Local);
+                    }
+                    if let Some(condition) = arm.condition {
+                        self.visit(condition);
+                    }
+                    self.visit(arm.expression);
+                }
+            }
+            Node::Switch(arms) => {
+                for arm in arms.iter() {
+                    if let Some(condition) = arm.condition {
+                        self.visit(condition);
+                    }
+                    self.visit(arm.expression);
+                }
+            }
+            Node::For(ast_for) => {
+                self.visit(ast_for.range);
+                let span = self.span(index);
+                for arg in ast_for.args.iter().flatten() {
+                    self.assign(*arg, Scope::Local, span, LocalKind::Unchecked);
+                }
+                self.visit(ast_for.body);
+            }
+            Node::Loop { body } => self.visit(*body),
+            Node::While { condition, body } | Node::Until { condition, body } => {
+                self.visit(*condition);
+                self.visit(*body);
+            }
+            Node::Try(ast_try) => {
+                self.try_depth += 1;
+                self.visit(ast_try.try_block);
+                self.try_depth -= 1;
+
+                if let Some(catch_arg) = ast_try.catch_arg {
+                    let span = self.span(ast_try.catch_block);
+                    self.assign(catch_arg, Scope::Local, span, LocalKind::Unchecked);
+                }
+                self.visit(ast_try.catch_block);
+                if let Some(finally_block) = ast_try.finally_block {
+                    self.visit(finally_block);
+                }
+            }
+            Node::ReturnExpression(expression)
+            | Node::Negate(expression)
+            | Node::Throw(expression)
+            | Node::Yield(expression)
+            | Node::Debug { expression, .. } => self.visit(*expression),
+            Node::Empty
+            | Node::BoolTrue
+            | Node::BoolFalse
+            | Node::Number0
+            | Node::Number1
+            | Node::Int(_)
+            | Node::Float(_)
+            | Node::Str(_)
+            | Node::RangeFull
+            | Node::Wildcard
+            | Node::Ellipsis(_)
+            | Node::Break
+            | Node::Continue
+            | Node::Return => {}
+        }
+    }
+
+    fn visit_nodes(&mut self, nodes: &[AstIndex]) {
+        for node in nodes.iter() {
+            self.visit(*node);
+        }
+    }
+
+    fn visit_block(&mut self, body: &[AstIndex]) {
+        if let Some(position) = body.iter().position(|node| self.ends_execution(*node)) {
+            if let Some(unreachable) = body.get(position + 1) {
+                let span = self.span(*unreachable);
+                self.warn(WarningKind::UnreachableCode, span);
+            }
+        }
+
+        if self.try_depth == 0 {
+            for (i, node) in body.iter().enumerate() {
+                if let Some((id, span)) = self.overwritten_assignment(*node, &body[i + 1..]) {
+                    self.frame_mut().unread_assignments.push((id, span));
+                }
+            }
+        }
+
+        self.visit_nodes(body);
+    }
+
+    fn visit_assign_target(&mut self, target: &AssignTarget) {
+        let target_node = self.ast.node(target.target_index);
+        match &target_node.node {
+            Node::Id(id) => {
+                let span = *self.ast.span(target_node.span);
+                self.assign(*id, target.scope, span, LocalKind::Local);
+            }
+            Node::Wildcard => {}
+            _ => self.visit(target.target_index),
+        }
+    }
+
+    // Defines the IDs in a function's args or in a match arm's patterns
+    fn define_pattern(&mut self, index: AstIndex, kind: LocalKind) {
+        let node = self.ast.node(index);
+        match &node.node {
+            Node::Id(id) | Node::Ellipsis(Some(id)) => {
+                let span = *self.ast.span(node.span);
+                self.assign(*id, Scope::Local, span, kind);
+            }
+            Node::List(elements) | Node::Tuple(elements) => {
+                for element in elements.iter() {
+                    self.define_pattern(*element, kind);
+                }
+            }
+            _ => self.visit(index),
+        }
+    }
+
+    fn assign(&mut self, id: ConstantIndex, scope: Scope, span: Span, kind: LocalKind) {
+        // Top-level values are exported in the REPL
+        if scope == Scope::Export || (self.repl_mode && self.frames.len() == 1) {
+            return;
+        }
+
+        let frame = self.frame_mut();
+        if frame.locals.iter().any(|local| local.id == id) {
+            return;
+        }
+
+        let kind = if frame.captures.contains(&id) {
+            self.warn(WarningKind::CaptureReassigned, span);
+            LocalKind::Unchecked
+        } else {
+            kind
+        };
+
+        self.frame_mut().locals.push(Local {
+            id,
+            span,
+            kind,
+            read: false,
+        });
+    }
+
+    // Marks the local with the given ID as having been read
+    //
+    // Returns false if the ID isn't a local in the current frame or in an enclosing frame,
+    // e.g. for exported values or values from the prelude.
+    fn read(&mut self, id: ConstantIndex) -> bool {
+        for frame in self.frames.iter_mut().rev() {
+            if let Some(local) = frame.locals.iter_mut().find(|local| local.id == id) {
+                local.read = true;
+                return true;
+            }
+        }
+        false
+    }
+
+    fn finish_frame(&mut self) {
+        let frame = self.frames.pop().expect("Missing frame");
+
+        for local in frame.locals.iter().filter(|local| !local.read) {
+            // Names starting with an underscore are intentionally unused
+            if self.constants.get_str(local.id).starts_with('_') {
+                continue;
+            }
+
+            let kind = match local.kind {
+                LocalKind::Local => WarningKind::UnusedLocal,
+                LocalKind::Argument => WarningKind::UnusedArgument,
+                LocalKind::Unchecked => continue,
+            };
+            self.warn(kind, local.span);
+        }
+
+        for (id, span) in frame.unread_assignments.iter() {
+            let local_is_read = frame
+                .locals
+                .iter()
+                .any(|local| local.id == *id && local.read);
+            if local_is_read {
+                self.warn(WarningKind::UnreadAssignment, *span);
+            }
+        }
+    }
+
+    // If the node is an assignment to a local that's overwritten by one of the following nodes
+    // before being read, then the local's ID and the assignment target's span are returned.
+    fn overwritten_assignment(
+        &self,
+        node: AstIndex,
+        following: &[AstIndex],
+    ) -> Option<(ConstantIndex, Span)> {
+        let (target, expression) = match &self.ast.node(node).node {
+            Node::Assign {
+                target,
+                op: AssignOp::Equal,
+                expression,
+            } if target.scope == Scope::Local && (!self.repl_mode || self.frames.len() > 1) => {
+                (target, *expression)
+            }
+            _ => return None,
+        };
+
+        let target_node = self.ast.node(target.target_index);
+        let id = match &target_node.node {
+            Node::Id(id) => *id,
+            _ => return None,
+        };
+
+        if self.reads_id(expression, id) {
+            return None;
+        }
+
+        for next in following.iter() {
+            match &self.ast.node(*next).node {
+                Node::Assign {
+                    target,
+                    op: AssignOp::Equal,
+                    expression,
+                } if target.scope == Scope::Local
+                    && self.ast.node(target.target_index).node == Node::Id(id) =>
+                {
+                    if self.reads_id(*expression, id) || self.may_exit(*expression) {
+                        return None;
+                    }
+                    return Some((id, *self.ast.span(target_node.span)));
+                }
+                _ => {
+                    if self.reads_id(*next, id) || self.may_exit(*next) {
+                        return None;
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    // True if the node always ends the execution of the block that it's in
+    fn ends_execution(&self, node: AstIndex) -> bool {
+        matches!(
+            self.ast.node(node).node,
+            Node::Return
+                | Node::ReturnExpression(_)
+                | Node::Throw(_)
+                | Node::Break
+                | Node::Continue
+        )
+    }
+
+    // True if the node might cause execution to leave the enclosing block,
+    // nested functions aren't included.
+    fn may_exit(&self, node: AstIndex) -> bool {
+        match &self.ast.node(node).node {
+            Node::Function(_) => false,
+            _ if self.ends_execution(node) => true,
+            other => node_children(other)
+                .iter()
+                .any(|child| self.may_exit(*child)),
+        }
+    }
+
+    // True if the node reads the value with the given ID, including via captures
+    fn reads_id(&self, node: AstIndex, id: ConstantIndex) -> bool {
+        match &self.ast.node(node).node {
+            Node::Id(node_id) => *node_id == id,
+            Node::Map(entries) if entries.contains(&(MapKey::Id(id), None)) => true,
+            Node::Function(function) => function.accessed_non_locals.contains(&id),
+            Node::Assign {
+                target,
+                op,
+                expression,
+            } => {
+                let target_is_read = match &self.ast.node(target.target_index).node {
+                    Node::Id(target_id) => *target_id == id && *op != AssignOp::Equal,
+                    _ => self.reads_id(target.target_index, id),
+                };
+                target_is_read || self.reads_id(*expression, id)
+            }
+            Node::MultiAssign {
+                targets,
+                expression,
+            } => {
+                targets.iter().any(|target| {
+                    !matches!(self.ast.node(target.target_index).node, Node::Id(_))
+                        && self.reads_id(target.target_index, id)
+                }) || self.reads_id(*expression, id)
+            }
+            other => node_children(other)
+                .iter()
+                .any(|child| self.reads_id(*child, id)),
+        }
+    }
+
+    fn warn(&mut self, kind: WarningKind, span: Span) {
+        self.warnings.push(CompilerWarning { kind, span });
+    }
+
+    fn span(&self, node: AstIndex) -> Span {
+        *self.ast.span(self.ast.node(node).span)
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("Missing frame")
+    }
+}
+
+// Returns the indices of a node's child nodes
+fn node_children(node: &Node) -> Vec<AstIndex> {
+    match node {
+        Node::Lookup((lookup, next)) => {
+            let mut result = match lookup {
+                LookupNode::Root(node) | LookupNode::Index(node) => vec![*node],
+                LookupNode::Call(args) => args.clone(),
+                LookupNode::Id(_) => vec![],
+            };
+            result.extend(next);
+            result
+        }
+        Node::Num2(elements)
+        | Node::Num4(elements)
+        | Node::List(elements)
+        | Node::Tuple(elements)
+        | Node::TempTuple(elements)
+        | Node::Block(elements)
+        | Node::MainBlock { body: elements, .. } => elements.clone(),
+        Node::Range { start, end, .. } => vec![*start, *end],
+        Node::RangeFrom { start: node }
+        | Node::RangeTo { end: node, .. }
+        | Node::Loop { body: node }
+        | Node::ReturnExpression(node)
+        | Node::Negate(node)
+        | Node::Throw(node)
+        | Node::Yield(node)
+        | Node::Debug {
+            expression: node, ..
+        } => vec![*node],
+        Node::Map(entries) => entries.iter().filter_map(|(_, value)| *value).collect(),
+        Node::Function(function) => {
+            let mut result = function.args.clone();
+            result.push(function.body);
+            result
+        }
+        Node::Call { function, args } => {
+            let mut result = vec![*function];
+            result.extend(args);
+            result
+        }
+        Node::Assign {
+            target, expression, ..
+        } => vec![target.target_index, *expression],
+        Node::MultiAssign {
+            targets,
+            expression,
+        } => {
+            let mut result: Vec<_> = targets.iter().map(|target| target.target_index).collect();
+            result.push(*expression);
+            result
+        }
+        Node::BinaryOp { lhs, rhs, .. } => vec![*lhs, *rhs],
+        Node::If(ast_if) => {
+            let mut result = vec![ast_if.condition, ast_if.then_node];
+            for (condition, block) in ast_if.else_if_blocks.iter() {
+                result.push(*condition);
+                result.push(*block);
+            }
+            result.extend(ast_if.else_node);
+            result
+        }
+        Node::Match { expression, arms } => {
+            let mut result = vec![*expression];
+            for arm in arms.iter() {
+                result.extend(&arm.patterns);
+                result.extend(arm.condition);
+                result.push(arm.expression);
+            }
+            result
+        }
+        Node::Switch(arms) => {
+            let mut result = vec![];
+            for arm in arms.iter() {
+                result.extend(arm.condition);
+                result.push(arm.expression);
+            }
+            result
+        }
+        Node::For(ast_for) => vec![ast_for.range, ast_for.body],
+        Node::While { condition, body } | Node::Until { condition, body } => {
+            vec![*condition, *body]
+        }
+        Node::Try(ast_try) => {
+            let mut result = vec![ast_try.try_block, ast_try.catch_block];
+            result.extend(ast_try.finally_block);
+            result
+        }
+        Node::Empty
+        | Node::Id(_)
+        | Node::BoolTrue
+        | Node::BoolFalse
+        | Node::Number0
+        | Node::Number1
+        | Node::Int(_)
+        | Node::Float(_)
+        | Node::Str(_)
+        | Node::RangeFull
+        | Node::Import { .. }
+        | Node::Wildcard
+        | Node::Ellipsis(_)
+        | Node::Break
+        | Node::Continue
+        | Node::Return => vec![],
+    }
+}
@@ -0,0 +1,70 @@
+mod loader {
+    use {
+        koto_bytecode::{Chunk, Loader},
+        std::{fs, path::PathBuf},
+    };
+
+    fn cached_chunk_files(cache_dir: &tempfile::TempDir) -> Vec<PathBuf> {
+        fs::read_dir(cache_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect()
+    }
+
+    #[test]
+    fn compiled_script_is_cached() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let script = "x = 1 + 2";
+
+        let mut loader = Loader::with_cache_dir(cache_dir.path().to_path_buf());
+        let chunk = loader.compile_script(script, &None).unwrap();
+
+        let cached = cached_chunk_files(&cache_dir);
+        assert_eq!(cached.len(), 1);
+        let cached_chunk = Chunk::from_bytes(&fs::read(&cached[0]).unwrap()).unwrap();
+        assert_eq!(*chunk, cached_chunk);
+
+        // A second loader should make use of the cached chunk
+        let mut loader = Loader::with_cache_dir(cache_dir.path().to_path_buf());
+        assert_eq!(chunk, loader.compile_script(script, &None).unwrap());
+        assert_eq!(cached_chunk_files(&cache_dir).len(), 1);
+    }
+
+    #[test]
+    fn changed_script_is_recompiled() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let mut loader = Loader::with_cache_dir(cache_dir.path().to_path_buf());
+
+        let a = loader.compile_script("x = 1", &None).unwrap();
+        let b = loader.compile_script("x = 2", &None).unwrap();
+
+        assert_ne!(a, b);
+        assert_eq!(cached_chunk_files(&cache_dir).len(), 2);
+    }
+
+    #[test]
+    fn invalid_cached_chunk_is_replaced() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let script = "x = 42";
+
+        let mut loader = Loader::with_cache_dir(cache_dir.path().to_path_buf());
+        let chunk = loader.compile_script(script, &None).unwrap();
+
+        let cached = cached_chunk_files(&cache_dir);
+        fs::write(&cached[0], b"not a chunk").unwrap();
+
+        let mut loader = Loader::with_cache_dir(cache_dir.path().to_path_buf());
+        assert_eq!(chunk, loader.compile_script(script, &None).unwrap());
+        assert!(Chunk::from_bytes(&fs::read(&cached[0]).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn repl_chunks_arent_cached() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let mut loader = Loader::with_cache_dir(cache_dir.path().to_path_buf());
+
+        loader.compile_repl("x = 1").unwrap();
+
+        assert!(cached_chunk_files(&cache_dir).is_empty());
+    }
+}
@@ -6,8 +6,8 @@ mod bytecode {
 
     fn check_compilation_fails(source: &str) {
         match Parser::parse(&source) {
-            Ok((ast, _constants)) => {
-                if Compiler::compile(&ast, CompilerSettings::default()).is_ok() {
+            Ok((ast, constants)) => {
+                if Compiler::compile(&ast, &constants, CompilerSettings::default()).is_ok() {
                     panic!("\nUnexpected success while compiling: {}", source,);
                 }
             }
@@ -0,0 +1,197 @@
+mod compiler_warnings {
+    use {
+        koto_bytecode::{Compiler, CompilerSettings, WarningKind},
+        koto_parser::Parser,
+    };
+
+    fn check_warnings_with_settings(
+        source: &str,
+        settings: CompilerSettings,
+        expected: &[(WarningKind, u32)],
+    ) {
+        let (ast, constants) = Parser::parse(source).expect("Failed to parse script");
+        let (_, _, warnings) =
+            Compiler::compile(&ast, &constants, settings).expect("Failed to compile script");
+
+        let warnings = warnings
+            .iter()
+            .map(|warning| (warning.kind, warning.span.start.line))
+            .collect::<Vec<_>>();
+        assert_eq!(warnings, expected, "\n{}", source);
+    }
+
+    fn check_warnings(source: &str, expected: &[(WarningKind, u32)]) {
+        check_warnings_with_settings(source, CompilerSettings::default(), expected);
+    }
+
+    use WarningKind::*;
+
+    #[test]
+    fn no_warnings() {
+        let source = "
+x = 1
+f = |a, b| a + b + x
+export y = f 2, 3
+for i in 0..y
+  debug i
+";
+        check_warnings(source, &[]);
+    }
+
+    #[test]
+    fn unused_local() {
+        let source = "
+x = 1
+y = 2
+debug y
+";
+        check_warnings(source, &[(UnusedLocal, 2)]);
+    }
+
+    #[test]
+    fn unused_local_in_function() {
+        let source = "
+f = ||
+  a = 1
+  b = 2
+  b
+f()
+";
+        check_warnings(source, &[(UnusedLocal, 3)]);
+    }
+
+    #[test]
+    fn recursive_function_capturing_itself() {
+        let source = "
+f = |n| if n > 0 then f n - 1
+";
+        check_warnings(source, &[]);
+    }
+
+    #[test]
+    fn top_level_values_in_repl_mode_are_exports() {
+        let source = "x = 1";
+        check_warnings_with_settings(source, CompilerSettings { repl_mode: true }, &[]);
+    }
+
+    #[test]
+    fn unused_argument() {
+        let source = "
+f = |a, (b, c), _| a + c
+f 1, (2, 3), 4
+";
+        check_warnings(source, &[(UnusedArgument, 2)]);
+    }
+
+    #[test]
+    fn underscore_prefixed_names_arent_reported() {
+        let source = "
+_x = 1
+f = |_a, b|
+  _c = 2
+  b
+f 1, 2
+";
+        check_warnings(source, &[]);
+    }
+
+    #[test]
+    fn self_arg_isnt_reported() {
+        let source = "
+export m =
+  foo: |self| 42
+";
+        check_warnings(source, &[]);
+    }
+
+    #[test]
+    fn unread_assignment() {
+        let source = "
+x = 1
+x = 2
+debug x
+";
+        check_warnings(source, &[(UnreadAssignment, 2)]);
+    }
+
+    #[test]
+    fn assignment_read_before_being_overwritten() {
+        let source = "
+x = 1
+debug x
+x = x + 1
+f = || x
+x = 3
+debug f(), x
+";
+        check_warnings(source, &[]);
+    }
+
+    #[test]
+    fn assignment_before_possible_exit_isnt_reported() {
+        let source = "
+f = |n|
+  x = 1
+  for i in 0..n
+    if i == 2
+      return x
+  x = 2
+  x
+f 3
+";
+        check_warnings(source, &[]);
+    }
+
+    #[test]
+    fn unreachable_code() {
+        let source = "
+f = |n|
+  for i in 0..n
+    if i == 1
+      break
+      debug i
+  return n
+  n + 1
+f 2
+";
+        check_warnings(source, &[(UnreachableCode, 6), (UnreachableCode, 8)]);
+    }
+
+    #[test]
+    fn unreachable_code_after_throw() {
+        let source = "
+f = ||
+  throw \"error\"
+  42
+f()
+";
+        check_warnings(source, &[(UnreachableCode, 4)]);
+    }
+
+    #[test]
+    fn reassigned_capture() {
+        let source = "
+count = 0
+increment = || count += 1
+increment()
+reset = ||
+  x = count
+  count = 0
+  x
+reset()
+";
+        check_warnings(source, &[(CaptureReassigned, 3), (CaptureReassigned, 7)]);
+    }
+
+    #[test]
+    fn local_with_captured_name_that_isnt_a_capture() {
+        let source = "
+x = 1
+f = ||
+  x = 2
+  x
+debug f(), x
+";
+        check_warnings(source, &[]);
+    }
+}
@@ -0,0 +1,48 @@
+mod loader {
+    use koto_bytecode::{Loader, LoaderErrorType};
+
+    #[test]
+    fn all_parser_errors_are_reported() {
+        let script = "\
+x = (1 +
+y = 2
+z = [3,
+";
+        let error = Loader::default().compile_script(script, &None).unwrap_err();
+
+        match error.error_type() {
+            LoaderErrorType::ParserErrors(errors) => {
+                assert_eq!(errors.len(), 2);
+                assert_eq!(errors[0].span.start.line, 1);
+                assert_eq!(errors[1].span.start.line, 3);
+            }
+            other => panic!("Unexpected error type: {:?}", other),
+        }
+
+        // Each error is displayed with its own excerpt
+        let message = error.to_string();
+        assert!(message.contains("1 | x = (1 +"), "{}", message);
+        assert!(message.contains("3 | z = [3,"), "{}", message);
+    }
+
+    #[test]
+    fn lexer_errors_are_reported() {
+        for script in ["'", "x = 'a'", "x = 1 + * 2\ny = 'a'"] {
+            let error = Loader::default().compile_script(script, &None).unwrap_err();
+
+            match error.error_type() {
+                LoaderErrorType::ParserErrors(errors) => assert_eq!(
+                    errors.last().unwrap().to_string(),
+                    "Found an unexpected token while lexing input"
+                ),
+                other => panic!("Unexpected error type: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn indentation_errors_are_detected_with_recovery() {
+        let error = Loader::default().compile_repl("f = ||").unwrap_err();
+        assert!(error.is_indentation_error());
+    }
+}
@@ -0,0 +1,177 @@
+mod module_resolver {
+    use {
+        koto_bytecode::{
+            FileSystemResolver, InMemoryResolver, Loader, LoaderError, ModuleResolver,
+            SearchPathResolver,
+        },
+        std::{
+            collections::HashMap,
+            fs,
+            path::{Path, PathBuf},
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Arc, Mutex,
+            },
+            time::{Duration, SystemTime},
+        },
+    };
+
+    fn write_module(dir: &Path, name: &str, source: &str) {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, source).unwrap();
+    }
+
+    #[test]
+    fn file_system_resolver() {
+        let dir = tempfile::tempdir().unwrap();
+        write_module(dir.path(), "foo.koto", "x = 1");
+        write_module(dir.path(), "bar/main.koto", "x = 2");
+        let script_path = dir.path().join("script.koto");
+        write_module(dir.path(), "script.koto", "");
+
+        let resolver = FileSystemResolver;
+        let foo = resolver.resolve("foo", Some(&script_path)).unwrap();
+        assert!(foo.ends_with("foo.koto"));
+        assert_eq!(resolver.load(&foo).unwrap(), "x = 1");
+        let bar = resolver.resolve("bar", Some(&script_path)).unwrap();
+        assert!(bar.ends_with("bar/main.koto"));
+
+        assert_eq!(
+            resolver
+                .resolve("baz", Some(&script_path))
+                .unwrap_err()
+                .to_string(),
+            "Unable to find module 'baz'"
+        );
+    }
+
+    #[test]
+    fn in_memory_resolver() {
+        let mut modules = HashMap::new();
+        modules.insert("foo".to_string(), "export x = 42");
+        let mut resolver = InMemoryResolver::from(modules);
+        resolver.add_module("bar", "export y = 99");
+
+        let foo = resolver.resolve("foo", None).unwrap();
+        assert_eq!(resolver.load(&foo).unwrap(), "export x = 42");
+        let bar = resolver.resolve("bar", Some(Path::new("foo"))).unwrap();
+        assert_eq!(resolver.load(&bar).unwrap(), "export y = 99");
+
+        assert_eq!(
+            resolver.resolve("baz", None).unwrap_err().to_string(),
+            "Unable to find module 'baz'"
+        );
+    }
+
+    #[test]
+    fn search_path_resolver() {
+        let script_dir = tempfile::tempdir().unwrap();
+        let search_dir_a = tempfile::tempdir().unwrap();
+        let search_dir_b = tempfile::tempdir().unwrap();
+
+        write_module(script_dir.path(), "script.koto", "");
+        write_module(script_dir.path(), "local.koto", "x = 0");
+        write_module(search_dir_a.path(), "local.koto", "x = 1");
+        write_module(search_dir_a.path(), "a.koto", "x = 1");
+        write_module(search_dir_a.path(), "shared.koto", "x = 1");
+        write_module(search_dir_b.path(), "shared.koto", "x = 2");
+        write_module(search_dir_b.path(), "b/main.koto", "x = 2");
+
+        let resolver = SearchPathResolver::new(vec![
+            search_dir_a.path().to_path_buf(),
+            search_dir_b.path().to_path_buf(),
+        ]);
+        let script_path = script_dir.path().join("script.koto");
+        let load = |name: &str| {
+            let path = resolver.resolve(name, Some(&script_path)).unwrap();
+            resolver.load(&path).unwrap()
+        };
+
+        // Modules next to the importing script take priority over the search paths
+        assert_eq!(load("local"), "x = 0");
+        assert_eq!(load("a"), "x = 1");
+        assert_eq!(load("b"), "x = 2");
+        // Search paths are checked in order
+        assert_eq!(load("shared"), "x = 1");
+
+        assert_eq!(
+            resolver
+                .resolve("missing", Some(&script_path))
+                .unwrap_err()
+                .to_string(),
+            "Unable to find module 'missing'"
+        );
+    }
+
+    #[test]
+    fn loader_uses_module_resolver() {
+        let mut resolver = InMemoryResolver::default();
+        resolver.add_module("foo", "export x = 42");
+
+        let mut loader = Loader::default();
+        loader.set_module_resolver(Arc::new(resolver));
+
+        let (chunk, path) = loader.compile_module("foo", None).unwrap();
+        assert_eq!(path, Path::new("foo"));
+        assert_eq!(chunk.source_path.as_deref(), Some(Path::new("foo")));
+
+        // Compiled modules are cached by path
+        let (cached, _) = loader.compile_module("foo", None).unwrap();
+        assert!(Arc::ptr_eq(&chunk, &cached));
+
+        assert_eq!(
+            loader.compile_module("bar", None).unwrap_err().to_string(),
+            "Unable to find module 'bar'"
+        );
+    }
+
+    // A resolver with a single module, that counts the number of times the module is loaded
+    struct CountingResolver {
+        source: Mutex<String>,
+        modified: Mutex<SystemTime>,
+        load_count: AtomicUsize,
+    }
+
+    impl ModuleResolver for CountingResolver {
+        fn resolve(&self, _name: &str, _importer: Option<&Path>) -> Result<PathBuf, LoaderError> {
+            Ok(PathBuf::from("foo"))
+        }
+
+        fn load(&self, _path: &Path) -> Result<String, LoaderError> {
+            self.load_count.fetch_add(1, Ordering::SeqCst);
+            Ok(self.source.lock().unwrap().clone())
+        }
+
+        fn modified(&self, _path: &Path) -> Option<SystemTime> {
+            Some(*self.modified.lock().unwrap())
+        }
+    }
+
+    #[test]
+    fn unmodified_modules_arent_loaded_when_checking_for_changes() {
+        let resolver = Arc::new(CountingResolver {
+            source: Mutex::new("export x = 1".to_string()),
+            modified: Mutex::new(SystemTime::UNIX_EPOCH),
+            load_count: AtomicUsize::new(0),
+        });
+
+        let mut loader = Loader::default();
+        loader.set_module_resolver(resolver.clone());
+        loader.compile_module("foo", None).unwrap();
+        assert_eq!(resolver.load_count.load(Ordering::SeqCst), 1);
+
+        // The modification time is unchanged, so the source isn't loaded
+        assert!(loader.changed_modules().is_empty());
+        assert_eq!(resolver.load_count.load(Ordering::SeqCst), 1);
+
+        // The modification time has changed, but the source is the same
+        *resolver.modified.lock().unwrap() += Duration::from_secs(1);
+        assert!(loader.changed_modules().is_empty());
+        assert_eq!(resolver.load_count.load(Ordering::SeqCst), 2);
+
+        *resolver.source.lock().unwrap() = "export x = 2".to_string();
+        assert_eq!(loader.changed_modules(), vec![PathBuf::from("foo")]);
+        assert_eq!(resolver.load_count.load(Ordering::SeqCst), 3);
+    }
+}
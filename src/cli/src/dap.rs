@@ -0,0 +1,676 @@
+//! A Debug Adapter Protocol server for Koto scripts
+//!
+//! The server communicates with the debugging client over stdin and stdout,
+//! see https://microsoft.github.io/debug-adapter-protocol/ for the protocol's specification.
+
+use {
+    koto::{
+        bytecode::Chunk,
+        parser::Span,
+        runtime::{
+            DebugCommand, DebugFrame, DebugHook, Debugger, InterruptHandle, KotoLogger, PauseEvent,
+            PauseReason, RuntimeError, SearchPathResolver, Vm,
+        },
+        Koto, KotoError, KotoSettings,
+    },
+    serde_json::{json, Value as JsonValue},
+    std::{
+        fs,
+        io::{self, BufRead, Write},
+        path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            mpsc, Arc, Mutex,
+        },
+        thread,
+    },
+};
+
+// Koto scripts run on a single thread, so a fixed id is used for all thread-related messages
+const THREAD_ID: u64 = 1;
+
+/// Runs a DAP server that communicates via stdin and stdout
+///
+/// The server returns when the client disconnects, or when stdin is closed.
+/// False is returned if the session ended due to a malformed message from the client.
+pub fn run() -> bool {
+    let stdin = io::stdin();
+    let mut server = DapServer::new(Box::new(io::stdout()));
+    server.run(&mut stdin.lock())
+}
+
+// Writes protocol messages to the client
+#[derive(Clone)]
+struct Connection {
+    state: Arc<Mutex<ConnectionState>>,
+}
+
+struct ConnectionState {
+    output: Box<dyn Write + Send>,
+    seq: u64,
+}
+
+impl Connection {
+    fn new(output: Box<dyn Write + Send>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(ConnectionState { output, seq: 0 })),
+        }
+    }
+
+    fn send(&self, mut message: JsonValue) {
+        let mut state = self.state.lock().unwrap();
+        state.seq += 1;
+        message["seq"] = json!(state.seq);
+
+        let message = message.to_string();
+        // Errors are ignored, a closed output means that the client has gone away
+        let _ = write!(
+            state.output,
+            "Content-Length: {}\r\n\r\n{}",
+            message.len(),
+            message
+        );
+        let _ = state.output.flush();
+    }
+
+    fn send_event(&self, event: &str, body: JsonValue) {
+        self.send(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }));
+    }
+
+    fn send_response(&self, request: &JsonValue, body: JsonValue) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }));
+    }
+
+    fn send_error_response(&self, request: &JsonValue, message: &str) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        }));
+    }
+
+    fn send_output(&self, category: &str, output: &str) {
+        self.send_event("output", json!({"category": category, "output": output}));
+    }
+}
+
+// Reads a single protocol message, returning None when the input has been closed
+//
+// An error is returned if the message is malformed, after which the input can't be relied on to
+// be at the start of the next message.
+fn read_message(input: &mut impl BufRead) -> Result<Option<JsonValue>, String> {
+    let mut content_length = None;
+
+    let content_length = loop {
+        let mut header = String::new();
+        match input.read_line(&mut header) {
+            Ok(0) | Err(_) => return Ok(None),
+            Ok(_) => {}
+        }
+
+        let header = header.trim();
+        if header.is_empty() {
+            match content_length {
+                Some(length) => break length,
+                None => continue,
+            }
+        }
+
+        if let Some(length) = header.strip_prefix("Content-Length:") {
+            match length.trim().parse::<usize>() {
+                Ok(length) => content_length = Some(length),
+                Err(_) => return Err(format!("Invalid Content-Length header: '{}'", header)),
+            }
+        }
+    };
+
+    let mut content = vec![0; content_length];
+    if input.read_exact(&mut content).is_err() {
+        return Ok(None);
+    }
+    serde_json::from_slice(&content)
+        .map(Some)
+        .map_err(|error| format!("Invalid message content: {}", error))
+}
+
+// A snapshot of a paused frame, with values converted to strings for display in the client
+struct FrameInfo {
+    name: String,
+    source_path: Option<PathBuf>,
+    line: u32,
+    column: u32,
+    locals: Vec<(String, String)>,
+}
+
+impl FrameInfo {
+    fn new(chunk: &Chunk, span: Option<Span>, locals: Vec<(String, String)>) -> Self {
+        let (line, column) = span.map_or((0, 0), |span| (span.start.line, span.start.column));
+
+        // Koto functions don't have names, so the frame's source line is used instead
+        let name = chunk
+            .debug_info
+            .source
+            .lines()
+            .nth((line as usize).saturating_sub(1))
+            .map_or("<unknown>", |source_line| source_line.trim())
+            .to_string();
+
+        Self {
+            name,
+            source_path: chunk.source_path.clone(),
+            line,
+            column,
+            locals,
+        }
+    }
+
+    fn from_debug_frame(frame: &DebugFrame) -> Self {
+        let locals = frame
+            .locals
+            .iter()
+            .map(|(name, value)| (name.clone(), value.to_string()))
+            .collect();
+        Self::new(&frame.chunk, frame.span, locals)
+    }
+
+    fn from_error(error: &RuntimeError) -> Vec<Self> {
+        error
+            .trace
+            .iter()
+            .map(|frame| {
+                let span = frame.chunk.debug_info.get_source_span(frame.instruction);
+                Self::new(&frame.chunk, span, Vec::new())
+            })
+            .collect()
+    }
+}
+
+// The state that's shared between the server and the thread that runs the script
+#[derive(Default)]
+struct SessionState {
+    // The call stack of the paused script, starting with the innermost frame
+    paused_frames: Option<Vec<FrameInfo>>,
+    commands: Option<mpsc::Sender<DebugCommand>>,
+    // Set when the script is being stopped, after which the script shouldn't pause again
+    stopping: bool,
+}
+
+// Waits for a command from the client after a pause, see DapServer::resume
+fn wait_for_command(
+    session: &Mutex<SessionState>,
+    connection: &Connection,
+    frames: Vec<FrameInfo>,
+    stopped_body: JsonValue,
+) -> DebugCommand {
+    let (sender, receiver) = mpsc::channel();
+    {
+        let mut session = session.lock().unwrap();
+        if session.stopping {
+            return DebugCommand::Continue;
+        }
+        session.paused_frames = Some(frames);
+        session.commands = Some(sender);
+    }
+
+    connection.send_event("stopped", stopped_body);
+
+    // If the server has shut down then the sender will have been dropped
+    receiver.recv().unwrap_or(DebugCommand::Continue)
+}
+
+struct DapHook {
+    connection: Connection,
+    session: Arc<Mutex<SessionState>>,
+    stop_on_entry: Arc<AtomicBool>,
+}
+
+impl DebugHook for DapHook {
+    fn on_pause(&self, _vm: &mut Vm, event: &PauseEvent) -> DebugCommand {
+        let reason = match event.reason {
+            PauseReason::Breakpoint => "breakpoint",
+            PauseReason::Step => "step",
+            PauseReason::PauseRequested if self.stop_on_entry.swap(false, Ordering::Relaxed) => {
+                "entry"
+            }
+            PauseReason::PauseRequested => "pause",
+        };
+
+        let frames = event
+            .call_stack
+            .iter()
+            .map(FrameInfo::from_debug_frame)
+            .collect();
+
+        wait_for_command(
+            &self.session,
+            &self.connection,
+            frames,
+            json!({"reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true}),
+        )
+    }
+}
+
+// Forwards the script's output to the client
+struct DapLogger {
+    connection: Connection,
+}
+
+impl KotoLogger for DapLogger {
+    fn writeln(&self, output: &str) {
+        self.connection
+            .send_output("stdout", &format!("{}\n", output));
+    }
+}
+
+struct LaunchConfig {
+    program: PathBuf,
+    args: Vec<String>,
+    stop_on_entry: bool,
+    no_debug: bool,
+}
+
+struct DapServer {
+    connection: Connection,
+    session: Arc<Mutex<SessionState>>,
+    debugger: Arc<Debugger>,
+    stop_on_entry: Arc<AtomicBool>,
+    interrupt_handle: InterruptHandle,
+    lines_start_at_1: bool,
+    launch_config: Option<LaunchConfig>,
+    configuration_done: bool,
+    script_thread: Option<thread::JoinHandle<()>>,
+}
+
+impl DapServer {
+    fn new(output: Box<dyn Write + Send>) -> Self {
+        let connection = Connection::new(output);
+        let session = Arc::new(Mutex::new(SessionState::default()));
+        let stop_on_entry = Arc::new(AtomicBool::new(false));
+
+        let debugger = Arc::new(Debugger::new(DapHook {
+            connection: connection.clone(),
+            session: session.clone(),
+            stop_on_entry: stop_on_entry.clone(),
+        }));
+
+        Self {
+            connection,
+            session,
+            debugger,
+            stop_on_entry,
+            interrupt_handle: InterruptHandle::default(),
+            lines_start_at_1: true,
+            launch_config: None,
+            configuration_done: false,
+            script_thread: None,
+        }
+    }
+
+    // Handles requests until the session ends, returning false if a malformed message was read
+    fn run(&mut self, input: &mut impl BufRead) -> bool {
+        let result = loop {
+            match read_message(input) {
+                Ok(Some(request)) => {
+                    if request["type"] != "request" {
+                        continue;
+                    }
+
+                    let finished = self.handle_request(&request);
+                    if finished {
+                        return true;
+                    }
+                }
+                Ok(None) => break true,
+                Err(error) => {
+                    self.connection
+                        .send_output("stderr", &format!("Error: {}\n", error));
+                    break false;
+                }
+            }
+        };
+
+        self.shutdown();
+        result
+    }
+
+    // Handles a request from the client, returning true when the session has ended
+    fn handle_request(&mut self, request: &JsonValue) -> bool {
+        let arguments = &request["arguments"];
+
+        match request["command"].as_str().unwrap_or_default() {
+            "initialize" => {
+                self.lines_start_at_1 = arguments["linesStartAt1"].as_bool().unwrap_or(true);
+                self.connection.send_response(
+                    request,
+                    json!({
+                        "supportsConfigurationDoneRequest": true,
+                        "supportsTerminateRequest": true,
+                    }),
+                );
+                self.connection.send_event("initialized", json!({}));
+            }
+            "launch" => match arguments["program"].as_str() {
+                Some(program) => {
+                    let args = arguments["args"]
+                        .as_array()
+                        .map(|args| {
+                            args.iter()
+                                .filter_map(|arg| arg.as_str().map(String::from))
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    self.launch_config = Some(LaunchConfig {
+                        program: PathBuf::from(program),
+                        args,
+                        stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+                        no_debug: arguments["noDebug"].as_bool().unwrap_or(false),
+                    });
+                    self.connection.send_response(request, json!({}));
+                    self.start_script_if_ready();
+                }
+                None => self
+                    .connection
+                    .send_error_response(request, "Missing 'program' in launch arguments"),
+            },
+            "setBreakpoints" => {
+                let path = arguments["source"]["path"].as_str().map(Path::new);
+                let lines = arguments["breakpoints"]
+                    .as_array()
+                    .map(|breakpoints| {
+                        breakpoints
+                            .iter()
+                            .filter_map(|breakpoint| breakpoint["line"].as_u64())
+                            .map(|line| self.line_from_client(line))
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+                self.debugger.set_breakpoints(path, &lines);
+
+                let breakpoints = lines
+                    .iter()
+                    .map(|line| json!({"verified": true, "line": self.line_to_client(*line)}))
+                    .collect::<Vec<_>>();
+                self.connection
+                    .send_response(request, json!({ "breakpoints": breakpoints }));
+            }
+            "setExceptionBreakpoints" => {
+                self.connection
+                    .send_response(request, json!({"breakpoints": []}));
+            }
+            "configurationDone" => {
+                self.configuration_done = true;
+                self.connection.send_response(request, json!({}));
+                self.start_script_if_ready();
+            }
+            "threads" => {
+                self.connection.send_response(
+                    request,
+                    json!({"threads": [{"id": THREAD_ID, "name": "main"}]}),
+                );
+            }
+            "stackTrace" => {
+                let session = self.session.lock().unwrap();
+                let frames = session
+                    .paused_frames
+                    .iter()
+                    .flatten()
+                    .enumerate()
+                    .map(|(id, frame)| {
+                        let source = match &frame.source_path {
+                            Some(path) => json!({
+                                "name": path.file_name().map(|name| name.to_string_lossy()),
+                                "path": path.to_string_lossy(),
+                            }),
+                            None => JsonValue::Null,
+                        };
+                        json!({
+                            "id": id,
+                            "name": frame.name,
+                            "source": source,
+                            "line": self.line_to_client(frame.line),
+                            "column": frame.column,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let total_frames = frames.len();
+                self.connection.send_response(
+                    request,
+                    json!({"stackFrames": frames, "totalFrames": total_frames}),
+                );
+            }
+            "scopes" => {
+                let frame_id = arguments["frameId"].as_u64().unwrap_or_default();
+                self.connection.send_response(
+                    request,
+                    json!({"scopes": [{
+                        "name": "Locals",
+                        "presentationHint": "locals",
+                        // Variable references need to be greater than zero
+                        "variablesReference": frame_id + 1,
+                        "expensive": false,
+                    }]}),
+                );
+            }
+            "variables" => {
+                let reference = arguments["variablesReference"].as_u64().unwrap_or_default();
+                let session = self.session.lock().unwrap();
+                let variables = session
+                    .paused_frames
+                    .as_ref()
+                    .and_then(|frames| frames.get((reference as usize).checked_sub(1)?))
+                    .map(|frame| {
+                        frame
+                            .locals
+                            .iter()
+                            .map(|(name, value)| {
+                                json!({"name": name, "value": value, "variablesReference": 0})
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+                self.connection
+                    .send_response(request, json!({ "variables": variables }));
+            }
+            // Responses are sent before resuming so that they're received before any events
+            // that are sent while the script is running.
+            "continue" => {
+                self.connection
+                    .send_response(request, json!({"allThreadsContinued": true}));
+                self.resume(DebugCommand::Continue);
+            }
+            "next" => {
+                self.connection.send_response(request, json!({}));
+                self.resume(DebugCommand::StepOver);
+            }
+            "stepIn" => {
+                self.connection.send_response(request, json!({}));
+                self.resume(DebugCommand::StepIn);
+            }
+            "stepOut" => {
+                self.connection.send_response(request, json!({}));
+                self.resume(DebugCommand::StepOut);
+            }
+            "pause" => {
+                self.debugger.request_pause();
+                self.connection.send_response(request, json!({}));
+            }
+            "terminate" => {
+                self.stop_script();
+                self.connection.send_response(request, json!({}));
+            }
+            "disconnect" => {
+                self.shutdown();
+                self.connection.send_response(request, json!({}));
+                return true;
+            }
+            unsupported => {
+                self.connection.send_error_response(
+                    request,
+                    &format!("Unsupported request '{}'", unsupported),
+                );
+            }
+        }
+
+        false
+    }
+
+    fn line_from_client(&self, line: u64) -> u32 {
+        if self.lines_start_at_1 {
+            line as u32
+        } else {
+            line as u32 + 1
+        }
+    }
+
+    fn line_to_client(&self, line: u32) -> u32 {
+        if self.lines_start_at_1 {
+            line
+        } else {
+            line.saturating_sub(1)
+        }
+    }
+
+    fn start_script_if_ready(&mut self) {
+        if !self.configuration_done || self.script_thread.is_some() {
+            return;
+        }
+
+        let config = match &self.launch_config {
+            Some(config) => config,
+            None => return,
+        };
+
+        if config.stop_on_entry && !config.no_debug {
+            self.stop_on_entry.store(true, Ordering::Relaxed);
+            self.debugger.request_pause();
+        }
+
+        let koto_settings = KotoSettings {
+            run_tests: false,
+            logger: Arc::new(DapLogger {
+                connection: self.connection.clone(),
+            }),
+            interrupt_handle: self.interrupt_handle.clone(),
+            debugger: if config.no_debug {
+                None
+            } else {
+                Some(self.debugger.clone())
+            },
+            module_resolver: Arc::new(SearchPathResolver::from_env()),
+            ..Default::default()
+        };
+        let program = config.program.clone();
+        let args = config.args.clone();
+        let connection = self.connection.clone();
+        let session = self.session.clone();
+
+        self.script_thread = Some(thread::spawn(move || {
+            let exit_code = match run_script(koto_settings, &program, &args, &connection, &session)
+            {
+                Ok(_) => 0,
+                Err(_) => 1,
+            };
+            connection.send_event("exited", json!({ "exitCode": exit_code }));
+            connection.send_event("terminated", json!({}));
+        }));
+    }
+
+    // Resumes a paused script
+    fn resume(&self, command: DebugCommand) {
+        let mut session = self.session.lock().unwrap();
+        session.paused_frames = None;
+        if let Some(commands) = session.commands.take() {
+            let _ = commands.send(command);
+        }
+    }
+
+    fn stop_script(&self) {
+        self.interrupt_handle.interrupt();
+        self.session.lock().unwrap().stopping = true;
+        self.resume(DebugCommand::Continue);
+    }
+
+    fn shutdown(&mut self) {
+        self.stop_script();
+        if let Some(script_thread) = self.script_thread.take() {
+            let _ = script_thread.join();
+        }
+    }
+}
+
+fn run_script(
+    koto_settings: KotoSettings,
+    program: &Path,
+    args: &[String],
+    connection: &Connection,
+    session: &Mutex<SessionState>,
+) -> Result<(), ()> {
+    let script = match fs::read_to_string(program) {
+        Ok(script) => script,
+        Err(error) => {
+            connection.send_output(
+                "stderr",
+                &format!(
+                    "Failed to load '{}': {}\n",
+                    program.to_string_lossy(),
+                    error
+                ),
+            );
+            return Err(());
+        }
+    };
+
+    let mut koto = Koto::with_settings(koto_settings);
+
+    let mut prelude = koto.prelude();
+    prelude.add_map("json", koto_json::make_module());
+    prelude.add_map("random", koto_random::make_module());
+    prelude.add_map("tempfile", koto_tempfile::make_module());
+    prelude.add_map("toml", koto_toml::make_module());
+
+    koto.set_script_path(Some(program.to_path_buf()));
+    if let Err(error) = koto.compile(&script) {
+        connection.send_output("stderr", &format!("Error: {}\n", error));
+        return Err(());
+    }
+
+    match koto.run_with_args(args) {
+        Ok(_) => Ok(()),
+        Err(error) => {
+            connection.send_output("stderr", &format!("Error: {}\n", error));
+
+            // Runtime errors are reported as exceptions, allowing the client to inspect the
+            // error's backtrace before the session ends.
+            if let KotoError::RuntimeError(error) = &error {
+                if !error.is_execution_limit() {
+                    wait_for_command(
+                        session,
+                        connection,
+                        FrameInfo::from_error(error),
+                        json!({
+                            "reason": "exception",
+                            "description": "Runtime error",
+                            "text": error.to_string(),
+                            "threadId": THREAD_ID,
+                            "allThreadsStopped": true,
+                        }),
+                    );
+                }
+            }
+
+            Err(())
+        }
+    }
+}
@@ -1,9 +1,16 @@
+mod dap;
 mod repl;
+mod test_runner;
 
 use {
-    koto::{bytecode::Chunk, Koto, KotoSettings},
+    koto::{
+        bytecode::Chunk,
+        parser::format_error_with_excerpt,
+        runtime::{Coverage, Profiler, SearchPathResolver},
+        Koto, KotoSettings,
+    },
     repl::{Repl, ReplSettings},
-    std::fs,
+    std::{fs, path::Path, process, sync::Arc},
 };
 
 #[cfg(all(jemalloc, not(target_env = "msvc")))]
@@ -20,17 +27,39 @@ fn help_string() -> String {
 
 USAGE:
     koto [FLAGS] [script] [<args>...]
+    koto test [--filter <pattern>] [--junit <path>] [--tap <path>] [--coverage <path>] [<paths>...]
 
 FLAGS:
     -i, --show_instructions  Show compiled instructions annotated with source lines
     -b, --show_bytecode      Show the script's compiled bytecode
     -t, --tests              Run the script's tests before running the script
+        --dap                Run a Debug Adapter Protocol server using stdin and stdout
+        --format             Format the given scripts in place
+        --check              Check that the given scripts are formatted, without modifying them
+        --profile <path>     Profile the script, writing collapsed stacks to the given path
+                             and printing a summary of the time spent in each function
+        --coverage <path>    Record the lines and branches that are executed, writing the
+                             results in the lcov format to the given path,
+                             or in the Cobertura format if the path ends with '.xml'
+        --filter <pattern>   Only run the tests with names containing the pattern,
+                             when running 'koto test'
+        --junit <path>       Write a JUnit XML report of the results of 'koto test' to the path
+        --tap <path>         Write a TAP report of the results of 'koto test' to the path
     -h, --help               Prints help information
     -v, --version            Prints version information
 
 ARGS:
-    <script>     The koto script to run
+    <script>     The koto script to run, or the first script to format
     <args>...    Arguments to pass into the script
+
+SUBCOMMANDS:
+    test         Run the tests exported by the given scripts, searching directories for
+                 .koto scripts, with the current directory used if no paths are given.
+                 A script file named 'test' is run as a script rather than as the subcommand
+
+ENVIRONMENT:
+    KOTO_PATH    Directories to search for imported modules when they aren't found next to
+                 the importing script, separated in the same way as PATH
 ",
         version = version_string()
     )
@@ -41,8 +70,16 @@ struct KotoArgs {
     help: bool,
     version: bool,
     run_tests: bool,
+    dap: bool,
+    format: bool,
+    check: bool,
     show_bytecode: bool,
     show_instructions: bool,
+    profile: Option<String>,
+    coverage: Option<String>,
+    filter: Option<String>,
+    junit: Option<String>,
+    tap: Option<String>,
     script: Option<String>,
     script_args: Vec<String>,
 }
@@ -53,8 +90,26 @@ fn parse_arguments() -> Result<KotoArgs, String> {
     let help = args.contains(["-h", "--help"]);
     let version = args.contains(["-v", "--version"]);
     let run_tests = args.contains(["-t", "--tests"]);
+    let dap = args.contains("--dap");
+    let format = args.contains("--format");
+    let check = args.contains("--check");
     let show_bytecode = args.contains(["-b", "--show_bytecode"]);
     let show_instructions = args.contains(["-i", "--show_instructions"]);
+    let profile = args
+        .opt_value_from_str("--profile")
+        .map_err(|e| format!("Error while parsing arguments: {}", e))?;
+    let coverage = args
+        .opt_value_from_str("--coverage")
+        .map_err(|e| format!("Error while parsing arguments: {}", e))?;
+    let filter = args
+        .opt_value_from_str("--filter")
+        .map_err(|e| format!("Error while parsing arguments: {}", e))?;
+    let junit = args
+        .opt_value_from_str("--junit")
+        .map_err(|e| format!("Error while parsing arguments: {}", e))?;
+    let tap = args
+        .opt_value_from_str("--tap")
+        .map_err(|e| format!("Error while parsing arguments: {}", e))?;
 
     let script = args
         .subcommand()
@@ -76,8 +131,16 @@ fn parse_arguments() -> Result<KotoArgs, String> {
         help,
         version,
         run_tests,
+        dap,
+        format,
+        check,
         show_bytecode,
         show_instructions,
+        profile,
+        coverage,
+        filter,
+        junit,
+        tap,
         script,
         script_args,
     })
@@ -102,8 +165,52 @@ fn main() {
         return;
     }
 
+    if args.dap {
+        if !dap::run() {
+            process::exit(1);
+        }
+        return;
+    }
+
+    if args.format || args.check {
+        let paths = args.script.iter().chain(args.script_args.iter());
+        if !format_scripts(paths, args.check) {
+            process::exit(1);
+        }
+        return;
+    }
+
+    let profiler = args.profile.as_ref().map(|_| Arc::new(Profiler::new()));
+    let coverage = args.coverage.as_ref().map(|_| Arc::new(Coverage::new()));
+
+    // A script file named 'test' takes precedence over the test subcommand
+    if args.script.as_deref() == Some("test") && !Path::new("test").is_file() {
+        let paths = if args.script_args.is_empty() {
+            vec![".".to_string()]
+        } else {
+            args.script_args
+        };
+        let settings = test_runner::TestRunnerSettings {
+            filter: args.filter,
+            coverage: coverage.clone(),
+            junit_path: args.junit,
+            tap_path: args.tap,
+        };
+        let success = test_runner::run(&paths, settings);
+        if let (Some(coverage), Some(coverage_path)) = (coverage, args.coverage) {
+            write_coverage(&coverage, &coverage_path);
+        }
+        if !success {
+            process::exit(1);
+        }
+        return;
+    }
+
     let koto_settings = KotoSettings {
         run_tests: args.run_tests,
+        profiler: profiler.clone(),
+        coverage: coverage.clone(),
+        module_resolver: Arc::new(SearchPathResolver::from_env()),
         ..Default::default()
     };
 
@@ -120,6 +227,7 @@ fn main() {
         koto.set_script_path(Some(script_path.into()));
         match koto.compile(&script) {
             Ok(chunk) => {
+                print_warnings(&chunk);
                 if args.show_bytecode {
                     println!("{}\n", &Chunk::bytes_as_string(chunk.clone()));
                 }
@@ -136,6 +244,12 @@ fn main() {
                     Ok(_) => {}
                     Err(e) => eprintln!("Error: {}", e),
                 }
+                if let (Some(profiler), Some(profile_path)) = (profiler, args.profile) {
+                    write_profile(&profiler, &profile_path);
+                }
+                if let (Some(coverage), Some(coverage_path)) = (coverage, args.coverage) {
+                    write_coverage(&coverage, &coverage_path);
+                }
             }
             Err(e) => eprintln!("Error: {}", e),
         }
@@ -150,3 +264,74 @@ fn main() {
         repl.run();
     }
 }
+
+fn write_profile(profiler: &Profiler, path: &str) {
+    if let Err(e) = fs::write(path, profiler.collapsed_stacks()) {
+        eprintln!("Error: Unable to write profile to '{}': {}", path, e);
+    }
+    eprint!("{}", profiler.summary());
+}
+
+fn write_coverage(coverage: &Coverage, path: &str) {
+    let output = if path.ends_with(".xml") {
+        coverage.cobertura()
+    } else {
+        coverage.lcov()
+    };
+    if let Err(e) = fs::write(path, output) {
+        eprintln!("Error: Unable to write coverage to '{}': {}", path, e);
+    }
+}
+
+fn print_warnings(chunk: &Chunk) {
+    for warning in chunk.warnings.iter() {
+        eprintln!(
+            "{}\n",
+            format_error_with_excerpt(
+                Some(&format!("Warning: {}", warning)),
+                &chunk.source_path,
+                &chunk.debug_info.source,
+                warning.span.start,
+                warning.span.end,
+            )
+        );
+    }
+}
+
+// Formats the scripts, or when checking, reports the scripts that aren't formatted
+//
+// Returns false if any of the scripts couldn't be formatted, or if they need formatting when
+// checking.
+fn format_scripts<'a>(paths: impl Iterator<Item = &'a String>, check: bool) -> bool {
+    let mut success = true;
+
+    for path in paths {
+        let script = match fs::read_to_string(path) {
+            Ok(script) => script,
+            Err(e) => {
+                eprintln!("Error: Unable to read '{}': {}", path, e);
+                success = false;
+                continue;
+            }
+        };
+
+        match koto_format::format(&script) {
+            Ok(formatted) if formatted == script => {}
+            Ok(formatted) => {
+                if check {
+                    println!("{} needs formatting", path);
+                    success = false;
+                } else if let Err(e) = fs::write(path, formatted) {
+                    eprintln!("Error: Unable to write '{}': {}", path, e);
+                    success = false;
+                }
+            }
+            Err(e) => {
+                eprintln!("Error: Unable to format '{}': {}", path, e);
+                success = false;
+            }
+        }
+    }
+
+    success
+}
@@ -0,0 +1,260 @@
+//! Discovers and runs the tests exported by Koto scripts, see `koto test`
+
+use {
+    koto::{
+        runtime::{
+            Coverage, JUnitReporter, RuntimeError, SearchPathResolver, TapReporter, TestReporter,
+            TestResult, Vm,
+        },
+        Koto, KotoSettings,
+    },
+    std::{
+        fs,
+        path::{Path, PathBuf},
+        sync::Arc,
+        time::{Duration, Instant},
+    },
+};
+
+pub struct TestRunnerSettings {
+    /// When set, only tests with names containing the pattern are run
+    pub filter: Option<String>,
+    /// Coverage that should be recorded while running the tests
+    pub coverage: Option<Arc<Coverage>>,
+    /// When set, a JUnit XML report is written to the path
+    pub junit_path: Option<String>,
+    /// When set, a TAP report is written to the path
+    pub tap_path: Option<String>,
+}
+
+// The reporters that were requested in the settings
+#[derive(Default)]
+struct Reporters {
+    junit: Option<JUnitReporter>,
+    tap: Option<TapReporter>,
+}
+
+impl Reporters {
+    fn iter_mut(&mut self) -> impl Iterator<Item = &mut dyn TestReporter> {
+        let junit = self.junit.as_mut().map(|r| r as &mut dyn TestReporter);
+        let tap = self.tap.as_mut().map(|r| r as &mut dyn TestReporter);
+        junit.into_iter().chain(tap)
+    }
+}
+
+#[derive(Default)]
+struct TestSummary {
+    passed: usize,
+    failed: usize,
+    filtered_out: usize,
+    // The names and errors of the tests, or scripts, that failed
+    failures: Vec<(String, String)>,
+}
+
+/// Runs the tests contained in the given paths
+///
+/// Directories are searched recursively for `.koto` scripts, and each script's exported `tests`
+/// map is run. Scripts that fail to compile or run count as failures.
+///
+/// Returns true if all of the tests passed.
+pub fn run(paths: &[String], settings: TestRunnerSettings) -> bool {
+    let mut scripts = Vec::new();
+    let mut summary = TestSummary::default();
+
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            if let Err(e) = find_scripts(path, &mut scripts) {
+                summary.failed += 1;
+                summary
+                    .failures
+                    .push((path.to_string_lossy().to_string(), e.to_string()));
+            }
+        } else if path.exists() {
+            scripts.push(path.to_path_buf());
+        } else {
+            summary.failed += 1;
+            summary.failures.push((
+                path.to_string_lossy().to_string(),
+                "File not found".to_string(),
+            ));
+        }
+    }
+
+    let mut reporters = Reporters {
+        junit: settings.junit_path.as_ref().map(|_| JUnitReporter::new()),
+        tap: settings.tap_path.as_ref().map(|_| TapReporter::new()),
+    };
+
+    let start_time = Instant::now();
+
+    for script in scripts.iter() {
+        run_script_tests(script, &settings, &mut summary, &mut reporters);
+    }
+
+    let elapsed = start_time.elapsed();
+
+    if !summary.failures.is_empty() {
+        println!("\nfailures:");
+        for (name, error) in summary.failures.iter() {
+            println!("\n---- {} ----\n{}", name, error);
+        }
+    }
+
+    println!(
+        "\ntest result: {}. {} passed; {} failed; {} filtered out; finished in {}",
+        if summary.failed == 0 { "ok" } else { "FAILED" },
+        summary.passed,
+        summary.failed,
+        summary.filtered_out,
+        format_duration(elapsed),
+    );
+
+    if let (Some(reporter), Some(path)) = (&reporters.junit, &settings.junit_path) {
+        write_report(path, reporter.output());
+    }
+    if let (Some(reporter), Some(path)) = (&reporters.tap, &settings.tap_path) {
+        write_report(path, reporter.output());
+    }
+
+    summary.failed == 0
+}
+
+fn write_report(path: &str, report: String) {
+    if let Err(e) = fs::write(path, report) {
+        eprintln!("Error: Unable to write test report to '{}': {}", path, e);
+    }
+}
+
+// Adds the paths of the Koto scripts in the directory and its subdirectories, in sorted order
+//
+// Hidden directories, e.g. `.git`, are skipped.
+fn find_scripts(dir: &Path, scripts: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            let hidden =
+                matches!(path.file_name(), Some(name) if name.to_string_lossy().starts_with('.'));
+            if !hidden {
+                find_scripts(&path, scripts)?;
+            }
+        } else if matches!(path.extension(), Some(extension) if extension == "koto") {
+            scripts.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn run_script_tests(
+    path: &Path,
+    settings: &TestRunnerSettings,
+    summary: &mut TestSummary,
+    reporters: &mut Reporters,
+) {
+    let display_path = path.to_string_lossy().to_string();
+
+    // Scripts that fail to load are reported as a failing test named after the script,
+    // so that the failure shows up in the reports.
+    let mut fail_script = |error: String| {
+        println!("{} ... FAILED", display_path);
+        summary.failed += 1;
+        summary.failures.push((display_path.clone(), error.clone()));
+
+        let result = TestResult {
+            name: display_path.clone(),
+            duration: Duration::default(),
+            error: Some(RuntimeError::from_string(error)),
+        };
+        for reporter in reporters.iter_mut() {
+            reporter.start_suite(&display_path);
+            reporter.test_finished(&result);
+        }
+    };
+
+    let script = match fs::read_to_string(path) {
+        Ok(script) => script,
+        Err(e) => return fail_script(e.to_string()),
+    };
+
+    let mut koto = Koto::with_settings(KotoSettings {
+        run_tests: false,
+        run_main: false,
+        coverage: settings.coverage.clone(),
+        module_resolver: Arc::new(SearchPathResolver::from_env()),
+        ..Default::default()
+    });
+
+    let mut prelude = koto.prelude();
+    prelude.add_map("json", koto_json::make_module());
+    prelude.add_map("random", koto_random::make_module());
+    prelude.add_map("tempfile", koto_tempfile::make_module());
+    prelude.add_map("toml", koto_toml::make_module());
+
+    koto.set_script_path(Some(path.to_path_buf()));
+    if let Err(e) = koto.compile(&script) {
+        return fail_script(e.to_string());
+    }
+    if let Err(e) = koto.run() {
+        return fail_script(e.to_string());
+    }
+
+    let tests = match koto.exported_tests() {
+        Ok(Some(tests)) => tests,
+        Ok(None) => return,
+        Err(e) => return fail_script(e.to_string()),
+    };
+
+    let test_names = Vm::test_names(&tests);
+    if test_names.is_empty() {
+        return;
+    }
+
+    println!("\nrunning tests in {}", display_path);
+    for reporter in reporters.iter_mut() {
+        reporter.start_suite(&display_path);
+    }
+
+    for name in test_names {
+        if let Some(filter) = &settings.filter {
+            if !name.contains(filter.as_str()) {
+                summary.filtered_out += 1;
+                continue;
+            }
+        }
+
+        let result = koto.run_test(tests.clone(), &name);
+        let elapsed = format_duration(result.duration);
+
+        match &result.error {
+            None => {
+                println!("test {} ... ok ({})", name, elapsed);
+                summary.passed += 1;
+            }
+            Some(error) => {
+                println!("test {} ... FAILED ({})", name, elapsed);
+                summary.failed += 1;
+                summary
+                    .failures
+                    .push((format!("{}: {}", display_path, name), error.to_string()));
+            }
+        }
+
+        for reporter in reporters.iter_mut() {
+            reporter.test_finished(&result);
+        }
+
+        // The runtime can't continue after an execution limit has been reached
+        if matches!(&result.error, Some(error) if error.is_execution_limit()) {
+            break;
+        }
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.2}ms", duration.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,77 @@
+use {
+    std::{fs, process::Command},
+    tempfile::TempDir,
+};
+
+mod coverage_tests {
+    use super::*;
+
+    const SCRIPT: &str = "\
+import test.assert_eq
+f = |n|
+  if n > 5
+    \"big\"
+  else
+    \"small\"
+export tests =
+  test_f: ||
+    assert_eq (f 10), \"big\"
+";
+
+    fn run_with_coverage(coverage_file: &str) -> (TempDir, String, String) {
+        let dir = TempDir::new().unwrap();
+        let script_path = dir.path().join("test.koto");
+        let coverage_path = dir.path().join(coverage_file);
+        fs::write(&script_path, SCRIPT).unwrap();
+
+        let output = Command::new(env!("CARGO_BIN_EXE_koto"))
+            .arg("--tests")
+            .arg("--coverage")
+            .arg(&coverage_path)
+            .arg(&script_path)
+            .output()
+            .expect("failed to execute child");
+
+        assert!(output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!stderr.contains("Error"), "{}", stderr);
+
+        let script_path = script_path.to_string_lossy().to_string();
+        let coverage = fs::read_to_string(&coverage_path).unwrap();
+        (dir, script_path, coverage)
+    }
+
+    #[test]
+    fn lcov_coverage_for_tests() {
+        let (_dir, script_path, coverage) = run_with_coverage("coverage.info");
+
+        assert!(
+            coverage.contains(&format!("SF:{}\n", script_path)),
+            "{}",
+            coverage
+        );
+        // The test only checks the 'big' branch
+        assert!(coverage.contains("DA:4,1\n"), "{}", coverage);
+        assert!(coverage.contains("DA:6,0\n"), "{}", coverage);
+        assert!(coverage.contains("BRDA:3,0,0,1\n"), "{}", coverage);
+        assert!(coverage.contains("BRDA:3,0,1,0\n"), "{}", coverage);
+        assert!(coverage.ends_with("end_of_record\n"), "{}", coverage);
+    }
+
+    #[test]
+    fn cobertura_coverage_for_tests() {
+        let (_dir, script_path, coverage) = run_with_coverage("coverage.xml");
+
+        assert!(coverage.starts_with("<?xml"), "{}", coverage);
+        assert!(
+            coverage.contains(&format!(r#"filename="{}""#, script_path)),
+            "{}",
+            coverage
+        );
+        assert!(
+            coverage.contains(r#"<line number="6" hits="0" branch="false"/>"#),
+            "{}",
+            coverage
+        );
+    }
+}
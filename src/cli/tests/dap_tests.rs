@@ -0,0 +1,261 @@
+use {
+    serde_json::{json, Value},
+    std::{
+        env,
+        io::{BufRead, BufReader, Read, Write},
+        path::Path,
+        process::{Child, ChildStdout, Command, Stdio},
+    },
+    tempfile::TempDir,
+};
+
+// A minimal DAP client that drives `koto --dap` with scripted requests
+struct DapClient {
+    process: Child,
+    output: BufReader<ChildStdout>,
+    seq: u64,
+}
+
+impl DapClient {
+    fn new() -> Self {
+        let mut process = Command::new(env!("CARGO_BIN_EXE_koto"))
+            .arg("--dap")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("failed to execute child");
+        let output = BufReader::new(process.stdout.take().unwrap());
+
+        Self {
+            process,
+            output,
+            seq: 0,
+        }
+    }
+
+    fn send(&mut self, command: &str, arguments: Value) {
+        self.seq += 1;
+        let message = json!({
+            "seq": self.seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        })
+        .to_string();
+
+        let stdin = self.process.stdin.as_mut().expect("failed to get stdin");
+        write!(
+            stdin,
+            "Content-Length: {}\r\n\r\n{}",
+            message.len(),
+            message
+        )
+        .expect("Failed to write to stdin");
+    }
+
+    fn read_message(&mut self) -> Value {
+        let mut content_length = None;
+        loop {
+            let mut header = String::new();
+            if self.output.read_line(&mut header).unwrap() == 0 {
+                panic!("Unexpected end of output");
+            }
+            let header = header.trim();
+            if header.is_empty() && content_length.is_some() {
+                break;
+            }
+            if let Some(length) = header.strip_prefix("Content-Length:") {
+                content_length = Some(length.trim().parse::<usize>().unwrap());
+            }
+        }
+
+        let mut content = vec![0; content_length.unwrap()];
+        self.output.read_exact(&mut content).unwrap();
+        serde_json::from_slice(&content).unwrap()
+    }
+
+    // Sends a request and then waits for its response, returning the response's body
+    fn request(&mut self, command: &str, arguments: Value) -> Value {
+        self.send(command, arguments);
+        loop {
+            let message = self.read_message();
+            if message["type"] == "response" && message["command"] == command {
+                assert_eq!(message["success"], true, "Failed request: {}", message);
+                return message["body"].clone();
+            }
+        }
+    }
+
+    // Waits for an event, returning the event's body
+    fn wait_for_event(&mut self, event: &str) -> Value {
+        loop {
+            let message = self.read_message();
+            if message["type"] == "event" && message["event"] == event {
+                return message["body"].clone();
+            }
+        }
+    }
+
+    fn launch(&mut self, program: &Path, breakpoints: &[u64], stop_on_entry: bool) {
+        self.request("initialize", json!({"adapterID": "koto"}));
+        self.request(
+            "launch",
+            json!({"program": program, "stopOnEntry": stop_on_entry}),
+        );
+        let breakpoints = breakpoints
+            .iter()
+            .map(|line| json!({ "line": line }))
+            .collect::<Vec<_>>();
+        self.request(
+            "setBreakpoints",
+            json!({"source": {"path": program}, "breakpoints": breakpoints}),
+        );
+        self.request("configurationDone", json!({}));
+    }
+
+    fn disconnect(mut self) {
+        self.request("disconnect", json!({}));
+        assert!(self.process.wait().unwrap().success());
+    }
+}
+
+impl Drop for DapClient {
+    fn drop(&mut self) {
+        // Make sure that the server doesn't outlive a failed test
+        let _ = self.process.kill();
+    }
+}
+
+fn write_script(dir: &TempDir, script: &str) -> std::path::PathBuf {
+    let path = dir.path().join("test.koto");
+    std::fs::write(&path, script).unwrap();
+    path
+}
+
+mod dap_tests {
+    use super::*;
+
+    #[test]
+    fn breakpoint_stack_trace_and_variables() {
+        let dir = TempDir::new().unwrap();
+        let program = write_script(
+            &dir,
+            "\
+import string.print
+f = |n|
+  x = n * 2
+  x + 1
+y = f 10
+print \"y: {}\", y
+",
+        );
+
+        let mut client = DapClient::new();
+        client.launch(&program, &[4], false);
+
+        let stopped = client.wait_for_event("stopped");
+        assert_eq!(stopped["reason"], "breakpoint");
+
+        let stack = client.request("stackTrace", json!({"threadId": 1}));
+        let frames = stack["stackFrames"].as_array().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0]["line"], 4);
+        assert_eq!(frames[1]["line"], 5);
+        assert_eq!(frames[0]["source"]["name"], "test.koto");
+
+        let scopes = client.request("scopes", json!({"frameId": 0}));
+        let reference = scopes["scopes"][0]["variablesReference"].clone();
+        let variables = client.request("variables", json!({ "variablesReference": reference }));
+        assert_eq!(
+            variables["variables"],
+            json!([
+                {"name": "n", "value": "10", "variablesReference": 0},
+                {"name": "x", "value": "20", "variablesReference": 0},
+            ])
+        );
+
+        client.request("next", json!({"threadId": 1}));
+        let stopped = client.wait_for_event("stopped");
+        assert_eq!(stopped["reason"], "step");
+        let stack = client.request("stackTrace", json!({"threadId": 1}));
+        assert_eq!(stack["stackFrames"][0]["line"], 6);
+
+        client.request("continue", json!({"threadId": 1}));
+        let output = client.wait_for_event("output");
+        assert_eq!(output["output"], "y: 21\n");
+        let exited = client.wait_for_event("exited");
+        assert_eq!(exited["exitCode"], 0);
+        client.wait_for_event("terminated");
+
+        client.disconnect();
+    }
+
+    #[test]
+    fn runtime_error_is_reported_as_an_exception() {
+        let dir = TempDir::new().unwrap();
+        let program = write_script(
+            &dir,
+            "\
+f = |n|
+  n.foo()
+f 1
+",
+        );
+
+        let mut client = DapClient::new();
+        client.launch(&program, &[], false);
+
+        let stopped = client.wait_for_event("stopped");
+        assert_eq!(stopped["reason"], "exception");
+
+        let stack = client.request("stackTrace", json!({"threadId": 1}));
+        let frames = stack["stackFrames"].as_array().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0]["line"], 2);
+        assert_eq!(frames[1]["line"], 3);
+
+        client.request("continue", json!({"threadId": 1}));
+        let exited = client.wait_for_event("exited");
+        assert_eq!(exited["exitCode"], 1);
+        client.wait_for_event("terminated");
+
+        client.disconnect();
+    }
+
+    #[test]
+    fn invalid_content_length_ends_the_session() {
+        let mut client = DapClient::new();
+        client.request("initialize", json!({"adapterID": "koto"}));
+
+        let stdin = client.process.stdin.as_mut().expect("failed to get stdin");
+        write!(stdin, "Content-Length: abc\r\n\r\n{{}}").expect("Failed to write to stdin");
+
+        let output = client.wait_for_event("output");
+        assert_eq!(output["category"], "stderr");
+        assert_eq!(
+            output["output"],
+            "Error: Invalid Content-Length header: 'Content-Length: abc'\n"
+        );
+        assert!(!client.process.wait().unwrap().success());
+    }
+
+    #[test]
+    fn stop_on_entry_then_disconnect() {
+        let dir = TempDir::new().unwrap();
+        let program = write_script(
+            &dir,
+            "\
+loop
+  x = 1
+",
+        );
+
+        let mut client = DapClient::new();
+        client.launch(&program, &[], true);
+
+        let stopped = client.wait_for_event("stopped");
+        assert_eq!(stopped["reason"], "entry");
+
+        client.disconnect();
+    }
+}
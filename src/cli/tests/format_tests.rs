@@ -0,0 +1,59 @@
+use {
+    std::{
+        env, fs,
+        path::Path,
+        process::{Command, Output},
+    },
+    tempfile::TempDir,
+};
+
+fn run_koto(flag: &str, script_path: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_koto"))
+        .arg(flag)
+        .arg(script_path)
+        .output()
+        .expect("failed to execute child")
+}
+
+mod format_tests {
+    use super::*;
+
+    const UNFORMATTED: &str = "x=1+2\nf = |n|\n    n*x\n";
+    const FORMATTED: &str = "x = 1 + 2\nf = |n|\n  n * x\n";
+
+    #[test]
+    fn check_reports_unformatted_scripts() {
+        let dir = TempDir::new().unwrap();
+        let script_path = dir.path().join("test.koto");
+        fs::write(&script_path, UNFORMATTED).unwrap();
+
+        let output = run_koto("--check", &script_path);
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("needs formatting"));
+        assert_eq!(fs::read_to_string(&script_path).unwrap(), UNFORMATTED);
+
+        fs::write(&script_path, FORMATTED).unwrap();
+        assert!(run_koto("--check", &script_path).status.success());
+    }
+
+    #[test]
+    fn format_rewrites_scripts() {
+        let dir = TempDir::new().unwrap();
+        let script_path = dir.path().join("test.koto");
+        fs::write(&script_path, UNFORMATTED).unwrap();
+
+        assert!(run_koto("--format", &script_path).status.success());
+        assert_eq!(fs::read_to_string(&script_path).unwrap(), FORMATTED);
+    }
+
+    #[test]
+    fn invalid_scripts_fail() {
+        let dir = TempDir::new().unwrap();
+        let script_path = dir.path().join("test.koto");
+        fs::write(&script_path, "x = (1 +").unwrap();
+
+        let output = run_koto("--format", &script_path);
+        assert!(!output.status.success());
+        assert_eq!(fs::read_to_string(&script_path).unwrap(), "x = (1 +");
+    }
+}
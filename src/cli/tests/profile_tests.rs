@@ -0,0 +1,56 @@
+use {
+    std::{fs, process::Command},
+    tempfile::TempDir,
+};
+
+mod profile_tests {
+    use super::*;
+
+    #[test]
+    fn profile_writes_collapsed_stacks_and_summary() {
+        let dir = TempDir::new().unwrap();
+        let script_path = dir.path().join("test.koto");
+        let profile_path = dir.path().join("test.folded");
+        fs::write(
+            &script_path,
+            "\
+import string.print
+f = |n|
+  x = 0
+  for i in 0..n
+    x += i
+  x
+print \"{}\", f 100000
+",
+        )
+        .unwrap();
+
+        let output = Command::new(env!("CARGO_BIN_EXE_koto"))
+            .arg("--profile")
+            .arg(&profile_path)
+            .arg(&script_path)
+            .output()
+            .expect("failed to execute child");
+
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "4999950000\n");
+
+        let script_name = script_path.to_string_lossy();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("Calls"), "{}", stderr);
+        assert!(
+            stderr.contains(&format!("{}:2:5", script_name)),
+            "{}",
+            stderr
+        );
+
+        let stacks = fs::read_to_string(&profile_path).unwrap();
+        assert!(
+            stacks
+                .lines()
+                .any(|line| line.starts_with(&format!("{0};{0}:2:5 ", script_name))),
+            "{}",
+            stacks
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use {
+    std::{fs, path::Path, process::Command},
+    tempfile::TempDir,
+};
+
+mod test_command_tests {
+    use super::*;
+
+    fn write_scripts(dir: &Path) {
+        fs::create_dir(dir.join("nested")).unwrap();
+        fs::write(
+            dir.join("nested").join("maths.koto"),
+            "\
+import test.assert_eq
+export tests =
+  pre_test: |self|
+    self.x = 42
+  post_test: |self|
+    self.x = ()
+  test_uses_pre_test: |self|
+    assert_eq self.x, 42
+  test_addition: ||
+    assert_eq 1 + 1, 2
+",
+        )
+        .unwrap();
+        fs::write(dir.join("not_a_test.txt"), "test_ignored").unwrap();
+        fs::write(dir.join("no_tests.koto"), "x = 1\n").unwrap();
+    }
+
+    fn run_koto_test(args: &[&str], dir: &Path) -> (bool, String) {
+        let output = Command::new(env!("CARGO_BIN_EXE_koto"))
+            .arg("test")
+            .args(args)
+            .arg(dir)
+            .output()
+            .expect("failed to execute child");
+
+        (
+            output.status.success(),
+            String::from_utf8_lossy(&output.stdout).to_string(),
+        )
+    }
+
+    #[test]
+    fn passing_tests() {
+        let dir = TempDir::new().unwrap();
+        write_scripts(dir.path());
+
+        let (success, stdout) = run_koto_test(&[], dir.path());
+
+        assert!(success, "{}", stdout);
+        assert!(stdout.contains("test uses_pre_test ... ok ("), "{}", stdout);
+        assert!(stdout.contains("test addition ... ok ("), "{}", stdout);
+        assert!(
+            stdout.contains("test result: ok. 2 passed; 0 failed; 0 filtered out"),
+            "{}",
+            stdout
+        );
+    }
+
+    #[test]
+    fn failing_test() {
+        let dir = TempDir::new().unwrap();
+        write_scripts(dir.path());
+        fs::write(
+            dir.path().join("failing.koto"),
+            "\
+import test.assert
+export tests =
+  test_failure: ||
+    assert false
+",
+        )
+        .unwrap();
+
+        let (success, stdout) = run_koto_test(&[], dir.path());
+
+        assert!(!success, "{}", stdout);
+        assert!(stdout.contains("test failure ... FAILED ("), "{}", stdout);
+        assert!(
+            stdout.contains("Error while running test 'failure'"),
+            "{}",
+            stdout
+        );
+        assert!(
+            stdout.contains("test result: FAILED. 2 passed; 1 failed; 0 filtered out"),
+            "{}",
+            stdout
+        );
+    }
+
+    #[test]
+    fn script_errors_count_as_failures() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("broken.koto"), "x = (1 + \n").unwrap();
+
+        let (success, stdout) = run_koto_test(&[], dir.path());
+
+        assert!(!success, "{}", stdout);
+        assert!(stdout.contains("broken.koto ... FAILED"), "{}", stdout);
+    }
+
+    #[test]
+    fn exported_main_isnt_called() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("with_main.koto"),
+            "\
+import test.assert
+export main = ||
+  print \"main was called\"
+  assert false
+export tests =
+  test_something: ||
+    assert true
+",
+        )
+        .unwrap();
+
+        let (success, stdout) = run_koto_test(&[], dir.path());
+
+        assert!(success, "{}", stdout);
+        assert!(!stdout.contains("main was called"), "{}", stdout);
+        assert!(stdout.contains("test something ... ok ("), "{}", stdout);
+        assert!(
+            stdout.contains("test result: ok. 1 passed; 0 failed; 0 filtered out"),
+            "{}",
+            stdout
+        );
+    }
+
+    #[test]
+    fn script_named_test_is_run() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("test"),
+            "import string.print\nprint \"running the test script\"\n",
+        )
+        .unwrap();
+
+        let output = Command::new(env!("CARGO_BIN_EXE_koto"))
+            .arg("test")
+            .current_dir(dir.path())
+            .output()
+            .expect("failed to execute child");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+
+        assert!(output.status.success(), "{}", stdout);
+        assert_eq!(stdout, "running the test script\n");
+    }
+
+    #[test]
+    fn junit_and_tap_reports() {
+        let dir = TempDir::new().unwrap();
+        write_scripts(dir.path());
+        fs::write(dir.path().join("broken.koto"), "x = (1 + \n").unwrap();
+        let junit_path = dir.path().join("report.xml");
+        let tap_path = dir.path().join("report.tap");
+
+        let (success, stdout) = run_koto_test(
+            &[
+                "--junit",
+                junit_path.to_str().unwrap(),
+                "--tap",
+                tap_path.to_str().unwrap(),
+            ],
+            dir.path(),
+        );
+        assert!(!success, "{}", stdout);
+
+        let junit = fs::read_to_string(&junit_path).unwrap();
+        assert!(
+            junit.contains(r#"<testsuites tests="3" failures="1""#),
+            "{}",
+            junit
+        );
+        assert!(junit.contains(r#"<testcase name="addition""#), "{}", junit);
+
+        let tap = fs::read_to_string(&tap_path).unwrap();
+        assert!(tap.starts_with("TAP version 13\n1..3\n"), "{}", tap);
+        assert!(tap.contains("broken.koto\n  ---\n"), "{}", tap);
+        assert!(tap.contains("ok 3 - addition"), "{}", tap);
+    }
+
+    #[test]
+    fn filter_by_name() {
+        let dir = TempDir::new().unwrap();
+        write_scripts(dir.path());
+
+        let (success, stdout) = run_koto_test(&["--filter", "addition"], dir.path());
+
+        assert!(success, "{}", stdout);
+        assert!(stdout.contains("test addition ... ok ("), "{}", stdout);
+        assert!(!stdout.contains("uses_pre_test"), "{}", stdout);
+        assert!(
+            stdout.contains("test result: ok. 1 passed; 0 failed; 1 filtered out"),
+            "{}",
+            stdout
+        );
+    }
+}
@@ -0,0 +1,32 @@
+use {
+    std::{fs, process::Command},
+    tempfile::TempDir,
+};
+
+mod warnings_tests {
+    use super::*;
+
+    #[test]
+    fn warnings_are_printed_with_excerpts() {
+        let dir = TempDir::new().unwrap();
+        let script_path = dir.path().join("test.koto");
+        fs::write(
+            &script_path,
+            "import string.print\nx = 1\ny = 2\nprint \"{}\", y\n",
+        )
+        .unwrap();
+
+        let output = Command::new(env!("CARGO_BIN_EXE_koto"))
+            .arg(&script_path)
+            .output()
+            .expect("failed to execute child");
+
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n");
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("Warning: Unused local value"), "{}", stderr);
+        assert!(stderr.contains("2 | x = 1"), "{}", stderr);
+        assert!(!stderr.contains("y = 2"), "{}", stderr);
+    }
+}
@@ -0,0 +1,169 @@
+use {
+    crate::{is_koto_attribute, parse_attributes, parse_nested_meta, runtime_path, KotoAttributes},
+    proc_macro2::TokenStream as TokenStream2,
+    quote::quote,
+    syn::{
+        AttributeArgs, Error, FnArg, ImplItem, ImplItemMethod, ItemImpl, LitStr, Path, Type,
+        Visibility,
+    },
+};
+
+// Generates the implementation for the `#[koto_impl]` attribute, see the docs in lib.rs
+pub fn expand(args: AttributeArgs, mut item: ItemImpl) -> syn::Result<TokenStream2> {
+    let mut attributes = KotoAttributes::default();
+    for nested in args.iter() {
+        parse_nested_meta(nested, &mut attributes)?;
+    }
+
+    if attributes.skip {
+        return Err(Error::new_spanned(&item.self_ty, crate::SKIP_UNSUPPORTED));
+    }
+    if let Some((_, path, _)) = &item.trait_ {
+        return Err(Error::new_spanned(
+            path,
+            "koto_impl can't be used with trait implementations",
+        ));
+    }
+    if !item.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &item.generics,
+            "koto_impl doesn't support generic types",
+        ));
+    }
+
+    let runtime = runtime_path(&attributes);
+    let self_type = item.self_ty.clone();
+    let type_name = match attributes.rename {
+        Some(name) => name,
+        None => type_name(&self_type)?,
+    };
+
+    let mut functions = Vec::new();
+
+    for impl_item in item.items.iter_mut() {
+        if let ImplItem::Method(method) = impl_item {
+            let method_attributes = parse_attributes(&method.attrs)?;
+            method
+                .attrs
+                .retain(|attribute| !is_koto_attribute(attribute));
+
+            if method_attributes.skip || !matches!(method.vis, Visibility::Public(_)) {
+                continue;
+            }
+
+            match method.sig.inputs.first() {
+                Some(FnArg::Receiver(receiver)) if receiver.reference.is_none() => {
+                    return Err(Error::new_spanned(
+                        receiver,
+                        "koto_impl methods need to take &self or &mut self, \
+                         use #[koto(skip)] to leave the method out",
+                    ));
+                }
+                Some(FnArg::Receiver(_)) => {}
+                // Associated functions without a receiver aren't exposed to Koto
+                _ => continue,
+            }
+
+            functions.push(instance_function(
+                &runtime,
+                &self_type,
+                &type_name,
+                method,
+                method_attributes.rename,
+            )?);
+        }
+    }
+
+    let type_name_lit = LitStr::new(&type_name, proc_macro2::Span::call_site());
+
+    Ok(quote! {
+        #item
+
+        impl #self_type {
+            /// Makes a map containing the value, with the type's methods as instance functions
+            pub fn into_value_map(self) -> #runtime::ValueMap {
+                let mut result = #runtime::ValueMap::new();
+
+                #(#functions)*
+
+                result.insert(
+                    #runtime::Value::ExternalDataId.into(),
+                    #runtime::Value::make_external_value(self),
+                );
+
+                result
+            }
+        }
+
+        impl #runtime::ExternalValue for #self_type {
+            fn value_type(&self) -> String {
+                #type_name_lit.to_string()
+            }
+        }
+
+        impl #runtime::IntoKoto for #self_type {
+            fn into_koto(self) -> #runtime::Value {
+                #runtime::Value::Map(self.into_value_map())
+            }
+        }
+    })
+}
+
+// Makes the statement that adds an instance function for the method to the value map
+fn instance_function(
+    runtime: &Path,
+    self_type: &Type,
+    type_name: &str,
+    method: &ImplItemMethod,
+    rename: Option<String>,
+) -> syn::Result<TokenStream2> {
+    let method_ident = &method.sig.ident;
+    let name = rename.unwrap_or_else(|| method_ident.to_string());
+    let full_name = format!("{}.{}", type_name, name);
+
+    let arg_types = method
+        .sig
+        .inputs
+        .iter()
+        .skip(1)
+        .map(|arg| match arg {
+            FnArg::Typed(arg) => Ok(&*arg.ty),
+            FnArg::Receiver(receiver) => Err(Error::new_spanned(receiver, "unexpected receiver")),
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+    let arg_count = arg_types.len();
+    let indices = 0..arg_count;
+
+    Ok(quote! {
+        result.add_instance_fn(#name, |vm, args| match vm.get_args(args) {
+            [#runtime::Value::Map(instance), args @ ..] => {
+                #runtime::typed_function::check_arg_count(#full_name, args, #arg_count)?;
+                #runtime::visit_external_value(instance, |instance: &mut #self_type| {
+                    #runtime::IntoKotoResult::into_koto_result(instance.#method_ident(
+                        #(#runtime::typed_function::convert_arg::<#arg_types>(
+                            #full_name,
+                            args,
+                            #indices,
+                            #arg_count,
+                        )?),*
+                    ))
+                })
+            }
+            _ => Err(#runtime::typed_function::instance_error(#full_name, #type_name)),
+        });
+    })
+}
+
+// The name of the type as written in the impl block, without any path prefix
+fn type_name(self_type: &Type) -> syn::Result<String> {
+    match self_type {
+        Type::Path(type_path) => match type_path.path.segments.last() {
+            Some(segment) => Ok(segment.ident.to_string()),
+            None => Err(Error::new_spanned(self_type, "expected a type name")),
+        },
+        other => Err(Error::new_spanned(
+            other,
+            "koto_impl only supports named types",
+        )),
+    }
+}
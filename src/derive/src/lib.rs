@@ -0,0 +1,434 @@
+//! Macros for converting between Rust types and Koto values
+//!
+//! The derived implementations are described in the documentation for the `koto_runtime`
+//! crate's `conversion` module.
+
+mod koto_impl;
+
+use {
+    proc_macro::TokenStream,
+    proc_macro2::{Span, TokenStream as TokenStream2},
+    quote::quote,
+    syn::{
+        parse_macro_input, parse_quote, Attribute, AttributeArgs, Data, DataEnum, DeriveInput,
+        Error, Fields, Generics, Ident, ItemImpl, Lit, LitStr, Meta, NestedMeta, Path,
+    },
+};
+
+/// Derives `FromKoto` for a struct or enum
+#[proc_macro_derive(FromKoto, attributes(koto))]
+pub fn derive_from_koto(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    derive_from(input)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
+}
+
+/// Derives `IntoKoto` for a struct or enum
+#[proc_macro_derive(IntoKoto, attributes(koto))]
+pub fn derive_into_koto(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    derive_into(input)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
+}
+
+/// Exposes a Rust type to Koto as an object backed by an `ExternalValue`
+///
+/// Applied to an `impl` block, a method is generated named `into_value_map`, which makes a
+/// `ValueMap` containing the value, along with an instance function for each of the block's public
+/// methods that take `&self` or `&mut self`. Method arguments and return values are converted via
+/// `FromKoto` and `IntoKotoResult`.
+///
+/// `ExternalValue` and `IntoKoto` are implemented for the type, with the type needing to implement
+/// `Debug` and `Display`.
+///
+/// The following attributes are accepted:
+///   - `#[koto_impl(rename = "Name")]`, to set the type's name in Koto.
+///   - `#[koto_impl(crate = "path")]`, to set the path to the runtime crate.
+///   - `#[koto(rename = "name")]` on a method, to use a different name in Koto.
+///   - `#[koto(skip)]` on a method, to leave it out of the generated map.
+#[proc_macro_attribute]
+pub fn koto_impl(attribute: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attribute as AttributeArgs);
+    let item = parse_macro_input!(item as ItemImpl);
+    koto_impl::expand(args, item)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
+}
+
+// The options that can be set with `#[koto(...)]` attributes
+#[derive(Default)]
+struct KotoAttributes {
+    // The name to use in Koto for a field or variant
+    rename: Option<String>,
+    // The path to the runtime crate
+    runtime_crate: Option<Path>,
+    // True if a method should be left out of a `koto_impl` map
+    skip: bool,
+}
+
+fn parse_attributes(attributes: &[Attribute]) -> syn::Result<KotoAttributes> {
+    let mut result = KotoAttributes::default();
+
+    for attribute in attributes.iter().filter(|a| is_koto_attribute(a)) {
+        let list = match attribute.parse_meta()? {
+            Meta::List(list) => list,
+            other => return Err(Error::new_spanned(other, "expected #[koto(...)]")),
+        };
+
+        for nested in list.nested.iter() {
+            parse_nested_meta(nested, &mut result)?;
+        }
+    }
+
+    Ok(result)
+}
+
+fn is_koto_attribute(attribute: &Attribute) -> bool {
+    attribute.path.is_ident("koto")
+}
+
+fn parse_nested_meta(nested: &NestedMeta, result: &mut KotoAttributes) -> syn::Result<()> {
+    match nested {
+        NestedMeta::Meta(Meta::NameValue(name_value)) => {
+            let value = match &name_value.lit {
+                Lit::Str(value) => value,
+                other => return Err(Error::new_spanned(other, "expected a string")),
+            };
+
+            if name_value.path.is_ident("rename") {
+                result.rename = Some(value.value());
+            } else if name_value.path.is_ident("crate") {
+                result.runtime_crate = Some(value.parse()?);
+            } else {
+                return Err(Error::new_spanned(
+                    &name_value.path,
+                    "unknown koto attribute",
+                ));
+            }
+        }
+        NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip") => result.skip = true,
+        other => return Err(Error::new_spanned(other, "unknown koto attribute")),
+    }
+
+    Ok(())
+}
+
+const SKIP_UNSUPPORTED: &str = "skip is only supported for methods in koto_impl blocks";
+
+fn runtime_path(attributes: &KotoAttributes) -> Path {
+    attributes
+        .runtime_crate
+        .clone()
+        .unwrap_or_else(|| parse_quote!(::koto_runtime))
+}
+
+// Adds the trait as a bound to each of the type's generic parameters
+fn add_bounds(mut generics: Generics, bound: &Path) -> Generics {
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(#bound));
+    }
+    generics
+}
+
+// The names of a struct's or variant's fields in Koto, or None for unnamed fields
+fn field_names(fields: &Fields) -> syn::Result<Vec<Option<LitStr>>> {
+    fields
+        .iter()
+        .map(|field| {
+            let attributes = parse_attributes(&field.attrs)?;
+            if attributes.skip {
+                return Err(Error::new_spanned(field, SKIP_UNSUPPORTED));
+            }
+            Ok(field.ident.as_ref().map(|ident| {
+                let name = attributes.rename.unwrap_or_else(|| ident.to_string());
+                LitStr::new(&name, ident.span())
+            }))
+        })
+        .collect()
+}
+
+fn variant_name(variant: &syn::Variant) -> syn::Result<LitStr> {
+    let attributes = parse_attributes(&variant.attrs)?;
+    if attributes.skip {
+        return Err(Error::new_spanned(variant, SKIP_UNSUPPORTED));
+    }
+    let name = attributes
+        .rename
+        .unwrap_or_else(|| variant.ident.to_string());
+    Ok(LitStr::new(&name, variant.ident.span()))
+}
+
+fn derive_from(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let runtime = runtime_path(&parse_attributes(&input.attrs)?);
+    let name = &input.ident;
+    let generics = add_bounds(input.generics.clone(), &parse_quote!(#runtime::FromKoto));
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(data) => from_fields(&runtime, quote!(Self), &data.fields, quote!(value))?,
+        Data::Enum(data) => from_enum(&runtime, data)?,
+        Data::Union(_) => {
+            return Err(Error::new_spanned(
+                name,
+                "FromKoto can't be derived for unions",
+            ))
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics #runtime::FromKoto for #name #type_generics #where_clause {
+            fn from_koto(
+                value: &#runtime::Value,
+            ) -> ::std::result::Result<Self, #runtime::ConversionError> {
+                #body
+            }
+        }
+    })
+}
+
+// Produces an expression that converts `value` into a struct or variant with the given fields
+fn from_fields(
+    runtime: &Path,
+    constructor: TokenStream2,
+    fields: &Fields,
+    value: TokenStream2,
+) -> syn::Result<TokenStream2> {
+    let result = match fields {
+        Fields::Named(named) => {
+            let idents = named.named.iter().map(|field| &field.ident);
+            let names = field_names(fields)?;
+            quote! {
+                match #value {
+                    #runtime::Value::Map(map) => Ok(#constructor {
+                        #(
+                            #idents: #runtime::conversion::from_map_entry(map, #names)?,
+                        )*
+                    }),
+                    unexpected => Err(#runtime::ConversionError::new("Map", unexpected)),
+                }
+            }
+        }
+        Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => {
+            quote! { Ok(#constructor(#runtime::FromKoto::from_koto(#value)?)) }
+        }
+        Fields::Unnamed(unnamed) => {
+            let size = unnamed.unnamed.len();
+            let indices = 0..size;
+            quote! {{
+                let values = #runtime::conversion::sequence_values(#value, #size)?;
+                Ok(#constructor(
+                    #(#runtime::conversion::from_element(&values, #indices)?),*
+                ))
+            }}
+        }
+        Fields::Unit => quote! {
+            match #value {
+                #runtime::Value::Empty => Ok(#constructor),
+                unexpected => Err(#runtime::ConversionError::new("Empty", unexpected)),
+            }
+        },
+    };
+
+    Ok(result)
+}
+
+fn from_enum(runtime: &Path, data: &DataEnum) -> syn::Result<TokenStream2> {
+    let mut all_names = Vec::new();
+    let mut unit_arms = Vec::new();
+    let mut data_arms = Vec::new();
+
+    for variant in data.variants.iter() {
+        let ident = &variant.ident;
+        let name = variant_name(variant)?;
+
+        if let Fields::Unit = variant.fields {
+            unit_arms.push(quote! { #name => Ok(Self::#ident), });
+        } else {
+            let conversion =
+                from_fields(runtime, quote!(Self::#ident), &variant.fields, quote!(data))?;
+            data_arms.push(quote! {
+                #name => (|| -> ::std::result::Result<Self, #runtime::ConversionError> {
+                    #conversion
+                })()
+                .map_err(|e| e.with_field(#name)),
+            });
+        }
+
+        all_names.push(name);
+    }
+
+    let unknown_variant = quote! {
+        other => Err(#runtime::conversion::unknown_variant(other, &[#(#all_names),*])),
+    };
+
+    let string_arm = if unit_arms.is_empty() {
+        quote! {}
+    } else {
+        quote! {
+            #runtime::Value::Str(s) => match s.as_str() {
+                #(#unit_arms)*
+                #unknown_variant
+            },
+        }
+    };
+
+    let map_arm = if data_arms.is_empty() {
+        quote! {}
+    } else {
+        quote! {
+            #runtime::Value::Map(map) if map.len() == 1 => {
+                let (key, data) = map.cloned_iter().next().unwrap();
+                let data = &data;
+                match key.to_string().as_str() {
+                    #(#data_arms)*
+                    #unknown_variant
+                }
+            }
+        }
+    };
+
+    let expected = match (unit_arms.is_empty(), data_arms.is_empty()) {
+        (false, true) => "String",
+        (true, false) => "Map with a single entry",
+        _ => "String, or Map with a single entry",
+    };
+
+    Ok(quote! {
+        match value {
+            #string_arm
+            #map_arm
+            unexpected => Err(#runtime::ConversionError::new(#expected, unexpected)),
+        }
+    })
+}
+
+fn derive_into(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let runtime = runtime_path(&parse_attributes(&input.attrs)?);
+    let name = &input.ident;
+    let generics = add_bounds(input.generics.clone(), &parse_quote!(#runtime::IntoKoto));
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let accessors: Vec<TokenStream2> = match &data.fields {
+                Fields::Named(named) => named
+                    .named
+                    .iter()
+                    .map(|field| {
+                        let ident = &field.ident;
+                        quote!(self.#ident)
+                    })
+                    .collect(),
+                fields => (0..fields.len())
+                    .map(|index| {
+                        let index = syn::Index::from(index);
+                        quote!(self.#index)
+                    })
+                    .collect(),
+            };
+            into_fields(&runtime, &data.fields, &accessors)?
+        }
+        Data::Enum(data) => into_enum(&runtime, data)?,
+        Data::Union(_) => {
+            return Err(Error::new_spanned(
+                name,
+                "IntoKoto can't be derived for unions",
+            ))
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics #runtime::IntoKoto for #name #type_generics #where_clause {
+            fn into_koto(self) -> #runtime::Value {
+                #body
+            }
+        }
+    })
+}
+
+// Produces an expression that converts the fields of a struct or variant into a Value
+//
+// The accessors are the expressions that produce each field's value.
+fn into_fields(
+    runtime: &Path,
+    fields: &Fields,
+    accessors: &[TokenStream2],
+) -> syn::Result<TokenStream2> {
+    let result = match fields {
+        Fields::Named(_) => {
+            let names = field_names(fields)?;
+            let size = accessors.len();
+            quote! {{
+                #[allow(unused_mut)]
+                let mut __koto_map = #runtime::ValueMap::with_capacity(#size);
+                #(
+                    __koto_map.add_value(#names, #runtime::IntoKoto::into_koto(#accessors));
+                )*
+                #runtime::Value::Map(__koto_map)
+            }}
+        }
+        Fields::Unnamed(_) if accessors.len() == 1 => {
+            let accessor = &accessors[0];
+            quote! { #runtime::IntoKoto::into_koto(#accessor) }
+        }
+        Fields::Unnamed(_) => quote! {
+            #runtime::Value::Tuple(
+                vec![#(#runtime::IntoKoto::into_koto(#accessors)),*].into()
+            )
+        },
+        Fields::Unit => quote! { #runtime::Value::Empty },
+    };
+
+    Ok(result)
+}
+
+fn into_enum(runtime: &Path, data: &DataEnum) -> syn::Result<TokenStream2> {
+    let mut arms = Vec::new();
+
+    for variant in data.variants.iter() {
+        let ident = &variant.ident;
+        let name = variant_name(variant)?;
+
+        let bindings: Vec<Ident> = (0..variant.fields.len())
+            .map(|index| Ident::new(&format!("__field{}", index), Span::call_site()))
+            .collect();
+        let accessors: Vec<TokenStream2> = bindings.iter().map(|b| quote!(#b)).collect();
+
+        let arm = match &variant.fields {
+            Fields::Named(named) => {
+                let idents = named.named.iter().map(|field| &field.ident);
+                let value = into_fields(runtime, &variant.fields, &accessors)?;
+                quote! {
+                    Self::#ident { #(#idents: #bindings),* } => {
+                        let mut __koto_map = #runtime::ValueMap::with_capacity(1);
+                        __koto_map.add_value(#name, #value);
+                        #runtime::Value::Map(__koto_map)
+                    }
+                }
+            }
+            Fields::Unnamed(_) => {
+                let value = into_fields(runtime, &variant.fields, &accessors)?;
+                quote! {
+                    Self::#ident(#(#bindings),*) => {
+                        let mut __koto_map = #runtime::ValueMap::with_capacity(1);
+                        __koto_map.add_value(#name, #value);
+                        #runtime::Value::Map(__koto_map)
+                    }
+                }
+            }
+            Fields::Unit => quote! {
+                Self::#ident => #runtime::Value::Str(#name.into()),
+            },
+        };
+
+        arms.push(arm);
+    }
+
+    Ok(quote! {
+        match self {
+            #(#arms)*
+        }
+    })
+}
@@ -0,0 +1,345 @@
+use koto_parser::{Ast, AstIndex, ConstantIndex, ConstantPool, LookupNode, MapKey, Node};
+
+/// A script's AST, along with its constants
+pub struct ParsedScript<'a> {
+    pub ast: &'a Ast,
+    pub constants: &'a ConstantPool,
+}
+
+/// Checks that two scripts have equivalent ASTs
+///
+/// Spans are ignored, and constants are compared by value.
+pub fn scripts_match(a: &ParsedScript, b: &ParsedScript) -> bool {
+    let comparison = Comparison { a, b };
+    match (a.ast.entry_point(), b.ast.entry_point()) {
+        (Some(_), Some(_)) => comparison.nodes_match(entry_index(a.ast), entry_index(b.ast)),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+fn entry_index(ast: &Ast) -> AstIndex {
+    // The entry point is the main block, which is always the last node to be added
+    (ast.nodes().len() - 1) as AstIndex
+}
+
+struct Comparison<'a> {
+    a: &'a ParsedScript<'a>,
+    b: &'a ParsedScript<'a>,
+}
+
+impl<'a> Comparison<'a> {
+    fn nodes_match(&self, a: AstIndex, b: AstIndex) -> bool {
+        use Node::*;
+
+        match (&self.a.ast.node(a).node, &self.b.ast.node(b).node) {
+            (Empty, Empty)
+            | (BoolTrue, BoolTrue)
+            | (BoolFalse, BoolFalse)
+            | (Number0, Number0)
+            | (Number1, Number1)
+            | (RangeFull, RangeFull)
+            | (Wildcard, Wildcard)
+            | (Break, Break)
+            | (Continue, Continue)
+            | (Return, Return) => true,
+            (Id(a), Id(b)) | (Str(a), Str(b)) => self.strings_match(*a, *b),
+            (Int(a), Int(b)) => self.a.constants.get_i64(*a) == self.b.constants.get_i64(*b),
+            (Float(a), Float(b)) => {
+                self.a.constants.get_f64(*a).to_bits() == self.b.constants.get_f64(*b).to_bits()
+            }
+            (Lookup((a_node, a_next)), Lookup((b_node, b_next))) => {
+                let lookup_nodes_match = match (a_node, b_node) {
+                    (LookupNode::Root(a), LookupNode::Root(b)) => self.nodes_match(*a, *b),
+                    (LookupNode::Id(a), LookupNode::Id(b)) => self.strings_match(*a, *b),
+                    (LookupNode::Index(a), LookupNode::Index(b)) => self.nodes_match(*a, *b),
+                    (LookupNode::Call(a), LookupNode::Call(b)) => self.node_lists_match(a, b),
+                    _ => false,
+                };
+                lookup_nodes_match && self.optional_nodes_match(*a_next, *b_next)
+            }
+            (Num2(a), Num2(b))
+            | (Num4(a), Num4(b))
+            | (List(a), List(b))
+            | (Tuple(a), Tuple(b))
+            | (TempTuple(a), TempTuple(b))
+            | (Block(a), Block(b)) => self.node_lists_match(a, b),
+            (
+                Range {
+                    start: a_start,
+                    end: a_end,
+                    inclusive: a_inclusive,
+                },
+                Range {
+                    start: b_start,
+                    end: b_end,
+                    inclusive: b_inclusive,
+                },
+            ) => {
+                a_inclusive == b_inclusive
+                    && self.nodes_match(*a_start, *b_start)
+                    && self.nodes_match(*a_end, *b_end)
+            }
+            (RangeFrom { start: a }, RangeFrom { start: b }) => self.nodes_match(*a, *b),
+            (
+                RangeTo {
+                    end: a_end,
+                    inclusive: a_inclusive,
+                },
+                RangeTo {
+                    end: b_end,
+                    inclusive: b_inclusive,
+                },
+            ) => a_inclusive == b_inclusive && self.nodes_match(*a_end, *b_end),
+            (Map(a), Map(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b.iter())
+                        .all(|((a_key, a_value), (b_key, b_value))| {
+                            let keys_match = match (a_key, b_key) {
+                                (MapKey::Id(a), MapKey::Id(b)) => self.strings_match(*a, *b),
+                                (MapKey::Meta(a), MapKey::Meta(b)) => a == b,
+                                _ => false,
+                            };
+                            keys_match && self.optional_nodes_match(*a_value, *b_value)
+                        })
+            }
+            (
+                MainBlock {
+                    body: a_body,
+                    local_count: a_local_count,
+                },
+                MainBlock {
+                    body: b_body,
+                    local_count: b_local_count,
+                },
+            ) => a_local_count == b_local_count && self.node_lists_match(a_body, b_body),
+            (Function(a), Function(b)) => {
+                // The non-local IDs are gathered in a HashSet, so their order isn't meaningful
+                let mut a_non_locals = self.a_strings(&a.accessed_non_locals);
+                let mut b_non_locals = self.b_strings(&b.accessed_non_locals);
+                a_non_locals.sort_unstable();
+                b_non_locals.sort_unstable();
+
+                a.local_count == b.local_count
+                    && a.is_instance_function == b.is_instance_function
+                    && a.is_variadic == b.is_variadic
+                    && a.is_generator == b.is_generator
+                    && a_non_locals == b_non_locals
+                    && self.node_lists_match(&a.args, &b.args)
+                    && self.nodes_match(a.body, b.body)
+            }
+            (
+                Call {
+                    function: a_function,
+                    args: a_args,
+                },
+                Call {
+                    function: b_function,
+                    args: b_args,
+                },
+            ) => {
+                self.nodes_match(*a_function, *b_function) && self.node_lists_match(a_args, b_args)
+            }
+            (
+                Import {
+                    from: a_from,
+                    items: a_items,
+                },
+                Import {
+                    from: b_from,
+                    items: b_items,
+                },
+            ) => {
+                self.a_strings(a_from) == self.b_strings(b_from)
+                    && a_items.len() == b_items.len()
+                    && a_items
+                        .iter()
+                        .zip(b_items.iter())
+                        .all(|(a, b)| self.a_strings(a) == self.b_strings(b))
+            }
+            (
+                Assign {
+                    target: a_target,
+                    op: a_op,
+                    expression: a_expression,
+                },
+                Assign {
+                    target: b_target,
+                    op: b_op,
+                    expression: b_expression,
+                },
+            ) => {
+                a_op == b_op
+                    && a_target.scope == b_target.scope
+                    && self.nodes_match(a_target.target_index, b_target.target_index)
+                    && self.nodes_match(*a_expression, *b_expression)
+            }
+            (
+                MultiAssign {
+                    targets: a_targets,
+                    expression: a_expression,
+                },
+                MultiAssign {
+                    targets: b_targets,
+                    expression: b_expression,
+                },
+            ) => {
+                a_targets.len() == b_targets.len()
+                    && a_targets.iter().zip(b_targets.iter()).all(|(a, b)| {
+                        a.scope == b.scope && self.nodes_match(a.target_index, b.target_index)
+                    })
+                    && self.nodes_match(*a_expression, *b_expression)
+            }
+            (
+                BinaryOp {
+                    op: a_op,
+                    lhs: a_lhs,
+                    rhs: a_rhs,
+                },
+                BinaryOp {
+                    op: b_op,
+                    lhs: b_lhs,
+                    rhs: b_rhs,
+                },
+            ) => {
+                a_op == b_op && self.nodes_match(*a_lhs, *b_lhs) && self.nodes_match(*a_rhs, *b_rhs)
+            }
+            (If(a), If(b)) => {
+                self.nodes_match(a.condition, b.condition)
+                    && self.nodes_match(a.then_node, b.then_node)
+                    && a.else_if_blocks.len() == b.else_if_blocks.len()
+                    && a.else_if_blocks.iter().zip(b.else_if_blocks.iter()).all(
+                        |((a_condition, a_block), (b_condition, b_block))| {
+                            self.nodes_match(*a_condition, *b_condition)
+                                && self.nodes_match(*a_block, *b_block)
+                        },
+                    )
+                    && self.optional_nodes_match(a.else_node, b.else_node)
+            }
+            (
+                Match {
+                    expression: a_expression,
+                    arms: a_arms,
+                },
+                Match {
+                    expression: b_expression,
+                    arms: b_arms,
+                },
+            ) => {
+                self.nodes_match(*a_expression, *b_expression)
+                    && a_arms.len() == b_arms.len()
+                    && a_arms.iter().zip(b_arms.iter()).all(|(a, b)| {
+                        self.node_lists_match(&a.patterns, &b.patterns)
+                            && self.optional_nodes_match(a.condition, b.condition)
+                            && self.nodes_match(a.expression, b.expression)
+                    })
+            }
+            (Switch(a), Switch(b)) => {
+                a.len() == b.len()
+                    && a.iter().zip(b.iter()).all(|(a, b)| {
+                        self.optional_nodes_match(a.condition, b.condition)
+                            && self.nodes_match(a.expression, b.expression)
+                    })
+            }
+            (Ellipsis(a), Ellipsis(b)) => match (a, b) {
+                (Some(a), Some(b)) => self.strings_match(*a, *b),
+                (None, None) => true,
+                _ => false,
+            },
+            (For(a), For(b)) => {
+                a.args.len() == b.args.len()
+                    && a.args.iter().zip(b.args.iter()).all(|(a, b)| match (a, b) {
+                        (Some(a), Some(b)) => self.strings_match(*a, *b),
+                        (None, None) => true,
+                        _ => false,
+                    })
+                    && self.nodes_match(a.range, b.range)
+                    && self.nodes_match(a.body, b.body)
+            }
+            (Loop { body: a }, Loop { body: b }) => self.nodes_match(*a, *b),
+            (
+                While {
+                    condition: a_condition,
+                    body: a_body,
+                },
+                While {
+                    condition: b_condition,
+                    body: b_body,
+                },
+            )
+            | (
+                Until {
+                    condition: a_condition,
+                    body: a_body,
+                },
+                Until {
+                    condition: b_condition,
+                    body: b_body,
+                },
+            ) => self.nodes_match(*a_condition, *b_condition) && self.nodes_match(*a_body, *b_body),
+            (ReturnExpression(a), ReturnExpression(b))
+            | (Negate(a), Negate(b))
+            | (Throw(a), Throw(b))
+            | (Yield(a), Yield(b)) => self.nodes_match(*a, *b),
+            (Try(a), Try(b)) => {
+                let catch_args_match = match (a.catch_arg, b.catch_arg) {
+                    (Some(a), Some(b)) => self.strings_match(a, b),
+                    (None, None) => true,
+                    _ => false,
+                };
+                catch_args_match
+                    && self.nodes_match(a.try_block, b.try_block)
+                    && self.nodes_match(a.catch_block, b.catch_block)
+                    && self.optional_nodes_match(a.finally_block, b.finally_block)
+            }
+            (
+                Debug {
+                    expression_string: a_string,
+                    expression: a_expression,
+                },
+                Debug {
+                    expression_string: b_string,
+                    expression: b_expression,
+                },
+            ) => {
+                self.strings_match(*a_string, *b_string)
+                    && self.nodes_match(*a_expression, *b_expression)
+            }
+            _ => false,
+        }
+    }
+
+    fn node_lists_match(&self, a: &[AstIndex], b: &[AstIndex]) -> bool {
+        a.len() == b.len()
+            && a.iter()
+                .zip(b.iter())
+                .all(|(a, b)| self.nodes_match(*a, *b))
+    }
+
+    fn optional_nodes_match(&self, a: Option<AstIndex>, b: Option<AstIndex>) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => self.nodes_match(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    fn strings_match(&self, a: ConstantIndex, b: ConstantIndex) -> bool {
+        self.a.constants.get_str(a) == self.b.constants.get_str(b)
+    }
+
+    fn a_strings(&self, indices: &[ConstantIndex]) -> Vec<&str> {
+        indices
+            .iter()
+            .map(|index| self.a.constants.get_str(*index))
+            .collect()
+    }
+
+    fn b_strings(&self, indices: &[ConstantIndex]) -> Vec<&str> {
+        indices
+            .iter()
+            .map(|index| self.b.constants.get_str(*index))
+            .collect()
+    }
+}
@@ -0,0 +1,1453 @@
+use {
+    crate::source::{is_before, SourceInfo},
+    koto_lexer::{is_id_continue, is_id_start, Position, Span, Token},
+    koto_parser::{
+        AssignOp, Ast, AstFor, AstIf, AstIndex, AstOp, AstTry, ConstantIndex, ConstantPool,
+        Function, LookupNode, MapKey, MatchArm, MetaId, Node, Scope, SwitchArm,
+    },
+};
+
+const INDENT: &str = "  ";
+const MAX_LINE_LENGTH: usize = 100;
+
+const KEYWORDS: &[&str] = &[
+    "and", "break", "catch", "continue", "debug", "else", "export", "false", "finally", "for",
+    "from", "if", "import", "in", "loop", "match", "not", "num2", "num4", "or", "return", "switch",
+    "then", "throw", "true", "try", "until", "while", "yield",
+];
+
+// The position of an expression relative to what follows it
+//
+// Koto's grammar allows some expressions to absorb whatever follows them, e.g. `f x, y` is a call
+// with two arguments, so the position determines whether or not parentheses are needed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Pos {
+    // The expression finishes the line, so bare tuples and indented blocks are allowed
+    Line,
+    // The expression is the last part of an enclosing expression
+    End,
+    // The expression is followed by a comma
+    Comma,
+    // The expression is followed by a keyword, e.g. `then`
+    Keyword,
+    // The expression is used as an index, e.g. `x[i]`
+    Index,
+    // The expression is the operand of an operator
+    Operator,
+    // The expression is the root of a lookup, e.g. `(a + b).foo()`
+    Postfix,
+}
+
+impl Pos {
+    // The position of an expression's last part
+    fn last(self) -> Self {
+        match self {
+            Pos::Line | Pos::End => Pos::End,
+            other => other,
+        }
+    }
+
+    // The position of a trailing expression that's allowed to finish the line
+    fn trailing(self) -> Self {
+        match self {
+            Pos::Line => Pos::Line,
+            _ => Pos::End,
+        }
+    }
+}
+
+// An item that's placed on its own line
+#[derive(Clone, Copy)]
+enum Line<'a> {
+    Statement(AstIndex),
+    MapEntry(&'a (MapKey, Option<AstIndex>)),
+    MatchArm(&'a MatchArm),
+    SwitchArm(&'a SwitchArm),
+}
+
+pub struct Formatter<'a> {
+    ast: &'a Ast,
+    constants: &'a ConstantPool,
+    source: &'a SourceInfo<'a>,
+    comment_emitted: Vec<bool>,
+    output: String,
+    indent: usize,
+    // The source line of the last comment that was written
+    last_comment_line: u32,
+    // When true, lists and maps that don't fit on a line are broken over multiple lines
+    allow_wrapping: bool,
+}
+
+impl<'a> Formatter<'a> {
+    pub fn new(
+        ast: &'a Ast,
+        constants: &'a ConstantPool,
+        source: &'a SourceInfo<'a>,
+        allow_wrapping: bool,
+    ) -> Self {
+        Self {
+            ast,
+            constants,
+            source,
+            comment_emitted: vec![false; source.comments.len()],
+            output: String::new(),
+            indent: 0,
+            last_comment_line: 0,
+            allow_wrapping,
+        }
+    }
+
+    // Returns the formatted script, or None if the AST doesn't cover the whole source
+    pub fn format(mut self) -> Option<String> {
+        let body = match self.ast.entry_point().map(|entry| &entry.node) {
+            Some(Node::MainBlock { body, .. }) => body.as_slice(),
+            _ => &[],
+        };
+        let lines = self.statement_lines(body);
+
+        // Formatting the script would lose any code that the parser didn't include in the AST
+        if let Some(last_token) = self.source.tokens.last() {
+            match lines.last() {
+                Some((_, extent)) if !is_before(extent.end, last_token.span.end) => {}
+                _ => return None,
+            }
+        }
+
+        self.lines(&lines, false);
+
+        // Each line starts with a newline, so the first one can be removed
+        let mut result = self.output.trim_start_matches('\n').to_string();
+        if !result.is_empty() {
+            result.push('\n');
+        }
+        Some(result)
+    }
+
+    fn node(&self, index: AstIndex) -> &'a Node {
+        let ast: &'a Ast = self.ast;
+        &ast.node(index).node
+    }
+
+    fn span(&self, index: AstIndex) -> Span {
+        *self.ast.span(self.ast.node(index).span)
+    }
+
+    // Returns the span in the source that's covered by a node and its children
+    fn extent(&self, index: AstIndex) -> Span {
+        let span = self.span(index);
+        let mut start = self.source.snap_forward(span.start);
+        let mut end = self.source.snap_back(span.end);
+        if is_before(end, start) {
+            end = start;
+        }
+
+        let node = self.node(index);
+        for child in children(node) {
+            let child_extent = self.extent(child);
+            if is_before(child_extent.start, start) {
+                start = child_extent.start;
+            }
+            if is_before(end, child_extent.end) {
+                end = child_extent.end;
+            }
+        }
+
+        // Some nodes don't include their leading keyword in their spans
+        let leading_tokens = leading_tokens(node);
+        if !leading_tokens.is_empty() {
+            let preceding = &self.source.tokens[..self.source.token_index_at_or_after(start)];
+            if let Some(token) = preceding
+                .iter()
+                .rev()
+                .find(|token| leading_tokens.contains(&token.token))
+            {
+                start = token.span.start;
+            }
+        }
+
+        Span { start, end }
+    }
+
+    fn statement_lines(&self, statements: &[AstIndex]) -> Vec<(Line<'a>, Span)> {
+        statements
+            .iter()
+            .map(|statement| (Line::Statement(*statement), self.extent(*statement)))
+            .collect()
+    }
+
+    fn map_entry_lines(
+        &self,
+        map: AstIndex,
+        entries: &'a [(MapKey, Option<AstIndex>)],
+    ) -> Vec<(Line<'a>, Span)> {
+        // Map keys don't have spans, so the entries are found by stepping through the tokens
+        let mut position = self.span(map).start;
+        entries
+            .iter()
+            .map(|entry| {
+                let key_token = self
+                    .source
+                    .tokens
+                    .get(self.source.token_index_at_or_after(position));
+                let start = key_token.map_or(position, |token| token.span.start);
+                let end = match entry.1 {
+                    Some(value) => self.extent(value).end,
+                    None => key_token.map_or(position, |token| token.span.end),
+                };
+                position = end;
+                (Line::MapEntry(entry), Span { start, end })
+            })
+            .collect()
+    }
+
+    fn match_arm_lines(&self, arms: &'a [MatchArm]) -> Vec<(Line<'a>, Span)> {
+        arms.iter()
+            .map(|arm| {
+                let start = match (arm.patterns.first(), arm.condition) {
+                    (Some(pattern), _) => self.extent(*pattern).start,
+                    (None, Some(condition)) => self.keyword_start(condition),
+                    (None, None) => self.keyword_start(arm.expression),
+                };
+                let end = self.extent(arm.expression).end;
+                (Line::MatchArm(arm), Span { start, end })
+            })
+            .collect()
+    }
+
+    fn switch_arm_lines(&self, arms: &'a [SwitchArm]) -> Vec<(Line<'a>, Span)> {
+        arms.iter()
+            .map(|arm| {
+                let start = match arm.condition {
+                    Some(condition) => self.extent(condition).start,
+                    None => self.keyword_start(arm.expression),
+                };
+                let end = self.extent(arm.expression).end;
+                (Line::SwitchArm(arm), Span { start, end })
+            })
+            .collect()
+    }
+
+    // Returns the start of the token that precedes the node, e.g. the `else` in a match arm
+    fn keyword_start(&self, index: AstIndex) -> Position {
+        let start = self.extent(index).start;
+        self.source
+            .token_before(start)
+            .map_or(start, |token| token.span.start)
+    }
+
+    fn token_before_node(&self, index: AstIndex) -> Option<Token> {
+        self.source
+            .token_before(self.extent(index).start)
+            .map(|token| token.token)
+    }
+
+    fn starts_on_new_line(&self, index: AstIndex) -> bool {
+        // Parentheses aren't included in node spans, so any opening parentheses are skipped
+        let mut token_index = self
+            .source
+            .token_index_at_or_after(self.extent(index).start);
+        while token_index > 0 && self.source.tokens[token_index - 1].token == Token::ParenOpen {
+            token_index -= 1;
+        }
+
+        match (token_index, self.source.tokens.get(token_index)) {
+            (0, _) | (_, None) => false,
+            (_, Some(token)) => {
+                self.source.tokens[token_index - 1].span.end.line < token.span.start.line
+            }
+        }
+    }
+
+    // True if the nodes are surrounded by a pair of parentheses in the source
+    fn is_parenthesized(&self, first: AstIndex, last: AstIndex) -> bool {
+        let open_index = self
+            .source
+            .token_index_at_or_after(self.extent(first).start);
+        if open_index == 0 || self.source.tokens[open_index - 1].token != Token::ParenOpen {
+            return false;
+        }
+
+        let end = self.extent(last).end;
+        let mut depth = 0;
+        for token in self.source.tokens[open_index - 1..].iter() {
+            match token.token {
+                Token::ParenOpen => depth += 1,
+                Token::ParenClose => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return !is_before(token.span.start, end);
+                    }
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    fn is_map_block(&self, index: AstIndex) -> bool {
+        match self.node(index) {
+            Node::Map(_) => !matches!(
+                self.source.token_at(self.span(index).start),
+                Some(token) if token.token == Token::MapStart
+            ),
+            _ => false,
+        }
+    }
+
+    fn is_block(&self, index: AstIndex) -> bool {
+        matches!(self.node(index), Node::Block(_)) || self.is_map_block(index)
+    }
+
+    // True if a Negate node was written as `not x` rather than `-x`
+    fn is_not(&self, negate: AstIndex) -> bool {
+        matches!(
+            self.source.token_at(self.extent(negate).start),
+            Some(token) if token.token == Token::Not
+        )
+    }
+
+    fn write(&mut self, s: &str) {
+        self.output.push_str(s);
+    }
+
+    fn new_line(&mut self) {
+        self.output.push('\n');
+        for _ in 0..self.indent {
+            self.output.push_str(INDENT);
+        }
+    }
+
+    fn blank_line(&mut self, previous_end: Option<u32>, start_line: u32) {
+        if matches!(previous_end, Some(previous_end) if start_line > previous_end + 1) {
+            self.output.push('\n');
+        }
+    }
+
+    fn current_line_length(&self) -> usize {
+        let line_start = self.output.rfind('\n').map_or(0, |i| i + 1);
+        self.output[line_start..].chars().count()
+    }
+
+    // Returns the first comment that hasn't been written yet, if it starts before the given line
+    fn next_comment_before(&self, line: u32) -> Option<usize> {
+        self.source
+            .comments
+            .iter()
+            .enumerate()
+            .find(|(index, _)| !self.comment_emitted[*index])
+            .filter(|(_, comment)| comment.span.start.line < line)
+            .map(|(index, _)| index)
+    }
+
+    fn comment_line(&mut self, index: usize, previous_end: &mut Option<u32>) {
+        let comment = self.source.comments[index];
+        self.blank_line(*previous_end, comment.span.start.line);
+        self.new_line();
+        self.write(comment.text);
+        self.comment_emitted[index] = true;
+        self.last_comment_line = comment.span.end.line;
+        *previous_end = Some(comment.span.end.line);
+    }
+
+    // Writes items on their own lines at the current indentation, along with any comments
+    fn lines(&mut self, lines: &[(Line<'a>, Span)], nested: bool) {
+        let mut previous_end = None;
+
+        for (line, extent) in lines.iter() {
+            while let Some(index) = self.next_comment_before(extent.start.line) {
+                let comment = self.source.comments[index];
+                if nested && previous_end.is_none() && comment.trailing {
+                    // A trailing comment on the line that introduced the block
+                    self.write(" ");
+                    self.write(comment.text);
+                    self.comment_emitted[index] = true;
+                } else {
+                    self.comment_line(index, &mut previous_end);
+                }
+            }
+
+            self.blank_line(previous_end, extent.start.line);
+            self.new_line();
+            let line_start = self.output.len();
+            self.line(*line);
+            self.interior_comments(line_start, extent);
+            // Comments at the end of a nested block can follow the line's extent
+            previous_end = Some(extent.end.line.max(self.last_comment_line));
+        }
+
+        if nested {
+            // Comments following the block that are indented to match the block's contents
+            if let Some((_, first_extent)) = lines.first() {
+                let last_end = lines.last().unwrap().1.end;
+                let next_line = self
+                    .source
+                    .token_after(last_end)
+                    .map_or(u32::MAX, |token| token.span.start.line);
+                while let Some(index) = self.next_comment_before(next_line) {
+                    if self.source.comments[index].span.start.column < first_extent.start.column {
+                        break;
+                    }
+                    self.comment_line(index, &mut previous_end);
+                }
+            }
+        } else {
+            while let Some(index) = self.next_comment_before(u32::MAX) {
+                self.comment_line(index, &mut previous_end);
+            }
+        }
+    }
+
+    // Writes the comments that were found within a line's extent
+    //
+    // A comment at the end of the line is kept as a trailing comment, other comments are moved
+    // to the lines preceding the line.
+    fn interior_comments(&mut self, line_start: usize, extent: &Span) {
+        let mut interior = self
+            .source
+            .comments
+            .iter()
+            .enumerate()
+            .filter(|(index, comment)| {
+                !self.comment_emitted[*index]
+                    && comment.span.start.line >= extent.start.line
+                    && comment.span.start.line <= extent.end.line
+            })
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+
+        if let Some(last) = interior.last() {
+            let comment = self.source.comments[*last];
+            if comment.trailing
+                && comment.span.start.line == extent.end.line
+                && comment.span.end.line == extent.end.line
+            {
+                self.write(" ");
+                self.write(comment.text);
+                self.comment_emitted[*last] = true;
+                interior.pop();
+            }
+        }
+
+        if !interior.is_empty() {
+            let mut preceding = String::new();
+            for index in interior {
+                preceding.push_str(self.source.comments[index].text);
+                preceding.push('\n');
+                for _ in 0..self.indent {
+                    preceding.push_str(INDENT);
+                }
+                self.comment_emitted[index] = true;
+            }
+            self.output.insert_str(line_start, &preceding);
+        }
+    }
+
+    fn line(&mut self, line: Line<'a>) {
+        match line {
+            Line::Statement(statement) => match self.node(statement) {
+                Node::Id(id) if self.token_before_node(statement) == Some(Token::Export) => {
+                    self.write("export ");
+                    self.id(*id);
+                }
+                Node::Map(entries) if self.is_map_block(statement) => {
+                    // A map block on its own line
+                    for (i, entry) in entries.iter().enumerate() {
+                        if i > 0 {
+                            self.new_line();
+                        }
+                        self.map_entry(entry);
+                    }
+                }
+                _ => self.expression(statement, Pos::Line),
+            },
+            Line::MapEntry(entry) => self.map_entry(entry),
+            Line::MatchArm(arm) => {
+                for (i, pattern) in arm.patterns.iter().enumerate() {
+                    if i > 0 {
+                        self.write(" or ");
+                    }
+                    self.expression(*pattern, Pos::Keyword);
+                }
+                if let Some(condition) = arm.condition {
+                    if !arm.patterns.is_empty() {
+                        self.write(" ");
+                    }
+                    self.write("if ");
+                    self.expression(condition, Pos::Keyword);
+                }
+                let is_else = arm.patterns.is_empty() && arm.condition.is_none();
+                self.arm_body(arm.expression, is_else);
+            }
+            Line::SwitchArm(arm) => {
+                if let Some(condition) = arm.condition {
+                    self.expression(condition, Pos::Keyword);
+                }
+                self.arm_body(arm.expression, arm.condition.is_none());
+            }
+        }
+    }
+
+    fn arm_body(&mut self, body: AstIndex, is_else: bool) {
+        if is_else {
+            self.write("else");
+        }
+        if self.is_block(body) || self.starts_on_new_line(body) {
+            self.body(body);
+        } else {
+            self.write(if is_else { " " } else { " then " });
+            self.expression(body, Pos::Line);
+        }
+    }
+
+    fn map_entry(&mut self, (key, value): &(MapKey, Option<AstIndex>)) {
+        self.map_key(key);
+        if let Some(value) = value {
+            self.write(":");
+            if self.is_block(*value) || self.starts_on_new_line(*value) {
+                self.body(*value);
+            } else {
+                self.write(" ");
+                self.expression(*value, Pos::Line);
+            }
+        }
+    }
+
+    // Writes an indented block on the following lines
+    fn body(&mut self, index: AstIndex) {
+        let lines = match self.node(index) {
+            Node::Block(statements) => self.statement_lines(statements),
+            Node::Map(entries) if self.is_map_block(index) => self.map_entry_lines(index, entries),
+            _ => self.statement_lines(&[index]),
+        };
+
+        self.indent += 1;
+        self.lines(&lines, true);
+        self.indent -= 1;
+    }
+
+    fn expression(&mut self, index: AstIndex, pos: Pos) {
+        if self.needs_parens(index, pos) {
+            self.write("(");
+            self.expression_without_parens(index, Pos::End);
+            self.write(")");
+        } else {
+            self.expression_without_parens(index, pos);
+        }
+    }
+
+    fn needs_parens(&self, index: AstIndex, pos: Pos) -> bool {
+        use {Node::*, Pos::*};
+
+        match self.node(index) {
+            Call { .. } => !matches!(pos, Line | End | Keyword),
+            Function(_)
+            | If(_)
+            | Match { .. }
+            | Switch(_)
+            | Yield(_)
+            | ReturnExpression(_)
+            | Throw(_) => !matches!(pos, Line | End),
+            Negate(_) if self.is_not(index) => matches!(pos, Operator | Postfix),
+            Negate(_) | BinaryOp { .. } => pos == Postfix,
+            Range { .. } | RangeFrom { .. } | RangeTo { .. } | RangeFull => {
+                matches!(pos, Operator | Postfix)
+            }
+            Assign { .. } | MultiAssign { .. } => pos != Line,
+            _ => false,
+        }
+    }
+
+    fn expression_without_parens(&mut self, index: AstIndex, pos: Pos) {
+        use Node::*;
+
+        match self.node(index) {
+            Empty => self.write("()"),
+            Id(id) => self.id(*id),
+            Lookup(_) => self.lookup(index, pos),
+            BoolTrue => self.write("true"),
+            BoolFalse => self.write("false"),
+            Number0 | Number1 | Int(_) | Float(_) => self.number(index),
+            Str(s) => self.string(index, *s),
+            Num2(args) => self.num_n(index, "num2", args, pos),
+            Num4(args) => self.num_n(index, "num4", args, pos),
+            List(items) => self.delimited("[", items, "]"),
+            Tuple(items) => {
+                let parenthesized = self.is_parenthesized(items[0], items[items.len() - 1]);
+                if pos == Pos::Line && items.len() > 1 && !parenthesized {
+                    self.bare_items(items, pos);
+                } else if items.len() == 1 {
+                    self.write("(");
+                    self.expression(items[0], Pos::Comma);
+                    self.write(",)");
+                } else {
+                    self.delimited("(", items, ")");
+                }
+            }
+            TempTuple(items) => self.bare_items(items, pos),
+            Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                self.expression(*start, Pos::Operator);
+                self.write(if *inclusive { "..=" } else { ".." });
+                self.expression(*end, Pos::Operator);
+            }
+            RangeFrom { start } => {
+                self.expression(*start, Pos::Operator);
+                self.write("..");
+            }
+            RangeTo { end, inclusive } => {
+                self.write(if *inclusive { "..=" } else { ".." });
+                self.expression(*end, Pos::Operator);
+            }
+            RangeFull => self.write(".."),
+            Map(entries) => {
+                if self.is_map_block(index) {
+                    self.body(index);
+                } else {
+                    self.inline_map(entries);
+                }
+            }
+            MainBlock { .. } | Block(_) => self.body(index),
+            Function(function) => self.function(function, pos),
+            Call { function, args } => {
+                self.expression(*function, Pos::Postfix);
+                self.call_args(args, pos);
+            }
+            Import { from, items } => {
+                if !from.is_empty() {
+                    self.write("from ");
+                    self.import_item(from);
+                    self.write(" ");
+                }
+                self.write("import ");
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    self.import_item(item);
+                }
+            }
+            Assign {
+                target,
+                op,
+                expression,
+            } => {
+                if target.scope == Scope::Export {
+                    self.write("export ");
+                }
+                self.expression(target.target_index, Pos::Operator);
+                self.write(match op {
+                    AssignOp::Equal => " =",
+                    AssignOp::Add => " +=",
+                    AssignOp::Subtract => " -=",
+                    AssignOp::Multiply => " *=",
+                    AssignOp::Divide => " /=",
+                    AssignOp::Modulo => " %=",
+                });
+                self.block_or_expression(*expression, pos.trailing());
+            }
+            MultiAssign {
+                targets,
+                expression,
+            } => {
+                for (i, target) in targets.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    self.expression(target.target_index, Pos::Comma);
+                }
+                self.write(" =");
+                self.block_or_expression(*expression, pos.trailing());
+            }
+            BinaryOp { op, lhs, rhs } => self.binary_op(*op, *lhs, *rhs, pos),
+            If(ast_if) => self.if_expression(ast_if, pos),
+            Match { expression, arms } => {
+                self.write("match ");
+                self.expression(*expression, Pos::End);
+                let lines = self.match_arm_lines(arms);
+                self.indent += 1;
+                self.lines(&lines, true);
+                self.indent -= 1;
+            }
+            Switch(arms) => {
+                self.write("switch");
+                let lines = self.switch_arm_lines(arms);
+                self.indent += 1;
+                self.lines(&lines, true);
+                self.indent -= 1;
+            }
+            Wildcard => self.write("_"),
+            Ellipsis(id) => {
+                if let Some(id) = id {
+                    self.id(*id);
+                }
+                self.write("...");
+            }
+            For(AstFor { args, range, body }) => {
+                self.write("for ");
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    match arg {
+                        Some(id) => self.id(*id),
+                        None => self.write("_"),
+                    }
+                }
+                self.write(" in ");
+                self.expression(*range, Pos::End);
+                self.body(*body);
+            }
+            Loop { body } => {
+                self.write("loop");
+                self.body(*body);
+            }
+            While { condition, body } => {
+                self.write("while ");
+                self.expression(*condition, Pos::End);
+                self.body(*body);
+            }
+            Until { condition, body } => {
+                self.write("until ");
+                self.expression(*condition, Pos::End);
+                self.body(*body);
+            }
+            Break => self.write("break"),
+            Continue => self.write("continue"),
+            Return => self.write("return"),
+            ReturnExpression(expression) => {
+                self.write("return");
+                self.block_or_expression(*expression, pos.trailing());
+            }
+            Negate(operand) => {
+                if self.is_not(index) {
+                    self.write("not ");
+                    if let BinaryOp { .. } | Call { .. } = self.node(*operand) {
+                        // e.g. `not (a < b)` rather than `not a < b`
+                        self.write("(");
+                        self.expression(*operand, Pos::End);
+                        self.write(")");
+                    } else {
+                        self.expression(*operand, pos.last());
+                    }
+                } else {
+                    self.write("-");
+                    let is_number =
+                        matches!(self.node(*operand), Number0 | Number1 | Int(_) | Float(_));
+                    if is_number {
+                        self.write("(");
+                        self.number(*operand);
+                        self.write(")");
+                    } else {
+                        self.expression(*operand, Pos::Postfix);
+                    }
+                }
+            }
+            Try(AstTry {
+                try_block,
+                catch_arg,
+                catch_block,
+                finally_block,
+            }) => {
+                self.write("try");
+                self.body(*try_block);
+                self.new_line();
+                self.write("catch ");
+                match catch_arg {
+                    Some(id) => self.id(*id),
+                    None => self.write("_"),
+                }
+                self.body(*catch_block);
+                if let Some(finally_block) = finally_block {
+                    self.new_line();
+                    self.write("finally");
+                    self.body(*finally_block);
+                }
+            }
+            Throw(expression) => {
+                self.write("throw");
+                self.block_or_expression(*expression, pos.trailing());
+            }
+            Yield(expression) => {
+                self.write("yield");
+                self.block_or_expression(*expression, pos.trailing());
+            }
+            Debug {
+                expression_string, ..
+            } => {
+                // The expression is written as-is, as its text is displayed at runtime
+                self.write("debug ");
+                let expression_string = self.constants.get_str(*expression_string);
+                self.write(expression_string);
+            }
+        }
+    }
+
+    // Writes a map block as an indented block, otherwise the expression follows a space
+    fn block_or_expression(&mut self, index: AstIndex, pos: Pos) {
+        if self.is_map_block(index) {
+            self.body(index);
+        } else {
+            self.write(" ");
+            self.expression(index, pos);
+        }
+    }
+
+    fn items(&mut self, items: &[AstIndex], last_pos: Pos) {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            let pos = if i == items.len() - 1 {
+                last_pos
+            } else {
+                Pos::Comma
+            };
+            self.expression(*item, pos);
+        }
+    }
+
+    // Returns the position of the last item in a list of items that aren't within delimiters
+    //
+    // Calls in the last item are kept in parentheses to make them easier to read,
+    // e.g. `f x, (g y)` rather than `f x, g y`.
+    fn last_bare_item_pos(&self, items: &[AstIndex], pos: Pos) -> Pos {
+        match items.last().map(|last| self.node(*last)) {
+            Some(Node::Call { .. }) | Some(Node::Num2(_)) | Some(Node::Num4(_)) => Pos::Comma,
+            Some(Node::Lookup(_)) if self.ends_with_call(items[items.len() - 1]) => Pos::Comma,
+            _ => pos.last(),
+        }
+    }
+
+    fn bare_items(&mut self, items: &[AstIndex], pos: Pos) {
+        let last_pos = self.last_bare_item_pos(items, pos);
+        self.items(items, last_pos);
+    }
+
+    // Writes the arguments of a space-separated call
+    //
+    // If the arguments started on an indented line in the source, then each argument is placed
+    // on its own line.
+    fn call_args(&mut self, args: &[AstIndex], pos: Pos) {
+        let last_pos = self.last_bare_item_pos(args, pos);
+
+        if args.is_empty() || !self.starts_on_new_line(args[0]) {
+            self.write(" ");
+            self.items(args, last_pos);
+            return;
+        }
+
+        self.indent += 1;
+        for (i, arg) in args.iter().enumerate() {
+            self.new_line();
+            if i < args.len() - 1 {
+                self.expression(*arg, Pos::Comma);
+                self.write(",");
+            } else {
+                self.expression(*arg, last_pos);
+            }
+        }
+        self.indent -= 1;
+    }
+
+    fn ends_with_call(&self, lookup: AstIndex) -> bool {
+        match self.node(lookup) {
+            Node::Lookup((_, Some(next))) => self.ends_with_call(*next),
+            Node::Lookup((LookupNode::Call(_), None)) => true,
+            _ => false,
+        }
+    }
+
+    // Writes a list of items surrounded by delimiters
+    //
+    // If the items don't fit on the current line then each item is placed on its own line.
+    fn delimited(&mut self, open: &str, items: &'a [AstIndex], close: &str) {
+        self.wrappable(open, items, close, |formatter, items, last| {
+            formatter.items(items, if last { Pos::End } else { Pos::Comma })
+        });
+    }
+
+    fn inline_map(&mut self, entries: &'a [(MapKey, Option<AstIndex>)]) {
+        self.wrappable("{", entries, "}", |formatter, entries, last| {
+            for (i, (key, value)) in entries.iter().enumerate() {
+                if i > 0 {
+                    formatter.write(", ");
+                }
+                formatter.map_key(key);
+                if let Some(value) = value {
+                    formatter.write(": ");
+                    let pos = if last && i == entries.len() - 1 {
+                        Pos::End
+                    } else {
+                        Pos::Comma
+                    };
+                    formatter.expression(*value, pos);
+                }
+            }
+        });
+    }
+
+    // Writes items inline, and then if they don't fit on the line writes them on separate lines
+    //
+    // The item writer is given a slice of items to write, along with a flag that's true if the
+    // items are the last to be written before the closing delimiter.
+    fn wrappable<T>(
+        &mut self,
+        open: &str,
+        items: &'a [T],
+        close: &str,
+        write_items: impl Fn(&mut Self, &'a [T], bool),
+    ) {
+        let start = self.output.len();
+        let comments_emitted = self.comment_emitted.clone();
+
+        self.write(open);
+        write_items(self, items, true);
+        self.write(close);
+
+        let fits = self.current_line_length() <= MAX_LINE_LENGTH;
+        if fits || !self.allow_wrapping || items.is_empty() || self.output[start..].contains('\n') {
+            return;
+        }
+
+        self.output.truncate(start);
+        self.comment_emitted = comments_emitted;
+
+        self.write(open);
+        self.indent += 1;
+        for i in 0..items.len() {
+            self.new_line();
+            write_items(self, &items[i..=i], false);
+            self.write(",");
+        }
+        self.indent -= 1;
+        self.new_line();
+        self.write(close);
+    }
+
+    fn lookup(&mut self, index: AstIndex, pos: Pos) {
+        let mut next = Some(index);
+        // Chains that were broken over multiple lines in the source are kept on separate lines
+        let indent = self.indent;
+
+        while let Some(index) = next {
+            let (lookup_node, next_node) = match self.node(index) {
+                Node::Lookup((lookup_node, next_node)) => (lookup_node, *next_node),
+                _ => return,
+            };
+
+            match lookup_node {
+                LookupNode::Root(root) => {
+                    let parens = !matches!(
+                        self.node(*root),
+                        Node::Id(_)
+                            | Node::Str(_)
+                            | Node::Number0
+                            | Node::Number1
+                            | Node::Int(_)
+                            | Node::Float(_)
+                            | Node::List(_)
+                            | Node::Map(_)
+                            | Node::Tuple(_)
+                            | Node::Empty
+                            | Node::BoolTrue
+                            | Node::BoolFalse
+                    );
+                    if parens {
+                        self.write("(");
+                        self.expression(*root, Pos::End);
+                        self.write(")");
+                    } else {
+                        self.expression(*root, Pos::Postfix);
+                    }
+                }
+                LookupNode::Id(id) => {
+                    if self.starts_chain_line(index) {
+                        self.indent = indent + 1;
+                        self.new_line();
+                    }
+                    self.write(".");
+                    self.key(*id, true);
+                }
+                LookupNode::Index(index_expression) => {
+                    self.write("[");
+                    self.index_expression(*index_expression);
+                    self.write("]");
+                }
+                LookupNode::Call(args) => {
+                    let allows_space_separated = match next_node {
+                        Some(next_node) => self.starts_chain_line(next_node),
+                        None => matches!(pos, Pos::Line | Pos::End | Pos::Keyword),
+                    };
+                    if allows_space_separated
+                        && !args.is_empty()
+                        && !self.is_parenthesized_call(index)
+                    {
+                        // Calls in the middle of a chain are followed by a new line
+                        let args_pos = if next_node.is_some() { Pos::End } else { pos };
+                        self.call_args(args, args_pos);
+                    } else {
+                        self.delimited("(", args, ")");
+                    }
+                }
+            }
+
+            next = next_node;
+        }
+
+        self.indent = indent;
+    }
+
+    // True if the lookup node is an ID that started a new line in the source, e.g. `.bar` in:
+    //   foo
+    //     .bar()
+    fn starts_chain_line(&self, index: AstIndex) -> bool {
+        if !matches!(self.node(index), Node::Lookup((LookupNode::Id(_), _))) {
+            return false;
+        }
+
+        // The lookup ID's span starts at the ID, which is preceded by a dot
+        let token_index = self.source.token_index_at_or_after(self.span(index).start);
+        token_index >= 2 && {
+            let dot = &self.source.tokens[token_index - 1];
+            let previous = &self.source.tokens[token_index - 2];
+            dot.token == Token::Dot && previous.span.end.line < dot.span.start.line
+        }
+    }
+
+    // True if a lookup call was written with parentheses, e.g. `x.foo(42)` rather than `x.foo 42`
+    fn is_parenthesized_call(&self, index: AstIndex) -> bool {
+        // The span of a parenthesized call ends with the closing parenthesis
+        let end = self.span(index).end;
+        let token_index = self.source.token_index_at_or_after(end);
+        token_index > 0 && {
+            let token = &self.source.tokens[token_index - 1];
+            token.token == Token::ParenClose && token.span.end == end
+        }
+    }
+
+    fn index_expression(&mut self, index: AstIndex) {
+        match self.node(index) {
+            Node::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                self.expression(*start, Pos::Index);
+                self.write(if *inclusive { "..=" } else { ".." });
+                self.expression(*end, Pos::Index);
+            }
+            Node::RangeFrom { start } => {
+                self.expression(*start, Pos::Index);
+                self.write("..");
+            }
+            Node::RangeTo { end, inclusive } => {
+                self.write(if *inclusive { "..=" } else { ".." });
+                self.expression(*end, Pos::Index);
+            }
+            _ => self.expression(index, Pos::Index),
+        }
+    }
+
+    fn binary_op(&mut self, op: AstOp, lhs: AstIndex, rhs: AstIndex, pos: Pos) {
+        let (left_priority, right_priority) = operator_precedence(op);
+
+        let lhs_parens = match self.node(lhs) {
+            // Parentheses are needed if the lhs operation would otherwise take this op's lhs
+            Node::BinaryOp { op: lhs_op, .. } => left_priority >= operator_precedence(*lhs_op).1,
+            Node::Call { .. } => true,
+            _ => self.needs_parens(lhs, Pos::Operator),
+        };
+        self.operand(lhs, Pos::Operator, lhs_parens);
+
+        self.write(match op {
+            AstOp::Add => " +",
+            AstOp::Subtract => " -",
+            AstOp::Multiply => " *",
+            AstOp::Divide => " /",
+            AstOp::Modulo => " %",
+            AstOp::Equal => " ==",
+            AstOp::NotEqual => " !=",
+            AstOp::Less => " <",
+            AstOp::LessOrEqual => " <=",
+            AstOp::Greater => " >",
+            AstOp::GreaterOrEqual => " >=",
+            AstOp::And => " and",
+            AstOp::Or => " or",
+        });
+
+        if self.is_map_block(rhs) {
+            self.body(rhs);
+            return;
+        }
+        self.write(" ");
+
+        let rhs_pos = match pos {
+            Pos::Line | Pos::End => Pos::End,
+            Pos::Postfix => Pos::Operator,
+            other => other,
+        };
+        let rhs_parens = match self.node(rhs) {
+            // Parentheses are needed if the rhs operation would otherwise be applied afterwards
+            Node::BinaryOp { op: rhs_op, .. } => operator_precedence(*rhs_op).0 < right_priority,
+            // Ranges are parsed after binary operations
+            Node::Range { .. }
+            | Node::RangeFrom { .. }
+            | Node::RangeTo { .. }
+            | Node::RangeFull => true,
+            // Calls are kept in parentheses to make them easier to read
+            Node::Call { .. } => true,
+            _ => self.needs_parens(rhs, rhs_pos),
+        };
+        self.operand(rhs, rhs_pos, rhs_parens);
+    }
+
+    fn operand(&mut self, index: AstIndex, pos: Pos, parens: bool) {
+        if parens {
+            self.write("(");
+            self.expression_without_parens(index, Pos::End);
+            self.write(")");
+        } else {
+            self.expression_without_parens(index, pos);
+        }
+    }
+
+    fn if_expression(&mut self, ast_if: &AstIf, pos: Pos) {
+        let inline = ast_if.else_if_blocks.is_empty()
+            && !self.is_block(ast_if.then_node)
+            && !self.starts_on_new_line(ast_if.then_node)
+            && !matches!(ast_if.else_node, Some(else_node) if self.is_block(else_node));
+
+        self.write("if ");
+
+        if inline {
+            self.expression(ast_if.condition, Pos::Keyword);
+            self.write(" then ");
+            match ast_if.else_node {
+                Some(else_node) => {
+                    self.expression(ast_if.then_node, Pos::Keyword);
+                    self.write(" else ");
+                    self.expression(else_node, pos.last());
+                }
+                None => self.expression(ast_if.then_node, pos.last()),
+            }
+        } else {
+            self.expression(ast_if.condition, Pos::End);
+            self.body(ast_if.then_node);
+            for (condition, block) in ast_if.else_if_blocks.iter() {
+                self.new_line();
+                self.write("else if ");
+                self.expression(*condition, Pos::End);
+                self.body(*block);
+            }
+            if let Some(else_node) = ast_if.else_node {
+                self.new_line();
+                self.write("else");
+                self.body(else_node);
+            }
+        }
+    }
+
+    fn function(&mut self, function: &Function, pos: Pos) {
+        self.write("|");
+        for (i, arg) in function.args.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            self.function_arg(*arg);
+        }
+        if function.is_variadic {
+            self.write("...");
+        }
+        self.write("|");
+
+        if self.is_block(function.body) || self.starts_on_new_line(function.body) {
+            self.body(function.body);
+        } else {
+            self.write(" ");
+            self.expression(function.body, pos.trailing());
+        }
+    }
+
+    fn function_arg(&mut self, arg: AstIndex) {
+        match self.node(arg) {
+            Node::Id(id) => self.id(*id),
+            Node::List(args) | Node::Tuple(args) => {
+                let is_list = matches!(self.node(arg), Node::List(_));
+                self.write(if is_list { "[" } else { "(" });
+                for (i, nested_arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    self.function_arg(*nested_arg);
+                }
+                self.write(if is_list { "]" } else { ")" });
+            }
+            _ => self.expression(arg, Pos::Comma),
+        }
+    }
+
+    fn num_n(&mut self, index: AstIndex, keyword: &str, args: &'a [AstIndex], pos: Pos) {
+        // Keep the original style, e.g. `num2 1, 2` or `num2(1, 2)`, if it's allowed here
+        let keyword_span = self.span(index);
+        let parenthesized = match self.source.token_at(keyword_span.start) {
+            Some(keyword_token) => {
+                let keyword_end = keyword_token.span.end;
+                matches!(
+                    self.source.token_at(keyword_end),
+                    Some(token) if token.token == Token::ParenOpen
+                )
+            }
+            None => true,
+        };
+
+        self.write(keyword);
+        if parenthesized || !matches!(pos, Pos::Line | Pos::End | Pos::Keyword) {
+            self.delimited("(", args, ")");
+        } else {
+            self.write(" ");
+            self.items(args, pos.last());
+        }
+    }
+
+    fn number(&mut self, index: AstIndex) {
+        let negative = match self.node(index) {
+            Node::Int(n) => self.constants.get_i64(*n) < 0,
+            Node::Float(n) => self.constants.get_f64(*n).is_sign_negative(),
+            _ => false,
+        };
+
+        // Numbers are written as they appear in the source, e.g. hex numbers stay in hex
+        match self.source.token_at(self.span(index).start) {
+            Some(token) if token.token == Token::Number => {
+                if negative {
+                    self.write("-");
+                }
+                self.write(token.text);
+            }
+            _ => {
+                let number = match self.node(index) {
+                    Node::Number0 => "0".to_string(),
+                    Node::Number1 => "1".to_string(),
+                    Node::Int(n) => self.constants.get_i64(*n).to_string(),
+                    Node::Float(n) => format!("{:?}", self.constants.get_f64(*n)),
+                    _ => return,
+                };
+                self.write(&number);
+            }
+        }
+    }
+
+    fn string(&mut self, index: AstIndex, constant: ConstantIndex) {
+        // Strings are written as they appear in the source to preserve escape sequences
+        match self.source.token_at(self.span(index).start) {
+            Some(token) if token.token == Token::String => self.write(token.text),
+            _ => {
+                let quoted = quote_string(self.constants.get_str(constant));
+                self.write(&quoted);
+            }
+        }
+    }
+
+    fn id(&mut self, id: ConstantIndex) {
+        let id = self.constants.get_str(id);
+        self.write(id);
+    }
+
+    // Writes a map key or lookup ID, which needs to be quoted if it's not a valid ID
+    //
+    // Keywords are allowed as lookup IDs, e.g. `x.and y`, but need to be quoted as map keys.
+    fn key(&mut self, id: ConstantIndex, allow_keyword: bool) {
+        let key = self.constants.get_str(id);
+        if is_valid_id(key) && (allow_keyword || !KEYWORDS.contains(&key)) {
+            self.write(key);
+        } else {
+            let quoted = quote_string(key);
+            self.write(&quoted);
+        }
+    }
+
+    fn map_key(&mut self, key: &MapKey) {
+        match key {
+            MapKey::Id(id) => self.key(*id, false),
+            MapKey::Meta(meta_id) => {
+                self.write("@");
+                self.write(match meta_id {
+                    MetaId::Add => "+",
+                    MetaId::Subtract => "-",
+                    MetaId::Multiply => "*",
+                    MetaId::Divide => "/",
+                    MetaId::Modulo => "%",
+                    MetaId::Less => "<",
+                    MetaId::LessOrEqual => "<=",
+                    MetaId::Greater => ">",
+                    MetaId::GreaterOrEqual => ">=",
+                    MetaId::Equal => "==",
+                    MetaId::NotEqual => "!=",
+                    MetaId::Index => "[]",
+                    MetaId::Display => "display",
+                    MetaId::Negate => "negate",
+                    MetaId::Type => "type",
+                    MetaId::Invalid => "",
+                });
+            }
+        }
+    }
+
+    fn import_item(&mut self, item: &[ConstantIndex]) {
+        for (i, id) in item.iter().enumerate() {
+            if i > 0 {
+                self.write(".");
+            }
+            self.id(*id);
+        }
+    }
+}
+
+fn operator_precedence(op: AstOp) -> (u8, u8) {
+    // Matches the precedence used by the parser
+    use AstOp::*;
+    match op {
+        Or => (1, 2),
+        And => (3, 4),
+        Equal | NotEqual => (8, 7),
+        Greater | GreaterOrEqual | Less | LessOrEqual => (10, 9),
+        Add | Subtract => (11, 12),
+        Multiply | Divide | Modulo => (13, 14),
+    }
+}
+
+fn is_valid_id(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if is_id_start(c) => chars.all(is_id_continue),
+        _ => false,
+    }
+}
+
+fn quote_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            _ => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn leading_tokens(node: &Node) -> &'static [Token] {
+    match node {
+        Node::If(_) => &[Token::If],
+        Node::Loop { .. } => &[Token::Loop],
+        Node::While { .. } => &[Token::While],
+        Node::Until { .. } => &[Token::Until],
+        Node::ReturnExpression(_) => &[Token::Return],
+        Node::Yield(_) => &[Token::Yield],
+        Node::Debug { .. } => &[Token::Debug],
+        Node::Negate(_) => &[Token::Not, Token::Subtract],
+        Node::Assign { target, .. } if target.scope == Scope::Export => &[Token::Export],
+        _ => &[],
+    }
+}
+
+fn children(node: &Node) -> Vec<AstIndex> {
+    use Node::*;
+
+    match node {
+        Lookup((lookup_node, next)) => {
+            let mut result = match lookup_node {
+                LookupNode::Root(index) | LookupNode::Index(index) => vec![*index],
+                LookupNode::Call(args) => args.clone(),
+                LookupNode::Id(_) => vec![],
+            };
+            result.extend(next);
+            result
+        }
+        Num2(nodes) | Num4(nodes) | List(nodes) | Tuple(nodes) | TempTuple(nodes)
+        | Block(nodes) => nodes.clone(),
+        Range { start, end, .. } => vec![*start, *end],
+        RangeFrom { start } => vec![*start],
+        RangeTo { end, .. } => vec![*end],
+        Map(entries) => entries.iter().filter_map(|(_, value)| *value).collect(),
+        MainBlock { body, .. } => body.clone(),
+        Function(function) => {
+            let mut result = function.args.clone();
+            result.push(function.body);
+            result
+        }
+        Call { function, args } => {
+            let mut result = vec![*function];
+            result.extend(args);
+            result
+        }
+        Assign {
+            target, expression, ..
+        } => vec![target.target_index, *expression],
+        MultiAssign {
+            targets,
+            expression,
+        } => {
+            let mut result = targets
+                .iter()
+                .map(|target| target.target_index)
+                .collect::<Vec<_>>();
+            result.push(*expression);
+            result
+        }
+        BinaryOp { lhs, rhs, .. } => vec![*lhs, *rhs],
+        If(ast_if) => {
+            let mut result = vec![ast_if.condition, ast_if.then_node];
+            for (condition, block) in ast_if.else_if_blocks.iter() {
+                result.push(*condition);
+                result.push(*block);
+            }
+            result.extend(ast_if.else_node);
+            result
+        }
+        Match { expression, arms } => {
+            let mut result = vec![*expression];
+            for arm in arms.iter() {
+                result.extend(&arm.patterns);
+                result.extend(arm.condition);
+                result.push(arm.expression);
+            }
+            result
+        }
+        Switch(arms) => {
+            let mut result = Vec::new();
+            for arm in arms.iter() {
+                result.extend(arm.condition);
+                result.push(arm.expression);
+            }
+            result
+        }
+        For(AstFor { range, body, .. }) => vec![*range, *body],
+        Loop { body } => vec![*body],
+        While { condition, body } | Until { condition, body } => vec![*condition, *body],
+        ReturnExpression(expression)
+        | Negate(expression)
+        | Throw(expression)
+        | Yield(expression)
+        | Debug { expression, .. } => vec![*expression],
+        Try(AstTry {
+            try_block,
+            catch_block,
+            finally_block,
+            ..
+        }) => {
+            let mut result = vec![*try_block, *catch_block];
+            result.extend(finally_block);
+            result
+        }
+        _ => vec![],
+    }
+}
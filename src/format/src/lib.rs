@@ -0,0 +1,79 @@
+//! A source code formatter for the Koto programming language
+//!
+//! Scripts are formatted with consistent indentation and spacing, while comments and blank lines
+//! between statements are preserved.
+//!
+//! The formatted output is parsed again and compared with the original script, and an error is
+//! returned if the meaning of the script would have been changed by formatting.
+
+mod compare;
+mod formatter;
+mod source;
+
+use {
+    compare::{scripts_match, ParsedScript},
+    formatter::Formatter,
+    koto_parser::{Parser, ParserError},
+    source::SourceInfo,
+    std::{error, fmt},
+};
+
+/// Errors that can be returned by [format]
+#[derive(Clone, Debug)]
+pub enum FormatError {
+    /// The script couldn't be parsed
+    ParserError(ParserError),
+    /// The script couldn't be formatted without changing its meaning
+    MismatchedOutput,
+}
+
+impl From<ParserError> for FormatError {
+    fn from(error: ParserError) -> Self {
+        Self::ParserError(error)
+    }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ParserError(error) => write!(f, "{}", error),
+            Self::MismatchedOutput => {
+                f.write_str("Unable to format the script without changing its meaning")
+            }
+        }
+    }
+}
+
+impl error::Error for FormatError {}
+
+/// Formats a Koto script
+///
+/// The formatted script is returned, ending with a newline.
+pub fn format(source: &str) -> Result<String, FormatError> {
+    let (ast, constants) = Parser::parse(source)?;
+    let source_info = SourceInfo::new(source);
+    let original = ParsedScript {
+        ast: &ast,
+        constants: &constants,
+    };
+
+    // Wrapping long lists and maps over multiple lines isn't allowed in every context,
+    // so if the wrapped output doesn't match then everything is kept on single lines.
+    for allow_wrapping in [true, false].iter() {
+        let output = Formatter::new(&ast, &constants, &source_info, *allow_wrapping)
+            .format()
+            .ok_or(FormatError::MismatchedOutput)?;
+
+        if let Ok((formatted_ast, formatted_constants)) = Parser::parse(&output) {
+            let formatted = ParsedScript {
+                ast: &formatted_ast,
+                constants: &formatted_constants,
+            };
+            if scripts_match(&original, &formatted) {
+                return Ok(output);
+            }
+        }
+    }
+
+    Err(FormatError::MismatchedOutput)
+}
@@ -0,0 +1,109 @@
+use koto_lexer::{Lexer, Position, Span, Token};
+
+/// A token from the script's source, along with its span and text
+#[derive(Clone, Copy, Debug)]
+pub struct SourceToken<'a> {
+    pub token: Token,
+    pub span: Span,
+    pub text: &'a str,
+}
+
+/// A comment from the script's source
+#[derive(Clone, Copy, Debug)]
+pub struct Comment<'a> {
+    pub span: Span,
+    pub text: &'a str,
+    /// True when the comment follows code on the same line
+    pub trailing: bool,
+}
+
+/// The tokens and comments in a script
+///
+/// The parser discards comments and layout while producing the AST, so the formatter re-lexes
+/// the script to keep track of the trivia that should be carried over to the formatted output.
+pub struct SourceInfo<'a> {
+    /// All tokens other than whitespace, newlines, and comments
+    pub tokens: Vec<SourceToken<'a>>,
+    pub comments: Vec<Comment<'a>>,
+}
+
+impl<'a> SourceInfo<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let mut tokens = Vec::new();
+        let mut comments = Vec::new();
+        let mut code_on_line = false;
+
+        let mut lexer = Lexer::new(source);
+        while let Some(token) = lexer.next() {
+            let span = lexer.span();
+            let text = lexer.slice();
+
+            match token {
+                Token::Whitespace => {}
+                Token::NewLine | Token::NewLineIndented => code_on_line = false,
+                Token::CommentSingle | Token::CommentMulti => comments.push(Comment {
+                    span,
+                    text: text.trim_end(),
+                    trailing: code_on_line,
+                }),
+                _ => {
+                    code_on_line = true;
+                    tokens.push(SourceToken { token, span, text });
+                }
+            }
+        }
+
+        Self { tokens, comments }
+    }
+
+    /// Returns the index of the first token that starts at or after the given position
+    pub fn token_index_at_or_after(&self, position: Position) -> usize {
+        self.tokens
+            .partition_point(|token| is_before(token.span.start, position))
+    }
+
+    /// Returns the token that starts at the given position
+    pub fn token_at(&self, position: Position) -> Option<&SourceToken<'a>> {
+        self.tokens
+            .get(self.token_index_at_or_after(position))
+            .filter(|token| token.span.start == position)
+    }
+
+    /// Returns the last token that starts before the given position
+    pub fn token_before(&self, position: Position) -> Option<&SourceToken<'a>> {
+        match self.token_index_at_or_after(position) {
+            0 => None,
+            index => self.tokens.get(index - 1),
+        }
+    }
+
+    /// Returns the first token that starts after the given position
+    pub fn token_after(&self, position: Position) -> Option<&SourceToken<'a>> {
+        self.tokens
+            .iter()
+            .skip(self.token_index_at_or_after(position))
+            .find(|token| is_before(position, token.span.start))
+    }
+
+    /// Moves the position forward to the start of the next token
+    pub fn snap_forward(&self, position: Position) -> Position {
+        self.tokens
+            .get(self.token_index_at_or_after(position))
+            .map_or(position, |token| token.span.start)
+    }
+
+    /// Moves the position back to the end of the last token that ends at or before it
+    pub fn snap_back(&self, position: Position) -> Position {
+        let index = self
+            .tokens
+            .partition_point(|token| !is_before(position, token.span.end));
+        match index {
+            0 => position,
+            _ => self.tokens[index - 1].span.end,
+        }
+    }
+}
+
+pub fn is_before(a: Position, b: Position) -> bool {
+    (a.line, a.column) < (b.line, b.column)
+}
@@ -0,0 +1,277 @@
+use {
+    koto_format::format,
+    std::{fs, path::Path},
+};
+
+fn check_format(input: &str, expected: &str) {
+    let output = match format(input) {
+        Ok(output) => output,
+        Err(error) => panic!("Failed to format script: {}\n{}", error, input),
+    };
+    assert_eq!(output, expected);
+
+    // Formatting the output again should leave it unchanged
+    assert_eq!(format(&output).unwrap(), output);
+}
+
+fn koto_scripts(dir: &Path, scripts: &mut Vec<std::path::PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            koto_scripts(&path, scripts);
+        } else if matches!(path.extension(), Some(extension) if extension == "koto") {
+            scripts.push(path);
+        }
+    }
+}
+
+mod format_tests {
+    use super::*;
+
+    mod spacing {
+        use super::*;
+
+        #[test]
+        fn operators() {
+            check_format(
+                "x=1+2*3\ny =  (x+1)  *2",
+                "x = 1 + 2 * 3\ny = (x + 1) * 2\n",
+            );
+        }
+
+        #[test]
+        fn redundant_parentheses_are_removed() {
+            check_format("x = ((1 + 2)) + (3 * 4)", "x = 1 + 2 + 3 * 4\n");
+        }
+
+        #[test]
+        fn required_parentheses_are_kept() {
+            check_format(
+                "x = 1 - (2 - 3)\ny = (a or b) and c",
+                "x = 1 - (2 - 3)\ny = (a or b) and c\n",
+            );
+        }
+
+        #[test]
+        fn negation() {
+            check_format(
+                "a = not  b\nc = -( d )\ne = -1",
+                "a = not b\nc = -d\ne = -1\n",
+            );
+        }
+
+        #[test]
+        fn lists_and_tuples() {
+            check_format(
+                "x = [1,2 , 3]\ny = (1,2)\nz = 1,2",
+                "x = [1, 2, 3]\ny = (1, 2)\nz = 1, 2\n",
+            );
+        }
+
+        #[test]
+        fn calls() {
+            check_format(
+                "f( 1,2 )\nx.foo  1, 2\ny = x.bar( )",
+                "f(1, 2)\nx.foo 1, 2\ny = x.bar()\n",
+            );
+        }
+    }
+
+    mod indentation {
+        use super::*;
+
+        #[test]
+        fn blocks_are_indented_with_two_spaces() {
+            let input = "\
+f = |x|
+    if x > 0
+        x
+    else
+        -x
+";
+            let expected = "\
+f = |x|
+  if x > 0
+    x
+  else
+    -x
+";
+            check_format(input, expected);
+        }
+
+        #[test]
+        fn map_block() {
+            let input = "\
+m =
+      foo: 42
+      bar:   |self|   self.foo
+";
+            let expected = "\
+m =
+  foo: 42
+  bar: |self| self.foo
+";
+            check_format(input, expected);
+        }
+
+        #[test]
+        fn match_arms() {
+            let input = "\
+x = match  y
+  0  or  1 then \"small\"
+  n if n <  10  then \"medium\"
+  else  \"large\"
+";
+            let expected = "\
+x = match y
+  0 or 1 then \"small\"
+  n if n < 10 then \"medium\"
+  else \"large\"
+";
+            check_format(input, expected);
+        }
+    }
+
+    mod comments {
+        use super::*;
+
+        #[test]
+        fn leading_and_trailing_comments() {
+            let input = "\
+# A comment
+x = 1   # The first value
+
+
+#- A multi-line
+comment -#
+y = 2
+";
+            let expected = "\
+# A comment
+x = 1 # The first value
+
+#- A multi-line
+comment -#
+y = 2
+";
+            check_format(input, expected);
+        }
+
+        #[test]
+        fn comments_in_blocks() {
+            let input = "\
+f = || # Returns 42
+   # Do the thing
+   x = 42
+   # Still in the block
+# Back at the top level
+f()
+";
+            let expected = "\
+f = || # Returns 42
+  # Do the thing
+  x = 42
+  # Still in the block
+# Back at the top level
+f()
+";
+            check_format(input, expected);
+        }
+
+        #[test]
+        fn comments_inside_expressions_are_moved_before_the_statement() {
+            let input = "\
+x = [
+  1, # one
+  2,
+]
+";
+            let expected = "\
+# one
+x = [1, 2]
+";
+            check_format(input, expected);
+        }
+    }
+
+    mod wrapping {
+        use super::*;
+
+        #[test]
+        fn long_lists_are_wrapped() {
+            let input = format!("x = [{}]", ["\"abcdefghijklmnop\""; 6].join(", "));
+            let expected = format!("x = [\n{}]\n", "  \"abcdefghijklmnop\",\n".repeat(6));
+            check_format(&input, &expected);
+        }
+    }
+
+    #[test]
+    fn empty_script() {
+        check_format("", "");
+        check_format("\n\n", "");
+    }
+
+    #[test]
+    fn parser_errors_are_reported() {
+        assert!(format("x = (1 + ").is_err());
+    }
+
+    #[test]
+    fn keywords_as_lookup_ids() {
+        check_format(
+            "x = y.and(1)\nz = {\"and\": 1}",
+            "x = y.and(1)\nz = {\"and\": 1}\n",
+        );
+    }
+
+    #[test]
+    fn not_and_negation() {
+        check_format(
+            "a = not (x < y)\nb = not (f x)\nc = -(f x)\nd = not x.y",
+            "a = not (x < y)\nb = not (f x)\nc = -(f x)\nd = not x.y\n",
+        );
+    }
+
+    #[test]
+    fn multi_line_chains() {
+        let input = "\
+x = (1..10)
+      .each |n| n * 2
+      .to_tuple()
+";
+        let expected = "\
+x = (1..10)
+  .each |n| n * 2
+  .to_tuple()
+";
+        check_format(input, expected);
+    }
+
+    #[test]
+    fn repo_scripts() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        let mut scripts = Vec::new();
+        for dir in &["koto/tests", "koto/benches", "examples"] {
+            koto_scripts(&root.join(dir), &mut scripts);
+        }
+        assert!(!scripts.is_empty());
+
+        // The parser currently stops early in maps.koto, after a map block that follows an
+        // operator, so the script can't be formatted without losing code.
+        scripts.retain(|path| !path.ends_with("koto/tests/maps.koto"));
+
+        let mut failures = Vec::new();
+        for script_path in scripts.iter() {
+            let script = fs::read_to_string(script_path).unwrap();
+            match format(&script) {
+                Ok(output) => match format(&output) {
+                    Ok(reformatted) if reformatted == output => {}
+                    _ => failures.push(format!("{}: not idempotent", script_path.display())),
+                },
+                Err(error) => failures.push(format!("{}: {}", script_path.display(), error)),
+            }
+        }
+
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+}
@@ -31,15 +31,17 @@ pub use {koto_bytecode as bytecode, koto_parser as parser, koto_runtime as runti
 use {
     koto_bytecode::{Chunk, LoaderError},
     koto_runtime::{
-        DefaultLogger, KotoLogger, Loader, RuntimeError, Value, ValueList, ValueMap, ValueVec, Vm,
-        VmSettings,
+        core::CoreModules, Coverage, CycleCollection, Debugger, DefaultLogger, FileSystemResolver,
+        InterruptHandle, KotoLogger, Loader, ModuleResolver, Profiler, RuntimeError, TestResult,
+        Value, ValueList, ValueMap, ValueVec, Vm, VmSettings,
     },
-    std::{error::Error, fmt, path::PathBuf, sync::Arc},
+    std::{error::Error, fmt, fs, path::PathBuf, sync::Arc},
 };
 
 #[derive(Debug)]
 pub enum KotoError {
     RuntimeError(RuntimeError),
+    LoaderError(LoaderError),
     NothingToRun,
     InvalidTestsType(String),
     FunctionNotFound(String),
@@ -51,6 +53,7 @@ impl fmt::Display for KotoError {
 
         match &self {
             RuntimeError(e) => e.fmt(f),
+            LoaderError(e) => e.fmt(f),
             NothingToRun => {
                 f.write_str("Missing compiled chunk, call compile() before calling run()")
             }
@@ -72,22 +75,71 @@ impl From<RuntimeError> for KotoError {
     }
 }
 
+impl From<LoaderError> for KotoError {
+    fn from(error: LoaderError) -> Self {
+        Self::LoaderError(error)
+    }
+}
+
 pub type KotoResult = Result<Value, KotoError>;
 
 /// Settings used to control the behaviour of the [Koto] runtime
 #[derive(Clone)]
 pub struct KotoSettings {
     pub run_tests: bool,
+    /// Whether or not a `main` function exported by the script should be called after it's run
+    pub run_main: bool,
     pub repl_mode: bool,
     pub logger: Arc<dyn KotoLogger>,
+    /// The number of instructions that the runtime is allowed to execute, see [VmSettings::fuel]
+    pub fuel: Option<u64>,
+    /// The number of bytes that the runtime is allowed to use for values,
+    /// see [VmSettings::memory_limit]
+    pub memory_limit: Option<usize>,
+    /// A handle that can be used to interrupt the runtime, see [Koto::interrupt_handle]
+    pub interrupt_handle: InterruptHandle,
+    /// The maximum number of nested function calls, see [VmSettings::max_call_depth]
+    pub max_call_depth: Option<usize>,
+    /// The maximum number of nested VMs, see [VmSettings::max_nested_vm_depth]
+    pub max_nested_vm_depth: Option<usize>,
+    /// Controls the collection of reference cycles, see [VmSettings::cycle_collection]
+    pub cycle_collection: CycleCollection,
+    /// The core library modules that should be made available to scripts
+    pub core_modules: CoreModules,
+    /// Whether or not scripts are allowed to import modules from other files
+    pub allow_module_loading: bool,
+    /// A directory used to cache compiled scripts and modules, see [Loader::set_cache_dir]
+    pub chunk_cache_dir: Option<PathBuf>,
+    /// The resolver used to find imported modules, see [VmSettings::module_resolver]
+    pub module_resolver: Arc<dyn ModuleResolver>,
+    /// A debugger that should be attached to the runtime, see [VmSettings::debugger]
+    pub debugger: Option<Arc<Debugger>>,
+    /// A profiler that should be attached to the runtime, see [VmSettings::profiler]
+    pub profiler: Option<Arc<Profiler>>,
+    /// Coverage that should be recorded while running scripts, see [VmSettings::coverage]
+    pub coverage: Option<Arc<Coverage>>,
 }
 
 impl Default for KotoSettings {
     fn default() -> Self {
         Self {
             run_tests: true,
+            run_main: true,
             repl_mode: false,
             logger: Arc::new(DefaultLogger {}),
+            fuel: None,
+            memory_limit: None,
+            interrupt_handle: InterruptHandle::default(),
+            max_call_depth: Some(1000),
+            max_nested_vm_depth: Some(64),
+            cycle_collection: CycleCollection::default(),
+            core_modules: CoreModules::default(),
+            allow_module_loading: true,
+            chunk_cache_dir: None,
+            module_resolver: Arc::new(FileSystemResolver),
+            debugger: None,
+            profiler: None,
+            coverage: None,
         }
     }
 }
@@ -119,8 +171,24 @@ impl Koto {
             settings: settings.clone(),
             runtime: Vm::with_settings(VmSettings {
                 logger: settings.logger,
+                fuel: settings.fuel,
+                memory_limit: settings.memory_limit,
+                interrupt_handle: settings.interrupt_handle,
+                max_call_depth: settings.max_call_depth,
+                max_nested_vm_depth: settings.max_nested_vm_depth,
+                cycle_collection: settings.cycle_collection,
+                core_modules: settings.core_modules,
+                allow_module_loading: settings.allow_module_loading,
+                chunk_cache_dir: settings.chunk_cache_dir.clone(),
+                module_resolver: settings.module_resolver.clone(),
+                debugger: settings.debugger.clone(),
+                profiler: settings.profiler.clone(),
+                coverage: settings.coverage.clone(),
             }),
-            loader: Loader::default(),
+            loader: match settings.chunk_cache_dir {
+                Some(cache_dir) => Loader::with_cache_dir(cache_dir),
+                None => Loader::default(),
+            },
             chunk: None,
             script_path: None,
         }
@@ -162,23 +230,117 @@ impl Koto {
             Ok(result)
         } else {
             if self.settings.run_tests {
-                let _test_result = match self.runtime.get_exported_value("tests") {
-                    Some(Value::Map(tests)) => {
-                        self.runtime.run_tests(tests)?;
+                if let Some(tests) = self.exported_tests()? {
+                    let results = self.runtime.run_tests(tests, None);
+                    if let Some(error) = results.into_iter().find_map(|result| result.error) {
+                        return Err(error.into());
                     }
-                    Some(other) => return Err(KotoError::InvalidTestsType(other.type_as_string())),
-                    None => {}
-                };
+                }
             }
 
-            if let Some(main) = self.runtime.get_exported_function("main") {
-                self.runtime.run_function(main, &[]).map_err(|e| e.into())
-            } else {
-                Ok(result)
+            match self.runtime.get_exported_function("main") {
+                Some(main) if self.settings.run_main => {
+                    self.runtime.run_function(main, &[]).map_err(|e| e.into())
+                }
+                _ => Ok(result),
             }
         }
     }
 
+    /// Reloads the script and the modules that it imports if their sources have changed
+    ///
+    /// Changed modules are invalidated along with the modules that import them, see
+    /// [Vm::invalidate_changed_modules]. If the script's source has changed, or if it imports an
+    /// invalidated module, then it gets recompiled and run again, with invalidated modules being
+    /// run again when they're imported. Modules that haven't been affected by the changes aren't
+    /// run again.
+    ///
+    /// Values that refer to the exports of invalidated modules remain valid, and will need to be
+    /// replaced by the host to make use of the reloaded modules.
+    ///
+    /// Returns the paths of the scripts and modules with sources that have changed, with an empty
+    /// list being returned when nothing has changed.
+    pub fn reload_changed(&mut self) -> Result<Vec<PathBuf>, KotoError> {
+        let changes = self.runtime.invalidate_changed_modules();
+        let mut changed = changes.changed;
+
+        let script_source = match (&self.script_path, &self.chunk) {
+            (Some(script_path), Some(chunk)) if !self.settings.repl_mode => {
+                match fs::read_to_string(script_path) {
+                    Ok(source) if source != chunk.debug_info.source => {
+                        changed.push(script_path.clone());
+                        Some(source)
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        if let Some(source) = &script_source {
+            self.compile(source)?;
+        }
+
+        if script_source.is_some() || changes.script_affected {
+            self.run()?;
+        }
+
+        Ok(changed)
+    }
+
+    /// Returns the `tests` map exported by the script, if it exists
+    pub fn exported_tests(&self) -> Result<Option<ValueMap>, KotoError> {
+        match self.runtime.get_exported_value("tests") {
+            Some(Value::Map(tests)) => Ok(Some(tests)),
+            Some(other) => Err(KotoError::InvalidTestsType(other.type_as_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Runs a single test from a `tests` map, see [Vm::run_test]
+    pub fn run_test(&mut self, tests: ValueMap, name: &str) -> TestResult {
+        self.runtime.run_test(tests, name)
+    }
+
+    /// Resumes execution after an execution limit has been reached
+    ///
+    /// See [Vm::continue_running].
+    pub fn continue_running(&mut self) -> KotoResult {
+        self.runtime.continue_running().map_err(|e| e.into())
+    }
+
+    /// Returns a handle that can be used to interrupt the runtime from another thread
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.runtime.interrupt_handle()
+    }
+
+    /// Adds to the runtime's instruction budget, see [Vm::add_fuel]
+    pub fn add_fuel(&self, amount: u64) {
+        self.runtime.add_fuel(amount);
+    }
+
+    /// Returns the number of bytes that are being used by values, see
+    /// [Vm::allocated_memory]
+    pub fn allocated_memory(&self) -> usize {
+        self.runtime.allocated_memory()
+    }
+
+    /// Sets the runtime's memory limit, see [Vm::set_memory_limit]
+    pub fn set_memory_limit(&self, limit: Option<usize>) {
+        self.runtime.set_memory_limit(limit);
+    }
+
+    /// Breaks leaked reference cycles, see [Vm::collect_cycles]
+    pub fn collect_cycles(&self) -> usize {
+        self.runtime.collect_cycles()
+    }
+
+    /// Returns the values that are being kept alive by leaked reference cycles,
+    /// see [Vm::find_leaked_cycles]
+    pub fn find_leaked_cycles(&self) -> Vec<Value> {
+        self.runtime.find_leaked_cycles()
+    }
+
     pub fn prelude(&self) -> ValueMap {
         self.runtime.prelude()
     }
@@ -191,19 +353,17 @@ impl Koto {
             .map(|arg| Str(arg.as_str().into()))
             .collect::<ValueVec>();
 
-        match self
+        // The koto module might have been left out of the prelude
+        if let Some(Map(map)) = self
             .runtime
             .prelude()
             .contents_mut()
             .data
             .get_with_string_mut("koto")
-            .unwrap()
         {
-            Map(map) => map
-                .contents_mut()
+            map.contents_mut()
                 .data
-                .add_list("args", ValueList::with_data(koto_args)),
-            _ => unreachable!(),
+                .add_list("args", ValueList::with_data(koto_args));
         }
     }
 
@@ -231,20 +391,16 @@ impl Koto {
 
         self.script_path = path;
 
-        match self
+        if let Some(Map(map)) = self
             .runtime
             .prelude()
             .contents_mut()
             .data
             .get_with_string_mut("koto")
-            .unwrap()
         {
-            Map(map) => {
-                let map = &mut map.contents_mut().data;
-                map.add_value("script_dir", script_dir);
-                map.add_value("script_path", script_path);
-            }
-            _ => unreachable!(),
+            let map = &mut map.contents_mut().data;
+            map.add_value("script_dir", script_dir);
+            map.add_value("script_path", script_path);
         }
     }
 
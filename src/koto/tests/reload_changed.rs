@@ -0,0 +1,122 @@
+use {
+    koto::{
+        runtime::{Value, ValueMap},
+        Koto,
+    },
+    std::{fs, path::Path},
+};
+
+mod reload_changed {
+    use super::*;
+
+    fn write_script(dir: &Path, name: &str, source: &str) {
+        fs::write(dir.join(name), source).unwrap();
+    }
+
+    fn get_string(runs: &ValueMap, name: &str) -> String {
+        runs.contents()
+            .data
+            .get_with_string(name)
+            .unwrap()
+            .to_string()
+    }
+
+    fn call(koto: &mut Koto, function_name: &str) -> String {
+        koto.call_function_by_name(function_name, &[])
+            .unwrap()
+            .to_string()
+    }
+
+    // Each script increments a counter in the `runs` map each time it's run
+    fn count_runs(name: &str) -> String {
+        format!("import runs\nruns.{name} = runs.{name} + 1\n", name = name)
+    }
+
+    #[test]
+    fn changed_modules_are_reloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let script_path = dir.path().join("main.koto");
+        write_script(
+            dir.path(),
+            "main.koto",
+            &format!(
+                "{}import foo, bar\nexport get_result = || foo.x + bar.y\nexport get_foo = || foo",
+                count_runs("main")
+            ),
+        );
+        write_script(
+            dir.path(),
+            "foo.koto",
+            &format!("{}import baz\nexport x = baz.z + 1", count_runs("foo")),
+        );
+        write_script(
+            dir.path(),
+            "bar.koto",
+            &format!("{}export y = 10", count_runs("bar")),
+        );
+        write_script(
+            dir.path(),
+            "baz.koto",
+            &format!("{}export z = 100", count_runs("baz")),
+        );
+
+        let mut koto = Koto::default();
+        let mut runs = ValueMap::new();
+        for name in &["main", "foo", "bar", "baz"] {
+            runs.add_value(name, Value::Number(0.into()));
+        }
+        koto.prelude().add_map("runs", runs.clone());
+        koto.set_script_path(Some(script_path.clone()));
+        koto.compile(&fs::read_to_string(&script_path).unwrap())
+            .unwrap();
+        koto.run().unwrap();
+        assert_eq!(call(&mut koto, "get_result"), "111");
+        let counts = |runs: &ValueMap| {
+            ["main", "foo", "bar", "baz"]
+                .iter()
+                .map(|name| get_string(runs, name))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        assert_eq!(counts(&runs), "1 1 1 1");
+
+        // Nothing has changed
+        assert!(koto.reload_changed().unwrap().is_empty());
+        assert_eq!(counts(&runs), "1 1 1 1");
+
+        // Keep hold of foo's exports from the first run
+        let old_foo = koto.call_function_by_name("get_foo", &[]).unwrap();
+
+        // Changing a nested module causes its importers to be run again
+        write_script(
+            dir.path(),
+            "baz.koto",
+            &format!("{}export z = 200", count_runs("baz")),
+        );
+        let changed = koto.reload_changed().unwrap();
+        assert_eq!(changed.len(), 1);
+        assert!(changed[0].ends_with("baz.koto"));
+        assert_eq!(call(&mut koto, "get_result"), "211");
+        // bar is unaffected by the change, so it isn't run again
+        assert_eq!(counts(&runs), "2 2 1 2");
+        // Values from before the reload remain valid
+        match old_foo {
+            Value::Map(old_foo) => assert_eq!(get_string(&old_foo, "x"), "101"),
+            other => panic!("Expected a map, found {}", other),
+        }
+
+        // Changing the script causes it to be recompiled and run
+        write_script(
+            dir.path(),
+            "main.koto",
+            &format!(
+                "{}import foo, bar\nexport get_result = || foo.x - bar.y\nexport get_foo = || foo",
+                count_runs("main")
+            ),
+        );
+        let changed = koto.reload_changed().unwrap();
+        assert_eq!(changed, vec![script_path]);
+        assert_eq!(call(&mut koto, "get_result"), "191");
+        assert_eq!(counts(&runs), "3 2 1 2");
+    }
+}
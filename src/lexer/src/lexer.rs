@@ -494,6 +494,10 @@ impl<'a> Iterator for TokenLexer<'a> {
                     Some('"') => Some(self.consume_string(chars)),
                     Some('0'..='9') => Some(self.consume_number(chars)),
                     Some(c) if is_id_start(*c) => Some(self.consume_id_or_keyword(chars)),
+                    // IDs can start with an underscore, while '_' by itself is a wildcard
+                    Some('_') if remaining[1..].starts_with(is_id_continue) => {
+                        Some(self.consume_id_or_keyword(chars))
+                    }
                     Some(_) => {
                         if let Some(id) = self.consume_symbol(remaining) {
                             Some(id)
@@ -777,7 +781,7 @@ mod tests {
 
     #[test]
     fn ids() {
-        let input = "id id1 id_2 i_d_3 ïd_ƒôûr if iff _";
+        let input = "id id1 id_2 i_d_3 ïd_ƒôûr if iff _ _id __";
         check_lexer_output(
             input,
             &[
@@ -789,6 +793,8 @@ mod tests {
                 (If, None, 1),
                 (Id, Some("iff"), 1),
                 (Wildcard, None, 1),
+                (Id, Some("_id"), 1),
+                (Id, Some("__"), 1),
             ],
         );
     }
@@ -0,0 +1,691 @@
+use {
+    koto_bytecode::{Compiler, CompilerSettings, ModuleResolver},
+    koto_parser::{
+        Ast, AstIndex, ConstantIndex, ConstantPool, LookupNode, MapKey, Node, Parser, Position,
+        Scope, Span,
+    },
+    koto_runtime::{
+        core::{CoreLib, CoreModules},
+        Value,
+    },
+    std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+    },
+};
+
+/// A problem that was found while parsing or compiling a script
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DefinitionKind {
+    Local,
+    Argument,
+    Export,
+    /// An imported value, along with the import's full path, e.g. `["foo", "bar"]`
+    Import(Vec<String>),
+    LoopVariable,
+    CatchVariable,
+    MatchBinding,
+}
+
+/// A named value that's defined in a script
+#[derive(Clone, Debug)]
+pub struct Definition {
+    pub name: String,
+    pub span: Span,
+    pub kind: DefinitionKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReferenceTarget {
+    /// A value defined in the script, the index refers to [Analysis::definitions]
+    Definition { index: usize, captured: bool },
+    /// A core library module, e.g. `string`
+    CoreModule(String),
+    /// An item in a core library module, e.g. `string.print`
+    CoreItem { module: String, name: String },
+    /// An ID that couldn't be resolved
+    Unresolved(String),
+}
+
+/// A usage of a named value
+#[derive(Clone, Debug)]
+pub struct Reference {
+    pub span: Span,
+    pub target: ReferenceTarget,
+}
+
+/// The result of analyzing a script
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Analysis {
+    /// Parses and compiles the script, and then collects the script's definitions and references
+    ///
+    /// Parsing continues past errors, with each error reported as a diagnostic, and the lines
+    /// that could be parsed are still analyzed.
+    pub fn new(source: &str, core: &CoreInfo) -> Self {
+        let (ast, constants, errors) = Parser::parse_with_recovery(source);
+
+        let mut diagnostics: Vec<Diagnostic> = errors
+            .iter()
+            .map(|error| Diagnostic {
+                message: error.to_string(),
+                span: error.span,
+            })
+            .collect();
+
+        // The AST is incomplete if there were parser errors, so it's only compiled when parsing
+        // succeeded.
+        if diagnostics.is_empty() {
+            if let Err(error) = Compiler::compile(&ast, &constants, CompilerSettings::default()) {
+                diagnostics.push(Diagnostic {
+                    message: error.message,
+                    span: error.span,
+                });
+            }
+        }
+
+        let mut walker = Walker {
+            ast: &ast,
+            constants: &constants,
+            source,
+            core,
+            frames: vec![Frame::default()],
+            exports: HashMap::new(),
+            result: Self {
+                diagnostics,
+                ..Default::default()
+            },
+        };
+
+        if let Some(entry_point) = ast.entry_point() {
+            if let Node::MainBlock { body, .. } = &entry_point.node {
+                for node in body.iter() {
+                    walker.visit(*node);
+                }
+            }
+        }
+
+        walker.result
+    }
+
+    /// Returns the reference or definition at the given position
+    pub fn target_at(&self, position: Position) -> Option<ReferenceTarget> {
+        if let Some(reference) = self
+            .references
+            .iter()
+            .find(|reference| span_contains(&reference.span, position))
+        {
+            return Some(reference.target.clone());
+        }
+
+        self.definitions
+            .iter()
+            .position(|definition| span_contains(&definition.span, position))
+            .map(|index| ReferenceTarget::Definition {
+                index,
+                captured: false,
+            })
+    }
+
+    /// Returns the definition of an exported value
+    pub fn export(&self, name: &str) -> Option<&Definition> {
+        self.definitions
+            .iter()
+            .find(|definition| definition.name == name && definition.kind == DefinitionKind::Export)
+    }
+}
+
+fn span_contains(span: &Span, position: Position) -> bool {
+    let after_start = (position.line, position.column) >= (span.start.line, span.start.column);
+    let before_end = (position.line, position.column) < (span.end.line, span.end.column);
+    after_start && before_end
+}
+
+/// The names of the items in the core library's modules
+pub struct CoreInfo {
+    /// The core modules, with each module's items and the items' types
+    pub modules: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl Default for CoreInfo {
+    fn default() -> Self {
+        let prelude = CoreLib::default().make_prelude(CoreModules::all());
+
+        let mut modules = prelude
+            .contents()
+            .data
+            .iter()
+            .filter_map(|(key, value)| match (key.value(), value) {
+                (Value::Str(name), Value::Map(module)) => {
+                    let mut items = module
+                        .contents()
+                        .data
+                        .iter()
+                        .filter_map(|(key, value)| match key.value() {
+                            Value::Str(name) => Some((name.to_string(), value.type_as_string())),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>();
+                    items.sort();
+                    Some((name.to_string(), items))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        modules.sort();
+
+        Self { modules }
+    }
+}
+
+impl CoreInfo {
+    pub fn module(&self, name: &str) -> Option<&[(String, String)]> {
+        self.modules
+            .iter()
+            .find(|(module_name, _)| module_name == name)
+            .map(|(_, items)| items.as_slice())
+    }
+
+    pub fn has_item(&self, module: &str, name: &str) -> bool {
+        match self.module(module) {
+            Some(items) => items.iter().any(|(item, _)| item == name),
+            None => false,
+        }
+    }
+}
+
+/// The location of an import target
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImportTarget {
+    /// A module that was found via a [ModuleResolver], with an optional exported item
+    Module { path: PathBuf, item: Option<Span> },
+    /// A core library module or item
+    Core(ReferenceTarget),
+}
+
+/// Resolves an import path in the same way as the runtime
+///
+/// The first element in the import path is looked up in the core library and then via the
+/// provided resolver. If an item in a module is imported, then the module is analyzed to find the
+/// item's export.
+pub fn resolve_import(
+    import_path: &[String],
+    script_path: Option<&Path>,
+    core: &CoreInfo,
+    resolver: &dyn ModuleResolver,
+) -> Option<ImportTarget> {
+    let (module, items) = import_path.split_first()?;
+
+    if core.module(module).is_some() {
+        return Some(ImportTarget::Core(match items.first() {
+            Some(item) => ReferenceTarget::CoreItem {
+                module: module.clone(),
+                name: item.clone(),
+            },
+            None => ReferenceTarget::CoreModule(module.clone()),
+        }));
+    }
+
+    // Search from the script's directory, the script itself might not have been saved yet
+    let path = resolver
+        .resolve(module, script_path.and_then(Path::parent))
+        .ok()?;
+    let item = items.first().and_then(|item| {
+        let source = resolver.load(&path).ok()?;
+        let analysis = Analysis::new(&source, core);
+        analysis.export(item).map(|definition| definition.span)
+    });
+
+    Some(ImportTarget::Module { path, item })
+}
+
+// The locals that have been assigned in a function
+#[derive(Default)]
+struct Frame {
+    locals: HashMap<String, usize>,
+}
+
+struct Walker<'a> {
+    ast: &'a Ast,
+    constants: &'a ConstantPool,
+    source: &'a str,
+    core: &'a CoreInfo,
+    frames: Vec<Frame>,
+    exports: HashMap<String, usize>,
+    result: Analysis,
+}
+
+impl<'a> Walker<'a> {
+    fn visit(&mut self, index: AstIndex) {
+        let node = &self.ast.node(index).node;
+        let span = *self.ast.span(self.ast.node(index).span);
+
+        match node {
+            Node::Id(id) => {
+                self.add_reference(*id, span);
+            }
+            Node::Lookup((LookupNode::Root(root), next)) => {
+                self.visit_lookup(*root, *next);
+            }
+            Node::Lookup(_) => {
+                // Lookup chains are visited from their root, see visit_lookup
+            }
+            Node::Num2(elements)
+            | Node::Num4(elements)
+            | Node::List(elements)
+            | Node::Tuple(elements)
+            | Node::TempTuple(elements)
+            | Node::Block(elements) => {
+                for element in elements.iter() {
+                    self.visit(*element);
+                }
+            }
+            Node::Range { start, end, .. } => {
+                self.visit(*start);
+                self.visit(*end);
+            }
+            Node::RangeFrom { start } => self.visit(*start),
+            Node::RangeTo { end, .. } => self.visit(*end),
+            Node::Map(entries) => {
+                for (key, value) in entries.iter() {
+                    match (key, value) {
+                        (_, Some(value)) => self.visit(*value),
+                        // A map entry without a value refers to a value with the same name
+                        (MapKey::Id(id), None) => {
+                            if let Some(span) = self.find_id_span(*id, span.start) {
+                                self.add_reference(*id, span);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Node::MainBlock { body, .. } => {
+                for node in body.iter() {
+                    self.visit(*node);
+                }
+            }
+            Node::Function(function) => {
+                self.frames.push(Frame::default());
+                for arg in function.args.iter() {
+                    self.define_pattern(*arg, DefinitionKind::Argument);
+                }
+                self.visit(function.body);
+                self.frames.pop();
+            }
+            Node::Call { function, args } => {
+                self.visit(*function);
+                for arg in args.iter() {
+                    self.visit(*arg);
+                }
+            }
+            Node::Import { from, items } => {
+                let from = from
+                    .iter()
+                    .map(|id| self.constants.get_str(*id).to_string())
+                    .collect::<Vec<_>>();
+
+                for item in items.iter() {
+                    let (name, item_path) = match item.split_last() {
+                        Some((name, item_path)) => (*name, item_path),
+                        None => continue,
+                    };
+
+                    let mut import_path = from.clone();
+                    for id in item_path.iter().chain(std::iter::once(&name)) {
+                        import_path.push(self.constants.get_str(*id).to_string());
+                    }
+
+                    if let Some(name_span) = self.find_import_item_span(item, span.start) {
+                        self.define(name, name_span, DefinitionKind::Import(import_path));
+                    }
+                }
+            }
+            Node::Assign {
+                target, expression, ..
+            } => {
+                self.visit(*expression);
+                self.visit_assign_target(target.target_index, target.scope);
+            }
+            Node::MultiAssign {
+                targets,
+                expression,
+            } => {
+                self.visit(*expression);
+                for target in targets.iter() {
+                    self.visit_assign_target(target.target_index, target.scope);
+                }
+            }
+            Node::BinaryOp { lhs, rhs, .. } => {
+                self.visit(*lhs);
+                self.visit(*rhs);
+            }
+            Node::If(ast_if) => {
+                self.visit(ast_if.condition);
+                self.visit(ast_if.then_node);
+                for (condition, block) in ast_if.else_if_blocks.iter() {
+                    self.visit(*condition);
+                    self.visit(*block);
+                }
+                if let Some(else_node) = ast_if.else_node {
+                    self.visit(else_node);
+                }
+            }
+            Node::Match { expression, arms } => {
+                self.visit(*expression);
+                for arm in arms.iter() {
+                    for pattern in arm.patterns.iter() {
+                        self.define_pattern(*pattern, DefinitionKind::MatchBinding);
+                    }
+                    if let Some(condition) = arm.condition {
+                        self.visit(condition);
+                    }
+                    self.visit(arm.expression);
+                }
+            }
+            Node::Switch(arms) => {
+                for arm in arms.iter() {
+                    if let Some(condition) = arm.condition {
+                        self.visit(condition);
+                    }
+                    self.visit(arm.expression);
+                }
+            }
+            Node::For(ast_for) => {
+                self.visit(ast_for.range);
+                let mut search_position = span.start;
+                for arg in ast_for.args.iter().flatten() {
+                    if let Some(arg_span) = self.find_id_span(*arg, search_position) {
+                        search_position = arg_span.end;
+                        self.define(*arg, arg_span, DefinitionKind::LoopVariable);
+                    }
+                }
+                self.visit(ast_for.body);
+            }
+            Node::Loop { body } => self.visit(*body),
+            Node::While { condition, body } | Node::Until { condition, body } => {
+                self.visit(*condition);
+                self.visit(*body);
+            }
+            Node::Try(ast_try) => {
+                self.visit(ast_try.try_block);
+                if let Some(catch_arg) = ast_try.catch_arg {
+                    let catch_block_start =
+                        self.ast.span(self.ast.node(ast_try.catch_block).span).start;
+                    if let Some(arg_span) = self.find_catch_arg_span(catch_arg, catch_block_start) {
+                        self.define(catch_arg, arg_span, DefinitionKind::CatchVariable);
+                    }
+                }
+                self.visit(ast_try.catch_block);
+                if let Some(finally_block) = ast_try.finally_block {
+                    self.visit(finally_block);
+                }
+            }
+            Node::ReturnExpression(expression)
+            | Node::Negate(expression)
+            | Node::Throw(expression)
+            | Node::Yield(expression)
+            | Node::Debug { expression, .. } => self.visit(*expression),
+            Node::Empty
+            | Node::BoolTrue
+            | Node::BoolFalse
+            | Node::Number0
+            | Node::Number1
+            | Node::Int(_)
+            | Node::Float(_)
+            | Node::Str(_)
+            | Node::RangeFull
+            | Node::Wildcard
+            | Node::Ellipsis(_)
+            | Node::Break
+            | Node::Continue
+            | Node::Return => {}
+        }
+    }
+
+    fn visit_lookup(&mut self, root: AstIndex, next: Option<AstIndex>) {
+        self.visit(root);
+
+        // Lookups on core modules are resolved to the module's items, e.g. `string.print`
+        let mut core_module = match &self.ast.node(root).node {
+            Node::Id(id) => {
+                let name = self.constants.get_str(*id);
+                match self.result.references.last() {
+                    Some(Reference {
+                        target: ReferenceTarget::CoreModule(module),
+                        ..
+                    }) if module == name => Some(module.clone()),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        let mut next = next;
+        while let Some(index) = next {
+            let lookup_node = self.ast.node(index);
+            next = match &lookup_node.node {
+                Node::Lookup((lookup, next)) => {
+                    match lookup {
+                        LookupNode::Id(id) => {
+                            if let Some(module) = core_module.take() {
+                                let name = self.constants.get_str(*id);
+                                if self.core.has_item(&module, name) {
+                                    self.result.references.push(Reference {
+                                        span: *self.ast.span(lookup_node.span),
+                                        target: ReferenceTarget::CoreItem {
+                                            module,
+                                            name: name.to_string(),
+                                        },
+                                    });
+                                }
+                            }
+                        }
+                        LookupNode::Root(node) | LookupNode::Index(node) => self.visit(*node),
+                        LookupNode::Call(args) => {
+                            for arg in args.iter() {
+                                self.visit(*arg);
+                            }
+                        }
+                    }
+                    core_module = None;
+                    *next
+                }
+                _ => None,
+            };
+        }
+    }
+
+    fn visit_assign_target(&mut self, target: AstIndex, scope: Scope) {
+        let target_node = self.ast.node(target);
+        match &target_node.node {
+            Node::Id(id) => {
+                let span = *self.ast.span(target_node.span);
+                let kind = match scope {
+                    Scope::Export => DefinitionKind::Export,
+                    Scope::Local => DefinitionKind::Local,
+                };
+                self.define(*id, span, kind);
+            }
+            _ => self.visit(target),
+        }
+    }
+
+    fn define_pattern(&mut self, index: AstIndex, kind: DefinitionKind) {
+        let node = self.ast.node(index);
+        match &node.node {
+            Node::Id(id) => {
+                let span = *self.ast.span(node.span);
+                self.define(*id, span, kind);
+            }
+            Node::Ellipsis(Some(id)) => {
+                let span = *self.ast.span(node.span);
+                let span = self.find_id_span(*id, span.start).unwrap_or(span);
+                self.define(*id, span, kind);
+            }
+            Node::List(elements) | Node::Tuple(elements) => {
+                for element in elements.iter() {
+                    self.define_pattern(*element, kind.clone());
+                }
+            }
+            _ => self.visit(index),
+        }
+    }
+
+    fn define(&mut self, id: ConstantIndex, span: Span, kind: DefinitionKind) {
+        let name = self.constants.get_str(id).to_string();
+
+        let existing = if kind == DefinitionKind::Export {
+            self.exports.get(&name)
+        } else {
+            self.frames.last().unwrap().locals.get(&name)
+        };
+
+        // Reassigned values refer to their first definition
+        if let Some(index) = existing {
+            self.result.references.push(Reference {
+                span,
+                target: ReferenceTarget::Definition {
+                    index: *index,
+                    captured: false,
+                },
+            });
+            return;
+        }
+
+        let index = self.result.definitions.len();
+        if kind == DefinitionKind::Export {
+            self.exports.insert(name.clone(), index);
+        } else {
+            self.frames
+                .last_mut()
+                .unwrap()
+                .locals
+                .insert(name.clone(), index);
+        }
+        self.result
+            .definitions
+            .push(Definition { name, span, kind });
+    }
+
+    fn add_reference(&mut self, id: ConstantIndex, span: Span) {
+        let name = self.constants.get_str(id);
+
+        let mut target = None;
+        for (depth, frame) in self.frames.iter().rev().enumerate() {
+            if let Some(index) = frame.locals.get(name) {
+                target = Some(ReferenceTarget::Definition {
+                    index: *index,
+                    captured: depth > 0,
+                });
+                break;
+            }
+        }
+
+        let target = target.unwrap_or_else(|| match self.exports.get(name) {
+            Some(index) => ReferenceTarget::Definition {
+                index: *index,
+                captured: false,
+            },
+            None if self.core.module(name).is_some() => {
+                ReferenceTarget::CoreModule(name.to_string())
+            }
+            None => ReferenceTarget::Unresolved(name.to_string()),
+        });
+
+        self.result.references.push(Reference { span, target });
+    }
+
+    // IDs that aren't stored as nodes don't have their own spans,
+    // so their positions are found by searching the source.
+    fn find_id_span(&self, id: ConstantIndex, search_from: Position) -> Option<Span> {
+        find_word(self.source, self.constants.get_str(id), search_from)
+    }
+
+    // Finds the span of the last ID in an import item, e.g. `bar` in `import foo.bar`
+    fn find_import_item_span(
+        &self,
+        item: &[ConstantIndex],
+        import_start: Position,
+    ) -> Option<Span> {
+        let line = self.source.lines().nth(import_start.line as usize - 1)?;
+        let import_column = line.find("import")?;
+        let search_from = Position {
+            line: import_start.line,
+            column: line[..import_column].chars().count() as u32 + 1 + "import".len() as u32,
+        };
+
+        let path = item
+            .iter()
+            .map(|id| self.constants.get_str(*id))
+            .collect::<Vec<_>>()
+            .join(".");
+        let path_span = find_word(self.source, &path, search_from)?;
+        let name_length = self.constants.get_str(*item.last()?).chars().count() as u32;
+
+        Some(Span {
+            start: Position {
+                line: path_span.end.line,
+                column: path_span.end.column - name_length,
+            },
+            end: path_span.end,
+        })
+    }
+
+    fn find_catch_arg_span(&self, id: ConstantIndex, catch_block_start: Position) -> Option<Span> {
+        // The catch arg follows the `catch` keyword on the line before the catch block
+        let catch_line = catch_block_start.line.saturating_sub(1).max(1);
+        let line = self.source.lines().nth(catch_line as usize - 1)?;
+        let catch_column = line.find("catch")?;
+        let search_from = Position {
+            line: catch_line,
+            column: line[..catch_column].chars().count() as u32 + 1 + "catch".len() as u32,
+        };
+        self.find_id_span(id, search_from)
+    }
+}
+
+// Finds a word in the source, starting at the given position
+//
+// The search is limited to the line containing the starting position.
+fn find_word(source: &str, word: &str, from: Position) -> Option<Span> {
+    let line = source.lines().nth((from.line as usize).checked_sub(1)?)?;
+    let chars = line.chars().collect::<Vec<_>>();
+    let word_chars = word.chars().collect::<Vec<_>>();
+    let is_id_char = |c: char| c.is_alphanumeric() || c == '_';
+
+    let mut column = (from.column as usize).saturating_sub(1);
+    while column + word_chars.len() <= chars.len() {
+        let is_match = chars[column..column + word_chars.len()] == word_chars[..]
+            && (column == 0 || !is_id_char(chars[column - 1]))
+            && !matches!(chars.get(column + word_chars.len()), Some(c) if is_id_char(*c));
+
+        if is_match {
+            return Some(Span {
+                start: Position {
+                    line: from.line,
+                    column: column as u32 + 1,
+                },
+                end: Position {
+                    line: from.line,
+                    column: (column + word_chars.len()) as u32 + 1,
+                },
+            });
+        }
+
+        column += 1;
+    }
+
+    None
+}
@@ -0,0 +1,14 @@
+//! A language server for the Koto programming language
+//!
+//! The server provides diagnostics, go-to-definition, hover, and completion support,
+//! communicating with the editor via the Language Server Protocol.
+
+mod analysis;
+mod server;
+
+pub use {
+    analysis::{
+        Analysis, CoreInfo, Definition, DefinitionKind, Diagnostic, Reference, ReferenceTarget,
+    },
+    server::Server,
+};
@@ -0,0 +1,15 @@
+use {
+    koto_bytecode::SearchPathResolver,
+    koto_lsp::Server,
+    std::{io, sync::Arc},
+};
+
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+
+    // Modules are found in the same way as the CLI, including the paths listed in KOTO_PATH
+    let mut server = Server::with_module_resolver(Arc::new(SearchPathResolver::from_env()));
+    let clean_exit = server.run(&mut stdin.lock(), &mut stdout.lock());
+    std::process::exit(if clean_exit { 0 } else { 1 });
+}
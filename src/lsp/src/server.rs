@@ -0,0 +1,474 @@
+use {
+    crate::analysis::{
+        resolve_import, Analysis, CoreInfo, DefinitionKind, ImportTarget, ReferenceTarget,
+    },
+    koto_bytecode::{FileSystemResolver, ModuleResolver},
+    koto_parser::{Position, Span},
+    serde_json::{json, Value as JsonValue},
+    std::{
+        collections::HashMap,
+        io::{BufRead, Write},
+        path::{Path, PathBuf},
+        sync::Arc,
+    },
+};
+
+// See https://microsoft.github.io/language-server-protocol/specification
+const TEXT_DOCUMENT_SYNC_FULL: u64 = 1;
+const DIAGNOSTIC_SEVERITY_ERROR: u64 = 1;
+const COMPLETION_KIND_FUNCTION: u64 = 3;
+const COMPLETION_KIND_VARIABLE: u64 = 6;
+const COMPLETION_KIND_MODULE: u64 = 9;
+const ERROR_METHOD_NOT_FOUND: i64 = -32601;
+
+struct Document {
+    text: String,
+    path: Option<PathBuf>,
+    analysis: Analysis,
+}
+
+/// A language server for Koto that communicates via JSON-RPC
+pub struct Server {
+    documents: HashMap<String, Document>,
+    core: CoreInfo,
+    module_resolver: Arc<dyn ModuleResolver>,
+    shutdown_requested: bool,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::with_module_resolver(Arc::new(FileSystemResolver))
+    }
+}
+
+impl Server {
+    /// Makes a server that finds imported modules with the given resolver
+    ///
+    /// The resolver should match the one used when running scripts, e.g. the CLI uses a
+    /// `SearchPathResolver` that searches the paths in `KOTO_PATH`.
+    pub fn with_module_resolver(module_resolver: Arc<dyn ModuleResolver>) -> Self {
+        Self {
+            documents: HashMap::new(),
+            core: CoreInfo::default(),
+            module_resolver,
+            shutdown_requested: false,
+        }
+    }
+
+    /// Runs the server until an exit notification is received, or until the input is closed
+    ///
+    /// Returns true if the server was shut down cleanly.
+    pub fn run(&mut self, input: &mut impl BufRead, output: &mut impl Write) -> bool {
+        while let Some(message) = read_message(input) {
+            let method = message["method"].as_str().unwrap_or_default();
+            if method == "exit" {
+                return self.shutdown_requested;
+            }
+
+            let (result, notifications) = self.handle_message(method, &message["params"]);
+
+            if let Some(id) = message.get("id") {
+                let response = match result {
+                    Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                    Err((code, message)) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {"code": code, "message": message},
+                    }),
+                };
+                write_message(output, &response);
+            }
+
+            for notification in notifications {
+                write_message(output, &notification);
+            }
+        }
+
+        false
+    }
+
+    // Handles a request or notification, returning a result and any notifications to send
+    fn handle_message(
+        &mut self,
+        method: &str,
+        params: &JsonValue,
+    ) -> (Result<JsonValue, (i64, String)>, Vec<JsonValue>) {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": TEXT_DOCUMENT_SYNC_FULL,
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": {"triggerCharacters": ["."]},
+                },
+                "serverInfo": {"name": "koto-lsp", "version": env!("CARGO_PKG_VERSION")},
+            }),
+            "shutdown" => {
+                self.shutdown_requested = true;
+                JsonValue::Null
+            }
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                return (Ok(JsonValue::Null), vec![self.update_document(uri, text)]);
+            }
+            "textDocument/didChange" => {
+                // Full text sync is used, so the last change contains the document's contents
+                let changes = params["contentChanges"].as_array();
+                match changes.and_then(|changes| changes.last()) {
+                    Some(change) => {
+                        let text = change["text"].as_str().unwrap_or_default();
+                        return (Ok(JsonValue::Null), vec![self.update_document(uri, text)]);
+                    }
+                    None => JsonValue::Null,
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return (
+                    Ok(JsonValue::Null),
+                    vec![diagnostics_notification(&uri, &[])],
+                );
+            }
+            "textDocument/definition" => self
+                .definition(&uri, position_from_lsp(&params["position"]))
+                .unwrap_or(JsonValue::Null),
+            "textDocument/hover" => self
+                .hover(&uri, position_from_lsp(&params["position"]))
+                .unwrap_or(JsonValue::Null),
+            "textDocument/completion" => {
+                self.completion(&uri, position_from_lsp(&params["position"]))
+            }
+            "initialized" | "$/cancelRequest" | "textDocument/didSave" => JsonValue::Null,
+            unsupported => {
+                return (
+                    Err((
+                        ERROR_METHOD_NOT_FOUND,
+                        format!("Unsupported method '{}'", unsupported),
+                    )),
+                    vec![],
+                )
+            }
+        };
+
+        (Ok(result), vec![])
+    }
+
+    fn update_document(&mut self, uri: String, text: &str) -> JsonValue {
+        let analysis = Analysis::new(text, &self.core);
+        let notification = diagnostics_notification(&uri, &analysis.diagnostics);
+
+        let path = path_from_uri(&uri);
+        match self.documents.get_mut(&uri) {
+            // When the script can't be parsed, the previous analysis is kept so that
+            // definitions and completions remain available while the script is being edited.
+            Some(document)
+                if analysis.definitions.is_empty() && !analysis.diagnostics.is_empty() =>
+            {
+                document.text = text.to_string();
+                document.analysis.diagnostics = analysis.diagnostics;
+            }
+            _ => {
+                self.documents.insert(
+                    uri,
+                    Document {
+                        text: text.to_string(),
+                        path,
+                        analysis,
+                    },
+                );
+            }
+        }
+
+        notification
+    }
+
+    fn definition(&self, uri: &str, position: Position) -> Option<JsonValue> {
+        let document = self.documents.get(uri)?;
+        let analysis = &document.analysis;
+
+        match analysis.target_at(position)? {
+            ReferenceTarget::Definition { index, .. } => {
+                let definition = &analysis.definitions[index];
+                if let DefinitionKind::Import(import_path) = &definition.kind {
+                    if let Some(location) = self.import_location(document, import_path) {
+                        return Some(location);
+                    }
+                }
+                Some(location_json(uri, &definition.span))
+            }
+            _ => None,
+        }
+    }
+
+    fn import_location(&self, document: &Document, import_path: &[String]) -> Option<JsonValue> {
+        // Exported values in the script take precedence over modules, matching the runtime
+        if let Some(export) = document.analysis.export(import_path.first()?) {
+            if import_path.len() == 1 {
+                return Some(location_json(
+                    &path_to_uri(document.path.as_deref()?),
+                    &export.span,
+                ));
+            }
+        }
+
+        match resolve_import(
+            import_path,
+            document.path.as_deref(),
+            &self.core,
+            self.module_resolver.as_ref(),
+        )? {
+            ImportTarget::Module { path, item } => {
+                let span = item.unwrap_or_default();
+                Some(location_json(&path_to_uri(&path), &span))
+            }
+            ImportTarget::Core(_) => None,
+        }
+    }
+
+    fn hover(&self, uri: &str, position: Position) -> Option<JsonValue> {
+        let document = self.documents.get(uri)?;
+        let analysis = &document.analysis;
+
+        let contents = match analysis.target_at(position)? {
+            ReferenceTarget::Definition { index, captured } => {
+                let definition = &analysis.definitions[index];
+                let name = &definition.name;
+                let line = definition.span.start.line;
+                let origin = match &definition.kind {
+                    DefinitionKind::Local => format!("local value, assigned on line {}", line),
+                    DefinitionKind::Argument => format!("function argument, line {}", line),
+                    DefinitionKind::Export => format!("exported value, assigned on line {}", line),
+                    DefinitionKind::LoopVariable => format!("loop variable, line {}", line),
+                    DefinitionKind::CatchVariable => format!("caught error, line {}", line),
+                    DefinitionKind::MatchBinding => format!("match binding, line {}", line),
+                    DefinitionKind::Import(import_path) => {
+                        let target = resolve_import(
+                            import_path,
+                            document.path.as_deref(),
+                            &self.core,
+                            self.module_resolver.as_ref(),
+                        );
+                        let import_path = import_path.join(".");
+                        match target {
+                            Some(ImportTarget::Module { path, .. }) => format!(
+                                "imported from `{}` ({})",
+                                import_path,
+                                path.to_string_lossy()
+                            ),
+                            Some(ImportTarget::Core(_)) => {
+                                format!("imported from `{}` in the core library", import_path)
+                            }
+                            None => format!("imported from `{}`", import_path),
+                        }
+                    }
+                };
+                let captured = if captured { ", captured" } else { "" };
+                format!("`{}`: {}{}", name, origin, captured)
+            }
+            ReferenceTarget::CoreModule(module) => format!("`{}`: core library module", module),
+            ReferenceTarget::CoreItem { module, name } => {
+                let item_type = self
+                    .core
+                    .module(&module)
+                    .and_then(|items| items.iter().find(|(item, _)| *item == name))
+                    .map(|(_, item_type)| item_type.clone())
+                    .unwrap_or_default();
+                format!("`{}.{}`: {} in the core library", module, name, item_type)
+            }
+            ReferenceTarget::Unresolved(name) => format!("`{}`: not found", name),
+        };
+
+        Some(json!({"contents": {"kind": "markdown", "value": contents}}))
+    }
+
+    fn completion(&self, uri: &str, position: Position) -> JsonValue {
+        let document = match self.documents.get(uri) {
+            Some(document) => document,
+            None => return json!([]),
+        };
+
+        // Completing a lookup on a core module?
+        if let Some(module) = lookup_root_before(&document.text, position) {
+            let shadowed = document
+                .analysis
+                .definitions
+                .iter()
+                .any(|definition| definition.name == module);
+            return match self.core.module(&module) {
+                Some(items) if !shadowed => items
+                    .iter()
+                    .map(|(name, item_type)| {
+                        let kind = if item_type.ends_with("Function") {
+                            COMPLETION_KIND_FUNCTION
+                        } else {
+                            COMPLETION_KIND_VARIABLE
+                        };
+                        json!({"label": name, "kind": kind, "detail": item_type})
+                    })
+                    .collect::<Vec<_>>()
+                    .into(),
+                _ => json!([]),
+            };
+        }
+
+        let mut names = Vec::new();
+        let mut items = Vec::new();
+        for definition in document.analysis.definitions.iter() {
+            if !names.contains(&definition.name) {
+                names.push(definition.name.clone());
+                items.push(json!({"label": definition.name, "kind": COMPLETION_KIND_VARIABLE}));
+            }
+        }
+        for (module, _) in self.core.modules.iter() {
+            if !names.contains(module) {
+                items.push(json!({
+                    "label": module,
+                    "kind": COMPLETION_KIND_MODULE,
+                    "detail": "core library module",
+                }));
+            }
+        }
+
+        items.into()
+    }
+}
+
+// Returns the ID that precedes a `.` before the given position, e.g. `string` in `string.pr`
+fn lookup_root_before(text: &str, position: Position) -> Option<String> {
+    let line = text.lines().nth((position.line as usize).checked_sub(1)?)?;
+    let before = line
+        .chars()
+        .take((position.column as usize).saturating_sub(1))
+        .collect::<String>();
+    let is_id_char = |c: char| c.is_alphanumeric() || c == '_';
+
+    let before = before.trim_end_matches(is_id_char);
+    let before = before.strip_suffix('.')?;
+    let root_start = before
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_id_char(*c))
+        .last()?
+        .0;
+    Some(before[root_start..].to_string())
+}
+
+fn read_message(input: &mut impl BufRead) -> Option<JsonValue> {
+    let mut content_length = None;
+
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+
+        let header = header.trim();
+        if header.is_empty() {
+            if content_length.is_some() {
+                break;
+            } else {
+                continue;
+            }
+        }
+
+        if let Some(length) = header.strip_prefix("Content-Length:") {
+            content_length = length.trim().parse::<usize>().ok();
+        }
+    }
+
+    let mut content = vec![0; content_length?];
+    input.read_exact(&mut content).ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+fn write_message(output: &mut impl Write, message: &JsonValue) {
+    let message = message.to_string();
+    // Errors are ignored, a closed output means that the client has gone away
+    let _ = write!(
+        output,
+        "Content-Length: {}\r\n\r\n{}",
+        message.len(),
+        message
+    );
+    let _ = output.flush();
+}
+
+fn diagnostics_notification(uri: &str, diagnostics: &[crate::analysis::Diagnostic]) -> JsonValue {
+    let diagnostics = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            json!({
+                "range": range_json(&diagnostic.span),
+                "severity": DIAGNOSTIC_SEVERITY_ERROR,
+                "source": "koto",
+                "message": diagnostic.message,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": {"uri": uri, "diagnostics": diagnostics},
+    })
+}
+
+// LSP positions are 0-based, while Koto's positions start at 1
+fn position_from_lsp(position: &JsonValue) -> Position {
+    Position {
+        line: position["line"].as_u64().unwrap_or_default() as u32 + 1,
+        column: position["character"].as_u64().unwrap_or_default() as u32 + 1,
+    }
+}
+
+fn position_json(position: &Position) -> JsonValue {
+    json!({
+        "line": position.line.saturating_sub(1),
+        "character": position.column.saturating_sub(1),
+    })
+}
+
+fn range_json(span: &Span) -> JsonValue {
+    json!({"start": position_json(&span.start), "end": position_json(&span.end)})
+}
+
+fn location_json(uri: &str, span: &Span) -> JsonValue {
+    json!({"uri": uri, "range": range_json(span)})
+}
+
+fn path_from_uri(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+
+    // Decode percent-encoded bytes, e.g. %20 for spaces
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut input = path.bytes();
+    while let Some(byte) = input.next() {
+        if byte == b'%' {
+            let hex = [input.next()?, input.next()?];
+            let decoded = u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?;
+            bytes.push(decoded);
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
@@ -0,0 +1,429 @@
+use {
+    koto_bytecode::SearchPathResolver,
+    koto_lsp::Server,
+    serde_json::{json, Value},
+    std::{
+        fs,
+        io::{BufRead, Cursor, Read},
+        path::Path,
+        sync::Arc,
+    },
+    tempfile::TempDir,
+};
+
+fn encode_messages(messages: &[Value]) -> Vec<u8> {
+    let mut result = Vec::new();
+    for message in messages.iter() {
+        let message = message.to_string();
+        result.extend_from_slice(format!("Content-Length: {}\r\n\r\n", message.len()).as_bytes());
+        result.extend_from_slice(message.as_bytes());
+    }
+    result
+}
+
+fn decode_messages(output: &[u8]) -> Vec<Value> {
+    let mut result = Vec::new();
+    let mut output = Cursor::new(output);
+    loop {
+        let mut header = String::new();
+        if output.read_line(&mut header).unwrap() == 0 {
+            break;
+        }
+        let length = header
+            .trim()
+            .strip_prefix("Content-Length:")
+            .unwrap()
+            .trim()
+            .parse::<usize>()
+            .unwrap();
+        output.read_line(&mut header).unwrap();
+
+        let mut content = vec![0; length];
+        output.read_exact(&mut content).unwrap();
+        result.push(serde_json::from_slice(&content).unwrap());
+    }
+    result
+}
+
+fn uri(path: &Path) -> String {
+    format!("file://{}", path.to_string_lossy())
+}
+
+// Runs a session that opens the document, sends the given requests, and then shuts down
+//
+// The responses to the requests are returned, along with the published diagnostics.
+fn run_session(
+    document_uri: &str,
+    script: &str,
+    requests: &[(&str, Value)],
+) -> (Vec<Value>, Value) {
+    run_session_with_server(Server::default(), document_uri, script, requests)
+}
+
+fn run_session_with_server(
+    mut server: Server,
+    document_uri: &str,
+    script: &str,
+    requests: &[(&str, Value)],
+) -> (Vec<Value>, Value) {
+    let mut messages = vec![
+        json!({"jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {}}),
+        json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {"textDocument": {
+                "uri": document_uri,
+                "languageId": "koto",
+                "version": 1,
+                "text": script,
+            }},
+        }),
+    ];
+    for (i, (method, params)) in requests.iter().enumerate() {
+        messages.push(json!({
+            "jsonrpc": "2.0",
+            "id": i + 1,
+            "method": method,
+            "params": params,
+        }));
+    }
+    messages.push(json!({"jsonrpc": "2.0", "id": 1000, "method": "shutdown"}));
+    messages.push(json!({"jsonrpc": "2.0", "method": "exit"}));
+
+    let input = encode_messages(&messages);
+    let mut output = Vec::new();
+    assert!(server.run(&mut Cursor::new(input), &mut output));
+
+    let output = decode_messages(&output);
+    let diagnostics = output
+        .iter()
+        .find(|message| message["method"] == "textDocument/publishDiagnostics")
+        .expect("Missing diagnostics")["params"]["diagnostics"]
+        .clone();
+    let responses = (1..=requests.len())
+        .map(|id| {
+            output
+                .iter()
+                .find(|message| message["id"] == id)
+                .expect("Missing response")["result"]
+                .clone()
+        })
+        .collect();
+
+    (responses, diagnostics)
+}
+
+fn position_params(document_uri: &str, line: u64, character: u64) -> Value {
+    json!({
+        "textDocument": {"uri": document_uri},
+        "position": {"line": line, "character": character},
+    })
+}
+
+fn range(start: (u64, u64), end: (u64, u64)) -> Value {
+    json!({
+        "start": {"line": start.0, "character": start.1},
+        "end": {"line": end.0, "character": end.1},
+    })
+}
+
+mod lsp_tests {
+    use super::*;
+
+    const TEST_URI: &str = "file:///test.koto";
+
+    mod diagnostics {
+        use super::*;
+
+        #[test]
+        fn valid_script() {
+            let (_, diagnostics) = run_session(TEST_URI, "x = 1 + 2", &[]);
+            assert_eq!(diagnostics, json!([]));
+        }
+
+        #[test]
+        fn parser_error() {
+            let script = "
+x = 1
+f = |a
+";
+            let (_, diagnostics) = run_session(TEST_URI, script, &[]);
+            let diagnostics = diagnostics.as_array().unwrap();
+            assert_eq!(diagnostics.len(), 1);
+            assert_eq!(diagnostics[0]["range"]["start"]["line"], 2);
+            assert_eq!(diagnostics[0]["severity"], 1);
+        }
+
+        #[test]
+        fn multiple_parser_errors() {
+            let script = "
+x = (1 +
+y = 2
+z = [3,
+";
+            let (_, diagnostics) = run_session(TEST_URI, script, &[]);
+            let diagnostics = diagnostics.as_array().unwrap();
+            assert_eq!(diagnostics.len(), 2);
+            assert_eq!(diagnostics[0]["range"]["start"]["line"], 1);
+            assert_eq!(diagnostics[1]["range"]["start"]["line"], 3);
+        }
+
+        #[test]
+        fn compiler_error() {
+            let script = "
+x = 1
+break
+";
+            let (_, diagnostics) = run_session(TEST_URI, script, &[]);
+            let diagnostics = diagnostics.as_array().unwrap();
+            assert_eq!(diagnostics.len(), 1);
+            assert_eq!(diagnostics[0]["range"]["start"]["line"], 2);
+        }
+    }
+
+    mod definition {
+        use super::*;
+
+        #[test]
+        fn locals_and_args() {
+            let script = "\
+x = 42
+f = |n|
+  n + x
+f x
+";
+            let (responses, _) = run_session(
+                TEST_URI,
+                script,
+                &[
+                    // n in `n + x`
+                    ("textDocument/definition", position_params(TEST_URI, 2, 2)),
+                    // x in `n + x`
+                    ("textDocument/definition", position_params(TEST_URI, 2, 6)),
+                    // f in `f x`
+                    ("textDocument/definition", position_params(TEST_URI, 3, 0)),
+                ],
+            );
+
+            assert_eq!(
+                responses,
+                vec![
+                    json!({"uri": TEST_URI, "range": range((1, 5), (1, 6))}),
+                    json!({"uri": TEST_URI, "range": range((0, 0), (0, 1))}),
+                    json!({"uri": TEST_URI, "range": range((1, 0), (1, 1))}),
+                ]
+            );
+        }
+
+        #[test]
+        fn loop_variables() {
+            let script = "\
+for i, x in (1..10).enumerate()
+  x + i
+";
+            let (responses, _) = run_session(
+                TEST_URI,
+                script,
+                &[("textDocument/definition", position_params(TEST_URI, 1, 6))],
+            );
+            assert_eq!(
+                responses[0],
+                json!({"uri": TEST_URI, "range": range((0, 4), (0, 5))})
+            );
+        }
+
+        #[test]
+        fn exports() {
+            let script = "\
+export foo = 99
+bar = ||
+  foo
+";
+            let (responses, _) = run_session(
+                TEST_URI,
+                script,
+                &[("textDocument/definition", position_params(TEST_URI, 2, 2))],
+            );
+            assert_eq!(
+                responses[0],
+                json!({"uri": TEST_URI, "range": range((0, 7), (0, 10))})
+            );
+        }
+
+        #[test]
+        fn import_targets() {
+            let dir = TempDir::new().unwrap();
+            let module_path = dir.path().join("foo.koto");
+            fs::write(&module_path, "x = 1\nexport bar = || x\n").unwrap();
+            let dir_module_path = dir.path().join("baz").join("main.koto");
+            fs::create_dir(dir.path().join("baz")).unwrap();
+            fs::write(&dir_module_path, "export qux = 1\n").unwrap();
+
+            let script_uri = uri(&dir.path().join("main.koto"));
+            let script = "\
+import foo.bar, baz
+bar()
+baz.qux
+";
+            let (responses, _) = run_session(
+                &script_uri,
+                script,
+                &[
+                    (
+                        "textDocument/definition",
+                        position_params(&script_uri, 1, 0),
+                    ),
+                    (
+                        "textDocument/definition",
+                        position_params(&script_uri, 2, 0),
+                    ),
+                ],
+            );
+            assert_eq!(
+                responses,
+                vec![
+                    json!({"uri": uri(&module_path), "range": range((1, 7), (1, 10))}),
+                    json!({"uri": uri(&dir_module_path), "range": range((0, 0), (0, 0))}),
+                ]
+            );
+        }
+
+        #[test]
+        fn import_from_search_path() {
+            let script_dir = TempDir::new().unwrap();
+            let search_dir = TempDir::new().unwrap();
+            let module_path = search_dir.path().join("foo.koto");
+            fs::write(&module_path, "export bar = 1\n").unwrap();
+
+            let server =
+                Server::with_module_resolver(Arc::new(SearchPathResolver::new(vec![search_dir
+                    .path()
+                    .to_path_buf()])));
+            let script_uri = uri(&script_dir.path().join("main.koto"));
+            let script = "\
+import foo.bar
+bar
+";
+            let (responses, _) = run_session_with_server(
+                server,
+                &script_uri,
+                script,
+                &[(
+                    "textDocument/definition",
+                    position_params(&script_uri, 1, 0),
+                )],
+            );
+            assert_eq!(
+                responses,
+                vec![json!({"uri": uri(&module_path), "range": range((0, 7), (0, 10))})]
+            );
+        }
+    }
+
+    mod completion {
+        use super::*;
+
+        fn labels(completions: &Value) -> Vec<String> {
+            completions
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["label"].as_str().unwrap().to_string())
+                .collect()
+        }
+
+        #[test]
+        fn core_module_items() {
+            let script = "\
+x = [1, 2, 3]
+string.
+";
+            let (responses, _) = run_session(
+                TEST_URI,
+                script,
+                &[("textDocument/completion", position_params(TEST_URI, 1, 7))],
+            );
+            let labels = labels(&responses[0]);
+            assert!(labels.contains(&"print".to_string()));
+            assert!(labels.contains(&"to_uppercase".to_string()));
+            assert!(!labels.contains(&"x".to_string()));
+        }
+
+        #[test]
+        fn locals_and_core_modules() {
+            let script = "\
+some_value = 42
+";
+            let (responses, _) = run_session(
+                TEST_URI,
+                script,
+                &[("textDocument/completion", position_params(TEST_URI, 1, 0))],
+            );
+            let labels = labels(&responses[0]);
+            assert!(labels.contains(&"some_value".to_string()));
+            assert!(labels.contains(&"string".to_string()));
+            assert!(labels.contains(&"io".to_string()));
+        }
+    }
+
+    mod hover {
+        use super::*;
+
+        fn hover_text(response: &Value) -> String {
+            response["contents"]["value"].as_str().unwrap().to_string()
+        }
+
+        #[test]
+        fn value_origins() {
+            let script = "\
+import string.print
+x = 42
+f = ||
+  print \"{}\", x
+f()
+";
+            let (responses, _) = run_session(
+                TEST_URI,
+                script,
+                &[
+                    ("textDocument/hover", position_params(TEST_URI, 3, 2)),
+                    ("textDocument/hover", position_params(TEST_URI, 3, 14)),
+                    ("textDocument/hover", position_params(TEST_URI, 4, 0)),
+                ],
+            );
+            assert_eq!(
+                hover_text(&responses[0]),
+                "`print`: imported from `string.print` in the core library, captured"
+            );
+            assert_eq!(
+                hover_text(&responses[1]),
+                "`x`: local value, assigned on line 2, captured"
+            );
+            assert_eq!(
+                hover_text(&responses[2]),
+                "`f`: local value, assigned on line 3"
+            );
+        }
+
+        #[test]
+        fn core_library_items() {
+            let script = "\
+string.to_uppercase \"hello\"
+";
+            let (responses, _) = run_session(
+                TEST_URI,
+                script,
+                &[
+                    ("textDocument/hover", position_params(TEST_URI, 0, 2)),
+                    ("textDocument/hover", position_params(TEST_URI, 0, 10)),
+                ],
+            );
+            assert_eq!(hover_text(&responses[0]), "`string`: core library module");
+            assert_eq!(
+                hover_text(&responses[1]),
+                "`string.to_uppercase`: ExternalFunction in the core library"
+            );
+        }
+    }
+}
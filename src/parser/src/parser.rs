@@ -96,6 +96,12 @@ impl Frame {
         self.pending_assignments.insert(id);
     }
 
+    // Discards the accesses and assignments of an expression that failed to parse
+    fn discard_expression(&mut self) {
+        self.pending_accesses.clear();
+        self.pending_assignments.clear();
+    }
+
     fn finish_expression(&mut self) {
         for id in self.pending_accesses.drain() {
             if !self.ids_assigned_in_scope.contains(&id) {
@@ -193,17 +199,17 @@ pub struct Parser<'source> {
     constants: ConstantPoolBuilder,
     lexer: Lexer<'source>,
     frame_stack: Vec<Frame>,
+    // When true, errors are collected in `errors` and parsing continues from the next line
+    recover_from_errors: bool,
+    errors: Vec<ParserError>,
+    // The source position of the most recent recovery, used to ensure that recovery makes progress
+    last_recovery_position: Option<usize>,
 }
 
 impl<'source> Parser<'source> {
+    /// Parses a script, stopping at the first error
     pub fn parse(source: &'source str) -> Result<(Ast, ConstantPool), ParserError> {
-        let capacity_guess = source.len() / 4;
-        let mut parser = Parser {
-            ast: Ast::with_capacity(capacity_guess),
-            constants: ConstantPoolBuilder::new(),
-            lexer: Lexer::new(source),
-            frame_stack: Vec::new(),
-        };
+        let mut parser = Parser::new(source, false);
 
         let main_block = parser.parse_main_block()?;
         parser.ast.set_entry_point(main_block);
@@ -211,6 +217,38 @@ impl<'source> Parser<'source> {
         Ok((parser.ast, parser.constants.build()))
     }
 
+    /// Parses a script, continuing past errors to report as many of them as possible
+    ///
+    /// When an error is encountered then the rest of the line is skipped, along with any
+    /// following lines that are indented further than the enclosing block, and then parsing
+    /// resumes. Lines containing errors are left out of the resulting AST.
+    ///
+    /// The errors are returned in the order that they were encountered in the script.
+    /// If the returned list of errors is empty then the AST is complete.
+    pub fn parse_with_recovery(source: &'source str) -> (Ast, ConstantPool, Vec<ParserError>) {
+        let mut parser = Parser::new(source, true);
+
+        match parser.parse_main_block() {
+            Ok(main_block) => parser.ast.set_entry_point(main_block),
+            Err(error) => parser.errors.push(error),
+        }
+
+        (parser.ast, parser.constants.build(), parser.errors)
+    }
+
+    fn new(source: &'source str, recover_from_errors: bool) -> Self {
+        let capacity_guess = source.len() / 4;
+        Self {
+            ast: Ast::with_capacity(capacity_guess),
+            constants: ConstantPoolBuilder::new(),
+            lexer: Lexer::new(source),
+            frame_stack: Vec::new(),
+            recover_from_errors,
+            errors: Vec::new(),
+            last_recovery_position: None,
+        }
+    }
+
     fn frame(&self) -> Result<&Frame, ParserError> {
         match self.frame_stack.last() {
             Some(frame) => Ok(frame),
@@ -240,32 +278,40 @@ impl<'source> Parser<'source> {
         context.expected_indentation = Some(0);
 
         let mut body = Vec::new();
-        while self.peek_next_token(&context).is_some() {
-            self.consume_until_next_token(&mut context);
-
-            if let Some(expression) = self.parse_line()? {
-                body.push(expression);
+        loop {
+            if self.peek_next_token(&context).is_none() {
+                // Check that all tokens were consumed
+                if self
+                    .peek_next_token(&ExpressionContext::permissive())
+                    .is_none()
+                {
+                    break;
+                }
 
-                match self.peek_next_token_on_same_line() {
-                    Some(Token::NewLine) | Some(Token::NewLineIndented) => continue,
-                    None => break,
-                    _ => {
-                        self.consume_next_token_on_same_line();
-                        return syntax_error!(UnexpectedToken, self);
-                    }
+                self.consume_until_next_token(&mut ExpressionContext::permissive());
+                self.consume_token();
+                let error: Result<(), ParserError> = syntax_error!(UnexpectedToken, self);
+                if let Err(error) = error {
+                    self.recover_from_error(error, 0, 1)?;
                 }
-            } else {
-                self.lexer.next();
-                return syntax_error!(ExpectedExpressionInMainBlock, self);
+                continue;
             }
-        }
 
-        // Check that all tokens were consumed
-        if self
-            .peek_next_token(&ExpressionContext::permissive())
-            .is_some()
-        {
-            return syntax_error!(UnexpectedToken, self);
+            self.consume_until_next_token(&mut context);
+
+            let line = match self.parse_block_line() {
+                Ok(Some(expression)) => Ok(expression),
+                Ok(None) => {
+                    self.lexer.next();
+                    syntax_error!(ExpectedExpressionInMainBlock, self)
+                }
+                Err(error) => Err(error),
+            };
+
+            match line {
+                Ok(expression) => body.push(expression),
+                Err(error) => self.recover_from_error(error, 0, 1)?,
+            }
         }
 
         let result = self.push_node_with_start_span(
@@ -468,6 +514,98 @@ impl<'source> Parser<'source> {
         Ok(Some(result))
     }
 
+    // Parses a line in a block, checking that the line is followed by a newline
+    fn parse_block_line(&mut self) -> Result<Option<AstIndex>, ParserError> {
+        let result = self.parse_line()?;
+
+        if result.is_some() {
+            match self.peek_next_token_on_same_line() {
+                None | Some(Token::NewLine) | Some(Token::NewLineIndented) => {}
+                _ => {
+                    self.consume_next_token_on_same_line();
+                    return syntax_error!(UnexpectedToken, self);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    // Records the error if recovery is enabled, otherwise the error is returned
+    //
+    // After recording the error, the rest of the current line is skipped along with any lines
+    // that are indented further than the block that contains the error. Any function frames that
+    // were started on the skipped lines are discarded.
+    //
+    // The lexer is unable to continue past invalid input, so recovery stops when a lexer error is
+    // encountered, with the lexer error being returned.
+    fn recover_from_error(
+        &mut self,
+        error: ParserError,
+        block_indent: usize,
+        frame_count: usize,
+    ) -> Result<(), ParserError> {
+        if !self.recover_from_errors
+            || matches!(error.error, ErrorType::SyntaxError(SyntaxError::LexerError))
+        {
+            return Err(error);
+        }
+
+        self.errors.push(error);
+        self.frame_stack.truncate(frame_count);
+        self.frame_mut()?.discard_expression();
+
+        // If the previous recovery ended at the same position then skip a token,
+        // ensuring that recovering from repeated errors can't get stuck.
+        let position = self.lexer.source_position();
+        if self.last_recovery_position == Some(position) {
+            self.consume_token();
+        }
+
+        let result = self.skip_lines_after_error(block_indent);
+        self.last_recovery_position = Some(self.lexer.source_position());
+        result
+    }
+
+    // Skips the rest of the current line, along with any following lines that are indented further
+    // than the block that contains the error
+    fn skip_lines_after_error(&mut self, block_indent: usize) -> Result<(), ParserError> {
+        loop {
+            // Skip the rest of the current line
+            while let Some(token) = self.peek_token() {
+                match token {
+                    _ if token.is_newline() => break,
+                    Token::Error => {
+                        self.consume_token();
+                        return syntax_error!(LexerError, self);
+                    }
+                    _ => {
+                        self.consume_token();
+                    }
+                }
+            }
+
+            // Find the indentation of the next line that contains a token
+            let mut peek_count = 0;
+            let next_indent = loop {
+                match self.peek_token_n(peek_count) {
+                    Some(token) if token.is_whitespace() || token.is_newline() => peek_count += 1,
+                    Some(_) => break Some(self.lexer.peek_indent(peek_count)),
+                    None => break None,
+                }
+            };
+
+            match next_indent {
+                Some(indent) if indent > block_indent => {
+                    for _ in 0..peek_count {
+                        self.consume_token();
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
     fn parse_line(&mut self) -> Result<Option<AstIndex>, ParserError> {
         let result = if let Some(for_loop) =
             self.parse_for_loop(&mut ExpressionContext::line_start())?
@@ -1561,31 +1699,18 @@ impl<'source> Parser<'source> {
 
         self.consume_until_next_token(context);
         let start_span = self.lexer.span();
+        let block_indent = self.lexer.current_indent();
+        let frame_count = self.frame_stack.len();
 
         let mut entries = Vec::new();
 
-        while let Some(key) = self.parse_map_key()? {
-            if self.peek_next_token_on_same_line() == Some(Token::Colon) {
-                self.consume_next_token_on_same_line();
-
-                if let Some(value) =
-                    self.parse_expressions(&mut ExpressionContext::inline(), false)?
-                {
-                    entries.push((key, Some(value)));
-                } else {
-                    // If a value wasn't found on the same line as the key,
-                    // look for an indented value
-                    if let Some(value) = self.parse_indented_map_or_block()? {
-                        entries.push((key, Some(value)));
-                    } else {
-                        return syntax_error!(ExpectedMapValue, self);
-                    }
-                }
-            } else {
-                entries.push((key, None));
+        loop {
+            match self.parse_map_block_entry() {
+                Ok(Some(entry)) => entries.push(entry),
+                Ok(None) => break,
+                Err(error) => self.recover_from_error(error, block_indent, frame_count)?,
             }
 
-            // self.consume_until_next_token(context);
             if self.peek_next_token(context).is_none() {
                 break;
             }
@@ -1603,6 +1728,29 @@ impl<'source> Parser<'source> {
         )?))
     }
 
+    fn parse_map_block_entry(&mut self) -> Result<Option<(MapKey, Option<AstIndex>)>, ParserError> {
+        let key = match self.parse_map_key()? {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+
+        if self.peek_next_token_on_same_line() != Some(Token::Colon) {
+            return Ok(Some((key, None)));
+        }
+
+        self.consume_next_token_on_same_line();
+
+        if let Some(value) = self.parse_expressions(&mut ExpressionContext::inline(), false)? {
+            Ok(Some((key, Some(value))))
+        } else if let Some(value) = self.parse_indented_map_or_block()? {
+            // If a value wasn't found on the same line as the key,
+            // look for an indented value
+            Ok(Some((key, Some(value))))
+        } else {
+            syntax_error!(ExpectedMapValue, self)
+        }
+    }
+
     fn parse_map_inline(
         &mut self,
         context: &mut ExpressionContext,
@@ -2396,17 +2544,14 @@ impl<'source> Parser<'source> {
         let mut body = Vec::new();
 
         let start_span = self.lexer.span();
-
-        while let Some(expression) = self.parse_line()? {
-            body.push(expression);
-
-            match self.peek_next_token_on_same_line() {
-                None => break,
-                Some(Token::NewLine) | Some(Token::NewLineIndented) => {}
-                _ => {
-                    self.consume_next_token_on_same_line();
-                    return syntax_error!(UnexpectedToken, self);
-                }
+        let block_indent = self.lexer.current_indent();
+        let frame_count = self.frame_stack.len();
+
+        loop {
+            match self.parse_block_line() {
+                Ok(Some(expression)) => body.push(expression),
+                Ok(None) => break,
+                Err(error) => self.recover_from_error(error, block_indent, frame_count)?,
             }
 
             // Peek ahead to see if the indented block continues after this line
@@ -23,6 +23,37 @@ mod parser {
                 ast.0.nodes()
             );
         }
+
+        let (_, _, errors) = Parser::parse_with_recovery(source);
+        assert!(
+            !errors.is_empty(),
+            "Missing errors while parsing with recovery:\n{}",
+            source
+        );
+    }
+
+    // Checks that the lines of the errors found while parsing with recovery match expectations,
+    // and that the expected number of statements were parsed in the main block
+    #[cfg(not(feature = "panic_on_parser_error"))]
+    fn check_recovered_errors(
+        source: &str,
+        expected_error_lines: &[u32],
+        expected_body_len: usize,
+    ) {
+        use koto_parser::Node;
+
+        let (ast, _, errors) = Parser::parse_with_recovery(source);
+
+        let error_lines = errors
+            .iter()
+            .map(|error| error.span.start.line)
+            .collect::<Vec<_>>();
+        assert_eq!(error_lines, expected_error_lines, "{:#?}", errors);
+
+        match ast.entry_point().map(|entry| &entry.node) {
+            Some(Node::MainBlock { body, .. }) => assert_eq!(body.len(), expected_body_len),
+            other => panic!("Expected main block, found {:?}", other),
+        }
     }
 
     mod should_fail {
@@ -246,4 +277,137 @@ match
             }
         }
     }
+
+    #[cfg(not(feature = "panic_on_parser_error"))]
+    mod recovery {
+        use super::*;
+
+        #[test]
+        fn valid_script_has_no_errors() {
+            let source = "
+x = 1
+f = |n| n + x
+";
+            check_recovered_errors(source, &[], 2);
+        }
+
+        #[test]
+        fn multiple_errors_in_main_block() {
+            let source = "
+x = 1 + * 2
+y = 42
+z = (1, 2
+w = y
+";
+            check_recovered_errors(source, &[2, 4], 2);
+        }
+
+        #[test]
+        fn errors_in_indented_blocks() {
+            let source = "
+f = ||
+  a = 1 +
+  b = 2
+  c = [1, 2
+  a + b
+g = 99
+";
+            check_recovered_errors(source, &[3, 5], 2);
+        }
+
+        #[test]
+        fn indented_lines_following_an_error_are_skipped() {
+            let source = "
+x = 1 + * 2
+  y = 3
+    z = 4
+w = 5
+";
+            check_recovered_errors(source, &[2], 1);
+        }
+
+        #[test]
+        fn errors_in_map_blocks() {
+            let source = "
+m =
+  foo: 1 + * 2
+  bar: 42
+  baz: ||
+    x = (
+    x
+n = m.bar
+";
+            check_recovered_errors(source, &[3, 6], 2);
+        }
+
+        #[test]
+        fn unexpected_indentation_in_main_block() {
+            let source = "
+x = 1
+    y = 2
+z = 3
+";
+            check_recovered_errors(source, &[3], 2);
+        }
+
+        mod lexer_errors {
+            use super::*;
+
+            // Lexer errors can't be recovered from, so parsing should stop with the lexer error
+            fn check_lexer_error(source: &str, expected_error_lines: &[u32]) {
+                check_parsing_fails(source);
+
+                let (_, _, errors) = Parser::parse_with_recovery(source);
+                let error_lines = errors
+                    .iter()
+                    .map(|error| error.span.start.line)
+                    .collect::<Vec<_>>();
+                assert_eq!(error_lines, expected_error_lines, "{:#?}", errors);
+                assert_eq!(
+                    errors.last().unwrap().to_string(),
+                    "Found an unexpected token while lexing input"
+                );
+            }
+
+            #[test]
+            fn single_quote() {
+                check_lexer_error("'", &[1]);
+            }
+
+            #[test]
+            fn single_quoted_string() {
+                check_lexer_error("x = 'a'", &[1]);
+            }
+
+            #[test]
+            fn lexer_error_following_a_syntax_error() {
+                let source = "
+x = 1 + * 2
+y = 'a'
+z = 3
+";
+                check_lexer_error(source, &[2, 3]);
+            }
+
+            #[test]
+            fn lexer_error_in_a_skipped_line() {
+                let source = "
+x = 1 + * 2 'a'
+z = 3
+";
+                check_lexer_error(source, &[2, 2]);
+            }
+
+            #[test]
+            fn lexer_error_in_an_indented_block() {
+                let source = "
+f = ||
+  x = 1 +
+  y = 'a'
+z = 3
+";
+                check_lexer_error(source, &[3, 4]);
+            }
+        }
+    }
 }
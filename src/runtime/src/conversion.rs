@@ -0,0 +1,405 @@
+//! Traits for converting between Koto values and Rust types
+//!
+//! [FromKoto] and [IntoKoto] are implemented for primitives, strings, `Vec`, `HashMap`, `Option`,
+//! and tuples, and can be derived for structs and enums with `#[derive(FromKoto, IntoKoto)]`.
+//!
+//! Derived implementations map structs with named fields to maps, tuple structs to tuples, and
+//! newtype structs to the value that they wrap. Enum variants without data are mapped to strings
+//! containing the variant's name, and variants with data are mapped to maps with a single entry,
+//! with the variant's name as the key.
+//!
+//! The derive macros accept the following attributes:
+//!   - `#[koto(rename = "name")]` on a field or variant, to use a different name in Koto.
+//!   - `#[koto(crate = "path")]` on the type, to set the path to the runtime crate, which
+//!     defaults to `::koto_runtime`, e.g. `#[koto(crate = "koto::runtime")]`.
+
+use {
+    crate::{
+        Num2, Num4, RuntimeError, Value, ValueKey, ValueList, ValueMap, ValueNumber, ValueString,
+        ValueTuple,
+    },
+    std::{
+        collections::HashMap,
+        convert::TryFrom,
+        error, fmt,
+        hash::{BuildHasher, Hash},
+    },
+};
+
+/// An error produced when a [Value] can't be converted into a Rust type, see [FromKoto]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConversionError {
+    /// A description of the expected value, e.g. `Number`
+    pub expected: String,
+    /// A description of the value that was found, typically its type
+    pub found: String,
+    /// The location of the mismatched value within the converted value, e.g. `items[2].x`
+    ///
+    /// The path is empty when the mismatch was with the converted value itself.
+    pub path: String,
+}
+
+impl ConversionError {
+    /// Makes an error for a value that doesn't have the expected type
+    pub fn new(expected: impl Into<String>, found: &Value) -> Self {
+        Self {
+            expected: expected.into(),
+            found: found.type_as_string(),
+            path: String::new(),
+        }
+    }
+
+    /// Prefixes the error's path with a map key or field name
+    pub fn with_field(mut self, field: &str) -> Self {
+        if self.path.is_empty() || self.path.starts_with('[') {
+            self.path = format!("{}{}", field, self.path);
+        } else {
+            self.path = format!("{}.{}", field, self.path);
+        }
+        self
+    }
+
+    /// Prefixes the error's path with an index into a list or tuple
+    pub fn with_index(mut self, index: usize) -> Self {
+        if self.path.is_empty() || self.path.starts_with('[') {
+            self.path = format!("[{}]{}", index, self.path);
+        } else {
+            self.path = format!("[{}].{}", index, self.path);
+        }
+        self
+    }
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "expected {}, found {}", self.expected, self.found)
+        } else {
+            write!(
+                f,
+                "expected {} for '{}', found {}",
+                self.expected, self.path, self.found
+            )
+        }
+    }
+}
+
+impl error::Error for ConversionError {}
+
+impl From<ConversionError> for RuntimeError {
+    fn from(error: ConversionError) -> Self {
+        RuntimeError::from_string(error.to_string())
+    }
+}
+
+/// A trait for Rust types that can be made from Koto values
+pub trait FromKoto: Sized {
+    /// Converts the value, or returns an error describing why it couldn't be converted
+    fn from_koto(value: &Value) -> Result<Self, ConversionError>;
+}
+
+/// A trait for Rust types that can be converted into Koto values
+pub trait IntoKoto {
+    /// Converts `self` into a Koto value
+    fn into_koto(self) -> Value;
+}
+
+/// A trait for Rust types that can be converted into the keys of Koto maps
+///
+/// Map keys need to be immutable, so the trait is only implemented for types that are converted
+/// into immutable values, i.e. numbers, strings, and bools.
+pub trait IntoKotoKey {
+    /// Converts `self` into a map key
+    fn into_koto_key(self) -> ValueKey;
+}
+
+/// Converts the value found in a map with the given key, used by `#[derive(FromKoto)]`
+///
+/// Missing entries are converted from `Empty`, so that `Option` fields can be left out.
+pub fn from_map_entry<T: FromKoto>(map: &ValueMap, key: &str) -> Result<T, ConversionError> {
+    let value = map
+        .contents()
+        .data
+        .get(&ValueKey::from(key))
+        .cloned()
+        .unwrap_or(Value::Empty);
+    T::from_koto(&value).map_err(|e| e.with_field(key))
+}
+
+/// Converts an element of a sequence of values, used by `#[derive(FromKoto)]`
+pub fn from_element<T: FromKoto>(values: &[Value], index: usize) -> Result<T, ConversionError> {
+    T::from_koto(&values[index]).map_err(|e| e.with_index(index))
+}
+
+/// Returns the elements of a tuple or list, checking that it has the expected size
+pub fn sequence_values(value: &Value, expected_size: usize) -> Result<Vec<Value>, ConversionError> {
+    let values = match value {
+        Value::Tuple(t) => t.data().to_vec(),
+        Value::List(l) => l.data().to_vec(),
+        unexpected => {
+            return Err(ConversionError::new(
+                format!("Tuple with {} elements", expected_size),
+                unexpected,
+            ))
+        }
+    };
+
+    if values.len() == expected_size {
+        Ok(values)
+    } else {
+        Err(ConversionError {
+            expected: format!("Tuple with {} elements", expected_size),
+            found: format!("{} with {} elements", value.type_as_string(), values.len()),
+            path: String::new(),
+        })
+    }
+}
+
+/// Makes an error for a string that doesn't match one of an enum's variants
+pub fn unknown_variant(found: &str, variants: &[&str]) -> ConversionError {
+    let variants: Vec<String> = variants.iter().map(|v| format!("'{}'", v)).collect();
+    ConversionError {
+        expected: format!("one of {}", variants.join(", ")),
+        found: format!("'{}'", found),
+        path: String::new(),
+    }
+}
+
+impl FromKoto for Value {
+    fn from_koto(value: &Value) -> Result<Self, ConversionError> {
+        Ok(value.clone())
+    }
+}
+
+impl IntoKoto for Value {
+    fn into_koto(self) -> Value {
+        self
+    }
+}
+
+impl IntoKoto for () {
+    fn into_koto(self) -> Value {
+        Value::Empty
+    }
+}
+
+// Implements the conversion traits for types that map to a single Value variant
+macro_rules! value_conversions {
+    ($type:ty, $variant:ident, $expected:expr, |$from:ident| $from_koto:expr, |$into:ident| $into_koto:expr) => {
+        impl FromKoto for $type {
+            fn from_koto(value: &Value) -> Result<Self, ConversionError> {
+                match value {
+                    Value::$variant($from) => Ok($from_koto),
+                    unexpected => Err(ConversionError::new($expected, unexpected)),
+                }
+            }
+        }
+
+        impl IntoKoto for $type {
+            fn into_koto(self) -> Value {
+                let $into = self;
+                Value::$variant($into_koto)
+            }
+        }
+    };
+}
+
+value_conversions!(bool, Bool, "Bool", |b| *b, |b| b);
+value_conversions!(ValueNumber, Number, "Number", |n| *n, |n| n);
+value_conversions!(f64, Number, "Number", |n| n.into(), |n| n.into());
+value_conversions!(f32, Number, "Number", |n| n.into(), |n| n.into());
+value_conversions!(String, Str, "String", |s| s.to_string(), |s| s.into());
+value_conversions!(ValueString, Str, "String", |s| s.clone(), |s| s);
+value_conversions!(Num2, Num2, "Num2", |n| *n, |n| n);
+value_conversions!(Num4, Num4, "Num4", |n| *n, |n| n);
+value_conversions!(ValueList, List, "List", |l| l.clone(), |l| l);
+value_conversions!(ValueMap, Map, "Map", |m| m.clone(), |m| m);
+value_conversions!(ValueTuple, Tuple, "Tuple", |t| t.clone(), |t| t);
+
+impl IntoKoto for &str {
+    fn into_koto(self) -> Value {
+        Value::Str(self.into())
+    }
+}
+
+// Integers are converted via i64, with an error if the number is out of range for the type
+//
+// Floats are accepted when they have no fractional part, and integers that are out of range
+// for i64 are converted into floats.
+macro_rules! integer_conversions {
+    ($($type:ty),+) => {
+        $(
+            impl FromKoto for $type {
+                fn from_koto(value: &Value) -> Result<Self, ConversionError> {
+                    match value {
+                        Value::Number(n) => {
+                            let result = match n {
+                                ValueNumber::I64(i) => <$type>::try_from(*i).ok(),
+                                // MAX + 1 is used as the upper bound given that it's exactly
+                                // representable as a float, unlike MAX for the 64 bit types
+                                ValueNumber::F64(f)
+                                    if f.fract() == 0.0
+                                        && *f >= <$type>::MIN as f64
+                                        && *f < <$type>::MAX as f64 + 1.0 =>
+                                {
+                                    Some(*f as $type)
+                                }
+                                ValueNumber::F64(_) => None,
+                            };
+
+                            result.ok_or_else(|| ConversionError {
+                                expected: format!(
+                                    "Integer between {} and {}",
+                                    <$type>::MIN,
+                                    <$type>::MAX
+                                ),
+                                found: n.to_string(),
+                                path: String::new(),
+                            })
+                        }
+                        unexpected => Err(ConversionError::new("Number", unexpected)),
+                    }
+                }
+            }
+
+            impl IntoKoto for $type {
+                fn into_koto(self) -> Value {
+                    match i64::try_from(self) {
+                        Ok(n) => Value::Number(n.into()),
+                        Err(_) => Value::Number((self as f64).into()),
+                    }
+                }
+            }
+        )+
+    };
+}
+
+integer_conversions!(i8, u8, i16, u16, i32, u32, i64, u64, isize, usize);
+
+// Implements IntoKotoKey for types that are converted into immutable values
+macro_rules! key_conversions {
+    ($($type:ty),+) => {
+        $(
+            impl IntoKotoKey for $type {
+                fn into_koto_key(self) -> ValueKey {
+                    self.into_koto().into()
+                }
+            }
+        )+
+    };
+}
+
+key_conversions!(
+    bool,
+    ValueNumber,
+    f64,
+    f32,
+    String,
+    ValueString,
+    &str,
+    Num2,
+    Num4
+);
+key_conversions!(i8, u8, i16, u16, i32, u32, i64, u64, isize, usize);
+
+impl<T: FromKoto> FromKoto for Option<T> {
+    fn from_koto(value: &Value) -> Result<Self, ConversionError> {
+        match value {
+            Value::Empty => Ok(None),
+            _ => T::from_koto(value).map(Some),
+        }
+    }
+}
+
+impl<T: IntoKoto> IntoKoto for Option<T> {
+    fn into_koto(self) -> Value {
+        match self {
+            Some(value) => value.into_koto(),
+            None => Value::Empty,
+        }
+    }
+}
+
+impl<T: FromKoto> FromKoto for Vec<T> {
+    fn from_koto(value: &Value) -> Result<Self, ConversionError> {
+        let convert = |values: &[Value]| {
+            (0..values.len())
+                .map(|index| from_element(values, index))
+                .collect()
+        };
+
+        match value {
+            Value::List(l) => convert(&l.data()),
+            Value::Tuple(t) => convert(t.data()),
+            unexpected => Err(ConversionError::new("List", unexpected)),
+        }
+    }
+}
+
+impl<T: IntoKoto> IntoKoto for Vec<T> {
+    fn into_koto(self) -> Value {
+        let values: Vec<Value> = self.into_iter().map(IntoKoto::into_koto).collect();
+        Value::List(ValueList::from_slice(&values))
+    }
+}
+
+impl<K, V, S> FromKoto for HashMap<K, V, S>
+where
+    K: FromKoto + Eq + Hash,
+    V: FromKoto,
+    S: BuildHasher + Default,
+{
+    fn from_koto(value: &Value) -> Result<Self, ConversionError> {
+        match value {
+            Value::Map(m) => m
+                .cloned_iter()
+                .map(|(key, value)| {
+                    let field = key.to_string();
+                    let key = K::from_koto(key.value()).map_err(|e| e.with_field(&field))?;
+                    let value = V::from_koto(&value).map_err(|e| e.with_field(&field))?;
+                    Ok((key, value))
+                })
+                .collect(),
+            unexpected => Err(ConversionError::new("Map", unexpected)),
+        }
+    }
+}
+
+impl<K, V, S> IntoKoto for HashMap<K, V, S>
+where
+    K: IntoKotoKey,
+    V: IntoKoto,
+{
+    fn into_koto(self) -> Value {
+        let mut result = ValueMap::with_capacity(self.len());
+        for (key, value) in self.into_iter() {
+            result.insert(key.into_koto_key(), value.into_koto());
+        }
+        Value::Map(result)
+    }
+}
+
+macro_rules! tuple_conversions {
+    ($size:expr; $($index:tt $type:ident),+) => {
+        impl<$($type: FromKoto),+> FromKoto for ($($type,)+) {
+            fn from_koto(value: &Value) -> Result<Self, ConversionError> {
+                let values = sequence_values(value, $size)?;
+                Ok(($(from_element::<$type>(&values, $index)?,)+))
+            }
+        }
+
+        impl<$($type: IntoKoto),+> IntoKoto for ($($type,)+) {
+            fn into_koto(self) -> Value {
+                let values = vec![$(self.$index.into_koto()),+];
+                Value::Tuple(values.into())
+            }
+        }
+    };
+}
+
+tuple_conversions!(1; 0 A);
+tuple_conversions!(2; 0 A, 1 B);
+tuple_conversions!(3; 0 A, 1 B, 2 C);
+tuple_conversions!(4; 0 A, 1 B, 2 C, 3 D);
+tuple_conversions!(5; 0 A, 1 B, 2 C, 3 D, 4 E);
+tuple_conversions!(6; 0 A, 1 B, 2 C, 3 D, 4 E, 5 F);
@@ -3,7 +3,7 @@ use crate::{
     value_iterator::{
         make_iterator, ValueIterator, ValueIteratorOutput as Output, ValueIteratorResult,
     },
-    BinaryOp, RuntimeResult, Value, ValueHashMap, ValueList, ValueMap, ValueVec, Vm,
+    BinaryOp, RuntimeResult, Value, ValueHashMap, ValueKey, ValueList, ValueMap, ValueVec, Vm,
 };
 
 pub fn make_module() -> ValueMap {
@@ -457,17 +457,24 @@ pub fn make_module() -> ValueMap {
         [iterable] if iterable.is_iterable() => {
             let mut iterator = make_iterator(iterable).unwrap();
             let mut result = ValueVec::new();
+            let mut allocation = vm.track_allocation::<Value>(0)?;
 
             loop {
                 match iterator.next().map(collect_pair) {
-                    Some(Ok(Output::Value(value))) => result.push(value),
+                    Some(Ok(Output::Value(value))) => {
+                        allocation.merge(vm.track_allocation::<Value>(1)?);
+                        result.push(value)
+                    }
                     Some(Err(error)) => return Err(error),
                     Some(_) => unreachable!(),
                     None => break,
                 }
             }
 
-            Ok(List(ValueList::with_data(result)))
+            let result = List(ValueList::with_data(result));
+            allocation.assign_to(&result);
+            vm.track_cycles(&result);
+            Ok(result)
         }
         _ => runtime_error!("iterator.to_list: Expected iterable as argument"),
     });
@@ -476,9 +483,14 @@ pub fn make_module() -> ValueMap {
         [iterable] if iterable.is_iterable() => {
             let mut iterator = make_iterator(iterable).unwrap();
             let mut result = ValueHashMap::new();
+            let mut allocation = vm.track_allocation::<(ValueKey, Value)>(0)?;
 
             loop {
-                match iterator.next() {
+                let output = iterator.next();
+                if matches!(output, Some(Ok(_))) {
+                    allocation.merge(vm.track_allocation::<(ValueKey, Value)>(1)?);
+                }
+                match output {
                     Some(Ok(Output::Value(Tuple(t)))) if t.data().len() == 2 => {
                         let key = t.data()[0].clone();
                         let value = t.data()[1].clone();
@@ -495,7 +507,10 @@ pub fn make_module() -> ValueMap {
                 }
             }
 
-            Ok(Map(ValueMap::with_data(result)))
+            let result = Map(ValueMap::with_data(result));
+            allocation.assign_to(&result);
+            vm.track_cycles(&result);
+            Ok(result)
         }
         _ => runtime_error!("iterator.to_map: Expected iterator as argument"),
     });
@@ -504,17 +519,23 @@ pub fn make_module() -> ValueMap {
         [iterable] if iterable.is_iterable() => {
             let mut iterator = make_iterator(iterable).unwrap();
             let mut result = Vec::new();
+            let mut allocation = vm.track_allocation::<Value>(0)?;
 
             loop {
                 match iterator.next().map(collect_pair) {
-                    Some(Ok(Output::Value(value))) => result.push(value),
+                    Some(Ok(Output::Value(value))) => {
+                        allocation.merge(vm.track_allocation::<Value>(1)?);
+                        result.push(value)
+                    }
                     Some(Err(error)) => return Err(error),
                     Some(_) => unreachable!(),
                     None => break,
                 }
             }
 
-            Ok(Tuple(result.into()))
+            let result = Tuple(result.into());
+            allocation.assign_to(&result);
+            Ok(result)
         }
         _ => runtime_error!("iterator.to_tuple: Expected iterable as argument"),
     });
@@ -45,7 +45,13 @@ pub fn make_module() -> ValueMap {
     });
 
     result.add_fn("copy", |vm, args| match vm.get_args(args) {
-        [List(l)] => Ok(List(ValueList::with_data(l.data().clone()))),
+        [List(l)] => {
+            let allocation = vm.track_allocation::<Value>(l.len())?;
+            let result = List(ValueList::with_data(l.data().clone()));
+            allocation.assign_to(&result);
+            vm.track_cycles(&result);
+            Ok(result)
+        }
         _ => runtime_error!("list.copy: Expected list as argument"),
     });
 
@@ -95,7 +101,9 @@ pub fn make_module() -> ValueMap {
                 return runtime_error!("list.insert: Index out of bounds");
             }
 
+            let allocation = vm.track_allocation::<Value>(1)?;
             l.data_mut().insert(index, value.clone());
+            allocation.assign_to(&List(l.clone()));
             Ok(Empty)
         }
         _ => runtime_error!("list.insert: Expected list, number, and value as arguments"),
@@ -129,7 +137,9 @@ pub fn make_module() -> ValueMap {
 
     result.add_fn("push", |vm, args| match vm.get_args(args) {
         [List(l), value] => {
+            let allocation = vm.track_allocation::<Value>(1)?;
             l.data_mut().push(value.clone());
+            allocation.assign_to(&List(l.clone()));
             Ok(Empty)
         }
         _ => runtime_error!("list.push: Expected list and value as arguments"),
@@ -160,7 +170,10 @@ pub fn make_module() -> ValueMap {
             if *n < 0.0 {
                 return runtime_error!("list.resize: Negative sizes aren't allowed");
             }
-            l.data_mut().resize(n.into(), value.clone());
+            let new_size = usize::from(n);
+            let allocation = vm.track_allocation::<Value>(new_size.saturating_sub(l.len()))?;
+            l.data_mut().resize(new_size, value.clone());
+            allocation.assign_to(&List(l.clone()));
             Ok(Empty)
         }
         _ => runtime_error!("list.resize: Expected list, number, and value as arguments"),
@@ -305,10 +318,13 @@ pub fn make_module() -> ValueMap {
 
     result.add_fn("sort_copy", |vm, args| match vm.get_args(args) {
         [List(l)] => {
+            let allocation = vm.track_allocation::<Value>(l.len())?;
             let mut result = l.data().clone();
             let vm = vm.child_vm();
             sort_values(vm, &mut result)?;
-            Ok(List(ValueList::with_data(result)))
+            let result = List(ValueList::with_data(result));
+            allocation.assign_to(&result);
+            Ok(result)
         }
         _ => runtime_error!("list.sort_copy: Expected list as argument"),
     });
@@ -323,7 +339,12 @@ pub fn make_module() -> ValueMap {
     });
 
     result.add_fn("to_tuple", |vm, args| match vm.get_args(args) {
-        [List(l)] => Ok(Value::Tuple(l.data().as_slice().into())),
+        [List(l)] => {
+            let allocation = vm.track_allocation::<Value>(l.len())?;
+            let result = Value::Tuple(l.data().as_slice().into());
+            allocation.assign_to(&result);
+            Ok(result)
+        }
         _ => runtime_error!("list.to_tuple expects a list as argument"),
     });
 
@@ -351,8 +372,13 @@ pub fn make_module() -> ValueMap {
                 return runtime_error!("list.with_size: Negative sizes aren't allowed");
             }
 
-            let result = smallvec::smallvec![value.clone(); n.into()];
-            Ok(List(ValueList::with_data(result)))
+            let size = usize::from(n);
+            let allocation = vm.track_allocation::<Value>(size)?;
+            let result = List(ValueList::with_data(
+                smallvec::smallvec![value.clone(); size],
+            ));
+            allocation.assign_to(&result);
+            Ok(result)
         }
         _ => runtime_error!("list.with_size: Expected number and value as arguments"),
     });
@@ -32,7 +32,13 @@ pub fn make_module() -> ValueMap {
     });
 
     result.add_fn("copy", |vm, args| match vm.get_args(args) {
-        [Map(m)] => Ok(Map(ValueMap::with_data(m.contents().data.clone()))),
+        [Map(m)] => {
+            let allocation = vm.track_allocation::<(ValueKey, Value)>(m.len())?;
+            let result = Map(ValueMap::with_data(m.contents().data.clone()));
+            allocation.assign_to(&result);
+            vm.track_cycles(&result);
+            Ok(result)
+        }
         _ => runtime_error!("map.copy: Expected map as argument"),
     });
 
@@ -69,12 +75,16 @@ pub fn make_module() -> ValueMap {
 
     result.add_fn("insert", |vm, args| match vm.get_args(args) {
         [Map(m), key] if key.is_immutable() => {
+            vm.track_allocation::<(ValueKey, Value)>(1)?
+                .assign_to(&Map(m.clone()));
             match m.contents_mut().data.insert(key.clone().into(), Empty) {
                 Some(old_value) => Ok(old_value),
                 None => Ok(Empty),
             }
         }
         [Map(m), key, value] if key.is_immutable() => {
+            vm.track_allocation::<(ValueKey, Value)>(1)?
+                .assign_to(&Map(m.clone()));
             match m
                 .contents_mut()
                 .data
@@ -269,6 +279,8 @@ fn do_map_update(
     vm: &mut Vm,
 ) -> RuntimeResult {
     if !map.contents().data.contains_key(&key) {
+        vm.track_allocation::<(ValueKey, Value)>(1)?
+            .assign_to(&Value::Map(map.clone()));
         map.contents_mut().data.insert(key.clone(), default);
     }
     let value = map.contents().data.get(&key).cloned().unwrap();
@@ -17,6 +17,66 @@ pub mod tuple;
 
 use crate::ValueMap;
 
+/// Selects the core library modules that are made available in a [Vm](crate::Vm)'s prelude
+///
+/// Modules that aren't included in the prelude can't be imported by scripts,
+/// although the core functions for value types (e.g. `"abc".to_uppercase()`) remain available.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CoreModules {
+    pub io: bool,
+    pub iterator: bool,
+    pub koto: bool,
+    pub list: bool,
+    pub map: bool,
+    pub number: bool,
+    pub os: bool,
+    pub range: bool,
+    pub string: bool,
+    pub test: bool,
+    pub thread: bool,
+    pub tuple: bool,
+}
+
+impl CoreModules {
+    /// All of the core library's modules
+    pub fn all() -> Self {
+        Self {
+            io: true,
+            iterator: true,
+            koto: true,
+            list: true,
+            map: true,
+            number: true,
+            os: true,
+            range: true,
+            string: true,
+            test: true,
+            thread: true,
+            tuple: true,
+        }
+    }
+
+    /// The core library without the modules that provide access to the filesystem, threads,
+    /// or the operating system
+    ///
+    /// This is useful when running untrusted scripts,
+    /// see also [VmSettings::allow_module_loading](crate::VmSettings::allow_module_loading).
+    pub fn pure() -> Self {
+        Self {
+            io: false,
+            os: false,
+            thread: false,
+            ..Self::all()
+        }
+    }
+}
+
+impl Default for CoreModules {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
 #[derive(Clone)]
 pub struct CoreLib {
     pub io: ValueMap,
@@ -55,3 +115,31 @@ impl Default for CoreLib {
         }
     }
 }
+
+impl CoreLib {
+    /// Makes a prelude containing the selected core modules
+    pub fn make_prelude(&self, modules: CoreModules) -> ValueMap {
+        let mut prelude = ValueMap::default();
+
+        let mut add_module = |name, module: &ValueMap, enabled| {
+            if enabled {
+                prelude.add_map(name, module.clone());
+            }
+        };
+
+        add_module("io", &self.io, modules.io);
+        add_module("iterator", &self.iterator, modules.iterator);
+        add_module("koto", &self.koto, modules.koto);
+        add_module("list", &self.list, modules.list);
+        add_module("map", &self.map, modules.map);
+        add_module("os", &self.os, modules.os);
+        add_module("number", &self.number, modules.number);
+        add_module("range", &self.range, modules.range);
+        add_module("string", &self.string, modules.string);
+        add_module("test", &self.test, modules.test);
+        add_module("thread", &self.thread, modules.thread);
+        add_module("tuple", &self.tuple, modules.tuple);
+
+        prelude
+    }
+}
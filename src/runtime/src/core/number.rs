@@ -27,15 +27,7 @@ pub fn make_module() -> ValueMap {
             number_f64_fn!(stringify!($fn), $fn)
         };
         ($name:expr, $fn:ident) => {
-            result.add_fn($name, |vm, args| match vm.get_args(args) {
-                [Number(n)] => Ok(Number(f64::from(n).$fn().into())),
-                [other] => runtime_error!(
-                    "number.{} expects a Number as argument, found {}",
-                    $name,
-                    other.type_as_string(),
-                ),
-                _ => runtime_error!("number.{} expects a Number as argument", $name),
-            })
+            result.add_typed_fn(concat!("number.", $name), |n: f64| n.$fn())
         };
     }
 
@@ -25,7 +25,13 @@ pub fn make_module() -> ValueMap {
     });
 
     result.add_fn("escape", |vm, args| match vm.get_args(args) {
-        [Str(s)] => Ok(Str(s.escape_default().to_string().into())),
+        [Str(s)] => {
+            let result = s.escape_default().to_string();
+            let allocation = vm.track_allocation::<u8>(result.len())?;
+            let result = Str(result.into());
+            allocation.assign_to(&result);
+            Ok(result)
+        }
         _ => runtime_error!("string.escape: Expected string as argument"),
     });
 
@@ -49,7 +55,12 @@ pub fn make_module() -> ValueMap {
             let format_args = format_args.to_vec();
             let vm = vm.child_vm();
             match format::format_string(vm, &format, &format_args) {
-                Ok(result) => Ok(Str(result.into())),
+                Ok(result) => {
+                    let allocation = vm.track_allocation::<u8>(result.len())?;
+                    let result = Str(result.into());
+                    allocation.assign_to(&result);
+                    Ok(result)
+                }
                 Err(error) => Err(error.with_prefix("string.format")),
             }
         }
@@ -168,7 +179,10 @@ pub fn make_module() -> ValueMap {
     result.add_fn("to_lowercase", |vm, args| match vm.get_args(args) {
         [Str(s)] => {
             let result = s.chars().flat_map(|c| c.to_lowercase()).collect::<String>();
-            Ok(Str(result.into()))
+            let allocation = vm.track_allocation::<u8>(result.len())?;
+            let result = Str(result.into());
+            allocation.assign_to(&result);
+            Ok(result)
         }
         _ => runtime_error!("string.to_lowercase: Expected string as argument"),
     });
@@ -189,7 +203,10 @@ pub fn make_module() -> ValueMap {
     result.add_fn("to_uppercase", |vm, args| match vm.get_args(args) {
         [Str(s)] => {
             let result = s.chars().flat_map(|c| c.to_uppercase()).collect::<String>();
-            Ok(Str(result.into()))
+            let allocation = vm.track_allocation::<u8>(result.len())?;
+            let result = Str(result.into());
+            allocation.assign_to(&result);
+            Ok(result)
         }
         _ => runtime_error!("string.to_uppercase: Expected string as argument"),
     });
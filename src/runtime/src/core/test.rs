@@ -1,3 +1,6 @@
+mod diff;
+mod property;
+
 use crate::{runtime_error, BinaryOp, Value, ValueMap, ValueNumber};
 
 pub fn make_module() -> ValueMap {
@@ -34,7 +37,7 @@ pub fn make_module() -> ValueMap {
             match result {
                 Ok(Bool(true)) => Ok(Empty),
                 Ok(Bool(false)) => {
-                    runtime_error!("Assertion failed, '{}' is not equal to '{}'", a, b)
+                    runtime_error!("{}", diff::assert_eq_failure_message(vm, &a, &b))
                 }
                 Ok(unexpected) => runtime_error!(
                     "assert_eq: expected Bool from comparison, found '{}'",
@@ -123,11 +126,19 @@ pub fn make_module() -> ValueMap {
     result.add_fn("run_tests", |vm, args| match vm.get_args(args) {
         [Map(tests)] => {
             let tests = tests.clone();
-            vm.run_tests(tests)
+            let results = vm.run_tests(tests, None);
+            match results.into_iter().find_map(|result| result.error) {
+                Some(error) => Err(error),
+                None => Ok(Empty),
+            }
         }
         _ => runtime_error!("run_tests expects a map as argument"),
     });
 
+    result.add_fn("check", property::check);
+
+    result.add_map("gen", property::make_generators_module());
+
     result
 }
 
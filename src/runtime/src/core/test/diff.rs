@@ -0,0 +1,204 @@
+use crate::{BinaryOp, MetaKey, RuntimeError, Value, ValueKey, ValueMap, Vm};
+
+// The maximum number of differences that are included in a failure message
+const MAX_DIFFERENCES: usize = 20;
+
+/// A difference found between two values
+#[derive(Debug, PartialEq)]
+pub enum Difference {
+    /// The values at the path aren't equal
+    Changed {
+        path: String,
+        left: String,
+        right: String,
+    },
+    /// The path only exists in the second value
+    Missing { path: String, right: String },
+    /// The path only exists in the first value
+    Extra { path: String, left: String },
+}
+
+/// Finds the differences between two values
+///
+/// Maps, lists, and tuples are compared recursively, with other values compared using `==`.
+/// Maps that overload `==` are compared as a whole.
+pub fn diff_values(
+    vm: &mut Vm,
+    left: &Value,
+    right: &Value,
+) -> Result<Vec<Difference>, RuntimeError> {
+    let mut result = Vec::new();
+    diff_at_path(vm, "", left, right, &mut result)?;
+    Ok(result)
+}
+
+/// Builds a failure message for `assert_eq` from the differences between two values
+///
+/// If the values aren't containers, or if no differences can be found, then a single line
+/// message containing both values is returned.
+pub fn assert_eq_failure_message(vm: &mut Vm, left: &Value, right: &Value) -> String {
+    let simple_message = || format!("Assertion failed, '{}' is not equal to '{}'", left, right);
+
+    if !is_container(left) || !is_container(right) {
+        return simple_message();
+    }
+
+    let differences = match diff_values(vm, left, right) {
+        Ok(differences) if !differences.is_empty() => differences,
+        _ => return simple_message(),
+    };
+
+    let mut message = "Assertion failed, the values are not equal".to_string();
+    for difference in differences.iter().take(MAX_DIFFERENCES) {
+        message.push_str("\n  ");
+        match difference {
+            Difference::Changed { path, left, right } => {
+                message.push_str(&format!("{}: '{}' != '{}'", path, left, right))
+            }
+            Difference::Missing { path, right } => {
+                message.push_str(&format!("{}: missing, expected '{}'", path, right))
+            }
+            Difference::Extra { path, left } => {
+                message.push_str(&format!("{}: unexpected value '{}'", path, left))
+            }
+        }
+    }
+    if differences.len() > MAX_DIFFERENCES {
+        message.push_str(&format!(
+            "\n  ...and {} more differences",
+            differences.len() - MAX_DIFFERENCES
+        ));
+    }
+
+    message
+}
+
+fn is_container(value: &Value) -> bool {
+    use Value::*;
+
+    match value {
+        Map(m) => !has_equality_overload(m),
+        List(_) | Tuple(_) => true,
+        _ => false,
+    }
+}
+
+fn has_equality_overload(map: &ValueMap) -> bool {
+    map.contents()
+        .meta
+        .contains_key(&MetaKey::BinaryOp(BinaryOp::Equal))
+}
+
+fn key_path(path: &str, key: &ValueKey) -> String {
+    match key.value() {
+        Value::Str(s) if path.is_empty() => s.to_string(),
+        Value::Str(s) => format!("{}.{}", path, s),
+        other => format!("{}[{}]", path, other),
+    }
+}
+
+fn index_path(path: &str, index: usize) -> String {
+    format!("{}[{}]", path, index)
+}
+
+fn diff_at_path(
+    vm: &mut Vm,
+    path: &str,
+    left: &Value,
+    right: &Value,
+    result: &mut Vec<Difference>,
+) -> Result<(), RuntimeError> {
+    use Value::*;
+
+    match (left, right) {
+        (Map(left_map), Map(right_map))
+            if !has_equality_overload(left_map) && !has_equality_overload(right_map) =>
+        {
+            // The map contents are cloned so that locks aren't held while comparing values
+            let left_entries: Vec<_> = left_map.cloned_iter().collect();
+            let right_entries: Vec<_> = right_map.cloned_iter().collect();
+
+            for (key, left_value) in left_entries.iter() {
+                let right_value = right_map.contents().data.get(key).cloned();
+                match right_value {
+                    Some(right_value) => {
+                        diff_at_path(vm, &key_path(path, key), left_value, &right_value, result)?;
+                    }
+                    None => result.push(Difference::Extra {
+                        path: key_path(path, key),
+                        left: left_value.to_string(),
+                    }),
+                }
+            }
+
+            for (key, right_value) in right_entries.iter() {
+                if !left_map.contents().data.contains_key(key) {
+                    result.push(Difference::Missing {
+                        path: key_path(path, key),
+                        right: right_value.to_string(),
+                    });
+                }
+            }
+        }
+        (List(left_list), List(right_list)) => {
+            let left_values = left_list.data().clone();
+            let right_values = right_list.data().clone();
+            diff_sequences(vm, path, &left_values, &right_values, result)?;
+        }
+        (Tuple(left_tuple), Tuple(right_tuple)) => {
+            diff_sequences(vm, path, left_tuple.data(), right_tuple.data(), result)?;
+        }
+        _ => {
+            let equal =
+                vm.child_vm()
+                    .run_binary_op(BinaryOp::Equal, left.clone(), right.clone())?;
+            if !matches!(equal, Bool(true)) {
+                result.push(Difference::Changed {
+                    path: if path.is_empty() {
+                        "value".to_string()
+                    } else {
+                        path.to_string()
+                    },
+                    left: left.to_string(),
+                    right: right.to_string(),
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn diff_sequences(
+    vm: &mut Vm,
+    path: &str,
+    left: &[Value],
+    right: &[Value],
+    result: &mut Vec<Difference>,
+) -> Result<(), RuntimeError> {
+    for (index, (left_value, right_value)) in left.iter().zip(right.iter()).enumerate() {
+        diff_at_path(
+            vm,
+            &index_path(path, index),
+            left_value,
+            right_value,
+            result,
+        )?;
+    }
+
+    for (index, left_value) in left.iter().enumerate().skip(right.len()) {
+        result.push(Difference::Extra {
+            path: index_path(path, index),
+            left: left_value.to_string(),
+        });
+    }
+
+    for (index, right_value) in right.iter().enumerate().skip(left.len()) {
+        result.push(Difference::Missing {
+            path: index_path(path, index),
+            right: right_value.to_string(),
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,624 @@
+use {
+    crate::{
+        external::Args, is_external_instance, runtime_error, visit_external_value, ExternalValue,
+        RuntimeError, RuntimeResult, Value, ValueKey, ValueList, ValueMap, Vm,
+    },
+    rand::{distributions::Uniform, Rng, SeedableRng},
+    rand_chacha::ChaCha20Rng,
+    std::fmt,
+};
+
+// The number of times a property is checked when no `runs` option is provided
+const DEFAULT_RUNS: usize = 100;
+// The maximum number of shrinking candidates that are tried when no `max_shrinks` option is
+// provided
+const DEFAULT_MAX_SHRINKS: usize = 1000;
+
+const DEFAULT_INTEGER_RANGE: (i64, i64) = (-1000, 1000);
+const DEFAULT_NUMBER_RANGE: (f64, f64) = (-1000.0, 1000.0);
+const DEFAULT_STRING_SIZE: usize = 20;
+const DEFAULT_CONTAINER_SIZE: usize = 10;
+
+/// A generator of input values for property checks
+///
+/// Generators are made available to scripts as maps containing the generator as an external
+/// value, so that they can be passed to other generators to build up composite inputs.
+#[derive(Clone, Debug)]
+enum InputGenerator {
+    Bool,
+    Integer {
+        min: i64,
+        max: i64,
+    },
+    Number {
+        min: f64,
+        max: f64,
+    },
+    // Strings are made up of printable ASCII characters
+    String {
+        max_size: usize,
+    },
+    List {
+        element: Box<InputGenerator>,
+        max_size: usize,
+    },
+    Map {
+        key: Box<InputGenerator>,
+        value: Box<InputGenerator>,
+        max_size: usize,
+    },
+    Tuple(Vec<InputGenerator>),
+    OneOf(Vec<Value>),
+}
+
+impl InputGenerator {
+    fn make_value(self) -> Value {
+        let mut result = ValueMap::new();
+        result.insert(
+            Value::ExternalDataId.into(),
+            Value::make_external_value(self),
+        );
+        Value::Map(result)
+    }
+
+    fn generate(&self, rng: &mut ChaCha20Rng) -> Value {
+        use Value::*;
+
+        match self {
+            InputGenerator::Bool => Bool(rng.gen()),
+            InputGenerator::Integer { min, max } => {
+                Number(rng.sample(Uniform::new_inclusive(*min, *max)).into())
+            }
+            InputGenerator::Number { min, max } => {
+                if min < max {
+                    Number(rng.gen_range(*min, *max).into())
+                } else {
+                    Number((*min).into())
+                }
+            }
+            InputGenerator::String { max_size } => {
+                let size = rng.gen_range(0, max_size + 1);
+                let s: String = (0..size)
+                    .map(|_| rng.gen_range(b' ', b'~' + 1) as char)
+                    .collect();
+                Str(s.into())
+            }
+            InputGenerator::List { element, max_size } => {
+                let size = rng.gen_range(0, max_size + 1);
+                let values: Vec<Value> = (0..size).map(|_| element.generate(rng)).collect();
+                List(ValueList::from_slice(&values))
+            }
+            InputGenerator::Map {
+                key,
+                value,
+                max_size,
+            } => {
+                let size = rng.gen_range(0, max_size + 1);
+                let mut result = ValueMap::new();
+                for _ in 0..size {
+                    let key = key.generate(rng);
+                    let value = value.generate(rng);
+                    result.insert(key.into(), value);
+                }
+                Map(result)
+            }
+            InputGenerator::Tuple(generators) => {
+                let values: Vec<Value> = generators.iter().map(|g| g.generate(rng)).collect();
+                Tuple(values.into())
+            }
+            InputGenerator::OneOf(values) => values[rng.gen_range(0, values.len())].clone(),
+        }
+    }
+
+    // Returns simpler variations of a generated value, with the simplest candidates first
+    fn shrink(&self, value: &Value) -> Vec<Value> {
+        use Value::*;
+
+        let mut result = Vec::new();
+
+        match (self, value) {
+            (InputGenerator::Bool, Bool(true)) => result.push(Bool(false)),
+            (InputGenerator::Integer { min, max }, Number(n)) => result.extend(
+                shrink_integer(n.into(), *min, *max)
+                    .into_iter()
+                    .map(|n| Number(n.into())),
+            ),
+            (InputGenerator::Number { min, max }, Number(n)) => result.extend(
+                shrink_number(n.into(), *min, *max)
+                    .into_iter()
+                    .map(|n| Number(n.into())),
+            ),
+            (InputGenerator::String { .. }, Str(s)) => {
+                let chars: Vec<char> = s.chars().collect();
+                for candidate in
+                    shrink_sequence(&chars, |c| if *c != 'a' { vec!['a'] } else { vec![] })
+                {
+                    result.push(Str(candidate.into_iter().collect::<String>().into()));
+                }
+            }
+            (InputGenerator::List { element, .. }, List(l)) => {
+                let values = l.data().to_vec();
+                for candidate in shrink_sequence(&values, |value| element.shrink(value)) {
+                    result.push(List(ValueList::from_slice(&candidate)));
+                }
+            }
+            (InputGenerator::Map { value, .. }, Map(m)) => {
+                let entries: Vec<(ValueKey, Value)> = m.cloned_iter().collect();
+                let shrink_entry = |(key, entry_value): &(ValueKey, Value)| {
+                    value
+                        .shrink(entry_value)
+                        .into_iter()
+                        .map(|shrunk| (key.clone(), shrunk))
+                        .collect()
+                };
+                for candidate in shrink_sequence(&entries, shrink_entry) {
+                    let mut map = ValueMap::new();
+                    for (key, value) in candidate {
+                        map.insert(key, value);
+                    }
+                    result.push(Map(map));
+                }
+            }
+            (InputGenerator::Tuple(generators), Tuple(t)) => {
+                let values = t.data();
+                for (i, (generator, value)) in generators.iter().zip(values.iter()).enumerate() {
+                    for shrunk in generator.shrink(value) {
+                        let mut candidate = values.to_vec();
+                        candidate[i] = shrunk;
+                        result.push(Tuple(candidate.into()));
+                    }
+                }
+            }
+            (InputGenerator::OneOf(values), value) => {
+                // Options that appear earlier in the list are considered to be simpler
+                let value_string = value.to_string();
+                if let Some(index) = values.iter().position(|v| v.to_string() == value_string) {
+                    result.extend(values[..index].iter().cloned());
+                }
+            }
+            _ => {}
+        }
+
+        result
+    }
+}
+
+impl ExternalValue for InputGenerator {
+    fn value_type(&self) -> String {
+        "Generator".to_string()
+    }
+}
+
+impl fmt::Display for InputGenerator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Generator")
+    }
+}
+
+// Returns integers that are closer to zero, or to the nearest bound if zero is out of range
+fn shrink_integer(n: i64, min: i64, max: i64) -> Vec<i64> {
+    let mut result = Vec::new();
+
+    let target = 0.max(min).min(max);
+    if n != target {
+        result.push(target);
+        let half_way = target + (n - target) / 2;
+        if half_way != target && half_way != n {
+            result.push(half_way);
+        }
+        let one_step = if n > target { n - 1 } else { n + 1 };
+        if one_step != half_way && one_step != target {
+            result.push(one_step);
+        }
+    }
+
+    result
+}
+
+// Returns numbers that are closer to zero, or to the nearest bound if zero is out of range
+//
+// Numbers with fractional parts are also shrunk by rounding them to fewer decimal places.
+fn shrink_number(n: f64, min: f64, max: f64) -> Vec<f64> {
+    let mut result = Vec::new();
+
+    let target = 0.0_f64.max(min).min(max);
+    if n == target {
+        return result;
+    }
+
+    let candidates = [
+        target,
+        (target + (n - target) / 2.0).trunc(),
+        n.trunc(),
+        // Integral numbers are stepped towards the target
+        n.trunc() - (n - target).signum(),
+        n.round(),
+        (n * 10.0).round() / 10.0,
+        (n * 100.0).round() / 100.0,
+    ];
+    for candidate in candidates.iter() {
+        if *candidate != n && *candidate >= min && *candidate <= max && !result.contains(candidate)
+        {
+            result.push(*candidate);
+        }
+    }
+
+    result
+}
+
+// Returns simpler variations of a sequence
+//
+// Shorter sequences are produced first, by removing the whole sequence, then halves, then single
+// elements, followed by sequences where a single element has been simplified.
+fn shrink_sequence<T: Clone>(values: &[T], shrink_element: impl Fn(&T) -> Vec<T>) -> Vec<Vec<T>> {
+    let mut result = Vec::new();
+
+    if values.is_empty() {
+        return result;
+    }
+
+    result.push(vec![]);
+
+    let len = values.len();
+    if len > 2 {
+        result.push(values[..len / 2].to_vec());
+        result.push(values[len / 2..].to_vec());
+    }
+
+    if len > 1 {
+        for i in 0..len {
+            let mut candidate = values.to_vec();
+            candidate.remove(i);
+            result.push(candidate);
+        }
+    }
+
+    for (i, value) in values.iter().enumerate() {
+        for shrunk in shrink_element(value) {
+            let mut candidate = values.to_vec();
+            candidate[i] = shrunk;
+            result.push(candidate);
+        }
+    }
+
+    result
+}
+
+fn get_generator(value: &Value, error_prefix: &str) -> Result<InputGenerator, RuntimeError> {
+    match value {
+        Value::Map(m) if is_external_instance::<InputGenerator>(m) => {
+            let mut result = None;
+            visit_external_value(m, |generator: &mut InputGenerator| {
+                result = Some(generator.clone());
+                Ok(Value::Empty)
+            })?;
+            Ok(result.unwrap())
+        }
+        unexpected => runtime_error!(
+            "{}: Expected a generator, found '{}'",
+            error_prefix,
+            unexpected.type_as_string()
+        ),
+    }
+}
+
+fn get_size(value: &Value, error_prefix: &str) -> Result<usize, RuntimeError> {
+    match value {
+        Value::Number(n) if *n >= 0.0 => Ok(usize::from(n)),
+        unexpected => runtime_error!(
+            "{}: Expected a non-negative number for the maximum size, found '{}'",
+            error_prefix,
+            unexpected
+        ),
+    }
+}
+
+/// Makes the `test.gen` module, containing the generators that can be used with `test.check`
+pub fn make_generators_module() -> ValueMap {
+    use Value::*;
+
+    let mut result = ValueMap::new();
+
+    result.add_fn("bool", |vm, args| match vm.get_args(args) {
+        [] => Ok(InputGenerator::Bool.make_value()),
+        _ => runtime_error!("gen.bool: Expected no arguments"),
+    });
+
+    result.add_fn("integer", |vm, args| {
+        let (min, max) = match vm.get_args(args) {
+            [] => DEFAULT_INTEGER_RANGE,
+            [Number(min), Number(max)] if min <= max => (i64::from(min), i64::from(max)),
+            _ => return runtime_error!("gen.integer: Expected no arguments, or min and max"),
+        };
+        Ok(InputGenerator::Integer { min, max }.make_value())
+    });
+
+    result.add_fn("number", |vm, args| {
+        let (min, max) = match vm.get_args(args) {
+            [] => DEFAULT_NUMBER_RANGE,
+            [Number(min), Number(max)] if min <= max => (f64::from(min), f64::from(max)),
+            _ => return runtime_error!("gen.number: Expected no arguments, or min and max"),
+        };
+        if !(max - min).is_finite() {
+            return runtime_error!(
+                "gen.number: Expected min and max values with a finite range, found '{}' and '{}'",
+                min,
+                max
+            );
+        }
+        Ok(InputGenerator::Number { min, max }.make_value())
+    });
+
+    result.add_fn("string", |vm, args| {
+        let max_size = match vm.get_args(args) {
+            [] => DEFAULT_STRING_SIZE,
+            [size] => get_size(size, "gen.string")?,
+            _ => return runtime_error!("gen.string: Expected no arguments, or a maximum size"),
+        };
+        Ok(InputGenerator::String { max_size }.make_value())
+    });
+
+    result.add_fn("list", |vm, args| {
+        let (element, max_size) = match vm.get_args(args) {
+            [element] => (get_generator(element, "gen.list")?, DEFAULT_CONTAINER_SIZE),
+            [element, size] => (
+                get_generator(element, "gen.list")?,
+                get_size(size, "gen.list")?,
+            ),
+            _ => {
+                return runtime_error!(
+                    "gen.list: Expected an element generator, and an optional maximum size"
+                )
+            }
+        };
+        Ok(InputGenerator::List {
+            element: Box::new(element),
+            max_size,
+        }
+        .make_value())
+    });
+
+    result.add_fn("map", |vm, args| {
+        let (key, value, max_size) = match vm.get_args(args) {
+            [key, value] => (
+                get_generator(key, "gen.map")?,
+                get_generator(value, "gen.map")?,
+                DEFAULT_CONTAINER_SIZE,
+            ),
+            [key, value, size] => (
+                get_generator(key, "gen.map")?,
+                get_generator(value, "gen.map")?,
+                get_size(size, "gen.map")?,
+            ),
+            _ => {
+                return runtime_error!(
+                    "gen.map: Expected key and value generators, and an optional maximum size"
+                )
+            }
+        };
+
+        // Only generators that produce immutable values can be used for keys
+        let valid_key = match &key {
+            InputGenerator::Bool
+            | InputGenerator::Integer { .. }
+            | InputGenerator::Number { .. }
+            | InputGenerator::String { .. } => true,
+            InputGenerator::OneOf(values) => values.iter().all(|value| value.is_immutable()),
+            _ => false,
+        };
+        if !valid_key {
+            return runtime_error!(
+                "gen.map: The key generator must produce bools, numbers, or strings"
+            );
+        }
+
+        Ok(InputGenerator::Map {
+            key: Box::new(key),
+            value: Box::new(value),
+            max_size,
+        }
+        .make_value())
+    });
+
+    result.add_fn("tuple", |vm, args| {
+        let generators = vm
+            .get_args(args)
+            .iter()
+            .map(|arg| get_generator(arg, "gen.tuple"))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(InputGenerator::Tuple(generators).make_value())
+    });
+
+    result.add_fn("one_of", |vm, args| {
+        let values = match vm.get_args(args) {
+            [List(l)] if !l.data().is_empty() => l.data().to_vec(),
+            [Tuple(t)] if !t.data().is_empty() => t.data().to_vec(),
+            _ => return runtime_error!("gen.one_of: Expected a non-empty list or tuple"),
+        };
+        Ok(InputGenerator::OneOf(values).make_value())
+    });
+
+    result
+}
+
+struct CheckOptions {
+    runs: usize,
+    seed: u64,
+    max_shrinks: usize,
+}
+
+impl CheckOptions {
+    fn from_map(options: Option<&ValueMap>) -> Result<Self, RuntimeError> {
+        let mut result = Self {
+            runs: DEFAULT_RUNS,
+            seed: rand::thread_rng().gen::<u32>() as u64,
+            max_shrinks: DEFAULT_MAX_SHRINKS,
+        };
+
+        if let Some(options) = options {
+            for (key, value) in options.cloned_iter() {
+                match (key.to_string().as_str(), &value) {
+                    ("runs", Value::Number(n)) if *n >= 1.0 => result.runs = usize::from(n),
+                    ("seed", Value::Number(n)) => result.seed = n.to_bits(),
+                    ("max_shrinks", Value::Number(n)) if *n >= 0.0 => {
+                        result.max_shrinks = usize::from(n)
+                    }
+                    (key, value) => {
+                        return runtime_error!(
+                            "check: Invalid option '{}' with value '{}'",
+                            key,
+                            value
+                        )
+                    }
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+/// `test.check`, checks a predicate against inputs produced by generators
+///
+/// The predicate fails if it returns `false` or throws an error. When a failure is found, the
+/// failing inputs are shrunk to a minimal counterexample, which is included in the error.
+pub fn check(vm: &mut Vm, args: &Args) -> RuntimeResult {
+    use Value::*;
+
+    let (generators, predicate, options) = match vm.get_args(args) {
+        [generators, predicate] if predicate.is_callable() => {
+            (generators.clone(), predicate.clone(), None)
+        }
+        [generators, predicate, Map(options)] if predicate.is_callable() => {
+            (generators.clone(), predicate.clone(), Some(options.clone()))
+        }
+        _ => {
+            return runtime_error!(
+                "check: Expected generators, a predicate function, and an optional options map"
+            )
+        }
+    };
+
+    // Either a single generator, or a list or tuple of generators can be provided,
+    // with each generator producing an argument for the predicate.
+    let generators = match &generators {
+        Map(_) => vec![get_generator(&generators, "check")?],
+        List(l) => l
+            .data()
+            .iter()
+            .map(|g| get_generator(g, "check"))
+            .collect::<Result<Vec<_>, _>>()?,
+        Tuple(t) => t
+            .data()
+            .iter()
+            .map(|g| get_generator(g, "check"))
+            .collect::<Result<Vec<_>, _>>()?,
+        unexpected => {
+            return runtime_error!(
+                "check: Expected a generator, or a list of generators, found '{}'",
+                unexpected.type_as_string()
+            )
+        }
+    };
+
+    let options = CheckOptions::from_map(options.as_ref())?;
+    let mut rng = ChaCha20Rng::seed_from_u64(options.seed);
+
+    for run in 1..=options.runs {
+        let inputs: Vec<Value> = generators.iter().map(|g| g.generate(&mut rng)).collect();
+
+        if let Some(failure) = run_predicate(vm, &predicate, &inputs)? {
+            let (counterexample, failure, shrinks) =
+                shrink_failure(vm, &predicate, &generators, inputs, failure, &options)?;
+
+            // Strings are quoted so that empty strings and whitespace are visible
+            let counterexample = match counterexample.as_slice() {
+                [Str(s)] => format!("\"{}\"", s),
+                [single] => single.to_string(),
+                _ => Tuple(counterexample.into()).to_string(),
+            };
+
+            return runtime_error!(
+                "Property failed after {} (seed: {}), minimal counterexample: {}{}\n  {}",
+                count_with_units(run, "run"),
+                options.seed,
+                counterexample,
+                if shrinks > 0 {
+                    format!(" (shrunk {})", count_with_units(shrinks, "time"))
+                } else {
+                    "".to_string()
+                },
+                failure
+            );
+        }
+    }
+
+    Ok(Empty)
+}
+
+fn count_with_units(count: usize, unit: &str) -> String {
+    if count == 1 {
+        format!("1 {}", unit)
+    } else {
+        format!("{} {}s", count, unit)
+    }
+}
+
+// Runs the predicate with the given inputs, returning a description of the failure if it failed
+//
+// The inputs are copied before being passed to the predicate so that they're unaffected by any
+// modifications that the predicate makes.
+fn run_predicate(
+    vm: &mut Vm,
+    predicate: &Value,
+    inputs: &[Value],
+) -> Result<Option<String>, RuntimeError> {
+    let args: Vec<Value> = inputs.iter().map(|input| input.deep_copy()).collect();
+
+    match vm.child_vm().run_function(predicate.clone(), &args) {
+        Ok(Value::Bool(false)) => Ok(Some("The predicate returned false".to_string())),
+        Ok(_) => Ok(None),
+        Err(error) if error.is_execution_limit() || error.is_stack_overflow() => Err(error),
+        Err(error) => Ok(Some(format!("{:#}", error))),
+    }
+}
+
+// Shrinks failing inputs, returning the simplest inputs that were found to fail,
+// along with their failure and the number of successful shrinks.
+fn shrink_failure(
+    vm: &mut Vm,
+    predicate: &Value,
+    generators: &[InputGenerator],
+    mut inputs: Vec<Value>,
+    mut failure: String,
+    options: &CheckOptions,
+) -> Result<(Vec<Value>, String, usize), RuntimeError> {
+    let mut attempts = 0;
+    let mut shrinks = 0;
+
+    'shrinking: while attempts < options.max_shrinks {
+        for (i, generator) in generators.iter().enumerate() {
+            for candidate in generator.shrink(&inputs[i]) {
+                if attempts == options.max_shrinks {
+                    break 'shrinking;
+                }
+                attempts += 1;
+
+                let mut candidate_inputs = inputs.clone();
+                candidate_inputs[i] = candidate;
+
+                if let Some(candidate_failure) = run_predicate(vm, predicate, &candidate_inputs)? {
+                    inputs = candidate_inputs;
+                    failure = candidate_failure;
+                    shrinks += 1;
+                    continue 'shrinking;
+                }
+            }
+        }
+
+        // None of the candidates failed, so the inputs are as simple as they can get
+        break;
+    }
+
+    Ok((inputs, failure, shrinks))
+}
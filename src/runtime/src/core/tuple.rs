@@ -78,18 +78,26 @@ pub fn make_module() -> ValueMap {
 
     result.add_fn("sort_copy", |vm, args| match vm.get_args(args) {
         [Tuple(t)] => {
+            let allocation = vm.track_allocation::<Value>(t.data().len())?;
             let mut result = t.data().to_vec();
             let vm = vm.child_vm();
 
             sort_values(vm, &mut result)?;
 
-            Ok(Tuple(result.into()))
+            let result = Tuple(result.into());
+            allocation.assign_to(&result);
+            Ok(result)
         }
         _ => runtime_error!("tuple.sort_copy: Expected tuple as argument"),
     });
 
     result.add_fn("to_list", |vm, args| match vm.get_args(args) {
-        [Tuple(t)] => Ok(List(ValueList::from_slice(t.data()))),
+        [Tuple(t)] => {
+            let allocation = vm.track_allocation::<Value>(t.data().len())?;
+            let result = List(ValueList::from_slice(t.data()));
+            allocation.assign_to(&result);
+            Ok(result)
+        }
         _ => runtime_error!("tuple.to_list: Expected tuple as argument"),
     });
 
@@ -0,0 +1,368 @@
+use {
+    koto_bytecode::{Chunk, Instruction, InstructionReader},
+    parking_lot::Mutex,
+    std::{
+        collections::{BTreeMap, HashMap},
+        fmt::Write,
+        path::PathBuf,
+        sync::Arc,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+/// The coverage results for a single line of a script
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineCoverage {
+    /// The line number, starting from 1
+    pub line: u32,
+    /// The number of times that the line was executed
+    pub hits: u64,
+    /// The branches that start on the line
+    pub branches: Vec<BranchCoverage>,
+}
+
+/// The coverage results for a conditional jump
+///
+/// Each conditional jump has two branches, the jump being taken, or execution continuing with
+/// the following instruction.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BranchCoverage {
+    /// The number of times that the jump was taken
+    pub taken: u64,
+    /// The number of times that execution continued without jumping
+    pub not_taken: u64,
+}
+
+impl BranchCoverage {
+    // The number of the jump's two branches that were followed
+    fn hit_count(&self) -> usize {
+        (self.taken > 0) as usize + (self.not_taken > 0) as usize
+    }
+}
+
+/// The coverage results for a script
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileCoverage {
+    /// The path of the script
+    pub source_path: PathBuf,
+    /// The lines of the script that contain instructions, in ascending order
+    pub lines: Vec<LineCoverage>,
+}
+
+impl FileCoverage {
+    /// The number of lines that were executed at least once
+    pub fn lines_hit(&self) -> usize {
+        self.lines.iter().filter(|line| line.hits > 0).count()
+    }
+
+    fn branches(&self) -> impl Iterator<Item = &BranchCoverage> {
+        self.lines.iter().flat_map(|line| line.branches.iter())
+    }
+
+    /// The number of branches in the script, with two branches for each conditional jump
+    pub fn branch_count(&self) -> usize {
+        self.branches().count() * 2
+    }
+
+    /// The number of branches that were followed at least once
+    pub fn branches_hit(&self) -> usize {
+        self.branches().map(BranchCoverage::hit_count).sum()
+    }
+}
+
+/// Records the lines and branches of scripts that are executed
+///
+/// Coverage gets recorded by attaching a [Coverage] to a Vm via
+/// [VmSettings::coverage](crate::VmSettings::coverage), and is then shared with any VMs that the
+/// Vm spawns, so that modules loaded via `import` are also included.
+///
+/// Instructions are mapped to lines via the source map in each chunk's
+/// [DebugInfo](koto_bytecode::DebugInfo). Chunks without a source path aren't included in the
+/// results.
+#[derive(Debug, Default)]
+pub struct Coverage {
+    data: Mutex<CoverageData>,
+}
+
+#[derive(Debug, Default)]
+struct CoverageData {
+    // The chunks that have been executed, keyed by chunk address
+    chunks: HashMap<usize, ChunkCoverage>,
+}
+
+// The hit counts for a chunk's instructions
+#[derive(Debug)]
+struct ChunkCoverage {
+    // The chunk is kept alive so that its address isn't reused while recording
+    chunk: Arc<Chunk>,
+    // Hit counts for each instruction in the chunk, keyed by ip
+    instructions: BTreeMap<usize, u64>,
+    // The results for each conditional jump in the chunk, keyed by ip
+    branches: BTreeMap<usize, BranchCoverage>,
+}
+
+impl ChunkCoverage {
+    fn new(chunk: Arc<Chunk>) -> Self {
+        let mut instructions = BTreeMap::new();
+        let mut branches = BTreeMap::new();
+
+        let mut reader = InstructionReader::new(chunk.clone());
+        let mut ip = reader.ip;
+        while let Some(instruction) = reader.next() {
+            instructions.insert(ip, 0);
+            if is_branch(&instruction) {
+                branches.insert(ip, BranchCoverage::default());
+            }
+            ip = reader.ip;
+        }
+
+        Self {
+            chunk,
+            instructions,
+            branches,
+        }
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the results for each script that has been executed, sorted by path
+    ///
+    /// The results for chunks with matching source paths are combined.
+    pub fn files(&self) -> Vec<FileCoverage> {
+        let data = self.data.lock();
+
+        let mut files: BTreeMap<PathBuf, BTreeMap<u32, LineCoverage>> = BTreeMap::new();
+        for chunk_coverage in data.chunks.values() {
+            let chunk = &chunk_coverage.chunk;
+            let source_path = match &chunk.source_path {
+                Some(path) => path.clone(),
+                None => continue,
+            };
+            let lines = files.entry(source_path).or_default();
+            let line_at_ip = |ip| {
+                chunk
+                    .debug_info
+                    .get_source_span(ip)
+                    .map_or(0, |span| span.start.line)
+            };
+
+            // Instructions that follow on from a line's first instruction can be shared with
+            // other code paths, e.g. the return at the end of a function after an `if` block,
+            // so a line's hit count is taken from the first instruction in each run of
+            // instructions for the line.
+            let mut previous_line = None;
+            for (ip, hits) in chunk_coverage.instructions.iter() {
+                let line = line_at_ip(*ip);
+                let entry = lines.entry(line).or_insert_with(|| LineCoverage {
+                    line,
+                    hits: 0,
+                    branches: vec![],
+                });
+                if previous_line != Some(line) {
+                    entry.hits = entry.hits.max(*hits);
+                    previous_line = Some(line);
+                }
+            }
+
+            for (ip, branch) in chunk_coverage.branches.iter() {
+                if let Some(entry) = lines.get_mut(&line_at_ip(*ip)) {
+                    entry.branches.push(branch.clone());
+                }
+            }
+        }
+
+        files
+            .into_iter()
+            .map(|(source_path, lines)| FileCoverage {
+                source_path,
+                lines: lines.into_values().filter(|line| line.line > 0).collect(),
+            })
+            .collect()
+    }
+
+    /// Returns the results in the lcov tracefile format
+    pub fn lcov(&self) -> String {
+        let mut result = String::new();
+
+        for file in self.files() {
+            let _ = writeln!(result, "TN:");
+            let _ = writeln!(result, "SF:{}", file.source_path.to_string_lossy());
+
+            let mut block = 0;
+            for line in file.lines.iter() {
+                for branch in line.branches.iter() {
+                    let executed = branch.taken > 0 || branch.not_taken > 0;
+                    for (index, count) in [branch.not_taken, branch.taken].iter().enumerate() {
+                        if executed {
+                            let _ = writeln!(
+                                result,
+                                "BRDA:{},{},{},{}",
+                                line.line, block, index, count
+                            );
+                        } else {
+                            let _ = writeln!(result, "BRDA:{},{},{},-", line.line, block, index);
+                        }
+                    }
+                    block += 1;
+                }
+            }
+            let _ = writeln!(result, "BRF:{}", file.branch_count());
+            let _ = writeln!(result, "BRH:{}", file.branches_hit());
+
+            for line in file.lines.iter() {
+                let _ = writeln!(result, "DA:{},{}", line.line, line.hits);
+            }
+            let _ = writeln!(result, "LF:{}", file.lines.len());
+            let _ = writeln!(result, "LH:{}", file.lines_hit());
+            let _ = writeln!(result, "end_of_record");
+        }
+
+        result
+    }
+
+    /// Returns the results in the Cobertura XML format
+    pub fn cobertura(&self) -> String {
+        let files = self.files();
+
+        let lines_valid: usize = files.iter().map(|file| file.lines.len()).sum();
+        let lines_covered: usize = files.iter().map(|file| file.lines_hit()).sum();
+        let branches_valid: usize = files.iter().map(|file| file.branch_count()).sum();
+        let branches_covered: usize = files.iter().map(|file| file.branches_hit()).sum();
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+
+        let mut result = String::new();
+        let _ = writeln!(result, r#"<?xml version="1.0" ?>"#);
+        let _ = writeln!(
+            result,
+            r#"<!DOCTYPE coverage SYSTEM "http://cobertura.sourceforge.net/xml/coverage-04.dtd">"#
+        );
+        let _ = writeln!(
+            result,
+            r#"<coverage line-rate="{}" branch-rate="{}" lines-covered="{}" lines-valid="{}" branches-covered="{}" branches-valid="{}" complexity="0" version="{}" timestamp="{}">"#,
+            rate(lines_covered, lines_valid),
+            rate(branches_covered, branches_valid),
+            lines_covered,
+            lines_valid,
+            branches_covered,
+            branches_valid,
+            env!("CARGO_PKG_VERSION"),
+            timestamp,
+        );
+        let _ = writeln!(result, "  <sources>");
+        let _ = writeln!(result, "    <source>.</source>");
+        let _ = writeln!(result, "  </sources>");
+        let _ = writeln!(result, "  <packages>");
+        let _ = writeln!(
+            result,
+            r#"    <package name="koto" line-rate="{}" branch-rate="{}" complexity="0">"#,
+            rate(lines_covered, lines_valid),
+            rate(branches_covered, branches_valid),
+        );
+        let _ = writeln!(result, "      <classes>");
+
+        for file in files.iter() {
+            let path = escape_xml(&file.source_path.to_string_lossy());
+            let _ = writeln!(
+                result,
+                r#"        <class name="{}" filename="{}" line-rate="{}" branch-rate="{}" complexity="0">"#,
+                path,
+                path,
+                rate(file.lines_hit(), file.lines.len()),
+                rate(file.branches_hit(), file.branch_count()),
+            );
+            let _ = writeln!(result, "          <methods/>");
+            let _ = writeln!(result, "          <lines>");
+            for line in file.lines.iter() {
+                if line.branches.is_empty() {
+                    let _ = writeln!(
+                        result,
+                        r#"            <line number="{}" hits="{}" branch="false"/>"#,
+                        line.line, line.hits
+                    );
+                } else {
+                    let count = line.branches.len() * 2;
+                    let hit: usize = line.branches.iter().map(BranchCoverage::hit_count).sum();
+                    let _ = writeln!(
+                        result,
+                        r#"            <line number="{}" hits="{}" branch="true" condition-coverage="{}% ({}/{})"/>"#,
+                        line.line,
+                        line.hits,
+                        hit * 100 / count,
+                        hit,
+                        count
+                    );
+                }
+            }
+            let _ = writeln!(result, "          </lines>");
+            let _ = writeln!(result, "        </class>");
+        }
+
+        let _ = writeln!(result, "      </classes>");
+        let _ = writeln!(result, "    </package>");
+        let _ = writeln!(result, "  </packages>");
+        let _ = writeln!(result, "</coverage>");
+
+        result
+    }
+
+    /// Clears the recorded results
+    pub fn reset(&self) {
+        *self.data.lock() = CoverageData::default();
+    }
+
+    // Called before the instruction at the given ip is executed
+    pub(crate) fn record_instruction(&self, chunk: &Arc<Chunk>, ip: usize) {
+        let mut data = self.data.lock();
+        let chunk_coverage = data
+            .chunks
+            .entry(Arc::as_ptr(chunk) as usize)
+            .or_insert_with(|| ChunkCoverage::new(chunk.clone()));
+        *chunk_coverage.instructions.entry(ip).or_default() += 1;
+    }
+
+    // Called after a conditional jump at the given ip has been executed
+    pub(crate) fn record_branch(&self, chunk: &Arc<Chunk>, ip: usize, taken: bool) {
+        let mut data = self.data.lock();
+        if let Some(chunk_coverage) = data.chunks.get_mut(&(Arc::as_ptr(chunk) as usize)) {
+            let branch = chunk_coverage.branches.entry(ip).or_default();
+            if taken {
+                branch.taken += 1;
+            } else {
+                branch.not_taken += 1;
+            }
+        }
+    }
+}
+
+/// Returns true if the instruction is a conditional jump
+pub(crate) fn is_branch(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::JumpIf { .. }
+            | Instruction::JumpBackIf { .. }
+            | Instruction::IterNext { .. }
+            | Instruction::IterNextTemp { .. }
+            | Instruction::IterNextQuiet { .. }
+    )
+}
+
+fn rate(covered: usize, valid: usize) -> String {
+    if valid == 0 {
+        "1".to_string()
+    } else {
+        format!("{:.4}", covered as f64 / valid as f64)
+    }
+}
+
+pub(crate) fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,342 @@
+use {
+    crate::{value_map::ValueMapContents, Value, ValueList, ValueMap, ValueVec},
+    parking_lot::{Mutex, RwLock},
+    std::{
+        collections::{hash_map::Entry, HashMap},
+        mem,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Weak,
+        },
+    },
+};
+
+/// Controls the collection of reference cycles between lists and maps
+///
+/// Lists and maps are reference counted, so values that refer to each other (e.g. a map
+/// containing a function that captures the map) won't be freed once they're no longer in use.
+///
+/// When cycle collection is enabled, the lists and maps that are made by the Vm are tracked, and
+/// the cycles between tracked values that are no longer reachable can be found and broken.
+/// See [Vm::collect_cycles](crate::Vm::collect_cycles) and
+/// [Vm::find_leaked_cycles](crate::Vm::find_leaked_cycles).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CycleCollection {
+    /// Lists and maps aren't tracked, and cycles can't be collected
+    Disabled,
+    /// Lists and maps are tracked, with cycles being collected when requested by the host
+    Manual,
+    /// Lists and maps are tracked, with cycles being collected automatically
+    ///
+    /// A collection is triggered once the given number of lists and maps have been made since
+    /// the previous collection. Automatic collections are skipped while threads that were
+    /// spawned by scripts (e.g. with `thread.create`) are running.
+    Threshold(usize),
+}
+
+// #[default] on enum variants isn't used so that older Rust versions are supported
+#[allow(clippy::derivable_impls)]
+impl Default for CycleCollection {
+    fn default() -> Self {
+        Self::Disabled
+    }
+}
+
+// Tracks lists and maps so that unreachable cycles can be found
+//
+// Shared between a Vm and the VMs that it spawns.
+//
+// Cycles are found via trial deletion: references to tracked values from other tracked values are
+// counted, and values with additional references are considered to be externally reachable.
+// Tracked values that aren't reachable from an externally reachable value are only being kept
+// alive by references from other unreachable values, i.e. they're part of (or referred to by)
+// leaked cycles.
+pub(crate) struct CycleTracker {
+    mode: CycleCollection,
+    state: Mutex<TrackerState>,
+    // The number of concurrent VMs that are sharing the tracked values
+    concurrent_vms: AtomicUsize,
+}
+
+#[derive(Default)]
+struct TrackerState {
+    tracked: Vec<TrackedValue>,
+    // The number of tracked values that will trigger the next automatic collection
+    next_collection: usize,
+}
+
+impl CycleTracker {
+    pub fn new(mode: CycleCollection) -> Self {
+        let next_collection = match mode {
+            CycleCollection::Threshold(threshold) => threshold,
+            _ => 0,
+        };
+
+        Self {
+            mode,
+            state: Mutex::new(TrackerState {
+                tracked: Vec::new(),
+                next_collection,
+            }),
+            concurrent_vms: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.mode != CycleCollection::Disabled
+    }
+
+    pub fn track(&self, value: &Value) {
+        if self.is_enabled() {
+            if let Some(container) = Container::from_value(value) {
+                self.state.lock().tracked.push(container.downgrade());
+            }
+        }
+    }
+
+    // Called when a concurrent Vm is spawned
+    pub fn add_concurrent_vm(&self) {
+        self.concurrent_vms.fetch_add(1, Ordering::SeqCst);
+    }
+
+    // Called when a concurrent Vm is dropped
+    pub fn remove_concurrent_vm(&self) {
+        self.concurrent_vms.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    // Returns true if an automatic collection should be performed
+    //
+    // Collections aren't performed while concurrent VMs are running, values could be modified
+    // by other threads while the references between tracked values are being counted.
+    pub fn collection_due(&self) -> bool {
+        match self.mode {
+            CycleCollection::Threshold(_) if self.concurrent_vms.load(Ordering::SeqCst) == 0 => {
+                let state = self.state.lock();
+                state.tracked.len() >= state.next_collection
+            }
+            _ => false,
+        }
+    }
+
+    // Breaks leaked cycles by clearing the unreachable values, returning the number of cleared values
+    pub fn collect(&self) -> usize {
+        let (garbage, tracked_count) = self.find_garbage();
+
+        for container in garbage.iter() {
+            container.clear();
+        }
+
+        if let CycleCollection::Threshold(threshold) = self.mode {
+            let remaining = tracked_count - garbage.len();
+            self.state.lock().next_collection = remaining + threshold;
+        }
+
+        garbage.len()
+    }
+
+    // Returns the tracked values that are being kept alive by leaked cycles
+    pub fn find_leaks(&self) -> Vec<Value> {
+        self.find_garbage()
+            .0
+            .into_iter()
+            .map(Container::into_value)
+            .collect()
+    }
+
+    // Returns the unreachable tracked values, along with the number of tracked values
+    fn find_garbage(&self) -> (Vec<Container>, usize) {
+        // Get the tracked values that are still alive, discarding the rest
+        let mut containers = Vec::new();
+        let mut index = HashMap::new();
+        self.state
+            .lock()
+            .tracked
+            .retain(|tracked| match tracked.upgrade() {
+                Some(container) => {
+                    if let Entry::Vacant(entry) = index.entry(container.id()) {
+                        entry.insert(containers.len());
+                        containers.push(container);
+                    }
+                    true
+                }
+                None => false,
+            });
+
+        // Count the references to each tracked value from other tracked values
+        //
+        // Values that are locked are in use (e.g. a list that's being modified by a function that
+        // calls back into the Vm), so they're considered to be reachable.
+        let mut internal_references = vec![0; containers.len()];
+        let mut locked = vec![false; containers.len()];
+        for (i, container) in containers.iter().enumerate() {
+            locked[i] = !container.for_each_value(|value| {
+                if let Some(i) = Container::id_of(value).and_then(|id| index.get(&id)) {
+                    internal_references[*i] += 1;
+                }
+            });
+        }
+
+        // Values with references that don't come from other tracked values are reachable,
+        // taking the reference held in `containers` into account.
+        let mut reachable: Vec<bool> = containers
+            .iter()
+            .zip(internal_references.iter().zip(locked))
+            .map(|(container, (internal, locked))| {
+                locked || container.strong_count() > internal + 1
+            })
+            .collect();
+
+        // Mark the values that can be reached from the reachable values
+        let mut to_visit = reachable
+            .iter()
+            .enumerate()
+            .filter_map(|(i, reachable)| if *reachable { Some(i) } else { None })
+            .collect::<Vec<_>>();
+        while let Some(i) = to_visit.pop() {
+            containers[i].for_each_value(|value| {
+                visit_contained_ids(value, &mut |id| {
+                    if let Some(&i) = index.get(&id) {
+                        if !reachable[i] {
+                            reachable[i] = true;
+                            to_visit.push(i);
+                        }
+                    }
+                })
+            });
+        }
+
+        let tracked_count = containers.len();
+        let garbage = containers
+            .into_iter()
+            .zip(reachable)
+            .filter_map(|(container, reachable)| if reachable { None } else { Some(container) })
+            .collect();
+
+        (garbage, tracked_count)
+    }
+}
+
+// Calls `f` with the ids of the containers that are referred to by a value
+//
+// Tuples are immutable, so they can't be tracked, but they're searched for contained values.
+fn visit_contained_ids(value: &Value, f: &mut dyn FnMut(*const ())) {
+    match value {
+        Value::Tuple(tuple) => {
+            for value in tuple.data() {
+                visit_contained_ids(value, f);
+            }
+        }
+        _ => {
+            if let Some(id) = Container::id_of(value) {
+                f(id);
+            }
+        }
+    }
+}
+
+enum TrackedValue {
+    List(Weak<RwLock<ValueVec>>),
+    Map(Weak<RwLock<ValueMapContents>>),
+}
+
+impl TrackedValue {
+    fn upgrade(&self) -> Option<Container> {
+        match self {
+            Self::List(list) => ValueList::upgrade(list).map(Container::List),
+            Self::Map(map) => ValueMap::upgrade(map).map(Container::Map),
+        }
+    }
+}
+
+// A list or map that can take part in a reference cycle
+//
+// Functions refer to their captured values via a list, so cycles involving functions are
+// represented by the functions' capture lists.
+enum Container {
+    List(ValueList),
+    Map(ValueMap),
+}
+
+impl Container {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::List(list) => Some(Self::List(list.clone())),
+            Value::Map(map) => Some(Self::Map(map.clone())),
+            Value::Function(function) | Value::Generator(function) => {
+                function.captures.clone().map(Self::List)
+            }
+            _ => None,
+        }
+    }
+
+    fn id_of(value: &Value) -> Option<*const ()> {
+        match value {
+            Value::List(list) => Some(list.data_ptr()),
+            Value::Map(map) => Some(map.data_ptr()),
+            Value::Function(function) | Value::Generator(function) => function
+                .captures
+                .as_ref()
+                .map(|captures| captures.data_ptr()),
+            _ => None,
+        }
+    }
+
+    fn id(&self) -> *const () {
+        match self {
+            Self::List(list) => list.data_ptr(),
+            Self::Map(map) => map.data_ptr(),
+        }
+    }
+
+    fn downgrade(&self) -> TrackedValue {
+        match self {
+            Self::List(list) => TrackedValue::List(list.downgrade()),
+            Self::Map(map) => TrackedValue::Map(map.downgrade()),
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Self::List(list) => list.strong_count(),
+            Self::Map(map) => map.strong_count(),
+        }
+    }
+
+    // Calls `f` with each of the container's values, returning false if the container is locked
+    fn for_each_value(&self, mut f: impl FnMut(&Value)) -> bool {
+        match self {
+            Self::List(list) => match list.try_data() {
+                Some(data) => data.iter().for_each(f),
+                None => return false,
+            },
+            Self::Map(map) => match map.try_contents() {
+                Some(contents) => {
+                    contents.data.values().for_each(&mut f);
+                    contents.meta.values().for_each(f);
+                }
+                None => return false,
+            },
+        }
+        true
+    }
+
+    // Clears the container's contents, breaking any cycles that it's part of
+    fn clear(&self) {
+        // The contents are dropped after the container's lock has been released
+        match self {
+            Self::List(list) => {
+                let _data = mem::take(&mut *list.data_mut());
+            }
+            Self::Map(map) => {
+                let _contents = mem::take(&mut *map.contents_mut());
+            }
+        }
+    }
+
+    fn into_value(self) -> Value {
+        match self {
+            Self::List(list) => Value::List(list),
+            Self::Map(map) => Value::Map(map),
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use {
+    crate::{Value, Vm},
+    koto_bytecode::Chunk,
+    koto_parser::Span,
+    parking_lot::{Mutex, MutexGuard, RwLock},
+    std::{
+        collections::HashMap,
+        fmt,
+        path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicU8, Ordering},
+            Arc,
+        },
+    },
+};
+
+/// The reason that a [Vm] has paused execution
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PauseReason {
+    /// A line with a breakpoint has been reached
+    Breakpoint,
+    /// A step has been completed, see [DebugCommand]
+    Step,
+    /// A pause was requested via [Debugger::request_pause]
+    PauseRequested,
+}
+
+/// Tells a paused [Vm] how execution should continue
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugCommand {
+    /// Continue until a breakpoint is reached or a pause is requested
+    Continue,
+    /// Pause at the next line that's reached, including lines in called functions
+    StepIn,
+    /// Pause at the next line in the current function, or in the function's caller
+    StepOver,
+    /// Pause when execution has returned to the current function's caller
+    StepOut,
+}
+
+/// A frame in a paused [Vm]'s call stack
+#[derive(Clone, Debug)]
+pub struct DebugFrame {
+    /// The chunk containing the frame's function
+    pub chunk: Arc<Chunk>,
+    /// The ip of the instruction that the frame is paused at
+    pub ip: usize,
+    /// The source span of the instruction that the frame is paused at
+    pub span: Option<Span>,
+    /// The frame's named local values
+    pub locals: Vec<(String, Value)>,
+}
+
+impl DebugFrame {
+    /// The path of the frame's source file
+    pub fn source_path(&self) -> Option<&Path> {
+        self.chunk.source_path.as_deref()
+    }
+
+    /// Returns the value of the local with the given name
+    pub fn get_local(&self, name: &str) -> Option<&Value> {
+        self.locals
+            .iter()
+            .find(|(local_name, _)| local_name == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// Information that's passed to a [DebugHook] when execution pauses
+#[derive(Clone, Debug)]
+pub struct PauseEvent {
+    pub reason: PauseReason,
+    /// The paused Vm's call stack, starting with the innermost frame
+    pub call_stack: Vec<DebugFrame>,
+}
+
+/// Hooks that get called by a [Vm] that has a [Debugger] attached
+pub trait DebugHook: Send + Sync {
+    /// Called when execution has paused
+    ///
+    /// Execution resumes when the function returns, following the returned command.
+    fn on_pause(&self, vm: &mut Vm, event: &PauseEvent) -> DebugCommand;
+}
+
+/// Breakpoint and stepping support for Koto scripts
+///
+/// A debugger gets attached to a Vm via [VmSettings::debugger](crate::VmSettings::debugger),
+/// and is then shared with any VMs that the Vm spawns, e.g. when importing modules.
+///
+/// Breakpoints can be set from other threads while the Vm is running.
+pub struct Debugger {
+    hook: Box<dyn DebugHook>,
+    // Breakpoint lines, keyed by source path
+    breakpoints: RwLock<HashMap<Option<PathBuf>, Vec<u32>>>,
+    pause_request: AtomicU8,
+    // Shared by all of the VMs that have the debugger attached, so that steps can continue
+    // through the VMs that are spawned to call functions, e.g. when `list.each` calls a function
+    state: Mutex<DebugState>,
+}
+
+const NO_PAUSE_REQUEST: u8 = 0;
+const PAUSE_REQUESTED: u8 = 1;
+const STEP_REQUESTED: u8 = 2;
+
+impl Debugger {
+    pub fn new(hook: impl DebugHook + 'static) -> Self {
+        Self {
+            hook: Box::new(hook),
+            breakpoints: Default::default(),
+            pause_request: AtomicU8::new(NO_PAUSE_REQUEST),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Replaces the breakpoints for a source file
+    ///
+    /// Lines are 1-based, matching the lines reported in source spans.
+    /// A path of `None` sets breakpoints for scripts that were compiled without a path.
+    pub fn set_breakpoints(&self, source_path: Option<&Path>, lines: &[u32]) {
+        let key = source_path.map(normalize_path);
+        let mut breakpoints = self.breakpoints.write();
+        if lines.is_empty() {
+            breakpoints.remove(&key);
+        } else {
+            breakpoints.insert(key, lines.to_vec());
+        }
+    }
+
+    /// Removes all breakpoints
+    pub fn clear_breakpoints(&self) {
+        self.breakpoints.write().clear();
+    }
+
+    /// Returns the breakpoint lines for a source file
+    pub fn breakpoints(&self, source_path: Option<&Path>) -> Vec<u32> {
+        self.breakpoints
+            .read()
+            .get(&source_path.map(normalize_path))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Requests that execution pauses at the next line that's reached
+    ///
+    /// This can be called before running a script to pause on entry.
+    pub fn request_pause(&self) {
+        self.pause_request.store(PAUSE_REQUESTED, Ordering::Relaxed);
+    }
+
+    pub(crate) fn hook(&self) -> &dyn DebugHook {
+        self.hook.as_ref()
+    }
+
+    pub(crate) fn state(&self) -> MutexGuard<'_, DebugState> {
+        self.state.lock()
+    }
+
+    pub(crate) fn request_step(&self) {
+        self.pause_request.store(STEP_REQUESTED, Ordering::Relaxed);
+    }
+
+    pub(crate) fn take_pause_request(&self) -> Option<PauseReason> {
+        if self.pause_request.load(Ordering::Relaxed) == NO_PAUSE_REQUEST {
+            return None;
+        }
+
+        match self.pause_request.swap(NO_PAUSE_REQUEST, Ordering::Relaxed) {
+            PAUSE_REQUESTED => Some(PauseReason::PauseRequested),
+            STEP_REQUESTED => Some(PauseReason::Step),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn has_breakpoint(&self, source_path: Option<&Path>, line: u32) -> bool {
+        let breakpoints = self.breakpoints.read();
+
+        // Check the line first to avoid normalizing the path for every line change
+        let line_matches = breakpoints.values().any(|lines| lines.contains(&line));
+        if !line_matches {
+            return false;
+        }
+
+        let matches_path = |path: Option<PathBuf>| matches!(breakpoints.get(&path), Some(lines) if lines.contains(&line));
+
+        matches_path(source_path.map(Path::to_path_buf))
+            || (source_path.is_some() && matches_path(source_path.map(normalize_path)))
+    }
+}
+
+impl fmt::Debug for Debugger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Debugger")
+    }
+}
+
+// The state used while stepping through a script, see Debugger::state
+//
+// Call depths include the calls made by the VMs that spawned the Vm, see Vm::call_depth.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DebugState {
+    // The call depth and line of the last instruction that was checked by the debugger
+    pub position: Option<(usize, u32)>,
+    // An active step command, along with the call depth and line where the step was started
+    pub step: Option<(DebugCommand, usize, u32)>,
+}
+
+fn normalize_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
@@ -9,10 +9,16 @@ use {
     },
 };
 
+// The maximum number of backtrace frames that are included when displaying a RuntimeError
+const MAX_DISPLAYED_TRACE_FRAMES: usize = 16;
+
+/// A frame in a [RuntimeError]'s backtrace
 #[derive(Clone, Debug)]
 pub struct ErrorFrame {
-    chunk: Arc<Chunk>,
-    instruction: usize,
+    /// The chunk that was being executed when the error occurred
+    pub chunk: Arc<Chunk>,
+    /// The ip of the instruction that was being executed
+    pub instruction: usize,
 }
 
 #[derive(Clone, Debug)]
@@ -28,6 +34,22 @@ pub enum RuntimeErrorType {
         thrown_value: Value,
         vm: Option<Arc<Mutex<Vm>>>,
     },
+    /// The Vm's instruction budget has been used up
+    ///
+    /// See [VmSettings::fuel](crate::VmSettings::fuel).
+    OutOfFuel,
+    /// Execution was stopped via an [InterruptHandle](crate::InterruptHandle)
+    Interrupted,
+    /// The maximum call depth was exceeded
+    ///
+    /// See [VmSettings::max_call_depth](crate::VmSettings::max_call_depth) and
+    /// [VmSettings::max_nested_vm_depth](crate::VmSettings::max_nested_vm_depth).
+    StackOverflow,
+    /// An allocation would have exceeded the Vm's memory limit
+    ///
+    /// Unlike the other limits, this error can be caught by scripts.
+    /// See [VmSettings::memory_limit](crate::VmSettings::memory_limit).
+    OutOfMemory { requested: usize, limit: usize },
 }
 
 #[derive(Debug)]
@@ -66,6 +88,23 @@ impl RuntimeError {
         self
     }
 
+    /// Returns true if the error was caused by the Vm reaching one of its execution limits
+    ///
+    /// Errors of this kind can't be caught by scripts.
+    pub fn is_execution_limit(&self) -> bool {
+        matches!(
+            self.error,
+            RuntimeErrorType::OutOfFuel | RuntimeErrorType::Interrupted
+        )
+    }
+
+    /// Returns true if the error was caused by the Vm exceeding its maximum call depth
+    ///
+    /// Errors of this kind can't be caught by scripts.
+    pub fn is_stack_overflow(&self) -> bool {
+        matches!(self.error, RuntimeErrorType::StackOverflow)
+    }
+
     pub fn extend_trace(&mut self, chunk: Arc<Chunk>, instruction: usize) {
         self.trace.push(ErrorFrame { chunk, instruction });
     }
@@ -92,13 +131,21 @@ impl fmt::Display for RuntimeError {
                 },
                 _ => "Unable to get error message".to_string(),
             },
+            OutOfFuel => "Execution stopped, the instruction budget has been used up".to_string(),
+            Interrupted => "Execution was interrupted".to_string(),
+            StackOverflow => "Stack overflow, the maximum call depth was exceeded".to_string(),
+            OutOfMemory { requested, limit } => format!(
+                "Out of memory, allocating {} bytes would exceed the limit of {} bytes",
+                requested, limit
+            ),
         };
 
         if f.alternate() {
             f.write_str(&message)
         } else {
             let mut first_frame = true;
-            for frame in self.trace.iter() {
+            // Deep backtraces (e.g. following a stack overflow) are truncated
+            for frame in self.trace.iter().take(MAX_DISPLAYED_TRACE_FRAMES) {
                 let frame_message = if first_frame {
                     first_frame = false;
                     Some(message.as_str())
@@ -121,6 +168,15 @@ impl fmt::Display for RuntimeError {
                     )?,
                 };
             }
+
+            if self.trace.len() > MAX_DISPLAYED_TRACE_FRAMES {
+                write!(
+                    f,
+                    "\n ... {} more frames",
+                    self.trace.len() - MAX_DISPLAYED_TRACE_FRAMES
+                )?;
+            }
+
             Ok(())
         }
     }
@@ -1,12 +1,20 @@
 //! Contains the runtime and core library for the Koto language
 
+pub mod conversion;
 pub mod core;
+mod coverage;
+mod cycles;
+mod debugger;
 mod error;
 mod external;
 mod frame;
+mod limits;
 mod logger;
 pub mod num2;
 pub mod num4;
+mod profiler;
+mod test_reporter;
+pub mod typed_function;
 pub mod value;
 mod value_iterator;
 mod value_key;
@@ -19,13 +27,25 @@ mod value_tuple;
 mod vm;
 
 pub use {
+    conversion::{ConversionError, FromKoto, IntoKoto, IntoKotoKey},
+    coverage::{BranchCoverage, Coverage, FileCoverage, LineCoverage},
+    cycles::CycleCollection,
+    debugger::{DebugCommand, DebugFrame, DebugHook, Debugger, PauseEvent, PauseReason},
     error::*,
     external::{is_external_instance, visit_external_value, ExternalFunction, ExternalValue},
-    koto_bytecode::{CompilerError, Loader, LoaderError},
+    koto_bytecode::{
+        CompilerError, FileSystemResolver, InMemoryResolver, Loader, LoaderError, ModuleResolver,
+        SearchPathResolver,
+    },
+    koto_derive::{koto_impl, FromKoto, IntoKoto},
     koto_parser::ParserError,
+    limits::{Allocation, InterruptHandle},
     logger::{DefaultLogger, KotoLogger},
     num2::Num2,
     num4::Num4,
+    profiler::{FunctionProfile, Profiler},
+    test_reporter::{JUnitReporter, TapReporter, TestReporter, TestResult},
+    typed_function::{IntoKotoResult, TypedFunction},
     value::{RuntimeFunction, Value},
     value_iterator::{IntRange, ValueIterator, ValueIteratorOutput},
     value_key::{ValueKey, ValueRef},
@@ -34,5 +54,5 @@ pub use {
     value_number::ValueNumber,
     value_string::ValueString,
     value_tuple::ValueTuple,
-    vm::{Vm, VmSettings},
+    vm::{ModuleChanges, Vm, VmSettings},
 };
@@ -0,0 +1,386 @@
+use {
+    crate::{value_map::ValueMapContents, Value, ValueKey, ValueVec},
+    parking_lot::{Mutex, RwLock},
+    std::{
+        collections::HashMap,
+        mem,
+        sync::{
+            atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering},
+            Arc, Weak,
+        },
+    },
+};
+
+/// A thread-safe handle that can be used to interrupt a running [Vm](crate::Vm)
+///
+/// The handle can be cloned and passed to other threads. When [InterruptHandle::interrupt] is
+/// called, the Vm (along with any VMs that it has spawned) will stop before executing its next
+/// instruction, returning a [RuntimeErrorType::Interrupted](crate::RuntimeErrorType) error.
+///
+/// The interrupt stays in effect until the Vm is run again, so that VMs running on other threads
+/// (e.g. those created with `thread.create`) also stop.
+#[derive(Clone, Debug, Default)]
+pub struct InterruptHandle(Arc<AtomicU8>);
+
+impl InterruptHandle {
+    // No interrupt has been requested
+    const NONE: u8 = 0;
+    // An interrupt has been requested, and execution hasn't stopped yet
+    const REQUESTED: u8 = 1;
+    // Execution has been stopped by the interrupt, VMs that are still running should also stop
+    const HANDLED: u8 = 2;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests that execution should stop
+    pub fn interrupt(&self) {
+        self.0.store(Self::REQUESTED, Ordering::Relaxed);
+    }
+
+    /// Returns true if an interrupt has been requested but not yet handled
+    pub fn is_interrupted(&self) -> bool {
+        self.0.load(Ordering::Relaxed) == Self::REQUESTED
+    }
+
+    // Returns true if VMs should stop running
+    pub(crate) fn should_stop(&self) -> bool {
+        self.0.load(Ordering::Relaxed) != Self::NONE
+    }
+
+    // Called when a top-level Vm has been stopped by an interrupt
+    pub(crate) fn set_handled(&self) {
+        let _ = self.0.compare_exchange(
+            Self::REQUESTED,
+            Self::HANDLED,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+    }
+
+    // Called when a top-level Vm starts running, clearing an interrupt that has been handled
+    //
+    // Interrupts that were requested before the Vm started running are left in place.
+    pub(crate) fn clear_handled(&self) {
+        let _ = self.0.compare_exchange(
+            Self::HANDLED,
+            Self::NONE,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+    }
+}
+
+// The number of instructions that a Vm is allowed to execute
+//
+// Shared between a Vm and the VMs that it spawns.
+#[derive(Debug)]
+pub(crate) struct Fuel(AtomicU64);
+
+impl Fuel {
+    const UNLIMITED: u64 = u64::MAX;
+
+    pub fn new(fuel: Option<u64>) -> Self {
+        Self(AtomicU64::new(fuel.unwrap_or(Self::UNLIMITED)))
+    }
+
+    pub fn get(&self) -> Option<u64> {
+        match self.0.load(Ordering::Relaxed) {
+            Self::UNLIMITED => None,
+            fuel => Some(fuel),
+        }
+    }
+
+    pub fn set(&self, fuel: Option<u64>) {
+        self.0
+            .store(fuel.unwrap_or(Self::UNLIMITED), Ordering::Relaxed);
+    }
+
+    pub fn add(&self, amount: u64) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |fuel| match fuel {
+                Self::UNLIMITED => None,
+                _ => Some(fuel.saturating_add(amount).min(Self::UNLIMITED - 1)),
+            });
+    }
+
+    // Consumes a unit of fuel, returning false if no fuel is available
+    pub fn consume(&self) -> bool {
+        if self.0.load(Ordering::Relaxed) == Self::UNLIMITED {
+            true
+        } else {
+            self.0
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |fuel| {
+                    fuel.checked_sub(1)
+                })
+                .is_ok()
+        }
+    }
+}
+
+// The number of bytes that are being used by values, along with the allowed maximum
+//
+// Shared between a Vm and the VMs that it spawns.
+//
+// Allocations are only tracked while a limit is set. Memory is reserved before making a value,
+// and the reservation is then assigned to the value once it has been made. The values that have
+// been assigned memory are tracked via weak references, and their memory is released once the
+// values have been dropped.
+//
+// Unused memory is released lazily, either when a reservation would exceed the limit, when the
+// allocated memory is requested, or when the number of tracked values has grown enough since the
+// last check. Lists and maps that have shrunk since their memory was assigned have the difference
+// released at the same time.
+#[derive(Debug)]
+pub(crate) struct MemoryTracker {
+    allocated: AtomicUsize,
+    limit: AtomicUsize,
+    owners: Mutex<Owners>,
+}
+
+#[derive(Debug)]
+struct Owners {
+    // The values that have been assigned memory, keyed by the address of their storage
+    assigned: HashMap<usize, (Storage, usize)>,
+    // The number of tracked values that triggers the next release of unused memory
+    next_release: usize,
+}
+
+impl MemoryTracker {
+    const UNLIMITED: usize = usize::MAX;
+    const MIN_RELEASE_THRESHOLD: usize = 256;
+
+    pub fn new(limit: Option<usize>) -> Self {
+        Self {
+            allocated: AtomicUsize::new(0),
+            limit: AtomicUsize::new(limit.unwrap_or(Self::UNLIMITED)),
+            owners: Mutex::new(Owners {
+                assigned: HashMap::new(),
+                next_release: Self::MIN_RELEASE_THRESHOLD,
+            }),
+        }
+    }
+
+    // Returns true if allocations should be tracked
+    pub fn is_enabled(&self) -> bool {
+        self.limit.load(Ordering::Relaxed) != Self::UNLIMITED
+    }
+
+    pub fn allocated(&self) -> usize {
+        self.release_unused();
+        self.allocated.load(Ordering::Relaxed)
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        match self.limit.load(Ordering::Relaxed) {
+            Self::UNLIMITED => None,
+            limit => Some(limit),
+        }
+    }
+
+    pub fn set_limit(&self, limit: Option<usize>) {
+        self.limit
+            .store(limit.unwrap_or(Self::UNLIMITED), Ordering::Relaxed);
+    }
+
+    // Reserves memory, returning false if the reservation would exceed the limit
+    //
+    // Reservations that would exceed the limit aren't recorded.
+    pub fn reserve(&self, bytes: usize) -> bool {
+        if self.try_reserve(bytes) {
+            true
+        } else {
+            // Values may have been dropped since the last check, so try again after releasing
+            // their memory.
+            self.release_unused();
+            self.try_reserve(bytes)
+        }
+    }
+
+    fn try_reserve(&self, bytes: usize) -> bool {
+        let limit = self.limit.load(Ordering::Relaxed);
+        self.allocated
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |allocated| {
+                let allocated = allocated.saturating_add(bytes);
+                if allocated <= limit {
+                    Some(allocated)
+                } else {
+                    None
+                }
+            })
+            .is_ok()
+    }
+
+    pub fn release(&self, bytes: usize) {
+        let _ = self
+            .allocated
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |allocated| {
+                Some(allocated.saturating_sub(bytes))
+            });
+    }
+
+    // Assigns reserved memory to a value, the memory is released once the value has been dropped
+    //
+    // Values without storage that can be tracked have their memory released immediately.
+    pub fn assign(&self, bytes: usize, value: &Value) {
+        let storage = match Storage::from_value(value) {
+            Some(storage) => storage,
+            None => {
+                self.release(bytes);
+                return;
+            }
+        };
+
+        let mut owners = self.owners.lock();
+        owners
+            .assigned
+            .entry(storage.id())
+            .or_insert((storage, 0))
+            .1 += bytes;
+
+        if owners.assigned.len() >= owners.next_release {
+            self.release_unused_by_owners(&mut owners);
+        }
+    }
+
+    fn release_unused(&self) {
+        self.release_unused_by_owners(&mut self.owners.lock());
+    }
+
+    fn release_unused_by_owners(&self, owners: &mut Owners) {
+        owners
+            .assigned
+            .retain(|_, (storage, bytes)| match storage.size(*bytes) {
+                Some(size) => {
+                    if size < *bytes {
+                        self.release(*bytes - size);
+                        *bytes = size;
+                    }
+                    true
+                }
+                None => {
+                    self.release(*bytes);
+                    false
+                }
+            });
+        owners.next_release = (owners.assigned.len() * 2).max(Self::MIN_RELEASE_THRESHOLD);
+    }
+}
+
+// A weak reference to the storage of a value that has been assigned memory
+#[derive(Debug)]
+enum Storage {
+    List(Weak<RwLock<ValueVec>>),
+    Map(Weak<RwLock<ValueMapContents>>),
+    Str(Weak<str>),
+    Tuple(Weak<[Value]>),
+}
+
+impl Storage {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::List(list) => Some(Self::List(list.downgrade())),
+            Value::Map(map) => Some(Self::Map(map.downgrade())),
+            Value::Str(string) => Some(Self::Str(string.downgrade())),
+            Value::Tuple(tuple) => Some(Self::Tuple(tuple.downgrade())),
+            _ => None,
+        }
+    }
+
+    // Returns the address of the value's storage
+    //
+    // The address can't be reused while the weak reference is held.
+    fn id(&self) -> usize {
+        match self {
+            Self::List(list) => list.as_ptr() as *const () as usize,
+            Self::Map(map) => map.as_ptr() as *const () as usize,
+            Self::Str(string) => string.as_ptr() as *const () as usize,
+            Self::Tuple(tuple) => tuple.as_ptr() as *const () as usize,
+        }
+    }
+
+    // Returns the number of bytes used by the value, or None if the value has been dropped
+    //
+    // Strings and tuples are immutable so the assigned size is returned, as it is for lists and
+    // maps that are currently locked.
+    fn size(&self, assigned: usize) -> Option<usize> {
+        match self {
+            Self::List(list) => list.upgrade().map(|list| match list.try_read() {
+                Some(data) => data.len() * mem::size_of::<Value>(),
+                None => assigned,
+            }),
+            Self::Map(map) => map.upgrade().map(|map| match map.try_read() {
+                Some(contents) => {
+                    (contents.data.len() + contents.meta.len())
+                        * mem::size_of::<(ValueKey, Value)>()
+                }
+                None => assigned,
+            }),
+            Self::Str(string) => {
+                if string.strong_count() > 0 {
+                    Some(assigned)
+                } else {
+                    None
+                }
+            }
+            Self::Tuple(tuple) => {
+                if tuple.strong_count() > 0 {
+                    Some(assigned)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// Memory that has been reserved for a value, see [Vm::track_allocation](crate::Vm::track_allocation)
+///
+/// The memory is released when the allocation is dropped, unless it has been assigned to a value
+/// with [Allocation::assign_to].
+#[must_use = "the reserved memory is released when the allocation is dropped"]
+pub struct Allocation {
+    // None when allocations aren't being tracked
+    memory: Option<Arc<MemoryTracker>>,
+    bytes: usize,
+}
+
+impl Allocation {
+    pub(crate) fn new(memory: Option<Arc<MemoryTracker>>, bytes: usize) -> Self {
+        Self { memory, bytes }
+    }
+
+    /// Adds the memory that was reserved by another allocation to this allocation
+    pub fn merge(&mut self, mut other: Allocation) {
+        if let Some(memory) = other.memory.take() {
+            if self.memory.is_some() {
+                self.bytes += other.bytes;
+            } else {
+                self.memory = Some(memory);
+                self.bytes = other.bytes;
+            }
+        }
+    }
+
+    /// Assigns the reserved memory to a value
+    ///
+    /// The memory remains counted towards the memory limit until the value has been dropped.
+    /// Memory can be assigned to lists, maps, strings, and tuples, for other values the memory is
+    /// released immediately. Memory that's assigned to a list or map that's being added to
+    /// (e.g. when pushing a value to a list) is added to the memory that was previously assigned.
+    pub fn assign_to(mut self, value: &Value) {
+        if let Some(memory) = self.memory.take() {
+            memory.assign(self.bytes, value);
+        }
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        if let Some(memory) = self.memory.take() {
+            memory.release(self.bytes);
+        }
+    }
+}
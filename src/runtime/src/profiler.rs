@@ -0,0 +1,278 @@
+use {
+    koto_bytecode::Chunk,
+    koto_parser::Span,
+    parking_lot::Mutex,
+    std::{
+        cmp::Reverse,
+        collections::HashMap,
+        fmt::Write,
+        path::PathBuf,
+        sync::Arc,
+        thread::{self, ThreadId},
+        time::{Duration, Instant},
+    },
+};
+
+/// The profiling results for a single function
+#[derive(Clone, Debug)]
+pub struct FunctionProfile {
+    /// The path of the script containing the function
+    pub source_path: Option<PathBuf>,
+    /// The source span of the function
+    pub span: Span,
+    /// True if the function is a script's main block, rather than a function defined in a script
+    pub is_main_block: bool,
+    /// The number of times that the function was called
+    pub calls: u64,
+    /// The time spent in the function, including time spent in functions that it called
+    pub inclusive_time: Duration,
+    /// The time spent in the function, excluding time spent in functions that it called
+    pub exclusive_time: Duration,
+}
+
+impl FunctionProfile {
+    /// A name for the function based on its location, e.g. `foo.koto:12:5`
+    pub fn name(&self) -> String {
+        let path = match &self.source_path {
+            Some(path) => path.to_string_lossy().to_string(),
+            None => "<script>".to_string(),
+        };
+
+        if self.is_main_block {
+            path
+        } else {
+            format!(
+                "{}:{}:{}",
+                path, self.span.start.line, self.span.start.column
+            )
+        }
+    }
+}
+
+/// An execution profiler for Koto scripts
+///
+/// A profiler gets attached to a Vm via [VmSettings::profiler](crate::VmSettings::profiler),
+/// and is then shared with any VMs that the Vm spawns.
+///
+/// Each function call is recorded, with time being attributed to the active call stack whenever
+/// a function is entered or exited. Time spent in external functions is attributed to the
+/// calling Koto function.
+#[derive(Debug, Default)]
+pub struct Profiler {
+    data: Mutex<ProfileData>,
+}
+
+#[derive(Debug, Default)]
+struct ProfileData {
+    functions: Vec<FunctionProfile>,
+    // Functions are identified by their source path and span, e.g. a script that's loaded in
+    // more than one Vm will have its functions' results combined.
+    function_indices: HashMap<(Option<PathBuf>, [u32; 4]), usize>,
+    // A cache of function indices, keyed by chunk address and function ip.
+    // The chunks are kept alive so that their addresses aren't reused while profiling.
+    chunk_functions: HashMap<(usize, usize), (Arc<Chunk>, usize)>,
+    // The time spent with each call stack active, stacks are made up of function indices
+    stacks: HashMap<Vec<usize>, Duration>,
+    threads: HashMap<ThreadId, ThreadState>,
+}
+
+// The active call stack on a thread, along with the time that it became active
+#[derive(Debug)]
+struct ThreadState {
+    stack: Vec<usize>,
+    since: Instant,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the results for each function that has been called, in the order of first call
+    pub fn functions(&self) -> Vec<FunctionProfile> {
+        let data = self.data.lock();
+
+        let mut result = data.functions.clone();
+        for (stack, time) in data.stacks.iter() {
+            if let Some(last) = stack.last() {
+                result[*last].exclusive_time += *time;
+            }
+
+            // Recursive calls only count once towards a function's inclusive time
+            let mut counted = Vec::with_capacity(stack.len());
+            for function in stack.iter() {
+                if !counted.contains(function) {
+                    result[*function].inclusive_time += *time;
+                    counted.push(*function);
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Returns the recorded call stacks in the 'collapsed stack' format used by flamegraph tools
+    ///
+    /// Each line contains a call stack with the function names separated by `;`, followed by the
+    /// number of microseconds that were spent with the stack active.
+    pub fn collapsed_stacks(&self) -> String {
+        let data = self.data.lock();
+
+        let names: Vec<String> = data.functions.iter().map(|f| f.name()).collect();
+        let mut lines: Vec<String> = data
+            .stacks
+            .iter()
+            .filter(|(_, time)| time.as_micros() > 0)
+            .map(|(stack, time)| {
+                let stack: Vec<&str> = stack.iter().map(|f| names[*f].as_str()).collect();
+                format!("{} {}", stack.join(";"), time.as_micros())
+            })
+            .collect();
+        lines.sort();
+
+        let mut result = lines.join("\n");
+        if !result.is_empty() {
+            result.push('\n');
+        }
+        result
+    }
+
+    /// Returns a table summarizing the results for each function
+    ///
+    /// Functions are sorted by their exclusive time, with the slowest functions first.
+    pub fn summary(&self) -> String {
+        let mut functions = self.functions();
+        functions.sort_by_key(|f| Reverse(f.exclusive_time));
+
+        let names: Vec<String> = functions.iter().map(|f| f.name()).collect();
+        let name_width = names
+            .iter()
+            .map(|name| name.len())
+            .max()
+            .unwrap_or(0)
+            .max(8);
+
+        let mut result = String::new();
+        let _ = writeln!(
+            result,
+            "{:<width$}  {:>10}  {:>14}  {:>14}",
+            "Function",
+            "Calls",
+            "Inclusive (ms)",
+            "Exclusive (ms)",
+            width = name_width
+        );
+        for (function, name) in functions.iter().zip(names.iter()) {
+            let _ = writeln!(
+                result,
+                "{:<width$}  {:>10}  {:>14.3}  {:>14.3}",
+                name,
+                function.calls,
+                function.inclusive_time.as_secs_f64() * 1000.0,
+                function.exclusive_time.as_secs_f64() * 1000.0,
+                width = name_width
+            );
+        }
+        result
+    }
+
+    /// Clears the recorded results
+    pub fn reset(&self) {
+        *self.data.lock() = ProfileData::default();
+    }
+
+    // Returns the index of the function whose frame starts at the given ip
+    pub(crate) fn function_index(&self, chunk: &Arc<Chunk>, ip: usize) -> usize {
+        let mut data = self.data.lock();
+
+        let cache_key = (Arc::as_ptr(chunk) as usize, ip);
+        if let Some((_, index)) = data.chunk_functions.get(&cache_key) {
+            return *index;
+        }
+
+        let span = chunk
+            .debug_info
+            .get_frame(ip)
+            .map(|frame| frame.span)
+            .unwrap_or_default();
+        let key = (
+            chunk.source_path.clone(),
+            [
+                span.start.line,
+                span.start.column,
+                span.end.line,
+                span.end.column,
+            ],
+        );
+
+        let index = match data.function_indices.get(&key) {
+            Some(index) => *index,
+            None => {
+                let index = data.functions.len();
+                data.functions.push(FunctionProfile {
+                    source_path: chunk.source_path.clone(),
+                    span,
+                    is_main_block: ip == 0,
+                    calls: 0,
+                    inclusive_time: Duration::default(),
+                    exclusive_time: Duration::default(),
+                });
+                data.function_indices.insert(key, index);
+                index
+            }
+        };
+
+        data.chunk_functions
+            .insert(cache_key, (chunk.clone(), index));
+        index
+    }
+
+    // Called when a function is entered, with the new call stack
+    pub(crate) fn enter_function(&self, stack: Vec<usize>) {
+        let mut data = self.data.lock();
+        if let Some(function) = stack.last() {
+            data.functions[*function].calls += 1;
+        }
+        data.set_active_stack(stack);
+    }
+
+    // Called when the active call stack has changed without a function being entered,
+    // e.g. when a function returns, or when a generator yields or is resumed.
+    pub(crate) fn set_active_stack(&self, stack: Vec<usize>) {
+        self.data.lock().set_active_stack(stack);
+    }
+}
+
+impl ProfileData {
+    fn set_active_stack(&mut self, stack: Vec<usize>) {
+        let now = Instant::now();
+        let previous = self
+            .threads
+            .insert(thread::current().id(), ThreadState { stack, since: now });
+
+        if let Some(previous) = previous {
+            if !previous.stack.is_empty() {
+                *self.stacks.entry(previous.stack).or_default() += now - previous.since;
+            }
+        }
+    }
+}
+
+// The per-Vm state used while a profiler is attached
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ProfileState {
+    // The call stack of the Vm that spawned this Vm, at the time it was spawned
+    pub parent_stack: Vec<usize>,
+    // The functions in the Vm's call stack
+    pub stack: Vec<usize>,
+}
+
+impl ProfileState {
+    // Returns the full call stack, including the parent's stack
+    pub fn full_stack(&self) -> Vec<usize> {
+        let mut result = Vec::with_capacity(self.parent_stack.len() + self.stack.len());
+        result.extend_from_slice(&self.parent_stack);
+        result.extend_from_slice(&self.stack);
+        result
+    }
+}
@@ -0,0 +1,236 @@
+use {
+    crate::{coverage::escape_xml, RuntimeError},
+    std::{fmt::Write, time::Duration},
+};
+
+/// The result of running a single test, see [Vm::run_tests](crate::Vm::run_tests)
+#[derive(Debug)]
+pub struct TestResult {
+    /// The test's name, without its `test_` prefix
+    pub name: String,
+    /// The time taken to run the test, including its `pre_test` and `post_test` functions
+    pub duration: Duration,
+    /// The error that caused the test to fail, or `None` if the test passed
+    pub error: Option<RuntimeError>,
+}
+
+impl TestResult {
+    /// Returns true if the test passed
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// A trait for types that report the results of running tests
+pub trait TestReporter {
+    /// Called before running a group of tests, e.g. the tests exported by a script
+    ///
+    /// Tests reported without a suite having been started are placed in a default suite.
+    fn start_suite(&mut self, _name: &str) {}
+
+    /// Called after each test has been run
+    fn test_finished(&mut self, result: &TestResult);
+}
+
+// A test result that has been recorded by a reporter
+struct ReportedTest {
+    name: String,
+    duration: Duration,
+    error: Option<String>,
+}
+
+impl From<&TestResult> for ReportedTest {
+    fn from(result: &TestResult) -> Self {
+        Self {
+            name: result.name.clone(),
+            duration: result.duration,
+            error: result.error.as_ref().map(|error| error.to_string()),
+        }
+    }
+}
+
+struct ReportedSuite {
+    name: String,
+    tests: Vec<ReportedTest>,
+}
+
+impl ReportedSuite {
+    fn failures(&self) -> usize {
+        self.tests
+            .iter()
+            .filter(|test| test.error.is_some())
+            .count()
+    }
+
+    fn duration(&self) -> Duration {
+        self.tests.iter().map(|test| test.duration).sum()
+    }
+}
+
+// Records suites and their test results, shared by the reporters
+#[derive(Default)]
+struct ReportedSuites(Vec<ReportedSuite>);
+
+impl ReportedSuites {
+    fn start_suite(&mut self, name: &str) {
+        self.0.push(ReportedSuite {
+            name: name.to_string(),
+            tests: vec![],
+        });
+    }
+
+    fn add_test(&mut self, result: &TestResult) {
+        if self.0.is_empty() {
+            self.start_suite("koto");
+        }
+        self.0.last_mut().unwrap().tests.push(result.into());
+    }
+}
+
+/// A [TestReporter] that produces a report in the JUnit XML format
+#[derive(Default)]
+pub struct JUnitReporter {
+    suites: ReportedSuites,
+}
+
+impl JUnitReporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the report as JUnit XML
+    pub fn output(&self) -> String {
+        let suites = &self.suites.0;
+        let tests: usize = suites.iter().map(|suite| suite.tests.len()).sum();
+        let failures: usize = suites.iter().map(|suite| suite.failures()).sum();
+        let duration: Duration = suites.iter().map(|suite| suite.duration()).sum();
+
+        let mut result = String::new();
+        let _ = writeln!(result, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        let _ = writeln!(
+            result,
+            r#"<testsuites tests="{}" failures="{}" time="{:.6}">"#,
+            tests,
+            failures,
+            duration.as_secs_f64()
+        );
+
+        for suite in suites.iter() {
+            let suite_name = escape_xml(&suite.name);
+            let _ = writeln!(
+                result,
+                r#"  <testsuite name="{}" tests="{}" failures="{}" errors="0" time="{:.6}">"#,
+                suite_name,
+                suite.tests.len(),
+                suite.failures(),
+                suite.duration().as_secs_f64()
+            );
+
+            for test in suite.tests.iter() {
+                let _ = write!(
+                    result,
+                    r#"    <testcase name="{}" classname="{}" time="{:.6}""#,
+                    escape_xml(&test.name),
+                    suite_name,
+                    test.duration.as_secs_f64()
+                );
+                match &test.error {
+                    Some(error) => {
+                        let message = error.lines().next().unwrap_or_default();
+                        let _ = writeln!(result, ">");
+                        let _ = writeln!(
+                            result,
+                            r#"      <failure message="{}">{}</failure>"#,
+                            escape_xml(message),
+                            escape_xml(error)
+                        );
+                        let _ = writeln!(result, "    </testcase>");
+                    }
+                    None => {
+                        let _ = writeln!(result, "/>");
+                    }
+                }
+            }
+
+            let _ = writeln!(result, "  </testsuite>");
+        }
+
+        let _ = writeln!(result, "</testsuites>");
+        result
+    }
+}
+
+impl TestReporter for JUnitReporter {
+    fn start_suite(&mut self, name: &str) {
+        self.suites.start_suite(name);
+    }
+
+    fn test_finished(&mut self, result: &TestResult) {
+        self.suites.add_test(result);
+    }
+}
+
+/// A [TestReporter] that produces a report in the Test Anything Protocol (TAP) format
+#[derive(Default)]
+pub struct TapReporter {
+    suites: ReportedSuites,
+}
+
+impl TapReporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the report in the TAP version 13 format
+    ///
+    /// Each suite is introduced with a comment, and failing tests include their errors in a YAML
+    /// diagnostic block.
+    pub fn output(&self) -> String {
+        let suites = &self.suites.0;
+        let tests: usize = suites.iter().map(|suite| suite.tests.len()).sum();
+
+        let mut result = String::new();
+        let _ = writeln!(result, "TAP version 13");
+        let _ = writeln!(result, "1..{}", tests);
+
+        let mut test_number = 1;
+        for suite in suites.iter() {
+            let _ = writeln!(result, "# {}", suite.name);
+
+            for test in suite.tests.iter() {
+                match &test.error {
+                    None => {
+                        let _ = writeln!(result, "ok {} - {}", test_number, test.name);
+                    }
+                    Some(error) => {
+                        let _ = writeln!(result, "not ok {} - {}", test_number, test.name);
+                        let _ = writeln!(result, "  ---");
+                        let _ = writeln!(result, "  message: |");
+                        for line in error.lines() {
+                            let _ = writeln!(result, "    {}", line);
+                        }
+                        let _ = writeln!(
+                            result,
+                            "  duration_ms: {:.3}",
+                            test.duration.as_secs_f64() * 1000.0
+                        );
+                        let _ = writeln!(result, "  ...");
+                    }
+                }
+                test_number += 1;
+            }
+        }
+
+        result
+    }
+}
+
+impl TestReporter for TapReporter {
+    fn start_suite(&mut self, name: &str) {
+        self.suites.start_suite(name);
+    }
+
+    fn test_finished(&mut self, result: &TestResult) {
+        self.suites.add_test(result);
+    }
+}
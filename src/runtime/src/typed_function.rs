@@ -0,0 +1,136 @@
+//! Support for calling Rust functions from Koto with automatically converted arguments
+//!
+//! See [ValueMap::add_typed_fn](crate::ValueMap::add_typed_fn), and the `koto_impl` attribute
+//! macro, which uses the helpers here in its generated code.
+
+use crate::{
+    external::ExternalFunction, ConversionError, FromKoto, IntoKoto, RuntimeError, RuntimeResult,
+    Value,
+};
+
+/// A trait for Rust functions that can be called from Koto with automatically converted arguments
+///
+/// Implemented for functions and closures with up to six arguments that implement [FromKoto],
+/// returning values that implement [IntoKotoResult]. Trailing `Option` arguments can be left out
+/// when calling the function.
+///
+/// See [ValueMap::add_typed_fn](crate::ValueMap::add_typed_fn).
+pub trait TypedFunction<ArgTypes>: Send + Sync + 'static {
+    /// Converts the arguments and then calls the function
+    ///
+    /// The name is used as a prefix in error messages.
+    fn call_typed(&self, name: &str, args: &[Value]) -> RuntimeResult;
+}
+
+/// A trait for the values that can be returned from a [TypedFunction]
+///
+/// Implemented for types that implement [IntoKoto], and for `Result`s containing them.
+pub trait IntoKotoResult {
+    fn into_koto_result(self) -> RuntimeResult;
+}
+
+impl<T: IntoKoto> IntoKotoResult for T {
+    fn into_koto_result(self) -> RuntimeResult {
+        Ok(self.into_koto())
+    }
+}
+
+impl<T: IntoKoto, E: Into<RuntimeError>> IntoKotoResult for Result<T, E> {
+    fn into_koto_result(self) -> RuntimeResult {
+        self.map(IntoKoto::into_koto).map_err(Into::into)
+    }
+}
+
+// Makes an ExternalFunction that calls a typed function
+pub(crate) fn make_typed_function<ArgTypes>(
+    name: &str,
+    f: impl TypedFunction<ArgTypes>,
+) -> ExternalFunction {
+    let name = name.to_string();
+    ExternalFunction::new(
+        move |vm, args| f.call_typed(&name, vm.get_args(args)),
+        false,
+    )
+}
+
+/// Returns an error if more than the expected number of arguments have been provided
+pub fn check_arg_count(name: &str, args: &[Value], arg_count: usize) -> Result<(), RuntimeError> {
+    if args.len() > arg_count {
+        Err(arity_error(name, arg_count, args.len()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Converts an argument, with errors referring to the argument's position, starting from 1
+///
+/// Missing arguments are converted from `Empty`, so that trailing `Option` arguments can be left
+/// out.
+pub fn convert_arg<T: FromKoto>(
+    name: &str,
+    args: &[Value],
+    index: usize,
+    arg_count: usize,
+) -> Result<T, RuntimeError> {
+    match args.get(index) {
+        Some(arg) => T::from_koto(arg).map_err(|error| argument_error(name, index + 1, error)),
+        None => T::from_koto(&Value::Empty).map_err(|_| arity_error(name, arg_count, args.len())),
+    }
+}
+
+/// Returns the error for a method that was called without an instance of its type
+pub fn instance_error(name: &str, type_name: &str) -> RuntimeError {
+    RuntimeError::from_string(format!(
+        "{}: Expected {} instance as first argument",
+        name, type_name
+    ))
+}
+
+fn argument_error(name: &str, position: usize, error: ConversionError) -> RuntimeError {
+    let location = if error.path.is_empty() {
+        String::new()
+    } else {
+        format!(" at '{}'", error.path)
+    };
+
+    RuntimeError::from_string(format!(
+        "{}: expected {} for argument {}{}, found {}",
+        name, error.expected, position, location, error.found
+    ))
+}
+
+fn arity_error(name: &str, expected: usize, found: usize) -> RuntimeError {
+    RuntimeError::from_string(format!(
+        "{}: expected {} argument{}, found {}",
+        name,
+        expected,
+        if expected == 1 { "" } else { "s" },
+        found
+    ))
+}
+
+macro_rules! typed_function {
+    ($arg_count:expr; $($index:tt $type:ident),*) => {
+        impl<F, R, $($type),*> TypedFunction<($($type,)*)> for F
+        where
+            F: Fn($($type),*) -> R + Send + Sync + 'static,
+            R: IntoKotoResult,
+            $($type: FromKoto,)*
+        {
+            #[allow(unused_variables)]
+            fn call_typed(&self, name: &str, args: &[Value]) -> RuntimeResult {
+                check_arg_count(name, args, $arg_count)?;
+
+                (self)($(convert_arg::<$type>(name, args, $index, $arg_count)?),*).into_koto_result()
+            }
+        }
+    };
+}
+
+typed_function!(0;);
+typed_function!(1; 0 A);
+typed_function!(2; 0 A, 1 B);
+typed_function!(3; 0 A, 1 B, 2 C);
+typed_function!(4; 0 A, 1 B, 2 C, 3 D);
+typed_function!(5; 0 A, 1 B, 2 C, 3 D, 4 E);
+typed_function!(6; 0 A, 1 B, 2 C, 3 D, 4 E, 5 G);
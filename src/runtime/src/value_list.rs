@@ -1,7 +1,10 @@
 use {
     crate::Value,
     parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard},
-    std::{fmt, sync::Arc},
+    std::{
+        fmt,
+        sync::{Arc, Weak},
+    },
 };
 
 pub type ValueVec = smallvec::SmallVec<[Value; 4]>;
@@ -46,6 +49,29 @@ impl ValueList {
     pub fn data_mut(&self) -> RwLockWriteGuard<ValueVec> {
         self.0.write()
     }
+
+    // The following functions are used by the cycle collector and the memory tracker
+
+    pub(crate) fn downgrade(&self) -> Weak<RwLock<ValueVec>> {
+        Arc::downgrade(&self.0)
+    }
+
+    pub(crate) fn upgrade(weak: &Weak<RwLock<ValueVec>>) -> Option<Self> {
+        weak.upgrade().map(Self)
+    }
+
+    pub(crate) fn try_data(&self) -> Option<RwLockReadGuard<'_, ValueVec>> {
+        self.0.try_read()
+    }
+
+    pub(crate) fn strong_count(&self) -> usize {
+        Arc::strong_count(&self.0)
+    }
+
+    // Returns a pointer that identifies the list's data
+    pub(crate) fn data_ptr(&self) -> *const () {
+        Arc::as_ptr(&self.0) as *const ()
+    }
 }
 
 impl fmt::Display for ValueList {
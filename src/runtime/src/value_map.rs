@@ -1,6 +1,7 @@
 use {
     crate::{
         external::{Args, ExternalFunction},
+        typed_function::{make_typed_function, TypedFunction},
         value_key::ValueKeyRef,
         RuntimeResult, Value, ValueKey, ValueList, Vm,
     },
@@ -13,10 +14,15 @@ use {
         hash::{BuildHasherDefault, Hash},
         iter::{FromIterator, IntoIterator},
         ops::{Deref, DerefMut},
-        sync::Arc,
+        sync::{Arc, Weak},
     },
 };
 
+// Returns the part of a typed function's name following the last `.`
+fn typed_function_id(name: &str) -> &str {
+    name.rsplit('.').next().unwrap_or(name)
+}
+
 type ValueHashMapType = IndexMap<ValueKey, Value, BuildHasherDefault<FxHasher>>;
 
 /// The underlying ValueKey -> Value 'data' hash map used in Koto
@@ -63,6 +69,18 @@ impl ValueHashMap {
         self.add_value(id, Value::ExternalFunction(ExternalFunction::new(f, true)));
     }
 
+    /// Adds a function with arguments that are converted automatically, see [TypedFunction]
+    ///
+    /// The function is added using the part of the name following the last `.`, with the full
+    /// name being used in error messages, e.g. `add_typed_fn("number.foo", ...)` adds a function
+    /// named `foo` that reports errors like `number.foo: expected Number for argument 1, found List`.
+    pub fn add_typed_fn<ArgTypes>(&mut self, name: &str, f: impl TypedFunction<ArgTypes>) {
+        self.add_value(
+            typed_function_id(name),
+            Value::ExternalFunction(make_typed_function(name, f)),
+        );
+    }
+
     #[inline]
     pub fn add_list(&mut self, id: &str, list: ValueList) {
         #[allow(clippy::useless_conversion)]
@@ -291,6 +309,29 @@ impl ValueMap {
         self.0.write()
     }
 
+    // The following functions are used by the cycle collector and the memory tracker
+
+    pub(crate) fn downgrade(&self) -> Weak<RwLock<ValueMapContents>> {
+        Arc::downgrade(&self.0)
+    }
+
+    pub(crate) fn upgrade(weak: &Weak<RwLock<ValueMapContents>>) -> Option<Self> {
+        weak.upgrade().map(Self)
+    }
+
+    pub(crate) fn try_contents(&self) -> Option<RwLockReadGuard<'_, ValueMapContents>> {
+        self.0.try_read()
+    }
+
+    pub(crate) fn strong_count(&self) -> usize {
+        Arc::strong_count(&self.0)
+    }
+
+    // Returns a pointer that identifies the map's contents
+    pub(crate) fn data_ptr(&self) -> *const () {
+        Arc::as_ptr(&self.0) as *const ()
+    }
+
     #[inline]
     pub fn insert(&mut self, key: ValueKey, value: Value) {
         self.contents_mut().data.insert(key, value);
@@ -324,6 +365,16 @@ impl ValueMap {
         self.add_value(id, Value::ExternalFunction(ExternalFunction::new(f, true)));
     }
 
+    /// Adds a function with arguments that are converted automatically
+    ///
+    /// See [ValueHashMap::add_typed_fn].
+    pub fn add_typed_fn<ArgTypes>(&mut self, name: &str, f: impl TypedFunction<ArgTypes>) {
+        self.add_value(
+            typed_function_id(name),
+            Value::ExternalFunction(make_typed_function(name, f)),
+        );
+    }
+
     #[inline]
     pub fn add_list(&mut self, id: &str, list: ValueList) {
         self.add_value(id, Value::List(list));
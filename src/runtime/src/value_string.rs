@@ -2,7 +2,7 @@ use std::{
     fmt,
     hash::{Hash, Hasher},
     ops::{Deref, Range},
-    sync::Arc,
+    sync::{Arc, Weak},
 };
 
 #[derive(Clone)]
@@ -44,6 +44,11 @@ impl ValueString {
         // Safety: bounds have already been checked in new_with_bounds / with_bounds
         unsafe { &self.string.get_unchecked(self.bounds.clone()) }
     }
+
+    // Used by the memory tracker to detect when the string's data has been dropped
+    pub(crate) fn downgrade(&self) -> Weak<str> {
+        Arc::downgrade(&self.string)
+    }
 }
 
 impl PartialEq for ValueString {
@@ -1,6 +1,9 @@
 use {
     crate::Value,
-    std::{fmt, sync::Arc},
+    std::{
+        fmt,
+        sync::{Arc, Weak},
+    },
 };
 
 #[derive(Clone, Debug)]
@@ -11,6 +14,11 @@ impl ValueTuple {
     pub fn data(&self) -> &[Value] {
         &self.0
     }
+
+    // Used by the memory tracker to detect when the tuple's data has been dropped
+    pub(crate) fn downgrade(&self) -> Weak<[Value]> {
+        Arc::downgrade(&self.0)
+    }
 }
 
 impl fmt::Display for ValueTuple {
@@ -1,25 +1,34 @@
 use {
     crate::{
-        core::CoreLib,
+        core::{CoreLib, CoreModules},
+        coverage::{self, Coverage},
+        cycles::{CycleCollection, CycleTracker},
+        debugger::{DebugCommand, DebugFrame, Debugger, PauseEvent, PauseReason},
         external::{self, Args, ExternalFunction},
         frame::Frame,
-        num2, num4, runtime_error,
+        limits::{Allocation, Fuel, InterruptHandle, MemoryTracker},
+        num2, num4,
+        profiler::{ProfileState, Profiler},
+        runtime_error,
+        test_reporter::{TestReporter, TestResult},
         value::{self, RegisterSlice, RuntimeFunction},
         value_iterator::{IntRange, Iterable, ValueIterator, ValueIteratorOutput},
-        BinaryOp, DefaultLogger, KotoLogger, Loader, MetaKey, RuntimeError, RuntimeErrorType,
-        RuntimeResult, UnaryOp, Value, ValueList, ValueMap, ValueNumber, ValueString, ValueVec,
+        BinaryOp, DefaultLogger, FileSystemResolver, KotoLogger, Loader, MetaKey, ModuleResolver,
+        RuntimeError, RuntimeErrorType, RuntimeResult, UnaryOp, Value, ValueKey, ValueList,
+        ValueMap, ValueNumber, ValueString, ValueVec,
     },
     koto_bytecode::{Chunk, Instruction, InstructionReader, TypeId},
     koto_parser::{ConstantIndex, MetaId},
     parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard},
     std::{
-        collections::HashMap,
-        fmt,
+        collections::{HashMap, HashSet},
+        fmt, mem,
         path::PathBuf,
         sync::{
             atomic::{AtomicBool, Ordering},
             Arc,
         },
+        time::Instant,
     },
 };
 
@@ -38,36 +47,43 @@ struct SharedContext {
     pub prelude: ValueMap,
     core_lib: CoreLib,
     logger: Arc<dyn KotoLogger>,
+    fuel: Fuel,
+    memory: Arc<MemoryTracker>,
+    cycles: CycleTracker,
+    interrupt_handle: InterruptHandle,
+    max_call_depth: Option<usize>,
+    max_nested_vm_depth: Option<usize>,
+    allow_module_loading: bool,
+    debugger: Option<Arc<Debugger>>,
+    profiler: Option<Arc<Profiler>>,
+    coverage: Option<Arc<Coverage>>,
 }
 
 impl Default for SharedContext {
     fn default() -> Self {
-        Self::with_logger(Arc::new(DefaultLogger {}))
+        Self::with_settings(VmSettings::default())
     }
 }
 
 impl SharedContext {
-    fn with_logger(logger: Arc<dyn KotoLogger>) -> Self {
+    fn with_settings(settings: VmSettings) -> Self {
         let core_lib = CoreLib::default();
-
-        let mut prelude = ValueMap::default();
-        prelude.add_map("io", core_lib.io.clone());
-        prelude.add_map("iterator", core_lib.iterator.clone());
-        prelude.add_map("koto", core_lib.koto.clone());
-        prelude.add_map("list", core_lib.list.clone());
-        prelude.add_map("map", core_lib.map.clone());
-        prelude.add_map("os", core_lib.os.clone());
-        prelude.add_map("number", core_lib.number.clone());
-        prelude.add_map("range", core_lib.range.clone());
-        prelude.add_map("string", core_lib.string.clone());
-        prelude.add_map("test", core_lib.test.clone());
-        prelude.add_map("thread", core_lib.thread.clone());
-        prelude.add_map("tuple", core_lib.tuple.clone());
+        let prelude = core_lib.make_prelude(settings.core_modules);
 
         Self {
             prelude,
             core_lib,
-            logger,
+            logger: settings.logger,
+            fuel: Fuel::new(settings.fuel),
+            memory: Arc::new(MemoryTracker::new(settings.memory_limit)),
+            cycles: CycleTracker::new(settings.cycle_collection),
+            interrupt_handle: settings.interrupt_handle,
+            max_call_depth: settings.max_call_depth,
+            max_nested_vm_depth: settings.max_nested_vm_depth,
+            allow_module_loading: settings.allow_module_loading,
+            debugger: settings.debugger,
+            profiler: settings.profiler,
+            coverage: settings.coverage,
         }
     }
 }
@@ -79,6 +95,8 @@ pub struct ModuleContext {
     pub exports: ValueMap,
     loader: Loader,
     modules: HashMap<PathBuf, Option<ValueMap>>,
+    // The paths of the scripts that import each module, with `None` for scripts without a path
+    importers: HashMap<PathBuf, HashSet<Option<PathBuf>>>,
     spawned_stop_flags: Vec<Arc<AtomicBool>>,
 }
 
@@ -87,13 +105,42 @@ impl ModuleContext {
         Self {
             loader: self.loader.clone(),
             modules: self.modules.clone(),
+            importers: self.importers.clone(),
+            exports: Default::default(),
+            spawned_stop_flags: Default::default(),
+        }
+    }
+
+    fn with_loader(loader: Loader) -> Self {
+        Self {
             exports: Default::default(),
+            loader,
+            modules: Default::default(),
+            importers: Default::default(),
             spawned_stop_flags: Default::default(),
         }
     }
 
+    // Adds the modules that were imported by a module's context
+    fn add_imported_modules(&mut self, module_context: &ModuleContext) {
+        self.loader.add_compiled_modules(&module_context.loader);
+
+        for (path, module) in module_context.modules.iter() {
+            if module.is_some() && !matches!(self.modules.get(path), Some(Some(_))) {
+                self.modules.insert(path.clone(), module.clone());
+            }
+        }
+
+        for (path, importers) in module_context.importers.iter() {
+            self.importers
+                .entry(path.clone())
+                .or_default()
+                .extend(importers.iter().cloned());
+        }
+    }
+
     fn reset(&mut self) {
-        self.loader = Default::default();
+        self.loader.clear();
         self.stop_spawned_vms();
     }
 
@@ -105,6 +152,26 @@ impl ModuleContext {
     }
 }
 
+/// The modules that were invalidated by [Vm::invalidate_changed_modules]
+#[derive(Clone, Debug, Default)]
+pub struct ModuleChanges {
+    /// The paths of the modules with sources that have changed
+    pub changed: Vec<PathBuf>,
+    /// The paths of the invalidated modules, including the changed modules and their importers
+    pub invalidated: Vec<PathBuf>,
+    /// True if one of the invalidated modules is imported by a script that isn't a module
+    ///
+    /// The script (typically the main script) needs to be run again to import the updated modules.
+    pub script_affected: bool,
+}
+
+impl ModuleChanges {
+    /// True if no modules have changed
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty()
+    }
+}
+
 impl Drop for ModuleContext {
     fn drop(&mut self) {
         self.stop_spawned_vms();
@@ -113,12 +180,103 @@ impl Drop for ModuleContext {
 
 pub struct VmSettings {
     pub logger: Arc<dyn KotoLogger>,
+    /// The number of instructions that the Vm is allowed to execute
+    ///
+    /// When the budget is used up then execution will stop with a
+    /// [RuntimeErrorType::OutOfFuel] error. More fuel can be added with [Vm::add_fuel], and then
+    /// execution can be resumed with [Vm::continue_running], unless the fuel ran out in a nested
+    /// call made by an external function.
+    ///
+    /// The budget is shared with any VMs spawned by the Vm. Default: `None`, no limit.
+    pub fuel: Option<u64>,
+    /// The number of bytes that the Vm is allowed to use for values
+    ///
+    /// Memory is counted for the lists, maps, strings, and tuples that are created by the Vm and
+    /// any VMs that it spawns, see [Vm::track_allocation]. Memory is released once the values that
+    /// are using it have been dropped, or when lists and maps shrink. Values that share another
+    /// value's memory (e.g. the strings produced by `string.split`) aren't counted separately.
+    ///
+    /// The count is an estimate based on the sizes of the values' elements, and some allocations
+    /// aren't included, e.g. capacity that's reserved ahead of time when making a list or map
+    /// (elements are counted as they're added), memory used by external values, or memory that's
+    /// allocated by external functions that don't call [Vm::track_allocation].
+    ///
+    /// An allocation that would exceed the limit fails with a [RuntimeErrorType::OutOfMemory]
+    /// error, which can be caught by scripts. The limit can be changed with
+    /// [Vm::set_memory_limit]. Default: `None`, no limit.
+    pub memory_limit: Option<usize>,
+    /// Controls the collection of reference cycles between lists and maps
+    ///
+    /// See [Vm::collect_cycles]. Default: [CycleCollection::Disabled]
+    pub cycle_collection: CycleCollection,
+    /// A handle that can be used to interrupt execution from another thread
+    ///
+    /// See [Vm::interrupt_handle].
+    pub interrupt_handle: InterruptHandle,
+    /// The maximum number of nested function calls that are allowed
+    ///
+    /// Calls made by VMs spawned by the Vm, e.g. when an external function calls a Koto function,
+    /// count towards the same limit. When the limit is exceeded then execution will stop with a
+    /// [RuntimeErrorType::StackOverflow] error, which can't be caught by scripts.
+    ///
+    /// Default: `Some(1000)`
+    pub max_call_depth: Option<usize>,
+    /// The maximum number of VMs that can be nested within each other
+    ///
+    /// A nested Vm is used when an external function calls a Koto function, e.g. the function
+    /// passed to `list.each`, and each level of nesting uses space on the native stack. Exceeding
+    /// the limit results in a [RuntimeErrorType::StackOverflow] error, rather than the native stack
+    /// overflowing.
+    ///
+    /// Default: `Some(64)`
+    pub max_nested_vm_depth: Option<usize>,
+    /// The core library modules that should be included in the prelude
+    ///
+    /// Default: [CoreModules::all]
+    pub core_modules: CoreModules,
+    /// Whether or not `import` is allowed to load modules that aren't in the prelude
+    ///
+    /// Default: `true`
+    pub allow_module_loading: bool,
+    /// A directory used to cache compiled modules, see [Loader::set_cache_dir]
+    ///
+    /// Default: `None`
+    pub chunk_cache_dir: Option<PathBuf>,
+    /// The resolver used to find and load the modules that get imported by scripts
+    ///
+    /// See [Loader::set_module_resolver]. Default: [FileSystemResolver]
+    pub module_resolver: Arc<dyn ModuleResolver>,
+    /// A debugger that should be attached to the Vm
+    ///
+    /// Default: `None`
+    pub debugger: Option<Arc<Debugger>>,
+    /// A profiler that records the time spent in each function
+    ///
+    /// Default: `None`
+    pub profiler: Option<Arc<Profiler>>,
+    /// Records the lines and branches of scripts that get executed
+    ///
+    /// Default: `None`
+    pub coverage: Option<Arc<Coverage>>,
 }
 
 impl Default for VmSettings {
     fn default() -> Self {
         Self {
             logger: Arc::new(DefaultLogger {}),
+            fuel: None,
+            memory_limit: None,
+            cycle_collection: CycleCollection::default(),
+            interrupt_handle: InterruptHandle::default(),
+            max_call_depth: Some(1000),
+            max_nested_vm_depth: Some(64),
+            core_modules: CoreModules::default(),
+            allow_module_loading: true,
+            chunk_cache_dir: None,
+            module_resolver: Arc::new(FileSystemResolver),
+            debugger: None,
+            profiler: None,
+            coverage: None,
         }
     }
 }
@@ -129,8 +287,16 @@ pub struct Vm {
     reader: InstructionReader,
     value_stack: Vec<Value>,
     call_stack: Vec<Frame>,
+    // The call depth of the Vm that spawned this Vm, see Vm::call_depth
+    call_depth_base: usize,
+    // The number of VMs that this Vm is nested within
+    nested_vm_depth: usize,
     stop_flag: Option<Arc<AtomicBool>>,
     child_vm: Option<Box<Vm>>,
+    // False when execution was stopped by an execution limit in a way that can't be resumed,
+    // see Vm::continue_running
+    resumable: bool,
+    profile_state: ProfileState,
 }
 
 impl Default for Vm {
@@ -141,14 +307,22 @@ impl Default for Vm {
 
 impl Vm {
     pub fn with_settings(settings: VmSettings) -> Self {
+        let mut loader = Loader::default();
+        loader.set_cache_dir(settings.chunk_cache_dir.clone());
+        loader.set_module_resolver(settings.module_resolver.clone());
+
         Self {
-            context: Arc::new(RwLock::new(ModuleContext::default())),
-            context_shared: Arc::new(SharedContext::with_logger(settings.logger)),
+            context: Arc::new(RwLock::new(ModuleContext::with_loader(loader))),
+            context_shared: Arc::new(SharedContext::with_settings(settings)),
             reader: InstructionReader::default(),
             value_stack: Vec::with_capacity(32),
             call_stack: vec![],
+            call_depth_base: 0,
+            nested_vm_depth: 0,
             stop_flag: None,
             child_vm: None,
+            resumable: true,
+            profile_state: ProfileState::default(),
         }
     }
 
@@ -159,8 +333,12 @@ impl Vm {
             reader: InstructionReader::default(),
             value_stack: Vec::with_capacity(32),
             call_stack: vec![],
+            call_depth_base: self.call_depth(),
+            nested_vm_depth: self.nested_vm_depth + 1,
             stop_flag: None,
             child_vm: None,
+            resumable: true,
+            profile_state: self.spawned_profile_state(),
         }
     }
 
@@ -171,8 +349,12 @@ impl Vm {
             reader: self.reader.clone(),
             value_stack: Vec::with_capacity(8),
             call_stack: vec![],
+            call_depth_base: self.call_depth(),
+            nested_vm_depth: self.nested_vm_depth + 1,
             stop_flag: None,
             child_vm: None,
+            resumable: true,
+            profile_state: self.spawned_profile_state(),
         }
     }
 
@@ -181,6 +363,7 @@ impl Vm {
         self.context_mut()
             .spawned_stop_flags
             .push(stop_flag.clone());
+        self.context_shared.cycles.add_concurrent_vm();
 
         Self {
             context: self.context.clone(),
@@ -188,18 +371,49 @@ impl Vm {
             reader: self.reader.clone(),
             value_stack: Vec::with_capacity(8),
             call_stack: vec![],
+            call_depth_base: self.call_depth(),
+            // Concurrent VMs run on their own threads, with their own native stacks
+            nested_vm_depth: 0,
             stop_flag: Some(stop_flag),
             child_vm: None,
+            resumable: true,
+            profile_state: self.spawned_profile_state(),
         }
     }
 
     pub fn child_vm(&mut self) -> &mut Vm {
-        if self.child_vm.is_none() {
-            self.child_vm = Some(Box::new(self.spawn_shared_vm()))
+        let call_depth = self.call_depth();
+        match &mut self.child_vm {
+            None => self.child_vm = Some(Box::new(self.spawn_shared_vm())),
+            Some(child_vm) => {
+                // The child Vm is reused, so its calls need to be placed in the current call stack
+                child_vm.call_depth_base = call_depth;
+                if self.context_shared.profiler.is_some() {
+                    child_vm.profile_state.parent_stack = self.profile_state.full_stack();
+                }
+            }
         }
         self.child_vm.as_mut().unwrap()
     }
 
+    /// The number of active function calls, including the calls made by the VMs that spawned
+    /// this Vm
+    pub fn call_depth(&self) -> usize {
+        self.call_depth_base + self.call_stack.len()
+    }
+
+    // Returns the profiling state for a newly spawned Vm
+    fn spawned_profile_state(&self) -> ProfileState {
+        if self.context_shared.profiler.is_some() {
+            ProfileState {
+                parent_stack: self.profile_state.full_stack(),
+                stack: vec![],
+            }
+        } else {
+            ProfileState::default()
+        }
+    }
+
     pub fn prelude(&self) -> ValueMap {
         self.context_shared.prelude.clone()
     }
@@ -217,6 +431,116 @@ impl Vm {
         &self.context_shared.logger
     }
 
+    /// Returns a handle that can be used to interrupt the Vm from another thread
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.context_shared.interrupt_handle.clone()
+    }
+
+    /// Returns the remaining instruction budget, or `None` if no limit is set
+    pub fn remaining_fuel(&self) -> Option<u64> {
+        self.context_shared.fuel.get()
+    }
+
+    /// Sets the instruction budget, `None` removes the limit
+    pub fn set_fuel(&self, fuel: Option<u64>) {
+        self.context_shared.fuel.set(fuel);
+    }
+
+    /// Adds to the instruction budget
+    ///
+    /// This has no effect if no limit is set.
+    pub fn add_fuel(&self, amount: u64) {
+        self.context_shared.fuel.add(amount);
+    }
+
+    /// Returns the number of bytes that are being used by values
+    ///
+    /// This includes the memory used by values made by VMs that were spawned by the Vm.
+    /// Allocations are only tracked while a memory limit is set, see [VmSettings::memory_limit].
+    pub fn allocated_memory(&self) -> usize {
+        self.context_shared.memory.allocated()
+    }
+
+    /// Returns the memory limit, or `None` if no limit is set
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.context_shared.memory.limit()
+    }
+
+    /// Sets the memory limit, `None` removes the limit
+    pub fn set_memory_limit(&self, limit: Option<usize>) {
+        self.context_shared.memory.set_limit(limit);
+    }
+
+    /// Reserves memory for `count` values of type `T`
+    ///
+    /// This should be called before allocating memory for values that are given to scripts,
+    /// e.g. `vm.track_allocation::<Value>(size)?` before making a list with `size` elements.
+    /// The returned [Allocation] should then be assigned to the new value with
+    /// [Allocation::assign_to], so that the memory remains counted until the value is dropped.
+    ///
+    /// An [RuntimeErrorType::OutOfMemory] error is returned if the allocation would exceed the
+    /// Vm's memory limit. Allocations are only tracked while a memory limit is set.
+    pub fn track_allocation<T>(&self, count: usize) -> Result<Allocation, RuntimeError> {
+        let memory = &self.context_shared.memory;
+        if !memory.is_enabled() {
+            return Ok(Allocation::new(None, 0));
+        }
+
+        let bytes = count.saturating_mul(mem::size_of::<T>());
+        if memory.reserve(bytes) {
+            Ok(Allocation::new(Some(memory.clone()), bytes))
+        } else {
+            Err(RuntimeError::new(RuntimeErrorType::OutOfMemory {
+                requested: bytes,
+                limit: memory.limit().unwrap_or(usize::MAX),
+            }))
+        }
+    }
+
+    /// Tracks a list or map so that it can be included in cycle collection
+    ///
+    /// The lists and maps that are made by the Vm are tracked automatically, external functions
+    /// that make new lists or maps can call this to allow cycles involving the new values to be
+    /// collected. Other values are ignored, as are all values when cycle collection is disabled.
+    ///
+    /// See [VmSettings::cycle_collection].
+    pub fn track_cycles(&self, value: &Value) {
+        self.context_shared.cycles.track(value);
+    }
+
+    /// Breaks the reference cycles between tracked lists and maps that are no longer reachable
+    ///
+    /// The contents of the lists and maps that are only being kept alive by cycles are cleared,
+    /// allowing them to be freed. Values that are reachable from outside of the tracked values,
+    /// e.g. from the Vm's exports or values held by the host, aren't affected.
+    ///
+    /// This should only be called while no other threads are modifying values that were made by
+    /// the Vm.
+    ///
+    /// Returns the number of lists and maps that were cleared.
+    pub fn collect_cycles(&self) -> usize {
+        self.context_shared.cycles.collect()
+    }
+
+    /// Returns the tracked lists and maps that are only being kept alive by reference cycles
+    ///
+    /// This can be used to detect leaks without collecting them, see [Vm::collect_cycles].
+    /// Function captures are included as lists.
+    pub fn find_leaked_cycles(&self) -> Vec<Value> {
+        self.context_shared.cycles.find_leaks()
+    }
+
+    // Tracks a newly made list or map, and runs an automatic collection if one is due
+    fn track_new_value(&self, value: &Value) {
+        let cycles = &self.context_shared.cycles;
+        if cycles.is_enabled() {
+            cycles.track(value);
+            if cycles.collection_due() {
+                cycles.collect();
+            }
+        }
+    }
+
     pub fn get_exported_value(&self, id: &str) -> Option<Value> {
         self.context()
             .exports
@@ -233,27 +557,96 @@ impl Vm {
         }
     }
 
+    /// Invalidates the imported modules with sources that have changed, along with their importers
+    ///
+    /// Invalidated modules will be recompiled and run the next time that they're imported.
+    /// Existing values that refer to the exports of invalidated modules remain valid.
+    ///
+    /// See [Loader::changed_modules].
+    pub fn invalidate_changed_modules(&mut self) -> ModuleChanges {
+        let mut context = self.context_mut();
+
+        let changed = context.loader.changed_modules();
+        let mut invalidated: Vec<PathBuf> = Vec::new();
+        let mut script_affected = false;
+
+        let mut pending = changed.clone();
+        while let Some(path) = pending.pop() {
+            if invalidated.contains(&path) {
+                continue;
+            }
+
+            context.loader.invalidate_module(&path);
+            context.modules.remove(&path);
+
+            for importer in context.importers.remove(&path).unwrap_or_default() {
+                match importer {
+                    Some(importer)
+                        if context.modules.contains_key(&importer)
+                            || changed.contains(&importer)
+                            || invalidated.contains(&importer) =>
+                    {
+                        pending.push(importer)
+                    }
+                    _ => script_affected = true,
+                }
+            }
+
+            invalidated.push(path);
+        }
+
+        ModuleChanges {
+            changed,
+            invalidated,
+            script_affected,
+        }
+    }
+
     pub fn reset(&mut self) {
         self.context_mut().reset();
         self.value_stack = Default::default();
         self.call_stack = Default::default();
+        self.profile_state.stack.clear();
+        self.resumable = true;
     }
 
     pub fn run(&mut self, chunk: Arc<Chunk>) -> RuntimeResult {
-        self.push_frame(chunk, 0, 0);
-        self.execute_instructions()
-    }
-
+        self.clear_handled_interrupt();
+        self.resumable = true;
+        self.push_frame(chunk, 0, 0)?;
+        let result = self.execute_instructions();
+        self.check_for_interrupt(result)
+    }
+
+    /// Continues execution of an active VM
+    ///
+    /// This can be used to resume execution after an execution limit has been reached,
+    /// e.g. after adding more fuel following an [RuntimeErrorType::OutOfFuel] error.
+    ///
+    /// Execution can't be resumed if the limit was reached during a nested call made by an
+    /// external function, e.g. in a function passed to `iterator.each`, given that the external
+    /// function's progress can't be suspended. In that case the call stack is unwound when the
+    /// limit is reached, and an error is returned here.
     pub fn continue_running(&mut self) -> RuntimeResult {
-        if self.call_stack.is_empty() {
+        self.clear_handled_interrupt();
+
+        if !self.resumable {
+            runtime_error!(
+                "continue_running: execution can't be resumed after an execution limit was \
+                 reached in a nested call"
+            )
+        } else if self.call_stack.is_empty() {
             Ok(Value::Empty)
         } else {
-            self.execute_instructions()
+            let result = self.execute_instructions();
+            self.check_for_interrupt(result)
         }
     }
 
     pub fn run_function(&mut self, function: Value, args: &[Value]) -> RuntimeResult {
-        self.call_and_run_function(None, function, args)
+        self.clear_handled_interrupt();
+        let result = self.call_and_run_function(None, function, args);
+        self.check_for_interrupt(result)
     }
 
     pub fn run_instance_function(
@@ -262,7 +655,36 @@ impl Vm {
         function: Value,
         args: &[Value],
     ) -> RuntimeResult {
-        self.call_and_run_function(Some(instance), function, args)
+        self.clear_handled_interrupt();
+        let result = self.call_and_run_function(Some(instance), function, args);
+        self.check_for_interrupt(result)
+    }
+
+    // True for VMs that are run by the host, rather than VMs that were spawned by another Vm
+    fn is_top_level(&self) -> bool {
+        self.nested_vm_depth == 0 && self.stop_flag.is_none()
+    }
+
+    // Clears an interrupt that stopped a previous run, see InterruptHandle
+    fn clear_handled_interrupt(&self) {
+        if self.is_top_level() {
+            self.context_shared.interrupt_handle.clear_handled();
+        }
+    }
+
+    // Marks an interrupt as handled when it has stopped a top-level Vm
+    //
+    // The interrupt stays in effect until the Vm is run again, so that any VMs that are running
+    // on other threads also get stopped.
+    fn check_for_interrupt(&self, result: RuntimeResult) -> RuntimeResult {
+        if self.is_top_level() {
+            if let Err(error) = &result {
+                if matches!(error.error, RuntimeErrorType::Interrupted) {
+                    self.context_shared.interrupt_handle.set_handled();
+                }
+            }
+        }
+        result
     }
 
     fn call_and_run_function(
@@ -282,6 +704,8 @@ impl Vm {
             return runtime_error!("run_function: the provided value isn't a function");
         }
 
+        self.resumable = true;
+
         let result_register = 0;
         let frame_base = 1;
         // If there's an instance value then it goes in the frame base
@@ -314,8 +738,21 @@ impl Vm {
         } else {
             self.frame_mut().catch_barrier = true;
             let result = self.execute_instructions();
-            if result.is_err() {
-                self.pop_frame(Value::Empty)?;
+            match &result {
+                // The VM has been paused, and can be resumed with continue_running.
+                // Nested VMs are called by external functions that can't be resumed,
+                // so their calls are always unwound.
+                Err(error)
+                    if error.is_execution_limit()
+                        && self.resumable
+                        && self.nested_vm_depth == 0
+                        && !self.call_stack.is_empty() => {}
+                Err(_) => {
+                    while !self.call_stack.is_empty() {
+                        self.pop_frame(Value::Empty)?;
+                    }
+                }
+                Ok(_) => {}
             }
             result
         }
@@ -384,99 +821,187 @@ impl Vm {
         }
     }
 
-    pub fn run_tests(&mut self, tests: ValueMap) -> RuntimeResult {
+    /// Runs the tests contained in a `tests` map
+    ///
+    /// Failing tests don't stop the remaining tests from being run, unless the failure was caused
+    /// by an execution limit being reached. Each test's result is passed to the reporter if one
+    /// is provided, and the results for all of the tests that were run are returned.
+    pub fn run_tests(
+        &mut self,
+        tests: ValueMap,
+        mut reporter: Option<&mut dyn TestReporter>,
+    ) -> Vec<TestResult> {
+        let mut results = Vec::new();
+
+        for name in Self::test_names(&tests) {
+            let result = self.run_test(tests.clone(), &name);
+
+            if let Some(reporter) = reporter.as_deref_mut() {
+                reporter.test_finished(&result);
+            }
+
+            let stop = matches!(&result.error, Some(error) if error.is_execution_limit());
+            results.push(result);
+            if stop {
+                break;
+            }
+        }
+
+        results
+    }
+
+    /// Returns the names of the tests contained in a `tests` map
+    ///
+    /// Tests are callable values with keys starting with `test_`,
+    /// the names are returned without the `test_` prefix.
+    pub fn test_names(tests: &ValueMap) -> Vec<String> {
+        tests
+            .contents()
+            .data
+            .iter()
+            .filter_map(|(key, value)| match key.value() {
+                Value::Str(id) if id.starts_with("test_") && value.is_callable() => {
+                    Some(id[5..].to_string())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Runs a single test from a `tests` map
+    ///
+    /// The map's `pre_test` and `post_test` functions are called before and after the test.
+    pub fn run_test(&mut self, tests: ValueMap, name: &str) -> TestResult {
+        let start_time = Instant::now();
+        let result = self.run_test_and_hooks(tests, name);
+
+        TestResult {
+            name: name.to_string(),
+            duration: start_time.elapsed(),
+            error: result.err(),
+        }
+    }
+
+    fn run_test_and_hooks(&mut self, tests: ValueMap, name: &str) -> RuntimeResult {
         use Value::*;
 
         // It's important here to make sure we don't hang on to any references to the internal
         // test map data while calling the test functions, otherwise we'll end up in deadlocks.
         let self_arg = Map(tests.clone());
 
-        let pre_test = tests.contents().data.get_with_string("pre_test").cloned();
-        let post_test = tests.contents().data.get_with_string("post_test").cloned();
-        let pass_self_to_pre_test = match &pre_test {
-            Some(Function(f)) => f.arg_count == 1,
-            _ => false,
-        };
-        let pass_self_to_post_test = match &post_test {
-            Some(Function(f)) => f.arg_count == 1,
-            _ => false,
+        let (test, pre_test, post_test) = {
+            let contents = tests.contents();
+            (
+                contents
+                    .data
+                    .get_with_string(&format!("test_{}", name))
+                    .cloned(),
+                contents.data.get_with_string("pre_test").cloned(),
+                contents.data.get_with_string("post_test").cloned(),
+            )
         };
 
-        for (key, value) in tests.cloned_iter() {
-            match (key.value(), value) {
-                (Str(id), test) if id.starts_with("test_") && test.is_callable() => {
-                    let make_test_error = |error: RuntimeError, message: &str| {
-                        Err(error.with_prefix(&format!("{} '{}'", message, &id[5..])))
-                    };
-
-                    if let Some(pre_test) = &pre_test {
-                        if pre_test.is_callable() {
-                            let pre_test_result = if pass_self_to_pre_test {
-                                self.run_instance_function(self_arg.clone(), pre_test.clone(), &[])
-                            } else {
-                                self.run_function(pre_test.clone(), &[])
-                            };
-
-                            if let Err(error) = pre_test_result {
-                                return make_test_error(error, "Error while preparing to run test");
-                            }
-                        }
-                    }
-
-                    let pass_self_to_test = match &test {
-                        Function(f) => f.arg_count == 1,
-                        _ => false,
-                    };
+        let test = match test {
+            Some(test) if test.is_callable() => test,
+            _ => return runtime_error!("Test '{}' not found", name),
+        };
 
-                    let test_result = if pass_self_to_test {
-                        self.run_instance_function(self_arg.clone(), test, &[])
-                    } else {
-                        self.run_function(test, &[])
-                    };
+        let make_test_error = |error: RuntimeError, message: &str| {
+            Err(error.with_prefix(&format!("{} '{}'", message, name)))
+        };
 
-                    if let Err(error) = test_result {
-                        return make_test_error(error, "Error while running test");
-                    }
+        if let Some(pre_test) = pre_test {
+            if pre_test.is_callable() {
+                if let Err(error) = self.run_test_function(&self_arg, pre_test) {
+                    return make_test_error(error, "Error while preparing to run test");
+                }
+            }
+        }
 
-                    if let Some(post_test) = &post_test {
-                        if post_test.is_callable() {
-                            let post_test_result = if pass_self_to_post_test {
-                                self.run_instance_function(self_arg.clone(), post_test.clone(), &[])
-                            } else {
-                                self.run_function(post_test.clone(), &[])
-                            };
+        if let Err(error) = self.run_test_function(&self_arg, test) {
+            return make_test_error(error, "Error while running test");
+        }
 
-                            if let Err(error) = post_test_result {
-                                return make_test_error(error, "Error after running test");
-                            }
-                        }
-                    }
+        if let Some(post_test) = post_test {
+            if post_test.is_callable() {
+                if let Err(error) = self.run_test_function(&self_arg, post_test) {
+                    return make_test_error(error, "Error after running test");
                 }
-                _ => {}
             }
         }
 
         Ok(Empty)
     }
 
+    // Runs a test function, passing the tests map as `self` if the function takes an argument
+    fn run_test_function(&mut self, self_arg: &Value, function: Value) -> RuntimeResult {
+        let pass_self = match &function {
+            Value::Function(f) => f.arg_count == 1,
+            _ => false,
+        };
+
+        if pass_self {
+            self.run_instance_function(self_arg.clone(), function, &[])
+        } else {
+            self.run_function(function, &[])
+        }
+    }
+
     fn execute_instructions(&mut self) -> RuntimeResult {
         let mut result = Value::Empty;
 
         let mut instruction_ip = self.ip();
 
+        if let Some(profiler) = &self.context_shared.profiler {
+            // A generator might be resuming, so its call stack needs to be made active
+            profiler.set_active_stack(self.profile_state.full_stack());
+        }
+
         while let Some(instruction) = self.reader.next() {
             if let Some(stop_flag) = &self.stop_flag {
                 if stop_flag.load(Ordering::Relaxed) {
                     break;
                 }
             }
+
+            if let Err(mut error) = self.check_execution_limits() {
+                // Rewind to the current instruction so that execution can be resumed
+                self.set_ip(instruction_ip);
+                error.extend_trace(self.chunk(), instruction_ip);
+                return Err(error);
+            }
+
+            if let Some(debugger) = self.context_shared.debugger.clone() {
+                self.run_debugger(&debugger, instruction_ip);
+            }
+
+            // The ip following a conditional jump, used to check if the jump was taken
+            let mut branch_fallthrough_ip = None;
+            if let Some(coverage) = &self.context_shared.coverage {
+                coverage.record_instruction(&self.reader.chunk, instruction_ip);
+                if coverage::is_branch(&instruction) {
+                    branch_fallthrough_ip = Some(self.ip());
+                }
+            }
+
             match self.execute_instruction(instruction, instruction_ip) {
-                Ok(ControlFlow::Continue) => {}
+                Ok(ControlFlow::Continue) => {
+                    if let (Some(coverage), Some(fallthrough_ip)) =
+                        (&self.context_shared.coverage, branch_fallthrough_ip)
+                    {
+                        let taken = self.ip() != fallthrough_ip;
+                        coverage.record_branch(&self.reader.chunk, instruction_ip, taken);
+                    }
+                }
                 Ok(ControlFlow::Return(value)) => {
                     result = value;
                     break;
                 }
                 Ok(ControlFlow::Yield(value)) => {
+                    if let Some(profiler) = &self.context_shared.profiler {
+                        // The generator is paused, so the caller's call stack becomes active
+                        profiler.set_active_stack(self.profile_state.parent_stack.clone());
+                    }
                     result = value;
                     break;
                 }
@@ -485,19 +1010,32 @@ impl Vm {
 
                     error.extend_trace(self.chunk(), instruction_ip);
 
+                    // Execution limit and stack overflow errors can't be caught by scripts
+                    let catchable = !error.is_execution_limit() && !error.is_stack_overflow();
+
+                    // Execution limits are checked before each instruction, so a limit error
+                    // coming from an instruction was reached in a nested call that can't be
+                    // resumed.
+                    if error.is_execution_limit() {
+                        self.resumable = false;
+                    }
+
                     while let Some(frame) = self.call_stack.last() {
-                        if let Some((error_register, catch_ip)) = frame.catch_stack.last() {
-                            recover_register_and_ip = Some((*error_register, *catch_ip));
-                            break;
-                        } else {
-                            if frame.catch_barrier {
-                                return Err(error);
+                        match frame.catch_stack.last() {
+                            Some((error_register, catch_ip)) if catchable => {
+                                recover_register_and_ip = Some((*error_register, *catch_ip));
+                                break;
                             }
+                            _ => {
+                                if frame.catch_barrier {
+                                    return Err(error);
+                                }
 
-                            self.pop_frame(Value::Empty)?;
+                                self.pop_frame(Value::Empty)?;
 
-                            if !self.call_stack.is_empty() {
-                                error.extend_trace(self.chunk(), self.ip());
+                                if !self.call_stack.is_empty() {
+                                    error.extend_trace(self.chunk(), self.ip());
+                                }
                             }
                         }
                     }
@@ -521,6 +1059,114 @@ impl Vm {
         Ok(result)
     }
 
+    fn check_execution_limits(&self) -> InstructionResult {
+        if self.context_shared.interrupt_handle.should_stop() {
+            return Err(RuntimeError::new(RuntimeErrorType::Interrupted));
+        }
+
+        if !self.context_shared.fuel.consume() {
+            return Err(RuntimeError::new(RuntimeErrorType::OutOfFuel));
+        }
+
+        Ok(())
+    }
+
+    fn run_debugger(&mut self, debugger: &Debugger, instruction_ip: usize) {
+        let line = match self.reader.chunk.debug_info.get_source_span(instruction_ip) {
+            Some(span) => span.start.line,
+            None => return,
+        };
+        let depth = self.call_depth();
+
+        let step_completed = {
+            let mut state = debugger.state();
+
+            // The debugger only gets checked when a new line is reached
+            if state.position == Some((depth, line)) {
+                return;
+            }
+            state.position = Some((depth, line));
+
+            match state.step {
+                Some((DebugCommand::StepOver, step_depth, step_line)) => {
+                    depth < step_depth || (depth == step_depth && line != step_line)
+                }
+                Some((DebugCommand::StepOut, step_depth, _)) => depth < step_depth,
+                _ => false,
+            }
+        };
+
+        let reason = if let Some(reason) = debugger.take_pause_request() {
+            reason
+        } else if step_completed {
+            PauseReason::Step
+        } else if debugger.has_breakpoint(self.reader.chunk.source_path.as_deref(), line) {
+            PauseReason::Breakpoint
+        } else {
+            return;
+        };
+
+        let event = PauseEvent {
+            reason,
+            call_stack: self.debug_call_stack(instruction_ip),
+        };
+
+        debugger.state().step = None;
+        let command = debugger.hook().on_pause(self, &event);
+
+        // The hook might have run code while the Vm was paused, so the position is restored
+        let mut state = debugger.state();
+        state.position = Some((depth, line));
+        match command {
+            DebugCommand::Continue => {}
+            DebugCommand::StepIn => debugger.request_step(),
+            DebugCommand::StepOver | DebugCommand::StepOut => {
+                state.step = Some((command, depth, line))
+            }
+        }
+    }
+
+    // Returns the VM's call stack, starting with the innermost frame
+    fn debug_call_stack(&self, instruction_ip: usize) -> Vec<DebugFrame> {
+        let mut result = Vec::with_capacity(self.call_stack.len());
+
+        for (i, frame) in self.call_stack.iter().enumerate().rev() {
+            let (ip, span_ip) = if i == self.call_stack.len() - 1 {
+                (instruction_ip, instruction_ip)
+            } else {
+                match frame.return_register_and_ip {
+                    // The return ip follows the call instruction,
+                    // so the span is taken from the previous ip.
+                    Some((_, return_ip)) => (return_ip, return_ip.saturating_sub(1)),
+                    None => (0, 0),
+                }
+            };
+
+            let locals = match frame.chunk.debug_info.get_frame(ip) {
+                Some(frame_info) => frame_info
+                    .locals
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(register, local)| {
+                        let name = frame.chunk.constants.get_str((*local)?);
+                        let value = self.value_stack.get(frame.register_base + register)?;
+                        Some((name.to_string(), value.clone()))
+                    })
+                    .collect(),
+                None => vec![],
+            };
+
+            result.push(DebugFrame {
+                chunk: frame.chunk.clone(),
+                ip,
+                span: frame.chunk.debug_info.get_source_span(span_ip),
+                locals,
+            });
+        }
+
+        result
+    }
+
     fn execute_instruction(
         &mut self,
         instruction: Instruction,
@@ -577,10 +1223,7 @@ impl Vm {
                 register,
                 start,
                 count,
-            } => {
-                self.run_make_tuple(register, start, count);
-                Ok(())
-            }
+            } => self.run_make_tuple(register, start, count),
             Instruction::MakeTempTuple {
                 register,
                 start,
@@ -593,14 +1236,18 @@ impl Vm {
                 register,
                 size_hint,
             } => {
-                self.set_register(register, List(ValueList::with_capacity(size_hint)));
+                let list = List(ValueList::with_capacity(size_hint));
+                self.track_new_value(&list);
+                self.set_register(register, list);
                 Ok(())
             }
             Instruction::MakeMap {
                 register,
                 size_hint,
             } => {
-                self.set_register(register, Map(ValueMap::with_capacity(size_hint)));
+                let map = Map(ValueMap::with_capacity(size_hint));
+                self.track_new_value(&map);
+                self.set_register(register, map);
                 Ok(())
             }
             Instruction::MakeNum2 {
@@ -876,14 +1523,19 @@ impl Vm {
             .insert(export_name.into(), value);
     }
 
-    fn run_make_tuple(&mut self, register: u8, start: u8, count: u8) {
+    fn run_make_tuple(&mut self, register: u8, start: u8, count: u8) -> InstructionResult {
+        let allocation = self.track_allocation::<Value>(count as usize)?;
+
         let mut copied = Vec::with_capacity(count as usize);
 
         for register in start..start + count {
             copied.push(self.clone_register(register));
         }
 
-        self.set_register(register, Value::Tuple(copied.into()));
+        let tuple = Value::Tuple(copied.into());
+        allocation.assign_to(&tuple);
+        self.set_register(register, tuple);
+        Ok(())
     }
 
     fn run_make_range(
@@ -1030,6 +1682,13 @@ impl Vm {
                     self.set_register(register, Tuple(vec![first, second].into()));
                 }
             }
+            (Some(Err(error)), _)
+                if error.is_execution_limit()
+                    || error.is_stack_overflow()
+                    || matches!(error.error, RuntimeErrorType::OutOfMemory { .. }) =>
+            {
+                return Err(error)
+            }
             (Some(Err(error)), _) => return runtime_error!(error.to_string()),
             (None, _) => self.jump_ip(jump_offset),
         };
@@ -1126,6 +1785,14 @@ impl Vm {
             }
         };
 
+        // The slice is no larger than the sliced value, so it's counted after it has been made
+        let size = match &result {
+            List(list) => list.len(),
+            Tuple(tuple) => tuple.data().len(),
+            _ => 0,
+        };
+        self.track_allocation::<Value>(size)?.assign_to(&result);
+
         self.set_register(register, result);
 
         Ok(())
@@ -1168,6 +1835,7 @@ impl Vm {
                     Function(function)
                 };
 
+                self.track_new_value(&value);
                 self.jump_ip(size);
                 self.set_register(register, value);
             }
@@ -1228,7 +1896,13 @@ impl Vm {
                 let map = map.clone();
                 return self.call_overloaded_unary_op(result, value, map, Display);
             }
-            other => Str(other.to_string().into()),
+            other => {
+                let string = other.to_string();
+                let allocation = self.track_allocation::<u8>(string.len())?;
+                let result = Str(string.into());
+                allocation.assign_to(&result);
+                result
+            }
         };
         self.set_register(result, result_value);
 
@@ -1249,18 +1923,26 @@ impl Vm {
             (Num4(a), Num4(b)) => Num4(a + b),
             (Num4(a), Number(b)) => Num4(a + b),
             (List(a), List(b)) => {
+                let allocation = self.track_allocation::<Value>(a.len() + b.len())?;
                 let mut result = ValueVec::new();
                 result.extend(a.data().iter().chain(b.data().iter()).cloned());
-                List(ValueList::with_data(result))
+                let result = List(ValueList::with_data(result));
+                allocation.assign_to(&result);
+                result
             }
             (List(a), Tuple(b)) => {
+                let allocation = self.track_allocation::<Value>(a.len() + b.data().len())?;
                 let mut result = ValueVec::new();
                 result.extend(a.data().iter().chain(b.data().iter()).cloned());
-                List(ValueList::with_data(result))
+                let result = List(ValueList::with_data(result));
+                allocation.assign_to(&result);
+                result
             }
             (Str(a), Str(b)) => {
-                let result = a.to_string() + b.as_ref();
-                Str(result.into())
+                let allocation = self.track_allocation::<u8>(a.len() + b.len())?;
+                let result = Str((a.to_string() + b.as_ref()).into());
+                allocation.assign_to(&result);
+                result
             }
             (Map(map), value) if map.contents().meta.contains_key(&MetaKey::BinaryOp(Add)) => {
                 let map = map.clone();
@@ -1268,9 +1950,12 @@ impl Vm {
                 return self.call_overloaded_binary_op(result, lhs, map, value, Add);
             }
             (Map(a), Map(b)) => {
+                let allocation = self.track_allocation::<(ValueKey, Value)>(a.len() + b.len())?;
                 let mut result = a.contents().clone();
                 result.extend(&b.contents());
-                Map(ValueMap::with_contents(result))
+                let result = Map(ValueMap::with_contents(result));
+                allocation.assign_to(&result);
+                result
             }
             _ => return self.binary_op_error(lhs_value, rhs_value, "+"),
         };
@@ -1802,16 +2487,26 @@ impl Vm {
                 .cloned();
             if let Some(value) = maybe_in_prelude {
                 self.set_register(result_register, value);
+            } else if !self.context_shared.allow_module_loading {
+                return runtime_error!(
+                    "Failed to import '{}': module loading is disabled",
+                    import_name
+                );
             } else {
                 let source_path = self.reader.chunk.source_path.clone();
                 let compile_result = self
                     .context_mut()
                     .loader
-                    .compile_module(&import_name, source_path);
+                    .compile_module(&import_name, source_path.clone());
                 let (module_chunk, module_path) = match compile_result {
                     Ok(chunk) => chunk,
                     Err(e) => return runtime_error!("Failed to import '{}': {}", import_name, e),
                 };
+                self.context_mut()
+                    .importers
+                    .entry(module_path.clone())
+                    .or_default()
+                    .insert(source_path);
                 let maybe_module = self.context().modules.get(&module_path).cloned();
                 match maybe_module {
                     Some(Some(module)) => self.set_register(result_register, Value::Map(module)),
@@ -1839,8 +2534,10 @@ impl Vm {
                             }
                         }
 
-                        // Cache the resulting module's exports map
+                        // Cache the resulting module's exports map, along with the modules that it
+                        // imported
                         let module_exports = vm.context().exports.clone();
+                        self.context_mut().add_imported_modules(&vm.context());
                         self.context_mut()
                             .modules
                             .insert(module_path, Some(module_exports.clone()));
@@ -1962,19 +2659,35 @@ impl Vm {
 
         let value = self.clone_register(value_register);
 
+        // Ranges get expanded when they're added to a list, e.g. `[0..10]`
+        let allocation = match &value {
+            Range(IntRange { start, end }) => {
+                let size = if start <= end {
+                    end - start
+                } else {
+                    start - end
+                };
+                self.track_allocation::<Value>(size as usize)?
+            }
+            _ => self.track_allocation::<Value>(1)?,
+        };
+
         match self.get_register_mut(list_register) {
-            List(list) => match value {
-                Range(range) => {
-                    list.data_mut()
-                        .extend(ValueIterator::new(Iterable::Range(range)).map(
-                            |iterator_output| match iterator_output {
-                                Ok(ValueIteratorOutput::Value(value)) => value,
-                                _ => unreachable!(),
-                            },
-                        ));
+            List(list) => {
+                match value {
+                    Range(range) => {
+                        list.data_mut()
+                            .extend(ValueIterator::new(Iterable::Range(range)).map(
+                                |iterator_output| match iterator_output {
+                                    Ok(ValueIteratorOutput::Value(value)) => value,
+                                    _ => unreachable!(),
+                                },
+                            ));
+                    }
+                    _ => list.data_mut().push(value),
                 }
-                _ => list.data_mut().push(value),
-            },
+                allocation.assign_to(&List(list.clone()));
+            }
             unexpected => {
                 return runtime_error!("Expected List, found '{}'", unexpected,);
             }
@@ -2153,20 +2866,20 @@ impl Vm {
 
             (List(l), Range(IntRange { start, end })) => {
                 self.validate_int_range(start, end, l.len())?;
-                self.set_register(
-                    result_register,
-                    List(ValueList::from_slice(
-                        &l.data()[(start as usize)..(end as usize)],
-                    )),
-                )
+                let allocation = self.track_allocation::<Value>((end - start) as usize)?;
+                let result = List(ValueList::from_slice(
+                    &l.data()[(start as usize)..(end as usize)],
+                ));
+                allocation.assign_to(&result);
+                self.set_register(result_register, result)
             }
             (List(l), IndexRange(value::IndexRange { start, end })) => {
                 let end = end.unwrap_or_else(|| l.len());
                 self.validate_index_range(start, end, l.len())?;
-                self.set_register(
-                    result_register,
-                    List(ValueList::from_slice(&l.data()[start..end])),
-                )
+                let allocation = self.track_allocation::<Value>(end - start)?;
+                let result = List(ValueList::from_slice(&l.data()[start..end]));
+                allocation.assign_to(&result);
+                self.set_register(result_register, result)
             }
             (Tuple(t), Number(n)) => {
                 let index = self.validate_index(n, t.data().len())?;
@@ -2175,15 +2888,18 @@ impl Vm {
 
             (Tuple(t), Range(IntRange { start, end })) => {
                 self.validate_int_range(start, end, t.data().len())?;
-                self.set_register(
-                    result_register,
-                    Tuple(t.data()[(start as usize)..(end as usize)].into()),
-                )
+                let allocation = self.track_allocation::<Value>((end - start) as usize)?;
+                let result = Tuple(t.data()[(start as usize)..(end as usize)].into());
+                allocation.assign_to(&result);
+                self.set_register(result_register, result)
             }
             (Tuple(t), IndexRange(value::IndexRange { start, end })) => {
                 let end = end.unwrap_or(t.data().len());
                 self.validate_index_range(start, end, t.data().len())?;
-                self.set_register(result_register, Tuple(t.data()[start..end].into()))
+                let allocation = self.track_allocation::<Value>(end - start)?;
+                let result = Tuple(t.data()[start..end].into());
+                allocation.assign_to(&result);
+                self.set_register(result_register, result)
             }
             (Num2(n), Number(i)) => {
                 let i = usize::from(i);
@@ -2229,9 +2945,12 @@ impl Vm {
         let key_string = self.value_string_from_constant(key);
         let value = self.clone_register(value);
 
+        let allocation = self.track_allocation::<(ValueKey, Value)>(1)?;
+
         match self.get_register_mut(map_register) {
             Value::Map(map) => {
                 map.contents_mut().data.insert(key_string.into(), value);
+                allocation.assign_to(&Value::Map(map.clone()));
                 Ok(())
             }
             unexpected => runtime_error!(
@@ -2441,7 +3160,7 @@ impl Vm {
             chunk,
             function_ip,
             0, // arguments will be copied starting in register 0
-        );
+        )?;
 
         let expected_arg_count = match (instance_function, variadic) {
             (true, true) => function_arg_count - 2,
@@ -2619,9 +3338,7 @@ impl Vm {
                 }
 
                 // Set up a new frame for the called function
-                self.push_frame(chunk, function_ip, adjusted_frame_base);
-
-                Ok(())
+                self.push_frame(chunk, function_ip, adjusted_frame_base)
             }
             unexpected => self.unexpected_type_error("Expected Function", &unexpected),
         }
@@ -2728,7 +3445,22 @@ impl Vm {
         self.call_stack.last_mut().expect("Empty call stack")
     }
 
-    fn push_frame(&mut self, chunk: Arc<Chunk>, ip: usize, frame_base: u8) {
+    fn push_frame(&mut self, chunk: Arc<Chunk>, ip: usize, frame_base: u8) -> InstructionResult {
+        let max_call_depth_exceeded = matches!(
+            self.context_shared.max_call_depth,
+            Some(max_call_depth) if self.call_depth() >= max_call_depth
+        );
+        // The nested VM depth only needs to be checked when the Vm starts running
+        let max_nested_vm_depth_exceeded = self.call_stack.is_empty()
+            && matches!(
+                self.context_shared.max_nested_vm_depth,
+                Some(max_nested_vm_depth) if self.nested_vm_depth > max_nested_vm_depth
+            );
+
+        if max_call_depth_exceeded || max_nested_vm_depth_exceeded {
+            return Err(RuntimeError::new(RuntimeErrorType::StackOverflow));
+        }
+
         let previous_frame_base = if let Some(frame) = self.call_stack.last() {
             frame.register_base
         } else {
@@ -2736,9 +3468,17 @@ impl Vm {
         };
         let new_frame_base = previous_frame_base + frame_base as usize;
 
+        if let Some(profiler) = &self.context_shared.profiler {
+            let function = profiler.function_index(&chunk, ip);
+            self.profile_state.stack.push(function);
+            profiler.enter_function(self.profile_state.full_stack());
+        }
+
         self.call_stack
             .push(Frame::new(chunk.clone(), new_frame_base));
         self.set_chunk_and_ip(chunk, ip);
+
+        Ok(())
     }
 
     fn pop_frame(&mut self, return_value: Value) -> Result<Option<Value>, RuntimeError> {
@@ -2748,6 +3488,11 @@ impl Vm {
             return runtime_error!("pop_frame: Empty call stack");
         };
 
+        if let Some(profiler) = &self.context_shared.profiler {
+            self.profile_state.stack.pop();
+            profiler.set_active_stack(self.profile_state.full_stack());
+        }
+
         if !self.call_stack.is_empty() && self.frame().return_register_and_ip.is_some() {
             let (return_register, return_ip) = self.frame().return_register_and_ip.unwrap();
 
@@ -2847,6 +3592,15 @@ impl Vm {
     }
 }
 
+impl Drop for Vm {
+    fn drop(&mut self) {
+        // Only concurrent VMs have a stop flag, see Vm::spawn_shared_concurrent_vm
+        if self.stop_flag.is_some() {
+            self.context_shared.cycles.remove_concurrent_vm();
+        }
+    }
+}
+
 impl fmt::Debug for Vm {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str("Vm")
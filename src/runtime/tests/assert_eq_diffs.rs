@@ -0,0 +1,100 @@
+use koto_runtime::{Loader, RuntimeErrorType, Vm};
+
+mod assert_eq_diffs {
+    use super::*;
+
+    fn assert_eq_message(script: &str) -> String {
+        let mut vm = Vm::default();
+        let mut loader = Loader::default();
+        let chunk = match loader.compile_script(script, &None) {
+            Ok(chunk) => chunk,
+            Err(error) => panic!("Error while compiling script: {}", error),
+        };
+
+        match vm.run(chunk) {
+            Ok(_) => panic!("Expected the assertion to fail"),
+            Err(error) => match error.error {
+                RuntimeErrorType::StringError(message) => message,
+                other => panic!("Unexpected error: {:?}", other),
+            },
+        }
+    }
+
+    #[test]
+    fn simple_values() {
+        let script = "
+import test.assert_eq
+assert_eq 1, 2
+";
+        assert_eq!(
+            assert_eq_message(script),
+            "Assertion failed, '1' is not equal to '2'"
+        );
+    }
+
+    #[test]
+    fn nested_maps_and_lists() {
+        let script = "
+import test.assert_eq
+a = {name: \"x\", items: [1, 2, 3], nested: {deep: (1, 2), gone: 1}}
+b = {name: \"x\", items: [1, 5], nested: {deep: (1, 3), added: true}}
+assert_eq a, b
+";
+        assert_eq!(
+            assert_eq_message(script),
+            "\
+Assertion failed, the values are not equal
+  items[1]: '2' != '5'
+  items[2]: unexpected value '3'
+  nested.deep[1]: '2' != '3'
+  nested.gone: unexpected value '1'
+  nested.added: missing, expected 'true'"
+        );
+    }
+
+    #[test]
+    fn list_missing_elements() {
+        let script = "
+import test.assert_eq
+assert_eq [1, [2, 3]], [1, [2, 3, 4], 5]
+";
+        assert_eq!(
+            assert_eq_message(script),
+            "\
+Assertion failed, the values are not equal
+  [1][2]: missing, expected '4'
+  [2]: missing, expected '5'"
+        );
+    }
+
+    #[test]
+    fn maps_with_overloaded_equality_are_compared_as_a_whole() {
+        let script = "
+import test.assert_eq
+foo = |x|
+  x: x
+  @==: |self, other| false
+x = foo 1
+assert_eq {a: x, b: 2}, {a: x, b: 2}
+";
+        assert_eq!(
+            assert_eq_message(script),
+            "\
+Assertion failed, the values are not equal
+  a: '{x: 1}' != '{x: 1}'"
+        );
+    }
+
+    #[test]
+    fn long_diffs_are_truncated() {
+        let script = "
+import test.assert_eq
+assert_eq (0..30).to_list(), (100..130).to_list()
+";
+        let message = assert_eq_message(script);
+        let lines: Vec<&str> = message.lines().collect();
+        assert_eq!(lines.len(), 22);
+        assert_eq!(lines[1], "  [0]: '0' != '100'");
+        assert_eq!(lines[21], "  ...and 10 more differences");
+    }
+}
@@ -0,0 +1,318 @@
+use {
+    koto_runtime::{
+        ConversionError, FromKoto, IntoKoto, Loader, Value, ValueKey, ValueList, ValueMap, Vm,
+    },
+    std::collections::HashMap,
+};
+
+mod conversion {
+    use super::*;
+
+    fn run_script(script: &str) -> Value {
+        let mut vm = Vm::default();
+        let mut loader = Loader::default();
+        let chunk = match loader.compile_script(script, &None) {
+            Ok(chunk) => chunk,
+            Err(error) => panic!("Error while compiling script: {}", error),
+        };
+
+        match vm.run(chunk) {
+            Ok(result) => result,
+            Err(error) => panic!("Error while running script: {}", error),
+        }
+    }
+
+    fn conversion_error<T: FromKoto + std::fmt::Debug>(value: &Value) -> String {
+        T::from_koto(value).unwrap_err().to_string()
+    }
+
+    mod primitives {
+        use super::*;
+
+        #[test]
+        fn numbers() {
+            assert_eq!(f64::from_koto(&run_script("1.5")), Ok(1.5));
+            assert_eq!(i64::from_koto(&run_script("-42")), Ok(-42));
+            assert_eq!(u8::from_koto(&run_script("255")), Ok(255));
+            assert_eq!(
+                conversion_error::<u8>(&run_script("256")),
+                "expected Integer between 0 and 255, found 256"
+            );
+            assert_eq!(
+                conversion_error::<f64>(&run_script("\"1\"")),
+                "expected Number, found String"
+            );
+            assert_eq!(42.into_koto().to_string(), "42");
+        }
+
+        #[test]
+        fn integers_from_floats() {
+            assert_eq!(i32::from_koto(&run_script("3.0")), Ok(3));
+            assert_eq!(
+                u64::from_koto(&Value::Number(2f64.powi(63).into())),
+                Ok(1 << 63)
+            );
+            assert_eq!(
+                conversion_error::<i64>(&run_script("2.7")),
+                "expected Integer between -9223372036854775808 and 9223372036854775807, found 2.7"
+            );
+            assert_eq!(
+                conversion_error::<u64>(&Value::Number(2f64.powi(64).into())),
+                "expected Integer between 0 and 18446744073709551615, found 18446744073709551616.0"
+            );
+            assert_eq!(
+                conversion_error::<u8>(&run_script("-1.0")),
+                "expected Integer between 0 and 255, found -1.0"
+            );
+            assert!(i32::from_koto(&run_script("import number\nnumber.nan")).is_err());
+            assert!(i32::from_koto(&run_script("import number\nnumber.infinity")).is_err());
+        }
+
+        #[test]
+        fn large_unsigned_integers() {
+            let value = u64::MAX.into_koto();
+            assert_eq!(value.to_string(), "18446744073709551616.0");
+            assert!(u64::from_koto(&value).is_err());
+
+            let value = (1_u64 << 63).into_koto();
+            assert_eq!(u64::from_koto(&value), Ok(1 << 63));
+            assert_eq!(
+                usize::MAX.into_koto().to_string(),
+                u64::MAX.into_koto().to_string()
+            );
+        }
+
+        #[test]
+        fn strings_and_bools() {
+            assert_eq!(String::from_koto(&run_script("\"hi\"")), Ok("hi".into()));
+            assert_eq!(bool::from_koto(&run_script("true")), Ok(true));
+            assert!(matches!("hi".into_koto(), Value::Str(s) if s.as_str() == "hi"));
+            assert_eq!(
+                conversion_error::<bool>(&run_script("[]")),
+                "expected Bool, found List"
+            );
+        }
+
+        #[test]
+        fn options() {
+            assert_eq!(Option::<f64>::from_koto(&Value::Empty), Ok(None));
+            assert_eq!(Option::<f64>::from_koto(&run_script("1")), Ok(Some(1.0)));
+            assert!(matches!(None::<f64>.into_koto(), Value::Empty));
+        }
+    }
+
+    mod containers {
+        use super::*;
+
+        #[test]
+        fn vec() {
+            let value = run_script("[1, 2, 3]");
+            assert_eq!(Vec::<i32>::from_koto(&value), Ok(vec![1, 2, 3]));
+            assert_eq!(vec![1, 2, 3].into_koto().to_string(), value.to_string());
+            assert_eq!(
+                conversion_error::<Vec<i32>>(&run_script("[1, 2, \"x\"]")),
+                "expected Number for '[2]', found String"
+            );
+        }
+
+        #[test]
+        fn hash_map() {
+            let value = run_script("{a: 1, b: 2}");
+            let map = HashMap::<String, f64>::from_koto(&value).unwrap();
+            assert_eq!(map.len(), 2);
+            assert_eq!(map["a"], 1.0);
+            assert_eq!(map["b"], 2.0);
+
+            match map.into_koto() {
+                Value::Map(m) => assert_eq!(m.len(), 2),
+                other => panic!("Expected a map, found {}", other),
+            }
+
+            let map: HashMap<u64, bool> = vec![(1, true), (2, false)].into_iter().collect();
+            match map.into_koto() {
+                Value::Map(m) => assert!(matches!(
+                    m.contents()
+                        .data
+                        .get(&ValueKey::from(Value::Number(2.into()))),
+                    Some(Value::Bool(false))
+                )),
+                other => panic!("Expected a map, found {}", other),
+            }
+
+            assert_eq!(
+                conversion_error::<HashMap<String, Vec<f64>>>(&run_script("{a: [1, true]}")),
+                "expected Number for 'a[1]', found Bool"
+            );
+        }
+
+        #[test]
+        fn tuples() {
+            let value = run_script("1, \"x\", true");
+            assert_eq!(
+                <(i32, String, bool)>::from_koto(&value),
+                Ok((1, "x".into(), true))
+            );
+            assert_eq!((1, "x", true).into_koto().to_string(), value.to_string());
+            assert_eq!(
+                conversion_error::<(i32, i32)>(&value),
+                "expected Tuple with 2 elements, found Tuple with 3 elements"
+            );
+        }
+    }
+
+    mod derive {
+        use super::*;
+
+        #[derive(Debug, PartialEq, FromKoto, IntoKoto)]
+        struct Point {
+            x: f64,
+            y: f64,
+        }
+
+        #[derive(Debug, PartialEq, FromKoto, IntoKoto)]
+        struct Shape {
+            name: String,
+            #[koto(rename = "pts")]
+            points: Vec<Point>,
+            color: Option<Color>,
+        }
+
+        #[derive(Debug, PartialEq, FromKoto, IntoKoto)]
+        enum Color {
+            Red,
+            #[koto(rename = "green")]
+            Green,
+            Rgb(u8, u8, u8),
+            Named {
+                name: String,
+            },
+        }
+
+        #[derive(Debug, PartialEq, FromKoto, IntoKoto)]
+        struct Meters(f64);
+
+        #[derive(Debug, PartialEq, FromKoto, IntoKoto)]
+        struct Pair<T>(T, T);
+
+        #[test]
+        fn struct_from_map() {
+            let value = run_script("{pts: [{x: 1, y: 2}, {x: 3, y: 4}], name: \"line\"}");
+            let shape = Shape::from_koto(&value).unwrap();
+            assert_eq!(
+                shape,
+                Shape {
+                    name: "line".into(),
+                    points: vec![Point { x: 1.0, y: 2.0 }, Point { x: 3.0, y: 4.0 }],
+                    color: None,
+                }
+            );
+        }
+
+        #[test]
+        fn struct_round_trip() {
+            let shape = Shape {
+                name: "dot".into(),
+                points: vec![Point { x: 0.5, y: 1.0 }],
+                color: Some(Color::Rgb(1, 2, 3)),
+            };
+
+            let value = shape.into_koto();
+            match &value {
+                Value::Map(m) => {
+                    assert_eq!(m.len(), 3);
+                    assert!(m.contents().data.contains_key(&ValueKey::from("pts")));
+                }
+                other => panic!("Expected a map, found {}", other),
+            }
+
+            assert_eq!(
+                Shape::from_koto(&value),
+                Ok(Shape {
+                    name: "dot".into(),
+                    points: vec![Point { x: 0.5, y: 1.0 }],
+                    color: Some(Color::Rgb(1, 2, 3)),
+                })
+            );
+        }
+
+        #[test]
+        fn struct_errors() {
+            assert_eq!(
+                conversion_error::<Shape>(&run_script("[]")),
+                "expected Map, found List"
+            );
+            assert_eq!(
+                conversion_error::<Shape>(&run_script("{name: \"x\", pts: [{x: 1}]}")),
+                "expected Number for 'pts[0].y', found Empty"
+            );
+        }
+
+        #[test]
+        fn enums() {
+            assert_eq!(Color::from_koto(&run_script("\"Red\"")), Ok(Color::Red));
+            assert_eq!(Color::from_koto(&run_script("\"green\"")), Ok(Color::Green));
+            assert_eq!(
+                Color::from_koto(&run_script("{Rgb: (1, 2, 3)}")),
+                Ok(Color::Rgb(1, 2, 3))
+            );
+            assert_eq!(
+                Color::from_koto(&run_script("{Named: {name: \"teal\"}}")),
+                Ok(Color::Named {
+                    name: "teal".into()
+                })
+            );
+
+            assert_eq!(Color::Green.into_koto().to_string(), "green");
+            let named = Color::Named {
+                name: "teal".into(),
+            };
+            assert_eq!(
+                Color::from_koto(&named.into_koto()),
+                Ok(Color::Named {
+                    name: "teal".into()
+                })
+            );
+        }
+
+        #[test]
+        fn enum_errors() {
+            assert_eq!(
+                conversion_error::<Color>(&run_script("\"Blue\"")),
+                "expected one of 'Red', 'green', 'Rgb', 'Named', found 'Blue'"
+            );
+            assert_eq!(
+                conversion_error::<Color>(&run_script("{Rgb: (1, 2, 300)}")),
+                "expected Integer between 0 and 255 for 'Rgb[2]', found 300"
+            );
+            assert_eq!(
+                conversion_error::<Color>(&run_script("1")),
+                "expected String, or Map with a single entry, found Int"
+            );
+        }
+
+        #[test]
+        fn newtype_and_generic_structs() {
+            assert_eq!(Meters::from_koto(&run_script("2.5")), Ok(Meters(2.5)));
+            assert_eq!(Meters(2.5).into_koto().to_string(), "2.5");
+
+            let value = run_script("1, 2");
+            assert_eq!(Pair::<i32>::from_koto(&value), Ok(Pair(1, 2)));
+            assert_eq!(Pair(1, 2).into_koto().to_string(), value.to_string());
+        }
+
+        #[test]
+        fn conversion_error_into_runtime_error() {
+            let error: ConversionError = Point::from_koto(&Value::Empty).unwrap_err();
+            let runtime_error = koto_runtime::RuntimeError::from(error);
+            assert_eq!(format!("{:#}", runtime_error), "expected Map, found Empty");
+        }
+
+        #[test]
+        fn values_pass_through() {
+            let list = ValueList::from_slice(&[1.into_koto(), 2.into_koto()]);
+            let value = Value::List(list);
+            assert_eq!(Value::from_koto(&value).unwrap().to_string(), "[1, 2]");
+            assert!(ValueMap::from_koto(&value).is_err());
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use koto_runtime::{core::CoreModules, Loader, RuntimeResult, Vm, VmSettings};
+
+mod vm {
+    use super::*;
+
+    fn run_with_settings(script: &str, settings: VmSettings) -> RuntimeResult {
+        let mut vm = Vm::with_settings(settings);
+
+        let mut loader = Loader::default();
+        let chunk = match loader.compile_script(script, &None) {
+            Ok(chunk) => chunk,
+            Err(error) => panic!("Error while compiling script: {}", error),
+        };
+
+        vm.run(chunk)
+    }
+
+    fn pure_settings() -> VmSettings {
+        VmSettings {
+            core_modules: CoreModules::pure(),
+            allow_module_loading: false,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn default_prelude_contains_all_modules() {
+        let vm = Vm::default();
+        let prelude = vm.prelude();
+        let prelude = prelude.contents();
+        for module in &[
+            "io", "iterator", "koto", "list", "map", "os", "thread", "tuple",
+        ] {
+            assert!(prelude.data.get_with_string(module).is_some());
+        }
+    }
+
+    #[test]
+    fn pure_prelude_excludes_io_os_and_thread() {
+        let vm = Vm::with_settings(pure_settings());
+        let prelude = vm.prelude();
+        let prelude = prelude.contents();
+        for module in &["io", "os", "thread"] {
+            assert!(prelude.data.get_with_string(module).is_none());
+        }
+        for module in &["iterator", "koto", "list", "map", "string", "test", "tuple"] {
+            assert!(prelude.data.get_with_string(module).is_some());
+        }
+    }
+
+    #[test]
+    fn core_ops_are_available_in_pure_prelude() {
+        let script = r#"
+x = "hello".to_uppercase()
+[1, 2, 3].size() + x.size()
+"#;
+        assert!(run_with_settings(script, pure_settings()).is_ok());
+    }
+
+    #[test]
+    fn excluded_module_cant_be_imported() {
+        let script = "
+import io.print
+print 42
+";
+        assert!(run_with_settings(script, pure_settings()).is_err());
+    }
+
+    #[test]
+    fn module_loading_can_be_disabled() {
+        let script = "
+import some_module
+";
+        match run_with_settings(script, pure_settings()) {
+            Ok(_) => panic!("Expected the import to fail"),
+            Err(error) => assert!(error.to_string().contains("module loading is disabled")),
+        }
+    }
+}
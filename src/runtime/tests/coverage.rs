@@ -0,0 +1,142 @@
+use {
+    koto_runtime::{Coverage, Loader, Vm, VmSettings},
+    std::{path::PathBuf, sync::Arc},
+};
+
+mod coverage {
+    use super::*;
+
+    fn run_with_coverage(script: &str) -> Arc<Coverage> {
+        let coverage = Arc::new(Coverage::new());
+        let mut vm = Vm::with_settings(VmSettings {
+            coverage: Some(coverage.clone()),
+            ..Default::default()
+        });
+
+        let mut loader = Loader::default();
+        let chunk = match loader.compile_script(script, &Some(PathBuf::from("test.koto"))) {
+            Ok(chunk) => chunk,
+            Err(error) => panic!("Error while compiling script: {}", error),
+        };
+
+        if let Err(error) = vm.run(chunk) {
+            panic!("Error while running script: {}", error);
+        }
+
+        coverage
+    }
+
+    fn line_hits(coverage: &Coverage) -> Vec<(u32, u64)> {
+        let files = coverage.files();
+        assert_eq!(files.len(), 1);
+        files[0]
+            .lines
+            .iter()
+            .map(|line| (line.line, line.hits))
+            .collect()
+    }
+
+    #[test]
+    fn lines_that_werent_run_have_no_hits() {
+        let script = "
+f = |n|
+  if n > 5
+    1
+  else
+    0
+for i in 6..9
+  f i
+";
+        let coverage = run_with_coverage(script);
+
+        assert_eq!(
+            line_hits(&coverage),
+            vec![(2, 1), (3, 3), (4, 3), (6, 0), (7, 1), (8, 3)]
+        );
+    }
+
+    #[test]
+    fn branches() {
+        let script = "
+x = 1
+if x == 1
+  x = 2
+y = if x == 3 then 1 else 2
+";
+        let coverage = run_with_coverage(script);
+        let files = coverage.files();
+        let file = &files[0];
+
+        let line_3 = file.lines.iter().find(|line| line.line == 3).unwrap();
+        assert_eq!(line_3.branches.len(), 1);
+        assert_eq!(line_3.branches[0].taken, 0);
+        assert_eq!(line_3.branches[0].not_taken, 1);
+
+        assert_eq!(file.branch_count(), 4);
+        assert_eq!(file.branches_hit(), 2);
+    }
+
+    #[test]
+    fn lcov() {
+        let script = "
+f = |x|
+  if x
+    1
+  else
+    2
+f true
+";
+        let coverage = run_with_coverage(script);
+
+        let expected = "\
+TN:
+SF:test.koto
+BRDA:3,0,0,1
+BRDA:3,0,1,0
+BRF:2
+BRH:1
+DA:2,1
+DA:3,1
+DA:4,1
+DA:6,0
+DA:7,1
+LF:5
+LH:4
+end_of_record
+";
+        assert_eq!(coverage.lcov(), expected);
+    }
+
+    #[test]
+    fn cobertura() {
+        let script = "
+f = |x|
+  if x
+    1
+  else
+    2
+f true
+";
+        let coverage = run_with_coverage(script);
+        let xml = coverage.cobertura();
+
+        assert!(
+            xml.contains(r#"lines-covered="4" lines-valid="5""#),
+            "{}",
+            xml
+        );
+        assert!(xml.contains(r#"filename="test.koto""#), "{}", xml);
+        assert!(
+            xml.contains(
+                r#"<line number="3" hits="1" branch="true" condition-coverage="50% (1/2)"/>"#
+            ),
+            "{}",
+            xml
+        );
+        assert!(
+            xml.contains(r#"<line number="6" hits="0" branch="false"/>"#),
+            "{}",
+            xml
+        );
+    }
+}
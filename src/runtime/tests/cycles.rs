@@ -0,0 +1,125 @@
+use koto_runtime::{CycleCollection, Loader, Value, Vm, VmSettings};
+
+mod cycles {
+    use super::*;
+
+    fn run_script(script: &str, cycle_collection: CycleCollection) -> Vm {
+        let mut vm = Vm::with_settings(VmSettings {
+            cycle_collection,
+            ..Default::default()
+        });
+
+        let mut loader = Loader::default();
+        let chunk = match loader.compile_script(script, &None) {
+            Ok(chunk) => chunk,
+            Err(error) => panic!("Error while compiling script: {}", error),
+        };
+
+        if let Err(error) = vm.run(chunk) {
+            panic!("Error while running script: {:#}", error);
+        }
+
+        vm
+    }
+
+    const MAKE_CYCLES: &str = "
+make_cycle = ||
+  m = {}
+  m.f = || m
+  m.list = [m]
+  0
+for _ in 0..100
+  make_cycle()
+0
+";
+
+    #[test]
+    fn leaked_cycles_are_collected() {
+        let vm = run_script(MAKE_CYCLES, CycleCollection::Manual);
+
+        // Each cycle contains a map, a list, and the function's captures
+        assert_eq!(vm.find_leaked_cycles().len(), 300);
+        assert_eq!(vm.collect_cycles(), 300);
+        assert!(vm.find_leaked_cycles().is_empty());
+        assert_eq!(vm.collect_cycles(), 0);
+    }
+
+    #[test]
+    fn reachable_cycles_are_kept() {
+        let script = "
+export m = {}
+m.f = || m
+m.list = [m]
+export copied = m.copy()
+m.list.push copied
+0
+";
+        let vm = run_script(script, CycleCollection::Manual);
+
+        assert!(vm.find_leaked_cycles().is_empty());
+        assert_eq!(vm.collect_cycles(), 0);
+
+        match vm.get_exported_value("m") {
+            Some(Value::Map(m)) => {
+                assert_eq!(m.len(), 2);
+                assert!(matches!(
+                    m.contents().data.get_with_string("list"),
+                    Some(Value::List(l)) if l.len() == 2
+                ));
+            }
+            other => panic!("Expected a map, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn cycles_are_collected_automatically_with_a_threshold() {
+        let vm = run_script(MAKE_CYCLES, CycleCollection::Threshold(30));
+
+        assert!(vm.find_leaked_cycles().len() < 30);
+    }
+
+    #[test]
+    fn automatic_collection_is_skipped_while_threads_are_running() {
+        let script = "
+import thread
+make_cycle = ||
+  m = {}
+  m.f = || m
+  m.list = [m]
+  0
+state = {done: false}
+t = thread.create ||
+  while not state.done
+    thread.sleep 0.001
+for _ in 0..100
+  make_cycle()
+export finish = ||
+  state.done = true
+  t.join()
+export make_cycles = ||
+  for _ in 0..100
+    make_cycle()
+0
+";
+        let mut vm = run_script(script, CycleCollection::Threshold(30));
+
+        assert_eq!(vm.find_leaked_cycles().len(), 300);
+
+        for name in ["finish", "make_cycles"] {
+            let function = vm.get_exported_function(name).unwrap();
+            if let Err(error) = vm.run_function(function, &[]) {
+                panic!("Error while calling '{}': {:#}", name, error);
+            }
+        }
+
+        assert!(vm.find_leaked_cycles().len() < 30);
+    }
+
+    #[test]
+    fn cycles_are_not_tracked_when_disabled() {
+        let vm = run_script(MAKE_CYCLES, CycleCollection::Disabled);
+
+        assert!(vm.find_leaked_cycles().is_empty());
+        assert_eq!(vm.collect_cycles(), 0);
+    }
+}
@@ -0,0 +1,248 @@
+use {
+    koto_runtime::{
+        DebugCommand, DebugHook, Debugger, Loader, PauseEvent, PauseReason, Value, Vm, VmSettings,
+    },
+    parking_lot::Mutex,
+    std::sync::Arc,
+};
+
+// A debug hook that records each pause, and then replies with the next scripted command
+struct TestHook {
+    pauses: Arc<Mutex<Vec<(PauseReason, u32, usize)>>>,
+    locals: Arc<Mutex<Vec<Vec<String>>>>,
+    commands: Mutex<Vec<DebugCommand>>,
+}
+
+impl DebugHook for TestHook {
+    fn on_pause(&self, _vm: &mut Vm, event: &PauseEvent) -> DebugCommand {
+        let frame = &event.call_stack[0];
+        let line = frame.span.unwrap().start.line;
+        self.pauses
+            .lock()
+            .push((event.reason, line, event.call_stack.len()));
+        self.locals.lock().push(
+            frame
+                .locals
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name, value))
+                .collect(),
+        );
+
+        let mut commands = self.commands.lock();
+        if commands.is_empty() {
+            DebugCommand::Continue
+        } else {
+            commands.remove(0)
+        }
+    }
+}
+
+struct DebugRun {
+    pauses: Vec<(PauseReason, u32, usize)>,
+    locals: Vec<Vec<String>>,
+}
+
+mod vm {
+    use super::*;
+
+    fn run_with_debugger(
+        script: &str,
+        breakpoints: &[u32],
+        pause_on_entry: bool,
+        commands: &[DebugCommand],
+    ) -> DebugRun {
+        let pauses = Arc::new(Mutex::new(Vec::new()));
+        let locals = Arc::new(Mutex::new(Vec::new()));
+
+        let debugger = Arc::new(Debugger::new(TestHook {
+            pauses: pauses.clone(),
+            locals: locals.clone(),
+            commands: Mutex::new(commands.to_vec()),
+        }));
+        debugger.set_breakpoints(None, breakpoints);
+        if pause_on_entry {
+            debugger.request_pause();
+        }
+
+        let mut vm = Vm::with_settings(VmSettings {
+            debugger: Some(debugger),
+            ..Default::default()
+        });
+
+        let chunk = match Loader::default().compile_script(script, &None) {
+            Ok(chunk) => chunk,
+            Err(error) => panic!("Error while compiling script: {}", error),
+        };
+
+        if let Err(error) = vm.run(chunk) {
+            panic!("Error while running script: {}", error);
+        }
+
+        let pauses = pauses.lock().clone();
+        let locals = locals.lock().clone();
+        DebugRun { pauses, locals }
+    }
+
+    #[test]
+    fn breakpoints() {
+        let script = "
+x = 1
+y = 2
+z = x + y
+";
+        let run = run_with_debugger(script, &[3, 4], false, &[]);
+        assert_eq!(
+            run.pauses,
+            vec![
+                (PauseReason::Breakpoint, 3, 1),
+                (PauseReason::Breakpoint, 4, 1)
+            ]
+        );
+        assert_eq!(run.locals[1], vec!["x: 1", "y: 2"]);
+    }
+
+    #[test]
+    fn pause_on_entry_and_step_in() {
+        let script = "
+f = |n|
+  a = n * 2
+  a + 1
+x = f 10
+x
+";
+        use DebugCommand::*;
+        let run = run_with_debugger(script, &[], true, &[StepIn, StepIn, StepIn, StepIn]);
+        assert_eq!(
+            run.pauses,
+            vec![
+                (PauseReason::PauseRequested, 2, 1),
+                (PauseReason::Step, 5, 1),
+                (PauseReason::Step, 3, 2),
+                (PauseReason::Step, 4, 2),
+                (PauseReason::Step, 5, 1),
+            ]
+        );
+        assert_eq!(run.locals[3], vec!["n: 10", "a: 20"]);
+    }
+
+    #[test]
+    fn step_over() {
+        let script = "
+f = |n|
+  a = n * 2
+  a + 1
+x = f 10
+y = f x
+z = y
+";
+        use DebugCommand::*;
+        let run = run_with_debugger(script, &[5], false, &[StepOver, StepOver, StepOver]);
+        assert_eq!(
+            run.pauses,
+            vec![
+                (PauseReason::Breakpoint, 5, 1),
+                (PauseReason::Step, 6, 1),
+                (PauseReason::Step, 7, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn step_out() {
+        let script = "
+f = |n|
+  a = n * 2
+  a + 1
+x = f 10
+y = x
+";
+        use DebugCommand::*;
+        let run = run_with_debugger(script, &[3], false, &[StepOut]);
+        assert_eq!(
+            run.pauses,
+            vec![(PauseReason::Breakpoint, 3, 2), (PauseReason::Step, 6, 1)]
+        );
+    }
+
+    #[test]
+    fn step_out_of_a_callback() {
+        let script = "
+f = |n|
+  a = n * 2
+  a + 1
+x = [10].each(f).to_list()
+y = x
+";
+        use DebugCommand::*;
+        let run = run_with_debugger(script, &[3], false, &[StepOut]);
+        assert_eq!(
+            run.pauses,
+            vec![(PauseReason::Breakpoint, 3, 1), (PauseReason::Step, 6, 1)]
+        );
+    }
+
+    #[test]
+    fn step_over_in_a_callback() {
+        let script = "
+f = |n|
+  a = n * 2
+  a + 1
+x = [10].each(f).to_list()
+y = x
+";
+        use DebugCommand::*;
+        let run = run_with_debugger(script, &[3], false, &[StepOver, StepOver]);
+        assert_eq!(
+            run.pauses,
+            vec![
+                (PauseReason::Breakpoint, 3, 1),
+                (PauseReason::Step, 4, 1),
+                (PauseReason::Step, 6, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn call_stack_locals() {
+        let script = "
+inner = |y|
+  z = y * 2
+  z + 1
+outer = |x|
+  inner x + 1
+outer 1
+";
+        let pauses = Arc::new(Mutex::new(Vec::new()));
+
+        struct StackHook(Arc<Mutex<Vec<Vec<Option<Value>>>>>);
+        impl DebugHook for StackHook {
+            fn on_pause(&self, _vm: &mut Vm, event: &PauseEvent) -> DebugCommand {
+                self.0.lock().push(vec![
+                    event.call_stack[0].get_local("z").cloned(),
+                    event.call_stack[1].get_local("x").cloned(),
+                ]);
+                DebugCommand::Continue
+            }
+        }
+
+        let debugger = Arc::new(Debugger::new(StackHook(pauses.clone())));
+        debugger.set_breakpoints(None, &[4]);
+
+        let mut vm = Vm::with_settings(VmSettings {
+            debugger: Some(debugger),
+            ..Default::default()
+        });
+        let chunk = Loader::default().compile_script(script, &None).unwrap();
+        vm.run(chunk).unwrap();
+
+        let pauses = pauses.lock();
+        assert_eq!(pauses.len(), 1);
+        match pauses[0].as_slice() {
+            [Some(Value::Number(z)), Some(Value::Number(x))] => {
+                assert_eq!(i64::from(z), 4);
+                assert_eq!(i64::from(x), 1);
+            }
+            unexpected => panic!("Unexpected locals: {:?}", unexpected),
+        }
+    }
+}
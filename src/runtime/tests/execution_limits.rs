@@ -0,0 +1,613 @@
+#![allow(clippy::float_cmp)]
+
+use {
+    koto_runtime::{Loader, RuntimeError, RuntimeErrorType, Value, Vm, VmSettings},
+    std::{mem, thread, time::Duration},
+};
+
+mod vm {
+    use super::*;
+
+    fn run_with_settings(script: &str, settings: VmSettings) -> (Vm, Result<Value, String>) {
+        let mut vm = Vm::with_settings(settings);
+
+        let mut loader = Loader::default();
+        let chunk = match loader.compile_script(script, &None) {
+            Ok(chunk) => chunk,
+            Err(error) => panic!("Error while compiling script: {}", error),
+        };
+
+        let result = vm.run(chunk).map_err(|e| format!("{:#}", e));
+        (vm, result)
+    }
+
+    fn check_number(value: Value, expected: f64) {
+        match value {
+            Value::Number(n) => assert_eq!(n, expected),
+            other => panic!("Expected a number, found '{}'", other),
+        }
+    }
+
+    mod fuel {
+        use super::*;
+
+        #[test]
+        fn infinite_loop_runs_out_of_fuel() {
+            let script = "
+x = 0
+loop
+  x += 1
+";
+            let mut vm = Vm::with_settings(VmSettings {
+                fuel: Some(1000),
+                ..Default::default()
+            });
+            let chunk = Loader::default().compile_script(script, &None).unwrap();
+
+            match vm.run(chunk) {
+                Err(error) => assert!(matches!(error.error, RuntimeErrorType::OutOfFuel)),
+                Ok(_) => panic!("Expected the fuel to run out"),
+            }
+            assert_eq!(vm.remaining_fuel(), Some(0));
+        }
+
+        #[test]
+        fn sufficient_fuel() {
+            let script = "
+x = 0
+for i in 0..10
+  x += i
+x
+";
+            let (_, result) = run_with_settings(
+                script,
+                VmSettings {
+                    fuel: Some(1000),
+                    ..Default::default()
+                },
+            );
+            check_number(result.unwrap(), 45.0);
+        }
+
+        #[test]
+        fn resume_after_refueling() {
+            let script = "
+x = 0
+for i in 0..100
+  x += i
+x
+";
+            let (mut vm, result) = run_with_settings(
+                script,
+                VmSettings {
+                    fuel: Some(10),
+                    ..Default::default()
+                },
+            );
+            assert!(result.is_err());
+
+            let final_result = loop {
+                vm.add_fuel(10);
+                match vm.continue_running() {
+                    Ok(result) => break result,
+                    Err(error) => assert!(matches!(error.error, RuntimeErrorType::OutOfFuel)),
+                }
+            };
+
+            check_number(final_result, 4950.0);
+        }
+
+        #[test]
+        fn out_of_fuel_cant_be_caught() {
+            let script = "
+try
+  loop
+    x = 1
+catch e
+  42
+";
+            let (_, result) = run_with_settings(
+                script,
+                VmSettings {
+                    fuel: Some(100),
+                    ..Default::default()
+                },
+            );
+            assert!(result.is_err());
+        }
+
+        #[test]
+        fn out_of_fuel_in_nested_call() {
+            let script = "
+x = (0..20).each(|n| n * 2).to_list()
+x.size()
+";
+            let (mut vm, result) = run_with_settings(
+                script,
+                VmSettings {
+                    fuel: Some(50),
+                    ..Default::default()
+                },
+            );
+            match result {
+                Err(error) => assert!(
+                    error.contains("the instruction budget has been used up"),
+                    "{}",
+                    error
+                ),
+                Ok(value) => panic!("Expected the fuel to run out, found '{}'", value),
+            }
+
+            // The external function that made the nested call can't be resumed
+            vm.add_fuel(1000);
+            match vm.continue_running() {
+                Err(error) => assert_eq!(
+                    format!("{:#}", error),
+                    "continue_running: execution can't be resumed after an execution limit was \
+                     reached in a nested call"
+                ),
+                Ok(value) => panic!("Expected an error, found '{}'", value),
+            }
+
+            // The Vm can be used again after the failed resume
+            let chunk = Loader::default().compile_script(script, &None).unwrap();
+            check_number(vm.run(chunk).unwrap(), 20.0);
+        }
+
+        #[test]
+        fn out_of_fuel_in_nested_call_from_run_function() {
+            let script = "
+export f = ||
+  (0..20).each(|n| n * 2).to_list()
+";
+            let (mut vm, result) = run_with_settings(script, VmSettings::default());
+            assert!(result.is_ok());
+
+            let f = vm.get_exported_function("f").unwrap();
+            vm.set_fuel(Some(20));
+            assert!(vm.run_function(f.clone(), &[]).is_err());
+            vm.set_fuel(None);
+            assert!(vm.continue_running().is_err());
+
+            // The list function's child Vm is left in a usable state
+            match vm.run_function(f, &[]) {
+                Ok(Value::List(list)) => assert_eq!(list.len(), 20),
+                other => panic!("Expected a list, found {:?}", other.map(|v| v.to_string())),
+            }
+        }
+
+        #[test]
+        fn run_function_can_be_resumed() {
+            let script = "
+export f = |n|
+  x = 0
+  for i in 0..n
+    x += i
+  x
+";
+            let (mut vm, result) = run_with_settings(script, VmSettings::default());
+            assert!(result.is_ok());
+
+            vm.set_fuel(Some(5));
+            let f = vm.get_exported_function("f").unwrap();
+            assert!(vm.run_function(f, &[Value::Number(10.into())]).is_err());
+
+            vm.set_fuel(None);
+            check_number(vm.continue_running().unwrap(), 45.0);
+        }
+    }
+
+    mod interrupt {
+        use super::*;
+
+        #[test]
+        fn interrupt_from_another_thread() {
+            let script = "
+loop
+  x = 1
+";
+            let mut vm = Vm::default();
+            let chunk = Loader::default().compile_script(script, &None).unwrap();
+
+            let interrupt_handle = vm.interrupt_handle();
+            let interrupter = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                interrupt_handle.interrupt();
+            });
+
+            match vm.run(chunk) {
+                Err(error) => assert!(matches!(error.error, RuntimeErrorType::Interrupted)),
+                Ok(_) => panic!("Expected execution to be interrupted"),
+            }
+
+            interrupter.join().unwrap();
+            assert!(!vm.interrupt_handle().is_interrupted());
+        }
+
+        #[test]
+        fn interrupt_stops_running_threads() {
+            let script = "
+import thread
+export counts = [0, 0, 0]
+make_thread = |i|
+  thread.create ||
+    loop
+      counts[i] = counts[i] + 1
+threads = [make_thread(0), make_thread(1), make_thread(2)]
+loop
+  x = 1
+";
+            let mut vm = Vm::default();
+            let chunk = Loader::default().compile_script(script, &None).unwrap();
+
+            let interrupt_handle = vm.interrupt_handle();
+            let interrupter = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                interrupt_handle.interrupt();
+            });
+
+            match vm.run(chunk) {
+                Err(error) => assert!(matches!(error.error, RuntimeErrorType::Interrupted)),
+                Ok(_) => panic!("Expected execution to be interrupted"),
+            }
+            interrupter.join().unwrap();
+
+            let get_counts = || match vm.get_exported_value("counts") {
+                Some(counts) => counts.to_string(),
+                None => panic!("Missing counts"),
+            };
+
+            // Give the threads time to stop, and then check that none of them are still counting
+            thread::sleep(Duration::from_millis(20));
+            let counts = get_counts();
+            thread::sleep(Duration::from_millis(50));
+            assert_eq!(counts, get_counts());
+        }
+
+        #[test]
+        fn interrupt_is_cleared_after_stopping() {
+            let script = "
+x = 1
+x + 1
+";
+            let settings = VmSettings::default();
+            let interrupt_handle = settings.interrupt_handle.clone();
+            interrupt_handle.interrupt();
+
+            let (mut vm, result) = run_with_settings(script, settings);
+            assert!(result.is_err());
+
+            check_number(vm.continue_running().unwrap(), 2.0);
+        }
+    }
+
+    mod call_depth {
+        use super::*;
+
+        fn check_stack_overflow(result: Result<Value, String>) {
+            match result {
+                Err(error) => {
+                    assert_eq!(error, "Stack overflow, the maximum call depth was exceeded")
+                }
+                Ok(value) => panic!("Expected a stack overflow, found '{}'", value),
+            }
+        }
+
+        #[test]
+        fn recursion_within_the_limit() {
+            let script = "
+f = |n| if n == 0 then 0 else 1 + f n - 1
+f 90
+";
+            let (_, result) = run_with_settings(
+                script,
+                VmSettings {
+                    max_call_depth: Some(100),
+                    ..Default::default()
+                },
+            );
+            check_number(result.unwrap(), 90.0);
+        }
+
+        #[test]
+        fn unbounded_recursion() {
+            let script = "
+f = |n| 1 + f n + 1
+f 0
+";
+            let (_, result) = run_with_settings(
+                script,
+                VmSettings {
+                    max_call_depth: Some(100),
+                    ..Default::default()
+                },
+            );
+            check_stack_overflow(result);
+        }
+
+        #[test]
+        fn unbounded_recursion_through_external_functions() {
+            let script = "
+f = |n| (0..1).each(|_| f n + 1).to_list()
+f 0
+";
+            let (_, result) = run_with_settings(
+                script,
+                VmSettings {
+                    max_call_depth: None,
+                    ..Default::default()
+                },
+            );
+            check_stack_overflow(result);
+        }
+
+        #[test]
+        fn nested_vm_depth_within_the_limit() {
+            let script = "
+f = |n| if n == 0 then 0 else (0..1).each(|_| f n - 1).to_list()[0] + 1
+f 10
+";
+            let (_, result) = run_with_settings(
+                script,
+                VmSettings {
+                    max_nested_vm_depth: Some(10),
+                    ..Default::default()
+                },
+            );
+            check_number(result.unwrap(), 10.0);
+        }
+
+        #[test]
+        fn stack_overflow_cant_be_caught() {
+            let script = "
+f = ||
+  try
+    f()
+  catch e
+    42
+f()
+";
+            let (_, result) = run_with_settings(
+                script,
+                VmSettings {
+                    max_call_depth: Some(100),
+                    ..Default::default()
+                },
+            );
+            check_stack_overflow(result);
+        }
+
+        #[test]
+        fn stack_overflow_in_an_iterator_adaptor_cant_be_caught() {
+            let script = "
+f = ||
+  try
+    for x in (0..1).each(|_| f())
+      x
+  catch e
+    42
+f()
+";
+            let (_, result) = run_with_settings(
+                script,
+                VmSettings {
+                    max_call_depth: Some(100),
+                    ..Default::default()
+                },
+            );
+            check_stack_overflow(result);
+        }
+
+        #[test]
+        fn stack_overflow_in_a_property_check() {
+            let script = "
+import test.check, test.gen
+f = |n| 1 + f n + 1
+check gen.integer(0, 10), |n| f(n) > n
+";
+            let (_, result) = run_with_settings(
+                script,
+                VmSettings {
+                    max_call_depth: Some(100),
+                    ..Default::default()
+                },
+            );
+            check_stack_overflow(result);
+        }
+
+        #[test]
+        fn backtrace_is_truncated() {
+            let script = "
+f = || f()
+f()
+";
+            let mut vm = Vm::default();
+            let chunk = Loader::default().compile_script(script, &None).unwrap();
+
+            let error = vm.run(chunk).unwrap_err();
+            assert!(error.is_stack_overflow());
+            assert_eq!(error.trace.len(), 1000);
+            assert!(error.to_string().ends_with("... 984 more frames"));
+        }
+
+        #[test]
+        fn vm_can_be_used_after_a_stack_overflow() {
+            let script = "
+export f = |n| if n > 0 then f n - 1 else n
+";
+            let (mut vm, result) = run_with_settings(
+                script,
+                VmSettings {
+                    max_call_depth: Some(100),
+                    ..Default::default()
+                },
+            );
+            assert!(result.is_ok());
+
+            let f = vm.get_exported_function("f").unwrap();
+            let error = vm
+                .run_function(f.clone(), &[Value::Number(1000.into())])
+                .unwrap_err();
+            assert!(error.is_stack_overflow());
+
+            check_number(
+                vm.run_function(f, &[Value::Number(10.into())]).unwrap(),
+                0.0,
+            );
+        }
+    }
+
+    mod memory {
+        use super::*;
+
+        fn with_memory_limit(limit: usize) -> VmSettings {
+            VmSettings {
+                memory_limit: Some(limit),
+                ..Default::default()
+            }
+        }
+
+        fn check_out_of_memory(result: Result<Value, RuntimeError>) {
+            match result {
+                Err(error) => assert!(
+                    matches!(error.error, RuntimeErrorType::OutOfMemory { .. }),
+                    "Expected an out of memory error, found '{:#}'",
+                    error
+                ),
+                Ok(value) => panic!("Expected an out of memory error, found '{}'", value),
+            }
+        }
+
+        fn run_script(script: &str, vm: &mut Vm) -> Result<Value, RuntimeError> {
+            let chunk = Loader::default().compile_script(script, &None).unwrap();
+            vm.run(chunk)
+        }
+
+        #[test]
+        fn allocations_are_tracked() {
+            let mut vm = Vm::with_settings(with_memory_limit(1_000_000));
+            assert_eq!(vm.allocated_memory(), 0);
+
+            run_script("export x = [1, 2, 3]", &mut vm).unwrap();
+            assert_eq!(vm.allocated_memory(), 3 * mem::size_of::<Value>());
+
+            run_script("export y = \"abc\" + \"def\"", &mut vm).unwrap();
+            assert_eq!(vm.allocated_memory(), 3 * mem::size_of::<Value>() + 6);
+        }
+
+        #[test]
+        fn allocations_are_only_tracked_with_a_limit() {
+            let mut vm = Vm::default();
+            run_script("export x = [1, 2, 3]", &mut vm).unwrap();
+            assert_eq!(vm.allocated_memory(), 0);
+        }
+
+        #[test]
+        fn memory_is_released_when_values_are_dropped() {
+            let mut vm = Vm::with_settings(with_memory_limit(1_000_000));
+
+            run_script("export x = [1, 2, 3]", &mut vm).unwrap();
+            assert_eq!(vm.allocated_memory(), 3 * mem::size_of::<Value>());
+
+            run_script("export x = ()", &mut vm).unwrap();
+            assert_eq!(vm.allocated_memory(), 0);
+        }
+
+        #[test]
+        fn temporary_values_dont_use_up_the_limit() {
+            let script = "
+x = 0
+for i in 0..10000
+  list = [i, i, i, i]
+  string = \"{}\".format list
+  x += list.size() + string.size()
+x
+";
+            let mut vm = Vm::with_settings(with_memory_limit(10_000));
+            check_number(run_script(script, &mut vm).unwrap(), 275_560.0);
+            assert!(vm.allocated_memory() < 10_000);
+        }
+
+        #[test]
+        fn memory_is_released_when_lists_shrink() {
+            let script = "
+export x = []
+for i in 0..10000
+  x.push i
+  x.pop()
+x.size()
+";
+            let mut vm = Vm::with_settings(with_memory_limit(1000));
+            check_number(run_script(script, &mut vm).unwrap(), 0.0);
+            assert_eq!(vm.allocated_memory(), 0);
+        }
+
+        #[test]
+        fn huge_list() {
+            let script = "
+import list
+list.with_size 1000000000000, 0
+";
+            let mut vm = Vm::with_settings(with_memory_limit(1_000_000));
+            check_out_of_memory(run_script(script, &mut vm));
+            assert!(vm.allocated_memory() <= 1_000_000);
+        }
+
+        #[test]
+        fn huge_list_from_range() {
+            let mut vm = Vm::with_settings(with_memory_limit(1_000_000));
+            check_out_of_memory(run_script("[0..1000000000000]", &mut vm));
+        }
+
+        #[test]
+        fn growing_string() {
+            let script = "
+s = \"x\"
+loop
+  s = s + s
+";
+            let mut vm = Vm::with_settings(with_memory_limit(1_000_000));
+            check_out_of_memory(run_script(script, &mut vm));
+        }
+
+        #[test]
+        fn growing_list_in_nested_call() {
+            let script = "
+x = []
+(0..1000000000).each(|n| x.push n).consume()
+";
+            let mut vm = Vm::with_settings(with_memory_limit(1_000_000));
+            check_out_of_memory(run_script(script, &mut vm));
+        }
+
+        #[test]
+        fn out_of_memory_can_be_caught() {
+            let script = "
+import list
+try
+  list.with_size 1000000000000, 0
+catch error
+  \"caught\"
+";
+            let (_, result) = run_with_settings(script, with_memory_limit(1_000_000));
+            assert_eq!(result.unwrap().to_string(), "caught");
+        }
+
+        #[test]
+        fn raising_the_limit() {
+            let script = "
+import list
+export make_list = |n| list.with_size n, 0
+";
+            let (mut vm, result) = run_with_settings(script, with_memory_limit(1000));
+            assert!(result.is_ok());
+
+            let make_list = vm.get_exported_function("make_list").unwrap();
+            let args = &[Value::Number(1000.into())];
+            check_out_of_memory(vm.run_function(make_list.clone(), args));
+
+            vm.set_memory_limit(Some(1000 * mem::size_of::<Value>()));
+            assert_eq!(vm.run_function(make_list, args).unwrap().size(), 1000);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use {
+    koto_runtime::{koto_impl, ExternalValue, IntoKoto, Loader, RuntimeError, Value, ValueKey, Vm},
+    std::fmt,
+};
+
+mod koto_impl {
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct Counter {
+        count: i64,
+    }
+
+    #[koto_impl]
+    impl Counter {
+        pub fn count(&self) -> i64 {
+            self.count
+        }
+
+        pub fn increment(&mut self, amount: Option<i64>) -> i64 {
+            self.count += amount.unwrap_or(1);
+            self.count
+        }
+
+        #[koto(rename = "set")]
+        pub fn set_count(&mut self, count: i64) {
+            self.count = count;
+        }
+
+        pub fn checked_sub(&mut self, amount: i64) -> Result<i64, RuntimeError> {
+            if amount > self.count {
+                Err(RuntimeError::from_string(
+                    "Counter.checked_sub: the count can't be negative".into(),
+                ))
+            } else {
+                self.count -= amount;
+                Ok(self.count)
+            }
+        }
+
+        #[koto(skip)]
+        pub fn reset(&mut self) {
+            self.count = 0;
+        }
+
+        #[allow(dead_code)]
+        fn private_count(&self) -> i64 {
+            self.count
+        }
+
+        pub fn new(count: i64) -> Self {
+            Self { count }
+        }
+    }
+
+    impl fmt::Display for Counter {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Counter({})", self.count)
+        }
+    }
+
+    #[derive(Debug)]
+    struct Named;
+
+    #[koto_impl(rename = "Thing")]
+    impl Named {
+        pub fn name(&self) -> String {
+            "thing".into()
+        }
+    }
+
+    impl fmt::Display for Named {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Named")
+        }
+    }
+
+    fn run_script(script: &str) -> Result<Value, String> {
+        let mut vm = Vm::default();
+        vm.prelude()
+            .add_value("counter", Counter::new(0).into_koto());
+        vm.prelude().add_value("thing", Named.into_koto());
+
+        let script = format!("import counter, thing\n{}", script);
+        let mut loader = Loader::default();
+        let chunk = match loader.compile_script(&script, &None) {
+            Ok(chunk) => chunk,
+            Err(error) => panic!("Error while compiling script: {}", error),
+        };
+
+        vm.run(chunk).map_err(|error| format!("{:#}", error))
+    }
+
+    fn check_result(script: &str, expected: &str) {
+        match run_script(script) {
+            Ok(result) => assert_eq!(result.to_string(), expected),
+            Err(error) => panic!("Unexpected error: {}", error),
+        }
+    }
+
+    fn check_error(script: &str, expected: &str) {
+        match run_script(script) {
+            Ok(result) => panic!("Expected an error, found '{}'", result),
+            Err(error) => assert_eq!(error, expected),
+        }
+    }
+
+    #[test]
+    fn instance_functions() {
+        check_result("counter.count()", "0");
+        check_result("counter.increment()", "1");
+        check_result(
+            "
+counter.increment 10
+counter.increment()
+counter.count()
+",
+            "11",
+        );
+    }
+
+    #[test]
+    fn renamed_method() {
+        check_result(
+            "
+counter.set 42
+counter.count()
+",
+            "42",
+        );
+    }
+
+    #[test]
+    fn renamed_type() {
+        check_result("thing.name()", "thing");
+        assert_eq!(Named.value_type(), "Thing");
+        assert_eq!(Counter::default().value_type(), "Counter");
+    }
+
+    #[test]
+    fn returned_errors() {
+        check_result(
+            "
+counter.set 3
+counter.checked_sub 2
+",
+            "1",
+        );
+        check_error(
+            "counter.checked_sub 1",
+            "Counter.checked_sub: the count can't be negative",
+        );
+    }
+
+    #[test]
+    fn skipped_methods_are_not_exposed() {
+        let mut counter = Counter::new(42);
+        counter.reset();
+        assert_eq!(counter.count(), 0);
+
+        let map = counter.into_value_map();
+        let contents = map.contents();
+        assert!(contents.data.contains_key(&ValueKey::from("set")));
+        assert!(!contents.data.contains_key(&ValueKey::from("set_count")));
+        assert!(!contents.data.contains_key(&ValueKey::from("reset")));
+        assert!(!contents.data.contains_key(&ValueKey::from("private_count")));
+        assert!(!contents.data.contains_key(&ValueKey::from("new")));
+    }
+
+    #[test]
+    fn argument_errors() {
+        check_error(
+            "counter.increment \"x\"",
+            "Counter.increment: expected Number for argument 1, found String",
+        );
+        check_error("counter.set()", "Counter.set: expected 1 argument, found 0");
+        check_error(
+            "counter.count 1",
+            "Counter.count: expected 0 arguments, found 1",
+        );
+    }
+
+    #[test]
+    fn missing_instance() {
+        check_error(
+            "
+m = {count: counter.count}
+m.count()
+",
+            "External value not found",
+        );
+        check_error(
+            "
+thing.count = counter.count
+thing.count()
+",
+            "Invalid type for external value, found 'Thing'",
+        );
+    }
+
+    #[test]
+    fn into_value_map() {
+        let map = Counter::new(7).into_value_map();
+        assert_eq!(map.len(), 5);
+        assert_eq!(
+            Value::Map(map).to_string(),
+            "{count: ||, increment: ||, set: ||, checked_sub: ||, External Data: Counter(7)}"
+        );
+    }
+}
@@ -25,6 +25,7 @@ mod vm {
             logger: Arc::new(TestLogger {
                 output: output.clone(),
             }),
+            ..Default::default()
         });
 
         let print_chunk = |script: &str, chunk: Arc<Chunk>| {
@@ -0,0 +1,39 @@
+use {
+    koto_runtime::{InMemoryResolver, Loader, Value, Vm, VmSettings},
+    std::sync::Arc,
+};
+
+mod module_resolver {
+    use super::*;
+
+    fn run_script(script: &str, resolver: InMemoryResolver) -> Result<Value, String> {
+        let mut vm = Vm::with_settings(VmSettings {
+            module_resolver: Arc::new(resolver),
+            ..Default::default()
+        });
+
+        let mut loader = Loader::default();
+        let chunk = match loader.compile_script(script, &None) {
+            Ok(chunk) => chunk,
+            Err(error) => panic!("Error while compiling script: {}", error),
+        };
+
+        vm.run(chunk).map_err(|error| format!("{:#}", error))
+    }
+
+    #[test]
+    fn import_from_in_memory_modules() {
+        let mut resolver = InMemoryResolver::default();
+        resolver.add_module("foo", "export x = 42");
+        resolver.add_module("bar", "import foo\nexport y = foo.x + 1");
+
+        let result = run_script("import bar\nbar.y", resolver).unwrap();
+        assert_eq!(result.to_string(), "43");
+    }
+
+    #[test]
+    fn missing_module() {
+        let error = run_script("import foo", InMemoryResolver::default()).unwrap_err();
+        assert_eq!(error, "Failed to import 'foo': Unable to find module 'foo'");
+    }
+}
@@ -0,0 +1,106 @@
+use {
+    koto_runtime::{Loader, Profiler, Vm, VmSettings},
+    std::{path::PathBuf, sync::Arc},
+};
+
+mod profiler {
+    use super::*;
+
+    fn run_with_profiler(script: &str) -> Arc<Profiler> {
+        let profiler = Arc::new(Profiler::new());
+        let mut vm = Vm::with_settings(VmSettings {
+            profiler: Some(profiler.clone()),
+            ..Default::default()
+        });
+
+        let mut loader = Loader::default();
+        let chunk = match loader.compile_script(script, &Some(PathBuf::from("test.koto"))) {
+            Ok(chunk) => chunk,
+            Err(error) => panic!("Error while compiling script: {}", error),
+        };
+
+        if let Err(error) = vm.run(chunk) {
+            panic!("Error while running script: {}", error);
+        }
+
+        profiler
+    }
+
+    #[test]
+    fn call_counts() {
+        let script = "
+f = |x| x + 1
+g = |x| f(x) * 2
+for i in 0..10
+  g i
+f 1
+";
+        let profiler = run_with_profiler(script);
+        let functions = profiler.functions();
+
+        let calls: Vec<(String, u64)> = functions.iter().map(|f| (f.name(), f.calls)).collect();
+        assert_eq!(
+            calls,
+            vec![
+                ("test.koto".to_string(), 1),
+                ("test.koto:3:5".to_string(), 10),
+                ("test.koto:2:5".to_string(), 11),
+            ]
+        );
+    }
+
+    #[test]
+    fn inclusive_time_contains_exclusive_time() {
+        let script = "
+f = |n|
+  if n == 0
+    0
+  else
+    f n - 1
+g = || (0..20).each(|_| f 10).to_tuple()
+g()
+";
+        let profiler = run_with_profiler(script);
+
+        for function in profiler.functions() {
+            assert!(function.inclusive_time >= function.exclusive_time);
+        }
+    }
+
+    #[test]
+    fn collapsed_stacks_contain_nested_calls() {
+        let script = "
+f = |n|
+  x = 0
+  for i in 0..n
+    x += i
+  x
+g = || f 100000
+g()
+";
+        let profiler = run_with_profiler(script);
+        let stacks = profiler.collapsed_stacks();
+
+        assert!(
+            stacks
+                .lines()
+                .any(|line| line.starts_with("test.koto;test.koto:7:5;test.koto:2:5 ")),
+            "{}",
+            stacks
+        );
+        for line in stacks.lines() {
+            let micros = line.rsplit(' ').next().unwrap();
+            assert!(micros.parse::<u64>().is_ok(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn reset() {
+        let profiler = run_with_profiler("f = || 1\nf()");
+        assert_eq!(profiler.functions().len(), 2);
+
+        profiler.reset();
+        assert!(profiler.functions().is_empty());
+        assert!(profiler.collapsed_stacks().is_empty());
+    }
+}
@@ -0,0 +1,156 @@
+use koto_runtime::{Loader, RuntimeErrorType, Vm};
+
+mod property_testing {
+    use super::*;
+
+    fn run_script(script: &str) -> Result<(), String> {
+        let mut vm = Vm::default();
+        let mut loader = Loader::default();
+        let chunk = match loader.compile_script(script, &None) {
+            Ok(chunk) => chunk,
+            Err(error) => panic!("Error while compiling script: {}", error),
+        };
+
+        match vm.run(chunk) {
+            Ok(_) => Ok(()),
+            Err(error) => match error.error {
+                RuntimeErrorType::StringError(message) => Err(message),
+                other => panic!("Unexpected error: {:?}", other),
+            },
+        }
+    }
+
+    fn check_failure(script: &str) -> String {
+        match run_script(script) {
+            Ok(_) => panic!("Expected the property check to fail"),
+            Err(message) => message,
+        }
+    }
+
+    #[test]
+    fn passing_property() {
+        let script = "
+import test.check, test.gen
+check gen.integer(), |n| n + 1 > n
+check [gen.string(), gen.string()], |a, b| (a + b).size() == a.size() + b.size()
+check gen.list(gen.number(0, 1), 5), (|xs| xs.size() <= 5), {runs: 10}
+";
+        assert_eq!(run_script(script), Ok(()));
+    }
+
+    #[test]
+    fn integer_counterexample() {
+        let script = "
+import test.check, test.gen
+check gen.integer(0, 1000), (|n| n < 100), {seed: 42}
+";
+        let message = check_failure(script);
+        assert!(message.starts_with("Property failed after"));
+        assert!(message.contains("(seed: 42), minimal counterexample: 100 "));
+        assert!(message.ends_with("\n  The predicate returned false"));
+    }
+
+    #[test]
+    fn list_counterexample() {
+        let script = "
+import test.check, test.gen
+check gen.list(gen.integer(0, 10)), (|xs| xs.size() < 3), {seed: 1}
+";
+        assert!(check_failure(script).contains("minimal counterexample: [0, 0, 0]"));
+    }
+
+    #[test]
+    fn map_counterexample() {
+        let script = "
+import test.check, test.gen
+keys = gen.one_of [\"x\", \"y\", \"z\"]
+check gen.map(keys, gen.integer(0, 10)), (|m| m.size() < 2), {seed: 1}
+";
+        assert!(check_failure(script).contains("minimal counterexample: {x: 0, z: 0}"));
+    }
+
+    #[test]
+    fn string_counterexample() {
+        let script = "
+import test.check, test.gen
+check gen.string(), (|s| not s.contains \"x\"), {seed: 4, runs: 1000}
+";
+        assert!(check_failure(script).contains("minimal counterexample: \"x\""));
+    }
+
+    #[test]
+    fn multiple_generators_with_failing_assertion() {
+        let script = "
+import test.assert, test.check, test.gen
+check [gen.bool(), gen.number(-5, 5)], (|a, b| assert not (a and b > 1.5)), {seed: 3}
+";
+        let message = check_failure(script);
+        assert!(message.contains("minimal counterexample: (true, 2.0)"));
+        assert!(message.ends_with("\n  Assertion failed"));
+    }
+
+    #[test]
+    fn same_seed_produces_same_result() {
+        let script = "
+import test.check, test.gen
+check gen.tuple(gen.integer(), gen.string()), (|t| t[0] < 500), {seed: 99}
+";
+        assert_eq!(check_failure(script), check_failure(script));
+    }
+
+    #[test]
+    fn integer_bounds_at_the_limits_of_the_integer_range() {
+        let script = "
+import number, test.check, test.gen
+check gen.integer(0, number.infinity), |n| n >= 0
+check gen.integer(number.negative_infinity, number.infinity), |n| true
+check gen.integer(number.infinity, number.infinity), |n| n > 0
+";
+        assert_eq!(run_script(script), Ok(()));
+    }
+
+    #[test]
+    fn number_bounds_without_a_finite_range() {
+        let script = "
+import number, test.check, test.gen
+check gen.number(0, number.infinity), |n| true
+";
+        assert_eq!(
+            check_failure(script),
+            "gen.number: Expected min and max values with a finite range, found '0' and 'inf'"
+        );
+
+        let script = "
+import number, test.check, test.gen
+check gen.number(number.nan, 1), |n| true
+";
+        assert_eq!(
+            check_failure(script),
+            "gen.number: Expected no arguments, or min and max"
+        );
+    }
+
+    #[test]
+    fn invalid_option() {
+        let script = "
+import test.check, test.gen
+check gen.bool(), (|b| true), {max_size: 5}
+";
+        assert_eq!(
+            check_failure(script),
+            "check: Invalid option 'max_size' with value '5'"
+        );
+    }
+
+    #[test]
+    fn invalid_generator() {
+        let script = "
+import test.check
+check \"x\", |n| true
+";
+        assert_eq!(
+            check_failure(script),
+            "check: Expected a generator, or a list of generators, found 'String'"
+        );
+    }
+}
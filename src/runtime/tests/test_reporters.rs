@@ -0,0 +1,113 @@
+use {
+    koto_runtime::{JUnitReporter, Loader, TapReporter, TestReporter, Value, Vm},
+    std::path::PathBuf,
+};
+
+mod test_reporters {
+    use super::*;
+
+    const SCRIPT: &str = "
+import test.assert
+export tests =
+  runs: 0
+  pre_test: |self|
+    self.runs += 1
+  test_first: ||
+    assert true
+  test_second: ||
+    assert false
+  test_third: |self|
+    assert self.runs == 3
+";
+
+    fn run_tests_with_reporter(reporter: &mut dyn TestReporter) -> Vec<(String, bool)> {
+        let mut vm = Vm::default();
+        let mut loader = Loader::default();
+        let chunk = match loader.compile_script(SCRIPT, &Some(PathBuf::from("test.koto"))) {
+            Ok(chunk) => chunk,
+            Err(error) => panic!("Error while compiling script: {}", error),
+        };
+        if let Err(error) = vm.run(chunk) {
+            panic!("Error while running script: {}", error);
+        }
+
+        let tests = match vm.get_exported_value("tests") {
+            Some(Value::Map(tests)) => tests,
+            _ => panic!("Missing tests"),
+        };
+
+        reporter.start_suite("test.koto");
+        vm.run_tests(tests, Some(reporter))
+            .iter()
+            .map(|result| (result.name.clone(), result.passed()))
+            .collect()
+    }
+
+    #[test]
+    fn testing_continues_after_a_failure() {
+        let mut reporter = TapReporter::new();
+        let results = run_tests_with_reporter(&mut reporter);
+
+        assert_eq!(
+            results,
+            vec![
+                ("first".to_string(), true),
+                ("second".to_string(), false),
+                ("third".to_string(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn junit() {
+        let mut reporter = JUnitReporter::new();
+        run_tests_with_reporter(&mut reporter);
+        let output = reporter.output();
+
+        assert!(output.starts_with("<?xml"), "{}", output);
+        assert!(
+            output.contains(r#"<testsuite name="test.koto" tests="3" failures="1" errors="0""#),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains(r#"<testcase name="first" classname="test.koto" time=""#),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains(
+                r#"<failure message="Error while running test 'second': Assertion failed">"#
+            ),
+            "{}",
+            output
+        );
+        assert!(output.ends_with("</testsuites>\n"), "{}", output);
+    }
+
+    #[test]
+    fn tap() {
+        let mut reporter = TapReporter::new();
+        run_tests_with_reporter(&mut reporter);
+        let output = reporter.output();
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            &lines[..6],
+            &[
+                "TAP version 13",
+                "1..3",
+                "# test.koto",
+                "ok 1 - first",
+                "not ok 2 - second",
+                "  ---",
+            ]
+        );
+        assert!(
+            lines.contains(&"    Error while running test 'second': Assertion failed"),
+            "{}",
+            output
+        );
+        assert_eq!(lines.last(), Some(&"ok 3 - third"));
+    }
+}
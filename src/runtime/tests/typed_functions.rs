@@ -0,0 +1,115 @@
+use koto_runtime::{runtime_error, FromKoto, IntoKoto, Loader, RuntimeError, Value, ValueMap, Vm};
+
+mod typed_functions {
+    use super::*;
+
+    #[derive(Debug, FromKoto, IntoKoto)]
+    struct Point {
+        x: f64,
+        y: f64,
+    }
+
+    fn make_module() -> ValueMap {
+        let mut result = ValueMap::new();
+
+        result.add_typed_fn("typed.add", |a: f64, b: f64| a + b);
+        result.add_typed_fn("typed.repeat", |s: String, count: usize| s.repeat(count));
+        result.add_typed_fn("typed.sum", |values: Vec<f64>| values.iter().sum::<f64>());
+        result.add_typed_fn("typed.answer", || 42);
+        result.add_typed_fn("typed.scale", |p: Point, factor: Option<f64>| {
+            let factor = factor.unwrap_or(2.0);
+            Point {
+                x: p.x * factor,
+                y: p.y * factor,
+            }
+        });
+        result.add_typed_fn(
+            "typed.checked_div",
+            |a: f64, b: f64| -> Result<f64, RuntimeError> {
+                if b == 0.0 {
+                    runtime_error!("typed.checked_div: division by zero")
+                } else {
+                    Ok(a / b)
+                }
+            },
+        );
+
+        result
+    }
+
+    fn run_script(script: &str) -> Result<Value, String> {
+        let mut vm = Vm::default();
+        vm.prelude().add_map("typed", make_module());
+
+        let script = format!("import typed\n{}", script);
+        let mut loader = Loader::default();
+        let chunk = match loader.compile_script(&script, &None) {
+            Ok(chunk) => chunk,
+            Err(error) => panic!("Error while compiling script: {}", error),
+        };
+
+        vm.run(chunk).map_err(|error| format!("{:#}", error))
+    }
+
+    fn check_result(script: &str, expected: &str) {
+        match run_script(script) {
+            Ok(result) => assert_eq!(result.to_string(), expected),
+            Err(error) => panic!("Unexpected error: {}", error),
+        }
+    }
+
+    fn check_error(script: &str, expected: &str) {
+        match run_script(script) {
+            Ok(result) => panic!("Expected an error, found '{}'", result),
+            Err(error) => assert_eq!(error, expected),
+        }
+    }
+
+    #[test]
+    fn arguments_are_converted() {
+        check_result("typed.add 1, 2.5", "3.5");
+        check_result("typed.repeat \"ab\", 3", "ababab");
+        check_result("typed.sum [1, 2, 3]", "6.0");
+        check_result("typed.answer()", "42");
+    }
+
+    #[test]
+    fn derived_types_as_arguments() {
+        check_result("(typed.scale {x: 1, y: 2}).y", "4.0");
+        check_result("(typed.scale {x: 1, y: 2}, 10).x", "10.0");
+    }
+
+    #[test]
+    fn returned_errors() {
+        check_result("typed.checked_div 1, 2", "0.5");
+        check_error(
+            "typed.checked_div 1, 0",
+            "typed.checked_div: division by zero",
+        );
+    }
+
+    #[test]
+    fn type_mismatch() {
+        check_error(
+            "typed.add 1, [2]",
+            "typed.add: expected Number for argument 2, found List",
+        );
+        check_error(
+            "typed.sum [1, \"x\"]",
+            "typed.sum: expected Number for argument 1 at '[1]', found String",
+        );
+        check_error(
+            "typed.scale {x: 1}, ()",
+            "typed.scale: expected Number for argument 1 at 'y', found Empty",
+        );
+    }
+
+    #[test]
+    fn wrong_number_of_arguments() {
+        check_error("typed.add 1", "typed.add: expected 2 arguments, found 1");
+        check_error(
+            "typed.sum [1], [2]",
+            "typed.sum: expected 1 argument, found 2",
+        );
+    }
+}
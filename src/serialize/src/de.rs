@@ -0,0 +1,301 @@
+use {
+    crate::Error,
+    koto_runtime::{Value, ValueNumber},
+    serde::de::{
+        self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess,
+        SeqAccess, Unexpected, VariantAccess, Visitor,
+    },
+    std::vec,
+};
+
+/// Deserializes a Rust type from a Koto value
+///
+/// Maps are deserialized as maps or structs, and lists and tuples as sequences. `Num2` and `Num4`
+/// values are deserialized as sequences of numbers, and ranges as structs with `start` and `end`
+/// fields.
+///
+/// Enum variants without data are deserialized from strings, and variants with data from maps with
+/// a single entry, with the variant's name as the key.
+///
+/// Errors include the path to the value that couldn't be deserialized, e.g.
+/// `invalid type: string "x", expected f64 at 'points[2].x'`.
+pub fn from_value<T: DeserializeOwned>(value: &Value) -> Result<T, Error> {
+    T::deserialize(ValueDeserializer::new(value.clone()))
+}
+
+/// A serde Deserializer for Koto values
+pub struct ValueDeserializer {
+    value: Value,
+}
+
+impl ValueDeserializer {
+    pub fn new(value: Value) -> Self {
+        Self { value }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::Empty => visitor.visit_unit(),
+            Value::Bool(b) => visitor.visit_bool(b),
+            Value::Number(n) => {
+                if n.is_f64() {
+                    visitor.visit_f64(n.into())
+                } else {
+                    visitor.visit_i64(n.into())
+                }
+            }
+            Value::Num2(n) => visit_sequence(vec![number(n.0), number(n.1)], visitor),
+            Value::Num4(n) => visit_sequence(
+                vec![number(n.0), number(n.1), number(n.2), number(n.3)],
+                visitor,
+            ),
+            Value::Range(r) => visit_map(
+                vec![
+                    (Value::Str("start".into()), number(r.start)),
+                    (Value::Str("end".into()), number(r.end)),
+                ],
+                visitor,
+            ),
+            Value::List(l) => visit_sequence(l.data().to_vec(), visitor),
+            Value::Tuple(t) => visit_sequence(t.data().to_vec(), visitor),
+            Value::Map(m) => {
+                let entries = m
+                    .contents()
+                    .data
+                    .iter()
+                    .map(|(key, value)| (key.value().clone(), value.clone()))
+                    .collect();
+                visit_map(entries, visitor)
+            }
+            Value::Str(s) => visitor.visit_str(&s),
+            unexpected => Err(de::Error::invalid_type(
+                Unexpected::Other(&unexpected.type_as_string()),
+                &visitor,
+            )),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::Empty => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match &self.value {
+            Value::Str(s) => visitor.visit_enum(s.to_string().into_deserializer()),
+            Value::Map(m) if m.len() == 1 => {
+                let (variant, value) = {
+                    let contents = m.contents();
+                    let (key, value) = contents.data.iter().next().unwrap();
+                    (key.value().clone(), value.clone())
+                };
+
+                match variant {
+                    Value::Str(variant) => visitor.visit_enum(EnumDeserializer {
+                        variant: variant.to_string(),
+                        value,
+                    }),
+                    unexpected => Err(de::Error::invalid_type(
+                        Unexpected::Other(&unexpected.type_as_string()),
+                        &"a variant name",
+                    )),
+                }
+            }
+            unexpected => Err(de::Error::invalid_type(
+                Unexpected::Other(&unexpected.type_as_string()),
+                &"a string, or a map with a single entry",
+            )),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf unit
+        unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+fn number(n: impl Into<ValueNumber>) -> Value {
+    Value::Number(n.into())
+}
+
+// Visits the values as a sequence, with an error if the visitor doesn't consume all of them
+fn visit_sequence<'de, V: Visitor<'de>>(values: Vec<Value>, visitor: V) -> Result<V::Value, Error> {
+    let size = values.len();
+    let mut sequence = SeqDeserializer {
+        values: values.into_iter(),
+        index: 0,
+    };
+
+    let result = visitor.visit_seq(&mut sequence)?;
+
+    if sequence.values.len() == 0 {
+        Ok(result)
+    } else {
+        Err(de::Error::invalid_length(
+            size,
+            &format!("{} elements", sequence.index).as_str(),
+        ))
+    }
+}
+
+fn visit_map<'de, V: Visitor<'de>>(
+    entries: Vec<(Value, Value)>,
+    visitor: V,
+) -> Result<V::Value, Error> {
+    visitor.visit_map(MapDeserializer {
+        entries: entries.into_iter(),
+        value: None,
+    })
+}
+
+struct SeqDeserializer {
+    values: vec::IntoIter<Value>,
+    index: usize,
+}
+
+impl<'de> SeqAccess<'de> for SeqDeserializer {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        match self.values.next() {
+            Some(value) => {
+                let index = self.index;
+                self.index += 1;
+                seed.deserialize(ValueDeserializer::new(value))
+                    .map(Some)
+                    .map_err(|e| e.with_index(index))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.values.len())
+    }
+}
+
+struct MapDeserializer {
+    entries: vec::IntoIter<(Value, Value)>,
+    // The value for the most recently visited key, along with the key as a string for errors
+    value: Option<(String, Value)>,
+}
+
+impl<'de> MapAccess<'de> for MapDeserializer {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                let field = key.to_string();
+                let result = seed
+                    .deserialize(ValueDeserializer::new(key))
+                    .map(Some)
+                    .map_err(|e| e.with_field(&field));
+                self.value = Some((field, value));
+                result
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        match self.value.take() {
+            Some((field, value)) => seed
+                .deserialize(ValueDeserializer::new(value))
+                .map_err(|e| e.with_field(&field)),
+            None => Err(de::Error::custom(
+                "next_value_seed called before next_key_seed",
+            )),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct EnumDeserializer {
+    variant: String,
+    value: Value,
+}
+
+impl<'de> EnumAccess<'de> for EnumDeserializer {
+    type Error = Error;
+    type Variant = VariantDeserializer;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Error> {
+        let variant = seed.deserialize(self.variant.clone().into_deserializer())?;
+        Ok((
+            variant,
+            VariantDeserializer {
+                variant: self.variant,
+                value: self.value,
+            },
+        ))
+    }
+}
+
+struct VariantDeserializer {
+    variant: String,
+    value: Value,
+}
+
+impl<'de> VariantAccess<'de> for VariantDeserializer {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        let Self { variant, value } = self;
+        de::Deserialize::deserialize(ValueDeserializer::new(value))
+            .map_err(|e: Error| e.with_field(&variant))
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        let Self { variant, value } = self;
+        seed.deserialize(ValueDeserializer::new(value))
+            .map_err(|e| e.with_field(&variant))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        let Self { variant, value } = self;
+        de::Deserializer::deserialize_tuple(ValueDeserializer::new(value), len, visitor)
+            .map_err(|e| e.with_field(&variant))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let Self { variant, value } = self;
+        de::Deserializer::deserialize_struct(ValueDeserializer::new(value), "", fields, visitor)
+            .map_err(|e| e.with_field(&variant))
+    }
+}
@@ -0,0 +1,73 @@
+use {
+    koto_runtime::RuntimeError,
+    serde::{de, ser},
+    std::{error, fmt},
+};
+
+/// An error produced while converting between Koto values and Rust types
+///
+/// See [from_value](crate::from_value) and [to_value](crate::to_value).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    /// A description of the error
+    pub message: String,
+    /// The location of the value that caused the error, e.g. `items[2].x`
+    ///
+    /// The path is empty when the error was caused by the converted value itself.
+    pub path: String,
+}
+
+impl Error {
+    /// Prefixes the error's path with a map key or struct field name
+    pub fn with_field(mut self, field: &str) -> Self {
+        if self.path.is_empty() || self.path.starts_with('[') {
+            self.path = format!("{}{}", field, self.path);
+        } else {
+            self.path = format!("{}.{}", field, self.path);
+        }
+        self
+    }
+
+    /// Prefixes the error's path with an index into a list or tuple
+    pub fn with_index(mut self, index: usize) -> Self {
+        if self.path.is_empty() || self.path.starts_with('[') {
+            self.path = format!("[{}]{}", index, self.path);
+        } else {
+            self.path = format!("[{}].{}", index, self.path);
+        }
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{} at '{}'", self.message, self.path)
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        Self {
+            message: message.to_string(),
+            path: String::new(),
+        }
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        de::Error::custom(message)
+    }
+}
+
+impl From<Error> for RuntimeError {
+    fn from(error: Error) -> Self {
+        RuntimeError::from_string(error.to_string())
+    }
+}
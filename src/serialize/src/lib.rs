@@ -1,5 +1,15 @@
 //! Serde serialization support for Koto value types
 
+mod de;
+mod error;
+mod ser;
+
+pub use {
+    de::{from_value, ValueDeserializer},
+    error::Error,
+    ser::{to_value, MapSerializer, SequenceSerializer, ValueSerializer, VariantSerializer},
+};
+
 use {
     koto_runtime::Value,
     serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer},
@@ -0,0 +1,379 @@
+use {
+    crate::Error,
+    koto_runtime::{Value, ValueKey, ValueList, ValueMap},
+    serde::ser::{self, Serialize},
+    std::convert::TryFrom,
+};
+
+/// Serializes a Rust value into a Koto value
+///
+/// Structs and maps are serialized as maps, sequences as lists, and tuples as tuples.
+///
+/// Unit enum variants are serialized as strings containing the variant's name, and variants with
+/// data as maps with a single entry, with the variant's name as the key.
+///
+/// Map keys need to serialize to immutable values like strings or numbers.
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, Error> {
+    value.serialize(ValueSerializer)
+}
+
+/// A serde Serializer that produces Koto values
+pub struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    type SerializeSeq = SequenceSerializer;
+    type SerializeTuple = SequenceSerializer;
+    type SerializeTupleStruct = SequenceSerializer;
+    type SerializeTupleVariant = VariantSerializer<SequenceSerializer>;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = VariantSerializer<MapSerializer>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+        Ok(Value::Number(v.into()))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    // Integers that are too large for Koto's 64 bit signed integers are serialized as floats
+    fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+        match i64::try_from(v) {
+            Ok(v) => self.serialize_i64(v),
+            Err(_) => self.serialize_f64(v as f64),
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, Error> {
+        Ok(Value::Number(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, Error> {
+        Ok(Value::Number(v.into()))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, Error> {
+        Ok(Value::Str(v.to_string().into()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, Error> {
+        Ok(Value::Str(v.into()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, Error> {
+        let bytes: Vec<Value> = v
+            .iter()
+            .map(|b| Value::Number(i64::from(*b).into()))
+            .collect();
+        Ok(Value::List(ValueList::from_slice(&bytes)))
+    }
+
+    fn serialize_none(self) -> Result<Value, Error> {
+        Ok(Value::Empty)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Ok(Value::Empty)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+        Ok(Value::Empty)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value, Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        let value = to_value(value).map_err(|e| e.with_field(variant))?;
+        Ok(variant_map(variant, value))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SequenceSerializer, Error> {
+        Ok(SequenceSerializer::new(len.unwrap_or(0), false))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SequenceSerializer, Error> {
+        Ok(SequenceSerializer::new(len, true))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SequenceSerializer, Error> {
+        Ok(SequenceSerializer::new(len, true))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<VariantSerializer<SequenceSerializer>, Error> {
+        Ok(VariantSerializer {
+            variant,
+            inner: SequenceSerializer::new(len, true),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer::new(len.unwrap_or(0)))
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer::new(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<VariantSerializer<MapSerializer>, Error> {
+        Ok(VariantSerializer {
+            variant,
+            inner: MapSerializer::new(len),
+        })
+    }
+}
+
+// Makes a map with a single entry, used for enum variants that contain data
+fn variant_map(variant: &str, value: Value) -> Value {
+    let mut result = ValueMap::with_capacity(1);
+    result.add_value(variant, value);
+    Value::Map(result)
+}
+
+/// Serializes sequences into lists, and tuples into tuples
+pub struct SequenceSerializer {
+    values: Vec<Value>,
+    is_tuple: bool,
+}
+
+impl SequenceSerializer {
+    fn new(capacity: usize, is_tuple: bool) -> Self {
+        Self {
+            values: Vec::with_capacity(capacity),
+            is_tuple,
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let index = self.values.len();
+        let value = to_value(value).map_err(|e| e.with_index(index))?;
+        self.values.push(value);
+        Ok(())
+    }
+
+    fn finish(self) -> Value {
+        if self.is_tuple {
+            Value::Tuple(self.values.into())
+        } else {
+            Value::List(ValueList::from_slice(&self.values))
+        }
+    }
+}
+
+impl ser::SerializeSeq for SequenceSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTuple for SequenceSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleStruct for SequenceSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+/// Serializes maps and structs into maps
+pub struct MapSerializer {
+    map: ValueMap,
+    // The most recently serialized key, waiting for its value
+    key: Option<Value>,
+}
+
+impl MapSerializer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            map: ValueMap::with_capacity(capacity),
+            key: None,
+        }
+    }
+
+    fn insert<T: Serialize + ?Sized>(&mut self, key: Value, value: &T) -> Result<(), Error> {
+        let field = key.to_string();
+        let value = to_value(value).map_err(|e| e.with_field(&field))?;
+        self.map.insert(ValueKey::from(key), value);
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        let key = to_value(key)?;
+        if key.is_immutable() {
+            self.key = Some(key);
+            Ok(())
+        } else {
+            Err(ser::Error::custom(format!(
+                "map keys need to be immutable, found {}",
+                key.type_as_string()
+            )))
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        match self.key.take() {
+            Some(key) => self.insert(key, value),
+            None => Err(ser::Error::custom(
+                "serialize_value called before serialize_key",
+            )),
+        }
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(Value::Map(self.map))
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(Value::Str(key.into()), value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(Value::Map(self.map))
+    }
+}
+
+/// Serializes enum variants that contain data into maps with a single entry
+pub struct VariantSerializer<T> {
+    variant: &'static str,
+    inner: T,
+}
+
+impl ser::SerializeTupleVariant for VariantSerializer<SequenceSerializer> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let variant = self.variant;
+        self.inner.push(value).map_err(|e| e.with_field(variant))
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(variant_map(self.variant, self.inner.finish()))
+    }
+}
+
+impl ser::SerializeStructVariant for VariantSerializer<MapSerializer> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        let variant = self.variant;
+        self.inner
+            .insert(Value::Str(key.into()), value)
+            .map_err(|e| e.with_field(variant))
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(variant_map(self.variant, Value::Map(self.inner.map)))
+    }
+}
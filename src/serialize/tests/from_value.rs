@@ -0,0 +1,171 @@
+use {
+    koto_runtime::{Loader, Value, Vm},
+    koto_serialize::from_value,
+    serde::Deserialize,
+    std::{collections::HashMap, ops::Range},
+};
+
+mod from_value {
+    use super::*;
+
+    fn run_script(script: &str) -> Value {
+        let mut vm = Vm::default();
+        let mut loader = Loader::default();
+        let chunk = match loader.compile_script(script, &None) {
+            Ok(chunk) => chunk,
+            Err(error) => panic!("Error while compiling script: {}", error),
+        };
+
+        match vm.run(chunk) {
+            Ok(result) => result,
+            Err(error) => panic!("Error while running script: {}", error),
+        }
+    }
+
+    fn deserialize_error<T: for<'de> Deserialize<'de> + std::fmt::Debug>(script: &str) -> String {
+        from_value::<T>(&run_script(script))
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct LevelConfig {
+        name: String,
+        size: (u32, u32),
+        enemies: Vec<Enemy>,
+        spawn: Option<[f64; 2]>,
+        #[serde(default)]
+        tags: HashMap<String, bool>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Enemy {
+        kind: EnemyKind,
+        health: u8,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum EnemyKind {
+        Slime,
+        Bat { speed: f64 },
+        Golem(u8),
+    }
+
+    #[test]
+    fn primitives() {
+        assert_eq!(from_value::<i64>(&run_script("-42")), Ok(-42));
+        assert_eq!(from_value::<f64>(&run_script("1.5")), Ok(1.5));
+        assert_eq!(from_value::<f32>(&run_script("2")), Ok(2.0));
+        assert_eq!(from_value::<bool>(&run_script("true")), Ok(true));
+        assert_eq!(from_value::<String>(&run_script("\"hi\"")), Ok("hi".into()));
+        assert_eq!(from_value::<()>(&run_script("()")), Ok(()));
+        assert_eq!(from_value::<Option<u8>>(&run_script("()")), Ok(None));
+        assert_eq!(from_value::<Option<u8>>(&run_script("7")), Ok(Some(7)));
+    }
+
+    #[test]
+    fn sequences() {
+        assert_eq!(
+            from_value::<Vec<i32>>(&run_script("[1, 2, 3]")),
+            Ok(vec![1, 2, 3])
+        );
+        assert_eq!(
+            from_value::<(String, bool)>(&run_script("\"x\", false")),
+            Ok(("x".into(), false))
+        );
+        assert_eq!(
+            from_value::<[f64; 2]>(&run_script("num2 1, 2")),
+            Ok([1.0, 2.0])
+        );
+        assert_eq!(
+            from_value::<Vec<f32>>(&run_script("num4 1, 2, 3, 4")),
+            Ok(vec![1.0, 2.0, 3.0, 4.0])
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(from_value::<Range<i64>>(&run_script("10..20")), Ok(10..20));
+    }
+
+    #[test]
+    fn config_struct() {
+        let script = "
+config =
+  name: \"cave\"
+  size: (20, 10)
+  enemies: [{kind: \"Slime\", health: 10}, {kind: {Bat: {speed: 2.5}}, health: 5}, {kind: {Golem: 3}, health: 200}]
+  tags: {dark: true}
+config
+";
+        assert_eq!(
+            from_value::<LevelConfig>(&run_script(script)),
+            Ok(LevelConfig {
+                name: "cave".into(),
+                size: (20, 10),
+                enemies: vec![
+                    Enemy {
+                        kind: EnemyKind::Slime,
+                        health: 10
+                    },
+                    Enemy {
+                        kind: EnemyKind::Bat { speed: 2.5 },
+                        health: 5
+                    },
+                    Enemy {
+                        kind: EnemyKind::Golem(3),
+                        health: 200
+                    },
+                ],
+                spawn: None,
+                tags: vec![("dark".to_string(), true)].into_iter().collect(),
+            })
+        );
+    }
+
+    #[test]
+    fn errors_include_the_path() {
+        assert_eq!(
+            deserialize_error::<LevelConfig>(
+                "{name: \"x\", size: (1, 2), enemies: [{kind: \"Slime\", health: \"lots\"}]}"
+            ),
+            "invalid type: string \"lots\", expected u8 at 'enemies[0].health'"
+        );
+        assert_eq!(
+            deserialize_error::<LevelConfig>(
+                "{name: \"x\", size: (1, 2), enemies: [{kind: {Bat: {}}, health: 1}]}"
+            ),
+            "missing field `speed` at 'enemies[0].kind.Bat'"
+        );
+        assert_eq!(
+            deserialize_error::<LevelConfig>("{name: \"x\", size: (1, 2, 3), enemies: []}"),
+            "invalid length 3, expected 2 elements at 'size'"
+        );
+        assert_eq!(
+            deserialize_error::<LevelConfig>("{name: \"x\", size: (1, 2)}"),
+            "missing field `enemies`"
+        );
+        assert_eq!(
+            deserialize_error::<EnemyKind>("\"Dragon\""),
+            "unknown variant `Dragon`, expected one of `Slime`, `Bat`, `Golem`"
+        );
+        assert_eq!(
+            deserialize_error::<u8>("300"),
+            "invalid value: integer `300`, expected u8"
+        );
+        assert_eq!(
+            deserialize_error::<String>("|x| x"),
+            "invalid type: Function, expected a string"
+        );
+    }
+
+    #[test]
+    fn error_into_runtime_error() {
+        let error = from_value::<Vec<u8>>(&run_script("[1, true]")).unwrap_err();
+        let runtime_error = koto_runtime::RuntimeError::from(error);
+        assert_eq!(
+            format!("{:#}", runtime_error),
+            "invalid type: boolean `true`, expected u8 at '[1]'"
+        );
+    }
+}
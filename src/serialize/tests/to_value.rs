@@ -0,0 +1,120 @@
+use {
+    koto_runtime::Value,
+    koto_serialize::{from_value, to_value},
+    serde::{Deserialize, Serialize},
+    std::collections::BTreeMap,
+};
+
+mod to_value {
+    use super::*;
+
+    fn to_string<T: Serialize + ?Sized>(value: &T) -> String {
+        to_value(value).unwrap().to_string()
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Player {
+        name: String,
+        position: (f64, f64),
+        inventory: Vec<Item>,
+        score: Option<u32>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Item {
+        Key,
+        Potion(u8),
+        Sword { damage: f64 },
+        Pair(u8, u8),
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Meters(f64);
+
+    #[test]
+    fn primitives() {
+        assert_eq!(to_string(&true), "true");
+        assert_eq!(to_string(&-42i8), "-42");
+        assert_eq!(to_string(&1.5f64), "1.5");
+        assert_eq!(to_string("hello"), "hello");
+        assert_eq!(to_string(&'x'), "x");
+        assert_eq!(to_string(&()), "()");
+        assert_eq!(to_string(&None::<i32>), "()");
+        assert_eq!(to_string(&Some(3)), "3");
+        assert_eq!(to_string(&Meters(2.5)), "2.5");
+    }
+
+    #[test]
+    fn large_unsigned_integers_become_floats() {
+        assert_eq!(to_string(&(i64::MAX as u64)), i64::MAX.to_string());
+        assert!(matches!(to_value(&u64::MAX), Ok(Value::Number(n)) if n.is_f64()));
+    }
+
+    #[test]
+    fn sequences_and_tuples() {
+        assert!(matches!(to_value(&vec![1, 2, 3]), Ok(Value::List(_))));
+        assert_eq!(to_string(&vec![1, 2, 3]), "[1, 2, 3]");
+        assert!(matches!(to_value(&(1, "x")), Ok(Value::Tuple(_))));
+        assert_eq!(to_string(&(1, "x")), "(1, \"x\")");
+    }
+
+    #[test]
+    fn maps() {
+        let mut map = BTreeMap::new();
+        map.insert("a", 1);
+        map.insert("b", 2);
+        assert_eq!(to_string(&map), "{a: 1, b: 2}");
+    }
+
+    #[test]
+    fn enums() {
+        assert_eq!(to_string(&Item::Key), "Key");
+        assert_eq!(to_string(&Item::Potion(3)), "{Potion: 3}");
+        match to_value(&Item::Sword { damage: 1.5 }) {
+            Ok(Value::Map(m)) => {
+                assert_eq!(m.len(), 1);
+                assert_eq!(
+                    m.contents()
+                        .data
+                        .get_with_string("Sword")
+                        .unwrap()
+                        .to_string(),
+                    "{damage: 1.5}"
+                );
+            }
+            other => panic!("Expected a map, found {:?}", other),
+        }
+        assert_eq!(to_string(&Item::Pair(1, 2)), "{Pair: (1, 2)}");
+    }
+
+    #[test]
+    fn struct_round_trip() {
+        let player = Player {
+            name: "ferris".into(),
+            position: (1.0, -2.5),
+            inventory: vec![Item::Key, Item::Potion(2), Item::Sword { damage: 4.0 }],
+            score: None,
+        };
+
+        let value = to_value(&player).unwrap();
+        assert!(matches!(&value, Value::Map(m) if m.len() == 4));
+        assert_eq!(from_value::<Player>(&value), Ok(player));
+    }
+
+    #[test]
+    fn mutable_map_keys() {
+        let mut map = BTreeMap::new();
+        map.insert(vec![1], 1);
+        assert_eq!(
+            to_value(&map).unwrap_err().to_string(),
+            "map keys need to be immutable, found List"
+        );
+
+        let mut nested = BTreeMap::new();
+        nested.insert("inner", map);
+        assert_eq!(
+            to_value(&vec![nested]).unwrap_err().to_string(),
+            "map keys need to be immutable, found List at '[0].inner'"
+        );
+    }
+}